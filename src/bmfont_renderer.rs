@@ -52,6 +52,10 @@ impl BMFontRenderer {
         self.font.line_height as f32 * constants.font_scale
     }
 
+    pub fn has_glyph(&self, chr: char) -> bool {
+        self.font.chars.contains_key(&chr)
+    }
+
     pub fn text_width<I: Iterator<Item = char>>(&self, iter: I, constants: &EngineConstants) -> f32 {
         let mut offset_x = 0.0;
 
@@ -65,31 +69,6 @@ impl BMFontRenderer {
         offset_x
     }
 
-    pub fn draw_text<I: Iterator<Item = char>>(
-        &self,
-        iter: I,
-        x: f32,
-        y: f32,
-        constants: &EngineConstants,
-        texture_set: &mut TextureSet,
-        ctx: &mut Context,
-    ) -> GameResult {
-        self.draw_colored_text(iter, x, y, (255, 255, 255, 255), constants, texture_set, ctx)
-    }
-
-    pub fn draw_text_with_shadow<I: Iterator<Item = char> + Clone>(
-        &self,
-        iter: I,
-        x: f32,
-        y: f32,
-        constants: &EngineConstants,
-        texture_set: &mut TextureSet,
-        ctx: &mut Context,
-    ) -> GameResult {
-        self.draw_colored_text(iter.clone(), x + 1.0, y + 1.0, (0, 0, 0, 150), constants, texture_set, ctx)?;
-        self.draw_colored_text(iter, x, y, (255, 255, 255, 255), constants, texture_set, ctx)
-    }
-
     pub fn draw_colored_text_scaled<I: Iterator<Item = char>>(
         &self,
         iter: I,
@@ -166,17 +145,4 @@ impl BMFontRenderer {
 
         Ok(())
     }
-
-    pub fn draw_colored_text<I: Iterator<Item = char>>(
-        &self,
-        iter: I,
-        x: f32,
-        y: f32,
-        color: (u8, u8, u8, u8),
-        constants: &EngineConstants,
-        texture_set: &mut TextureSet,
-        ctx: &mut Context,
-    ) -> GameResult {
-        self.draw_colored_text_scaled(iter, x, y, 1.0, color, constants, texture_set, ctx)
-    }
 }
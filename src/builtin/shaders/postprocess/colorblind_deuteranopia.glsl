@@ -0,0 +1,23 @@
+#version 100
+
+// Simulates how the frame looks with deuteranopia (no green cones), using the model by Machado et al. (2009).
+
+precision mediump float;
+
+uniform sampler2D Texture;
+varying vec2 Frag_UV;
+varying vec4 Frag_Color;
+
+// rows of the simulation matrix, so the color is multiplied from the left
+const mat3 SIMULATION = mat3(
+        0.367322, 0.860646, -0.227968,
+        0.280085, 0.672501, 0.047413,
+        -0.011820, 0.042940, 0.968881);
+
+void main()
+{
+    vec3 linear = pow(texture2D(Texture, Frag_UV).rgb, vec3(2.2));
+    vec3 simulated = clamp(linear * SIMULATION, 0.0, 1.0);
+
+    gl_FragColor = vec4(pow(simulated, vec3(1.0 / 2.2)), 1.0);
+}
@@ -0,0 +1,23 @@
+#version 100
+
+// Simulates how the frame looks with protanopia (no red cones), using the model by Machado et al. (2009).
+
+precision mediump float;
+
+uniform sampler2D Texture;
+varying vec2 Frag_UV;
+varying vec4 Frag_Color;
+
+// rows of the simulation matrix, so the color is multiplied from the left
+const mat3 SIMULATION = mat3(
+        0.152286, 1.052583, -0.204868,
+        0.114503, 0.786281, 0.099216,
+        -0.003882, -0.048116, 1.051998);
+
+void main()
+{
+    vec3 linear = pow(texture2D(Texture, Frag_UV).rgb, vec3(2.2));
+    vec3 simulated = clamp(linear * SIMULATION, 0.0, 1.0);
+
+    gl_FragColor = vec4(pow(simulated, vec3(1.0 / 2.2)), 1.0);
+}
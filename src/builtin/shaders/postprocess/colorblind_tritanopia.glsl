@@ -0,0 +1,23 @@
+#version 100
+
+// Simulates how the frame looks with tritanopia (no blue cones), using the model by Machado et al. (2009).
+
+precision mediump float;
+
+uniform sampler2D Texture;
+varying vec2 Frag_UV;
+varying vec4 Frag_Color;
+
+// rows of the simulation matrix, so the color is multiplied from the left
+const mat3 SIMULATION = mat3(
+        1.255528, -0.076749, -0.178779,
+        -0.078411, 0.930809, 0.147602,
+        0.004733, 0.691367, 0.303900);
+
+void main()
+{
+    vec3 linear = pow(texture2D(Texture, Frag_UV).rgb, vec3(2.2));
+    vec3 simulated = clamp(linear * SIMULATION, 0.0, 1.0);
+
+    gl_FragColor = vec4(pow(simulated, vec3(1.0 / 2.2)), 1.0);
+}
@@ -0,0 +1,43 @@
+#version 100
+
+// Approximation of a CRT television: slightly curved screen, scanlines between rows
+// of game pixels, an aperture grille mask and darkened corners.
+
+precision mediump float;
+
+uniform sampler2D Texture;
+uniform vec2 SourceSize;
+uniform vec2 OutputSize;
+uniform float Scale;
+varying vec2 Frag_UV;
+varying vec4 Frag_Color;
+
+const float CURVATURE = 0.03;
+const float SCANLINE_STRENGTH = 0.35;
+const float MASK_STRENGTH = 0.15;
+const float BRIGHTNESS = 1.2;
+
+void main()
+{
+    vec2 centered = Frag_UV * 2.0 - 1.0;
+    centered *= 1.0 + CURVATURE * vec2(centered.y * centered.y, centered.x * centered.x);
+    vec2 uv = centered * 0.5 + 0.5;
+
+    if (uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0) {
+        gl_FragColor = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+
+    vec3 color = texture2D(Texture, uv).rgb;
+
+    // scanlines need at least two screen pixels per game pixel to be visible
+    float row = fract(uv.y * SourceSize.y / Scale) - 0.5;
+    float scanline = 1.0 - SCANLINE_STRENGTH * step(2.0, Scale) * row * row * 4.0;
+
+    float column = mod(floor(Frag_UV.x * OutputSize.x), 3.0);
+    vec3 mask = vec3(1.0 - MASK_STRENGTH) + MASK_STRENGTH * vec3(equal(vec3(column), vec3(0.0, 1.0, 2.0)));
+
+    float vignette = 1.0 - 0.25 * dot(centered, centered) * 0.5;
+
+    gl_FragColor = vec4(clamp(color * scanline * mask * vignette * BRIGHTNESS, 0.0, 1.0), 1.0);
+}
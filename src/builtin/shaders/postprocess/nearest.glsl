@@ -0,0 +1,18 @@
+#version 100
+
+// Snaps the frame to the game's pixel grid, so sub-pixel movement and smooth scrolling
+// look like they would at native resolution scaled up by an integer factor.
+
+precision mediump float;
+
+uniform sampler2D Texture;
+uniform vec2 SourceSize;
+uniform float Scale;
+varying vec2 Frag_UV;
+varying vec4 Frag_Color;
+
+void main()
+{
+    vec2 pixel = (floor(Frag_UV * SourceSize / Scale) + 0.5) * Scale;
+    gl_FragColor = vec4(texture2D(Texture, (floor(pixel) + 0.5) / SourceSize).rgb, 1.0);
+}
@@ -0,0 +1,38 @@
+#version 100
+
+// Scales the game's pixel grid with nearest neighbour inside pixels and bilinear filtering
+// only along their edges, which keeps pixels sharp while evening out their sizes.
+
+precision mediump float;
+
+uniform sampler2D Texture;
+uniform vec2 SourceSize;
+uniform vec2 OutputSize;
+uniform float Scale;
+varying vec2 Frag_UV;
+varying vec4 Frag_Color;
+
+vec3 game_pixel(vec2 pos)
+{
+    vec2 texel = floor((pos + 0.5) * Scale) + 0.5;
+    return texture2D(Texture, texel / SourceSize).rgb;
+}
+
+void main()
+{
+    vec2 game_size = SourceSize / Scale;
+    vec2 prescale = max(OutputSize / game_size, vec2(1.0));
+    vec2 texel = Frag_UV * game_size;
+
+    vec2 region = 0.5 - 0.5 / prescale;
+    vec2 center_dist = fract(texel) - 0.5;
+    vec2 f = (center_dist - clamp(center_dist, -region, region)) * prescale + 0.5;
+    vec2 pos = floor(texel) + f - 0.5;
+
+    vec2 base = floor(pos);
+    vec2 weight = pos - base;
+    vec3 top = mix(game_pixel(base), game_pixel(base + vec2(1.0, 0.0)), weight.x);
+    vec3 bottom = mix(game_pixel(base + vec2(0.0, 1.0)), game_pixel(base + vec2(1.0, 1.0)), weight.x);
+
+    gl_FragColor = vec4(mix(top, bottom, weight.y), 1.0);
+}
@@ -111,12 +111,21 @@ impl BuiltinFS {
                     FSNode::File("builtin_font_0.png", include_bytes!("builtin/builtin_font_0.png")),
                     FSNode::File("builtin_font_1.png", include_bytes!("builtin/builtin_font_1.png")),
                     FSNode::File("organya-wavetable-doukutsu.bin", include_bytes!("builtin/organya-wavetable-doukutsu.bin")),
+                    FSNode::File("organya-wavetable-org.bin", include_bytes!("builtin/organya-wavetable-org.bin")),
                     FSNode::File("touch.png", include_bytes!("builtin/touch.png")),
                     FSNode::Directory("shaders", vec![
                         // FSNode::File("basic_150.vert.glsl", include_bytes!("builtin/shaders/basic_150.vert.glsl")),
                         // FSNode::File("water_150.frag.glsl", include_bytes!("builtin/shaders/water_150.frag.glsl")),
                         // FSNode::File("basic_es300.vert.glsl", include_bytes!("builtin/shaders/basic_es300.vert.glsl")),
                         // FSNode::File("water_es300.frag.glsl", include_bytes!("builtin/shaders/water_es300.frag.glsl")),
+                        FSNode::Directory("postprocess", vec![
+                            FSNode::File("colorblind_deuteranopia.glsl", include_bytes!("builtin/shaders/postprocess/colorblind_deuteranopia.glsl")),
+                            FSNode::File("colorblind_protanopia.glsl", include_bytes!("builtin/shaders/postprocess/colorblind_protanopia.glsl")),
+                            FSNode::File("colorblind_tritanopia.glsl", include_bytes!("builtin/shaders/postprocess/colorblind_tritanopia.glsl")),
+                            FSNode::File("crt.glsl", include_bytes!("builtin/shaders/postprocess/crt.glsl")),
+                            FSNode::File("nearest.glsl", include_bytes!("builtin/shaders/postprocess/nearest.glsl")),
+                            FSNode::File("sharp_bilinear.glsl", include_bytes!("builtin/shaders/postprocess/sharp_bilinear.glsl")),
+                        ]),
                     ]),
                     FSNode::Directory("lightmap", vec![
                         FSNode::File("spot.png", include_bytes!("builtin/lightmap/spot.png")),
@@ -125,7 +125,7 @@ bitfield! {
 }
 
 bitfield! {
-  #[derive(Clone, Copy)]
+  #[derive(Clone, Copy, Serialize, Deserialize)]
   #[repr(C)]
   pub struct BulletFlag(u16);
   impl Debug;
@@ -181,7 +181,7 @@ pub enum FadeState {
     FadeOut(i8, FadeDirection),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum Direction {
     Left = 0,
@@ -396,8 +396,7 @@ fn lerp_f64(v1: f64, v2: f64, t: f64) -> f64 {
 }
 
 #[inline(always)]
-#[allow(dead_code)]
-fn lerp_f32(v1: f32, v2: f32, t: f32) -> f32 {
+pub fn lerp_f32(v1: f32, v2: f32, t: f32) -> f32 {
     v1 * (1.0 - t) + v2 * t
 }
 
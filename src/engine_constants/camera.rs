@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::Rect;
+
+/// Part of a stage with its own camera bounds, applied while the camera target is inside of it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CameraRegion {
+    /// Area in tiles the camera target has to be in.
+    pub area: Rect<u16>,
+    /// Area in tiles the view is kept in, `area` if not set.
+    #[serde(default)]
+    pub bounds: Option<Rect<u16>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CameraConsts {
+    /// Distance in pixels the camera leads ahead of the player in the direction they're facing and looking.
+    pub look_ahead: (i32, i32),
+    /// Number of ticks zoom changes are smoothed over, the same way `<FOM` wait works for movement.
+    pub zoom_wait: i32,
+    pub min_zoom: f32,
+    pub max_zoom: f32,
+    /// Frames both players in co-op, zooming out down to `min_zoom` if they're far apart.
+    pub multi_target: bool,
+    /// Space in pixels kept between the players and edges of the view when framing both of them.
+    pub multi_target_margin: i32,
+    /// Camera regions keyed by map name, the first one containing the camera target applies.
+    pub regions: HashMap<String, Vec<CameraRegion>>,
+}
+
+impl CameraConsts {
+    pub fn defaults() -> CameraConsts {
+        CameraConsts {
+            look_ahead: (64, 64),
+            zoom_wait: 16,
+            min_zoom: 0.5,
+            max_zoom: 4.0,
+            multi_target: false,
+            multi_target_margin: 48,
+            regions: HashMap::new(),
+        }
+    }
+
+    /// Returns the region of given map containing given point in fix9 coordinates, if there's any.
+    pub fn region_at(&self, map: &str, tile_size: i32, x: i32, y: i32) -> Option<&CameraRegion> {
+        // tiles are centered on their coordinates
+        let block_size = tile_size * 0x200;
+        let (x, y) = ((x + block_size / 2) / block_size, (y + block_size / 2) / block_size);
+
+        self.regions.get(map)?.iter().find(|region| {
+            (region.area.left as i32..=region.area.right as i32).contains(&x)
+                && (region.area.top as i32..=region.area.bottom as i32).contains(&y)
+        })
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::Direction;
+use crate::npc::NPC;
+
+/// A light attached to NPCs of given type, drawn only when all of the set conditions match the NPC state.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NPCLight {
+    #[serde(default)]
+    pub direction: Option<Direction>,
+    #[serde(default)]
+    pub action_num: Option<u16>,
+    #[serde(default)]
+    pub anim_num: Option<u16>,
+    /// Only matches when `anim_num` is lower than this value.
+    #[serde(default)]
+    pub anim_num_below: Option<u16>,
+    /// Offset from the NPC center in pixels.
+    #[serde(default)]
+    pub offset: (f32, f32),
+    pub size: f32,
+    pub color: (u8, u8, u8),
+    /// If not empty, overrides `color` with the entry indexed by `anim_num`, used for flickering lights.
+    #[serde(default)]
+    pub anim_colors: Vec<(u8, u8, u8)>,
+}
+
+impl NPCLight {
+    fn new(size: f32, color: (u8, u8, u8)) -> NPCLight {
+        NPCLight {
+            direction: None,
+            action_num: None,
+            anim_num: None,
+            anim_num_below: None,
+            offset: (0.0, 0.0),
+            size,
+            color,
+            anim_colors: Vec::new(),
+        }
+    }
+
+    pub fn matches(&self, npc: &NPC) -> bool {
+        self.direction.map_or(true, |direction| npc.direction == direction)
+            && self.action_num.map_or(true, |action_num| npc.action_num == action_num)
+            && self.anim_num.map_or(true, |anim_num| npc.anim_num == anim_num)
+            && self.anim_num_below.map_or(true, |anim_num| npc.anim_num < anim_num)
+    }
+
+    pub fn color_for(&self, npc: &NPC) -> (u8, u8, u8) {
+        if self.anim_colors.is_empty() {
+            self.color
+        } else {
+            self.anim_colors[npc.anim_num as usize % self.anim_colors.len()]
+        }
+    }
+}
+
+/// Per-stage light map settings, unset fields fall back to the global ones.
+#[derive(Debug, Copy, Clone, Default, Serialize, Deserialize)]
+pub struct StageLighting {
+    #[serde(default)]
+    pub enabled: Option<bool>,
+    #[serde(default)]
+    pub ambient: Option<(u8, u8, u8)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LightingConsts {
+    /// Color the light map is cleared with before drawing lights.
+    pub ambient: (u8, u8, u8),
+    pub npc_lights: HashMap<u16, Vec<NPCLight>>,
+    /// Stage settings keyed by map name.
+    pub stages: HashMap<String, StageLighting>,
+}
+
+impl LightingConsts {
+    pub fn defaults() -> LightingConsts {
+        let mut npc_lights = HashMap::new();
+
+        npc_lights.insert(1, vec![NPCLight::new(0.4, (255, 255, 0))]);
+        npc_lights.insert(4, vec![NPCLight { direction: Some(Direction::Up), ..NPCLight::new(1.0, (200, 100, 0)) }]);
+        npc_lights.insert(7, vec![NPCLight::new(1.0, (100, 100, 100))]);
+        npc_lights.insert(
+            17,
+            vec![
+                NPCLight { anim_num: Some(0), ..NPCLight::new(2.0, (160, 0, 0)) },
+                NPCLight { anim_num: Some(0), ..NPCLight::new(0.5, (255, 0, 0)) },
+            ],
+        );
+        npc_lights.insert(
+            20,
+            vec![
+                NPCLight { direction: Some(Direction::Right), ..NPCLight::new(2.0, (0, 0, 150)) },
+                NPCLight {
+                    direction: Some(Direction::Right),
+                    anim_num_below: Some(2),
+                    ..NPCLight::new(2.1, (0, 0, 30))
+                },
+            ],
+        );
+        npc_lights
+            .insert(22, vec![NPCLight { action_num: Some(1), anim_num: Some(1), ..NPCLight::new(3.0, (0, 0, 255)) }]);
+        for npc_type in [32, 87, 211].iter() {
+            npc_lights.insert(*npc_type, vec![NPCLight::new(2.0, (255, 30, 30))]);
+        }
+        npc_lights.insert(
+            38,
+            vec![NPCLight {
+                anim_colors: vec![(145, 55, 0), (130, 40, 0), (175, 85, 0), (160, 70, 0)],
+                ..NPCLight::new(3.5, (130, 40, 0))
+            }],
+        );
+        npc_lights.insert(
+            70,
+            vec![NPCLight {
+                anim_colors: vec![(50, 50, 50), (65, 65, 65), (80, 80, 80), (95, 95, 95)],
+                ..NPCLight::new(2.0, (50, 50, 50))
+            }],
+        );
+        for npc_type in [75, 77].iter() {
+            npc_lights.insert(*npc_type, vec![NPCLight::new(3.0, (255, 100, 0))]);
+        }
+
+        // green when facing left, red otherwise
+        let mut terminal_lights = Vec::new();
+        for direction in [Direction::Left, Direction::Up, Direction::Right, Direction::Bottom].iter() {
+            let (color, color2) =
+                if *direction == Direction::Left { ((0, 150, 100), (0, 50, 30)) } else { ((150, 0, 0), (50, 0, 0)) };
+
+            terminal_lights.push(NPCLight {
+                direction: Some(*direction),
+                action_num: Some(1),
+                ..NPCLight::new(1.5, color)
+            });
+            terminal_lights.push(NPCLight {
+                direction: Some(*direction),
+                action_num: Some(1),
+                anim_num_below: Some(2),
+                offset: (0.0, -8.0),
+                ..NPCLight::new(2.1, color2)
+            });
+        }
+        npc_lights.insert(85, terminal_lights);
+
+        LightingConsts { ambient: (100, 100, 110), npc_lights, stages: HashMap::new() }
+    }
+
+    /// Returns light map settings for stage with given map name.
+    pub fn stage(&self, map: &str) -> StageLighting {
+        self.stages.get(map).copied().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn npc(npc_type: u16, direction: Direction, action_num: u16, anim_num: u16) -> NPC {
+        let mut npc = NPC::empty();
+        npc.npc_type = npc_type;
+        npc.direction = direction;
+        npc.action_num = action_num;
+        npc.anim_num = anim_num;
+        npc
+    }
+
+    #[test]
+    fn light_conditions() {
+        let light = NPCLight::new(1.0, (255, 255, 255));
+        assert!(light.matches(&npc(0, Direction::Left, 0, 0)));
+        assert!(light.matches(&npc(0, Direction::Bottom, 5, 7)));
+
+        let light = NPCLight {
+            direction: Some(Direction::Right),
+            action_num: Some(1),
+            anim_num_below: Some(2),
+            ..NPCLight::new(1.0, (255, 255, 255))
+        };
+        assert!(light.matches(&npc(0, Direction::Right, 1, 0)));
+        assert!(light.matches(&npc(0, Direction::Right, 1, 1)));
+        assert!(!light.matches(&npc(0, Direction::Right, 1, 2)));
+        assert!(!light.matches(&npc(0, Direction::Left, 1, 0)));
+        assert!(!light.matches(&npc(0, Direction::Right, 0, 0)));
+
+        let light = NPCLight { anim_num: Some(1), ..NPCLight::new(1.0, (255, 255, 255)) };
+        assert!(!light.matches(&npc(0, Direction::Left, 0, 0)));
+        assert!(light.matches(&npc(0, Direction::Left, 0, 1)));
+        assert!(!light.matches(&npc(0, Direction::Left, 0, 2)));
+    }
+
+    #[test]
+    fn flickering_colors() {
+        let light = NPCLight::new(1.0, (10, 20, 30));
+        assert_eq!(light.color_for(&npc(0, Direction::Left, 0, 3)), (10, 20, 30));
+
+        let light = NPCLight { anim_colors: vec![(1, 1, 1), (2, 2, 2), (3, 3, 3)], ..NPCLight::new(1.0, (10, 20, 30)) };
+        let colors: Vec<_> = (0..5).map(|anim_num| light.color_for(&npc(0, Direction::Left, 0, anim_num))).collect();
+        assert_eq!(colors, vec![(1, 1, 1), (2, 2, 2), (3, 3, 3), (1, 1, 1), (2, 2, 2)]);
+    }
+
+    /// Light as (offset, size, color).
+    type Light = ((f32, f32), f32, (u8, u8, u8));
+
+    /// Lights the `match npc.npc_type` block in `GameScene::draw_light_map` used to draw.
+    fn hard_coded_lights(npc: &NPC) -> Vec<Light> {
+        match npc.npc_type {
+            1 => vec![((0.0, 0.0), 0.4, (255, 255, 0))],
+            4 if npc.direction == Direction::Up => vec![((0.0, 0.0), 1.0, (200, 100, 0))],
+            7 => vec![((0.0, 0.0), 1.0, (100, 100, 100))],
+            17 if npc.anim_num == 0 => vec![((0.0, 0.0), 2.0, (160, 0, 0)), ((0.0, 0.0), 0.5, (255, 0, 0))],
+            20 if npc.direction == Direction::Right => {
+                let mut lights = vec![((0.0, 0.0), 2.0, (0, 0, 150))];
+                if npc.anim_num < 2 {
+                    lights.push(((0.0, 0.0), 2.1, (0, 0, 30)));
+                }
+                lights
+            }
+            22 if npc.action_num == 1 && npc.anim_num == 1 => vec![((0.0, 0.0), 3.0, (0, 0, 255))],
+            32 | 87 | 211 => vec![((0.0, 0.0), 2.0, (255, 30, 30))],
+            38 => {
+                let flicker = (npc.anim_num ^ (5 & 3)) as u8 * 15;
+                vec![((0.0, 0.0), 3.5, (130 + flicker, 40 + flicker, 0))]
+            }
+            70 => {
+                let flicker = 50 + npc.anim_num as u8 * 15;
+                vec![((0.0, 0.0), 2.0, (flicker, flicker, flicker))]
+            }
+            75 | 77 => vec![((0.0, 0.0), 3.0, (255, 100, 0))],
+            85 if npc.action_num == 1 => {
+                let (color, color2) = if npc.direction == Direction::Left {
+                    ((0, 150, 100), (0, 50, 30))
+                } else {
+                    ((150, 0, 0), (50, 0, 0))
+                };
+
+                let mut lights = vec![((0.0, 0.0), 1.5, color)];
+                if npc.anim_num < 2 {
+                    lights.push(((0.0, -8.0), 2.1, color2));
+                }
+                lights
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn defaults_match_hard_coded_lights() {
+        let lighting = LightingConsts::defaults();
+        let directions = [Direction::Left, Direction::Up, Direction::Right, Direction::Bottom];
+
+        for npc_type in 0..400 {
+            for direction in directions.iter() {
+                for action_num in 0..4 {
+                    // flickering NPCs have four animation frames, the table wraps around after them
+                    for anim_num in 0..4 {
+                        let npc = npc(npc_type, *direction, action_num, anim_num);
+                        let lights: Vec<Light> = lighting
+                            .npc_lights
+                            .get(&npc_type)
+                            .into_iter()
+                            .flatten()
+                            .filter(|light| light.matches(&npc))
+                            .map(|light| (light.offset, light.size, light.color_for(&npc)))
+                            .collect();
+
+                        assert_eq!(
+                            lights,
+                            hard_coded_lights(&npc),
+                            "type {}, direction {:?}, action {}, animation {}",
+                            npc_type,
+                            direction,
+                            action_num,
+                            anim_num
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
@@ -2,9 +2,17 @@ use std::collections::HashMap;
 
 use case_insensitive_hashmap::CaseInsensitiveHashMap;
 use log::info;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 use crate::case_insensitive_hashmap;
 use crate::common::{BulletFlag, Color, Rect};
+use crate::framework::context::Context;
+use crate::framework::error::{GameError, GameResult};
+use crate::framework::filesystem;
+use crate::engine_constants::camera::CameraConsts;
+use crate::engine_constants::lighting::LightingConsts;
 use crate::engine_constants::npcs::NPCConsts;
 use crate::player::ControlMode;
 use crate::sound::pixtone::{Channel, PixToneParameters, Waveform, Envelope};
@@ -12,9 +20,11 @@ use crate::sound::SoundManager;
 use crate::str;
 use crate::text_script::TextScriptEncoding;
 
+mod camera;
+mod lighting;
 mod npcs;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct PhysicsConsts {
     pub max_dash: i32,
     pub max_move: i32,
@@ -26,7 +36,7 @@ pub struct PhysicsConsts {
     pub jump: i32,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct BoosterConsts {
     pub fuel: u32,
     pub b2_0_up: i32,
@@ -36,7 +46,7 @@ pub struct BoosterConsts {
     pub b2_0_right: i32,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct PlayerConsts {
     pub life: u16,
     pub max_life: u16,
@@ -48,7 +58,7 @@ pub struct PlayerConsts {
     pub frames_bubble: [Rect<u16>; 2],
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct GameConsts {
     pub intro_stage: u16,
     pub intro_event: u16,
@@ -58,7 +68,7 @@ pub struct GameConsts {
     pub new_game_player_pos: (i16, i16),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct CaretConsts {
     pub offsets: [(i32, i32); 18],
     pub bubble_left_rects: Vec<Rect<u16>>,
@@ -105,7 +115,7 @@ impl Clone for CaretConsts {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct BulletData {
     pub damage: u8,
     pub life: u8,
@@ -118,7 +128,7 @@ pub struct BulletData {
     pub display_bounds: Rect<u8>,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct BulletRects {
     pub b001_snake_l1: [Rect<u16>; 8],
     pub b002_003_snake_l2_3: [Rect<u16>; 3],
@@ -155,7 +165,7 @@ pub struct BulletRects {
     pub b042_spur_trail_l3: [Rect<u16>; 6],
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct WeaponConsts {
     pub bullet_table: Vec<BulletData>,
     pub bullet_rects: BulletRects,
@@ -172,12 +182,12 @@ impl Clone for WeaponConsts {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct WorldConsts {
     pub snack_rect: Rect<u16>,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct TextScriptConsts {
     pub encoding: TextScriptEncoding,
     pub encrypted: bool,
@@ -207,7 +217,7 @@ pub struct TextScriptConsts {
     pub text_speed_fast: u8,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct TitleConsts {
     pub intro_text: String,
     pub logo_rect: Rect<u16>,
@@ -256,12 +266,23 @@ pub struct EngineConstants {
     pub textscript: TextScriptConsts,
     pub title: TitleConsts,
     pub inventory_dim_color: Color,
+    /// BMFont descriptor, or TrueType/OpenType font drawn at `font_size`.
     pub font_path: String,
+    /// TrueType/OpenType fonts searched in order for characters missing in `font_path`.
+    pub font_fallback_paths: Vec<String>,
+    /// Size of TrueType/OpenType fonts in canvas pixels.
+    pub font_size: f32,
     pub font_scale: f32,
     pub font_space_offset: f32,
     pub soundtracks: HashMap<String, String>,
     pub music_table: Vec<String>,
     pub organya_paths: Vec<String>,
+    /// NPC types spawned for unit types of PxPack maps, units of other types are skipped.
+    /// Empty by default: Kero Blaster units have no Cave Story NPC counterparts, so this is only plumbing
+    /// for mods that ship their own NPCs, and PxPack maps spawn no units unless a mod fills it in.
+    pub pxpack_unit_types: HashMap<u8, u16>,
+    pub lighting: LightingConsts,
+    pub camera: CameraConsts,
 }
 
 impl Clone for EngineConstants {
@@ -282,11 +303,16 @@ impl Clone for EngineConstants {
             title: self.title.clone(),
             inventory_dim_color: self.inventory_dim_color,
             font_path: self.font_path.clone(),
+            font_fallback_paths: self.font_fallback_paths.clone(),
+            font_size: self.font_size,
             font_scale: self.font_scale,
             font_space_offset: self.font_space_offset,
             soundtracks: self.soundtracks.clone(),
             music_table: self.music_table.clone(),
             organya_paths: self.organya_paths.clone(),
+            pxpack_unit_types: self.pxpack_unit_types.clone(),
+            lighting: self.lighting.clone(),
+            camera: self.camera.clone(),
         }
     }
 }
@@ -1430,6 +1456,8 @@ impl EngineConstants {
             },
             inventory_dim_color: Color::from_rgba(0, 0, 0, 0),
             font_path: "csfont.fnt".to_string(),
+            font_fallback_paths: Vec::new(),
+            font_size: 12.0,
             font_scale: 1.0,
             font_space_offset: 0.0,
             soundtracks: HashMap::new(),
@@ -1483,10 +1511,13 @@ impl EngineConstants {
                 "/base/Org/".to_string(),     // CS+
                 "/Resource/ORG/".to_string(), // CSE2E
             ],
+            pxpack_unit_types: HashMap::new(),
+            lighting: LightingConsts::defaults(),
+            camera: CameraConsts::defaults(),
         }
     }
 
-    pub fn apply_csplus_patches(&mut self, sound_manager: &SoundManager) {
+    pub fn apply_csplus_patches(&mut self, sound_manager: &mut SoundManager) {
         info!("Applying Cave Story+ constants patches...");
 
         self.is_cs_plus = true;
@@ -1547,4 +1578,146 @@ impl EngineConstants {
         self.soundtracks.insert("Famitracks".to_string(), "/base/ogg17/".to_string());
         self.soundtracks.insert("Ridiculon".to_string(), "/base/ogg_ridic/".to_string());
     }
+
+    /// Loads constant overrides from `engine_constants.json` or `engine_constants.yml` in data directory.
+    pub fn load_overrides(&mut self, base_path: &str, ctx: &Context) -> GameResult {
+        for dir in [base_path, "/"].iter() {
+            let path = format!("{}/engine_constants.json", dir.trim_end_matches('/'));
+            if let Ok(file) = filesystem::open(ctx, &path) {
+                info!("Loading engine constants overrides from {}", path);
+                let overrides: Value = serde_json::from_reader(file)?;
+                return self.apply_overrides(&overrides);
+            }
+
+            let path = format!("{}/engine_constants.yml", dir.trim_end_matches('/'));
+            if let Ok(file) = filesystem::open(ctx, &path) {
+                info!("Loading engine constants overrides from {}", path);
+                let overrides: Value = serde_yaml::from_reader(file)?;
+                return self.apply_overrides(&overrides);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies a partial set of constants on top of current ones. Only the fields present in `overrides`
+    /// are changed, arrays can be either replaced as a whole or patched by passing an object with indices as keys.
+    pub fn apply_overrides(&mut self, overrides: &Value) -> GameResult {
+        let overrides = match overrides {
+            Value::Object(map) => map,
+            Value::Null => return Ok(()),
+            _ => return Err(GameError::ParseError("Engine constants overrides must be an object.".to_string())),
+        };
+
+        for (key, value) in overrides.iter() {
+            match key.as_str() {
+                "game" => merge_into(&mut self.game, value)?,
+                "player" => merge_into(&mut self.player, value)?,
+                "booster" => merge_into(&mut self.booster, value)?,
+                "caret" => merge_into(&mut self.caret, value)?,
+                "world" => merge_into(&mut self.world, value)?,
+                "npc" => merge_into(&mut self.npc, value)?,
+                "weapon" => merge_into(&mut self.weapon, value)?,
+                "textscript" => merge_into(&mut self.textscript, value)?,
+                "title" => merge_into(&mut self.title, value)?,
+                "inventory_dim_color" => merge_into(&mut self.inventory_dim_color, value)?,
+                "font_path" => merge_into(&mut self.font_path, value)?,
+                "font_fallback_paths" => merge_into(&mut self.font_fallback_paths, value)?,
+                "font_size" => merge_into(&mut self.font_size, value)?,
+                "font_scale" => merge_into(&mut self.font_scale, value)?,
+                "font_space_offset" => merge_into(&mut self.font_space_offset, value)?,
+                "soundtracks" => merge_into(&mut self.soundtracks, value)?,
+                "music_table" => merge_into(&mut self.music_table, value)?,
+                "organya_paths" => merge_into(&mut self.organya_paths, value)?,
+                "pxpack_unit_types" => merge_into(&mut self.pxpack_unit_types, value)?,
+                "lighting" => merge_into(&mut self.lighting, value)?,
+                "camera" => merge_into(&mut self.camera, value)?,
+                "tex_sizes" => {
+                    let sizes: HashMap<String, (u16, u16)> = serde_json::from_value(value.clone())?;
+                    for (name, size) in sizes {
+                        self.tex_sizes.insert(name, size);
+                    }
+                }
+                _ => log::warn!("Unknown engine constant group: {}", key),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn merge_into<T: Serialize + DeserializeOwned>(target: &mut T, patch: &Value) -> GameResult {
+    let mut value = serde_json::to_value(&*target)?;
+    merge_value(&mut value, patch);
+    *target = serde_json::from_value(value)?;
+
+    Ok(())
+}
+
+fn merge_value(target: &mut Value, patch: &Value) {
+    match (target, patch) {
+        (Value::Object(target), Value::Object(patch)) => {
+            for (key, value) in patch.iter() {
+                match target.get_mut(key) {
+                    Some(entry) => merge_value(entry, value),
+                    None => {
+                        target.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (Value::Array(target), Value::Object(patch)) => {
+            // keys are iterated in string order, which would put "10" before "9" and break appending
+            let mut entries = Vec::with_capacity(patch.len());
+            for (key, value) in patch.iter() {
+                match key.parse::<usize>() {
+                    Ok(idx) => entries.push((idx, value)),
+                    Err(_) => log::warn!("Invalid array index in engine constants overrides: {}", key),
+                }
+            }
+            entries.sort_by_key(|(idx, _)| *idx);
+
+            for (idx, value) in entries {
+                if idx < target.len() {
+                    merge_value(&mut target[idx], value);
+                } else if idx == target.len() {
+                    target.push(value.clone());
+                } else {
+                    log::warn!("Invalid array index in engine constants overrides: {}", idx);
+                }
+            }
+        }
+        (target, patch) => *target = patch.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn merge_value_patches_array_elements() {
+        let mut target = json!([{ "a": 1, "b": 2 }, { "a": 3, "b": 4 }]);
+        merge_value(&mut target, &json!({ "1": { "b": 5 } }));
+
+        assert_eq!(target, json!([{ "a": 1, "b": 2 }, { "a": 3, "b": 5 }]));
+    }
+
+    #[test]
+    fn merge_value_appends_to_array_in_index_order() {
+        let mut target = json!([0, 1, 2, 3, 4, 5, 6, 7]);
+        merge_value(&mut target, &json!({ "10": 10, "8": 8, "9": 9, "11": 11, "0": 100 }));
+
+        assert_eq!(target, json!([100, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]));
+    }
+
+    #[test]
+    fn merge_value_skips_indices_past_the_end() {
+        let mut target = json!([0, 1]);
+        merge_value(&mut target, &json!({ "3": 3, "x": 4 }));
+
+        assert_eq!(target, json!([0, 1]));
+    }
 }
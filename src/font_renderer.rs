@@ -0,0 +1,195 @@
+use itertools::Itertools;
+
+use crate::bmfont_renderer::BMFontRenderer;
+use crate::engine_constants::EngineConstants;
+use crate::framework::context::Context;
+use crate::framework::error::GameResult;
+use crate::texture_set::TextureSet;
+use crate::ttf_renderer::TTFFontRenderer;
+
+/// Returns true if given font path points to a TrueType or OpenType font rather than a BMFont descriptor.
+fn is_outline_font(path: &str) -> bool {
+    let path = path.to_lowercase();
+
+    path.ends_with(".ttf") || path.ends_with(".otf") || path.ends_with(".ttc")
+}
+
+/// Splits text into runs of characters drawn with the same font, flagged true if they're in the BMFont.
+fn font_runs<I: Iterator<Item = char>, F: Fn(char) -> bool>(iter: I, in_bmfont: F) -> Vec<(bool, Vec<char>)> {
+    iter.group_by(|chr| in_bmfont(*chr)).into_iter().map(|(in_bmfont, run)| (in_bmfont, run.collect())).collect()
+}
+
+/// Text renderer used by the game. Draws with a BMFont if there is one, characters it doesn't contain
+/// are drawn with the outline fonts listed in `font_fallback_paths`.
+pub struct FontRenderer {
+    bmfont: Option<BMFontRenderer>,
+    ttf: Option<TTFFontRenderer>,
+}
+
+impl FontRenderer {
+    pub fn load(root: &str, constants: &EngineConstants, ctx: &mut Context) -> GameResult<FontRenderer> {
+        if is_outline_font(&constants.font_path) {
+            let mut paths = vec![constants.font_path.clone()];
+            paths.extend(constants.font_fallback_paths.iter().cloned());
+
+            match TTFFontRenderer::load(root, &paths, constants.font_size, ctx) {
+                Ok(ttf) => return Ok(FontRenderer { bmfont: None, ttf: Some(ttf) }),
+                Err(err) => log::warn!("Failed to load font {}, using builtin one: {}", constants.font_path, err),
+            }
+        }
+
+        let bmfont = BMFontRenderer::load(root, &constants.font_path, ctx)
+            .or_else(|_| BMFontRenderer::load("/", "builtin/builtin_font.fnt", ctx))?;
+
+        let ttf = if constants.font_fallback_paths.is_empty() {
+            None
+        } else {
+            TTFFontRenderer::load(root, &constants.font_fallback_paths, constants.font_size, ctx)
+                .map_err(|err| log::warn!("Failed to load fallback fonts: {}", err))
+                .ok()
+        };
+
+        Ok(FontRenderer { bmfont: Some(bmfont), ttf })
+    }
+
+    pub fn line_height(&self, constants: &EngineConstants) -> f32 {
+        match (&self.bmfont, &self.ttf) {
+            (Some(bmfont), _) => bmfont.line_height(constants),
+            (None, Some(ttf)) => ttf.line_height(),
+            (None, None) => 0.0,
+        }
+    }
+
+    pub fn text_width<I: Iterator<Item = char>>(&self, iter: I, constants: &EngineConstants) -> f32 {
+        match (&self.bmfont, &self.ttf) {
+            (Some(bmfont), None) => bmfont.text_width(iter, constants),
+            (None, Some(ttf)) => ttf.text_width(iter),
+            (Some(bmfont), Some(ttf)) => iter
+                .map(|chr| {
+                    if bmfont.has_glyph(chr) {
+                        bmfont.text_width(std::iter::once(chr), constants)
+                    } else {
+                        ttf.text_width(std::iter::once(chr))
+                    }
+                })
+                .sum(),
+            (None, None) => 0.0,
+        }
+    }
+
+    pub fn draw_text<I: Iterator<Item = char>>(
+        &self,
+        iter: I,
+        x: f32,
+        y: f32,
+        constants: &EngineConstants,
+        texture_set: &mut TextureSet,
+        ctx: &mut Context,
+    ) -> GameResult {
+        self.draw_colored_text(iter, x, y, (255, 255, 255, 255), constants, texture_set, ctx)
+    }
+
+    pub fn draw_text_with_shadow<I: Iterator<Item = char> + Clone>(
+        &self,
+        iter: I,
+        x: f32,
+        y: f32,
+        constants: &EngineConstants,
+        texture_set: &mut TextureSet,
+        ctx: &mut Context,
+    ) -> GameResult {
+        self.draw_colored_text(iter.clone(), x + 1.0, y + 1.0, (0, 0, 0, 150), constants, texture_set, ctx)?;
+        self.draw_colored_text(iter, x, y, (255, 255, 255, 255), constants, texture_set, ctx)
+    }
+
+    pub fn draw_colored_text_scaled<I: Iterator<Item = char>>(
+        &self,
+        iter: I,
+        x: f32,
+        y: f32,
+        scale: f32,
+        color: (u8, u8, u8, u8),
+        constants: &EngineConstants,
+        texture_set: &mut TextureSet,
+        ctx: &mut Context,
+    ) -> GameResult {
+        match (&self.bmfont, &self.ttf) {
+            (Some(bmfont), None) => {
+                bmfont.draw_colored_text_scaled(iter, x, y, scale, color, constants, texture_set, ctx)
+            }
+            (None, Some(ttf)) => ttf.draw_colored_text_scaled(iter, x, y, scale, color, ctx),
+            (Some(bmfont), Some(ttf)) => {
+                let mut offset_x = x;
+
+                for (in_bmfont, run) in font_runs(iter, |chr| bmfont.has_glyph(chr)) {
+                    if in_bmfont {
+                        bmfont.draw_colored_text_scaled(
+                            run.iter().copied(),
+                            offset_x,
+                            y,
+                            scale,
+                            color,
+                            constants,
+                            texture_set,
+                            ctx,
+                        )?;
+                        offset_x += bmfont.text_width(run.iter().copied(), constants) * scale;
+                    } else {
+                        ttf.draw_colored_text_scaled(run.iter().copied(), offset_x, y, scale, color, ctx)?;
+                        offset_x += ttf.text_width(run.iter().copied()) * scale;
+                    }
+                }
+
+                Ok(())
+            }
+            (None, None) => Ok(()),
+        }
+    }
+
+    pub fn draw_colored_text<I: Iterator<Item = char>>(
+        &self,
+        iter: I,
+        x: f32,
+        y: f32,
+        color: (u8, u8, u8, u8),
+        constants: &EngineConstants,
+        texture_set: &mut TextureSet,
+        ctx: &mut Context,
+    ) -> GameResult {
+        self.draw_colored_text_scaled(iter, x, y, 1.0, color, constants, texture_set, ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outline_font_extensions() {
+        assert!(is_outline_font("font.ttf"));
+        assert!(is_outline_font("fonts/NotoSans.OTF"));
+        assert!(is_outline_font("msgothic.ttc"));
+        assert!(!is_outline_font("csfont.fnt"));
+        assert!(!is_outline_font("ttf.fnt"));
+        assert!(!is_outline_font("font.ttf.fnt"));
+        assert!(!is_outline_font(""));
+    }
+
+    #[test]
+    fn runs_of_same_font() {
+        let runs = |text: &str| -> Vec<(bool, String)> {
+            font_runs(text.chars(), |chr| chr.is_ascii())
+                .into_iter()
+                .map(|(in_bmfont, run)| (in_bmfont, run.into_iter().collect()))
+                .collect()
+        };
+
+        assert_eq!(runs(""), vec![]);
+        assert_eq!(runs("Hello"), vec![(true, "Hello".to_string())]);
+        assert_eq!(runs("\u{4e16}\u{754c}"), vec![(false, "\u{4e16}\u{754c}".to_string())]);
+        assert_eq!(
+            runs("Hi, \u{4e16}\u{754c}!"),
+            vec![(true, "Hi, ".to_string()), (false, "\u{4e16}\u{754c}".to_string()), (true, "!".to_string())]
+        );
+    }
+}
@@ -1,4 +1,5 @@
-use crate::common::{fix9_scale, interpolate_fix9_scale};
+use crate::common::{fix9_scale, interpolate_fix9_scale, lerp_f32, Rect};
+use crate::player::Player;
 use crate::rng::RNG;
 use crate::shared_game_state::SharedGameState;
 use crate::stage::Stage;
@@ -20,6 +21,50 @@ pub struct Frame {
     pub target_x: i32,
     pub target_y: i32,
     pub wait: i32,
+    /// Magnification of the view, values above 1.0 zoom in and show less of the stage.
+    pub zoom: f32,
+    pub prev_zoom: f32,
+    pub target_zoom: f32,
+    pub zoom_wait: i32,
+    /// Distance in pixels the camera leads ahead of the player, see `player_target`.
+    pub look_ahead: (i32, i32),
+    /// Area in fix9 coordinates the view is kept in, overriding camera regions of the stage and map bounds.
+    pub bounds: Option<Rect<i32>>,
+    /// Keeps both players in view in co-op.
+    pub multi_target: bool,
+    /// Highest zoom all framed targets fit in the view at, set each tick while framing more than one.
+    pub fit_zoom: Option<f32>,
+}
+
+/// Keeps a view of given size within bounds, or centers it on them if they're smaller than the view.
+fn fit_view(pos: i32, min: i32, max: i32, view: i32) -> i32 {
+    if max - min < view {
+        min - (view - (max - min)) / 2
+    } else {
+        pos.clamp(min, max - view)
+    }
+}
+
+/// Moves a camera target of the player to lead ahead by up to `look_ahead` pixels instead of 64,
+/// `camera_target` being how far ahead of the player it is.
+fn lead_ahead(target: i32, camera_target: i32, look_ahead: i32) -> i32 {
+    target + camera_target * (look_ahead - 64) / 64
+}
+
+/// Returns the highest zoom at which an area of given size in fix9 units fits in the canvas,
+/// with `margin` pixels around it.
+fn zoom_to_fit(canvas_size: (f32, f32), margin: i32, width: i32, height: i32) -> f32 {
+    let zoom_x = canvas_size.0 / (width / 0x200 + margin * 2).max(1) as f32;
+    let zoom_y = canvas_size.1 / (height / 0x200 + margin * 2).max(1) as f32;
+
+    zoom_x.min(zoom_y)
+}
+
+/// Returns the point halfway between two targets and the highest zoom they both fit in the canvas at.
+fn fit_targets(canvas_size: (f32, f32), margin: i32, a: (i32, i32), b: (i32, i32)) -> ((i32, i32), f32) {
+    let center = ((a.0 + b.0) / 2, (a.1 + b.1) / 2);
+
+    (center, zoom_to_fit(canvas_size, margin, (a.0 - b.0).abs(), (a.1 - b.1).abs()))
 }
 
 impl Frame {
@@ -34,73 +79,122 @@ impl Frame {
         (x, y)
     }
 
-    pub fn immediate_update(&mut self, state: &mut SharedGameState, stage: &Stage) {
-        let tile_size = state.tile_size.as_int();
+    pub fn zoom_interpolated(&self, frame_time: f64) -> f32 {
+        lerp_f32(self.prev_zoom, self.zoom, frame_time as f32)
+    }
 
-        if (stage.map.width as usize).saturating_sub(1) * (tile_size as usize) < state.canvas_size.0 as usize {
-            self.x = -(((state.canvas_size.0 as i32 - (stage.map.width as i32 - 1) * tile_size) * 0x200) / 2);
-        } else {
-            self.x = self.target_x - (state.canvas_size.0 as i32 * 0x200 / 2);
+    /// Returns true while zoom changes between ticks, drawing at a different scale every frame.
+    pub fn is_zooming(&self) -> bool {
+        (self.zoom - self.prev_zoom).abs() > f32::EPSILON
+    }
 
-            if self.x < 0 {
-                self.x = 0;
-            }
+    /// Returns size of the area of the stage visible on the screen, in pixels.
+    pub fn viewport_size(&self, state: &SharedGameState) -> (f32, f32) {
+        (state.canvas_size.0 / self.zoom, state.canvas_size.1 / self.zoom)
+    }
 
-            let max_x = (((stage.map.width as i32 - 1) * tile_size) - state.canvas_size.0 as i32) * 0x200;
-            if self.x > max_x {
-                self.x = max_x;
-            }
+    /// Returns the point the camera follows for given player, leading ahead of them by up to `look_ahead` pixels.
+    pub fn player_target(&self, player: &Player) -> (i32, i32) {
+        // target of the player already leads ahead of them by up to 64 pixels
+        (
+            lead_ahead(player.target_x, player.camera_target_x, self.look_ahead.0),
+            lead_ahead(player.target_y, player.camera_target_y, self.look_ahead.1),
+        )
+    }
+
+    /// Targets the point between two targets, zooming out so both of them fit in the view.
+    pub fn target_both(&mut self, state: &SharedGameState, a: (i32, i32), b: (i32, i32)) {
+        let ((x, y), zoom) = fit_targets(state.canvas_size, state.constants.camera.multi_target_margin, a, b);
+
+        self.target_x = x;
+        self.target_y = y;
+        self.fit_zoom = Some(zoom);
+    }
+
+    pub fn set_zoom(&mut self, zoom: f32, wait: i32) {
+        self.target_zoom = zoom;
+        self.zoom_wait = wait.max(1);
+    }
+
+    fn effective_target_zoom(&self, state: &SharedGameState) -> f32 {
+        let camera = &state.constants.camera;
+        let zoom = self.fit_zoom.map_or(self.target_zoom, |fit_zoom| fit_zoom.min(self.target_zoom));
+
+        zoom.max(camera.min_zoom).min(camera.max_zoom)
+    }
+
+    /// Returns bounds set by scripts or camera region the target is in, in fix9 coordinates.
+    fn custom_bounds(&self, state: &SharedGameState, stage: &Stage) -> Option<Rect<i32>> {
+        if self.bounds.is_some() {
+            return self.bounds;
         }
 
-        if (stage.map.height as usize).saturating_sub(1) * (tile_size as usize) < state.canvas_size.1 as usize {
-            self.y = -(((state.canvas_size.1 as i32 - (stage.map.height as i32 - 1) * tile_size) * 0x200) / 2);
-        } else {
-            self.y = self.target_y - (state.canvas_size.1 as i32 * 0x200 / 2);
+        let tile_size = state.tile_size.as_int();
+        let region = state.constants.camera.region_at(&stage.data.map, tile_size, self.target_x, self.target_y)?;
+        let bounds = region.bounds.unwrap_or(region.area);
+        let block_size = tile_size * 0x200;
 
-            if self.y < 0 {
-                self.y = 0;
-            }
+        Some(Rect {
+            left: bounds.left as i32 * block_size,
+            top: bounds.top as i32 * block_size,
+            right: bounds.right as i32 * block_size,
+            bottom: bounds.bottom as i32 * block_size,
+        })
+    }
 
-            let max_y = (((stage.map.height as i32 - 1) * tile_size) - state.canvas_size.1 as i32) * 0x200;
-            if self.y > max_y {
-                self.y = max_y;
-            }
+    fn map_bounds(state: &SharedGameState, stage: &Stage) -> Rect<i32> {
+        let block_size = state.tile_size.as_int() * 0x200;
+
+        Rect {
+            left: 0,
+            top: 0,
+            right: (stage.map.width as i32 - 1) * block_size,
+            bottom: (stage.map.height as i32 - 1) * block_size,
         }
+    }
 
-        self.prev_x = self.x;
-        self.prev_y = self.y;
+    /// Returns size of the view in fix9 units.
+    fn view_size(&self, state: &SharedGameState) -> (i32, i32) {
+        let (width, height) = self.viewport_size(state);
+
+        (width as i32 * 0x200, height as i32 * 0x200)
     }
 
-    pub fn update(&mut self, state: &mut SharedGameState, stage: &Stage) {
-        let tile_size = state.tile_size.as_int();
+    pub fn immediate_update(&mut self, state: &mut SharedGameState, stage: &Stage) {
+        self.zoom = self.effective_target_zoom(state);
+        self.prev_zoom = self.zoom;
 
-        if (stage.map.width as usize).saturating_sub(1) * (tile_size as usize) < state.canvas_size.0 as usize {
-            self.x = -(((state.canvas_size.0 as i32 - (stage.map.width as i32 - 1) * tile_size) * 0x200) / 2);
-        } else {
-            self.x += (self.target_x - (state.canvas_size.0 as i32 * 0x200 / 2) - self.x) / self.wait;
+        let (view_width, view_height) = self.view_size(state);
+        let bounds = self.custom_bounds(state, stage).unwrap_or_else(|| Frame::map_bounds(state, stage));
 
-            if self.x < 0 {
-                self.x = 0;
-            }
+        self.x = fit_view(self.target_x - view_width / 2, bounds.left, bounds.right, view_width);
+        self.y = fit_view(self.target_y - view_height / 2, bounds.top, bounds.bottom, view_height);
 
-            let max_x = (((stage.map.width as i32 - 1) * tile_size) - state.canvas_size.0 as i32) * 0x200;
-            if self.x > max_x {
-                self.x = max_x;
-            }
+        self.prev_x = self.x;
+        self.prev_y = self.y;
+    }
+
+    pub fn update(&mut self, state: &mut SharedGameState, stage: &Stage) {
+        let target_zoom = self.effective_target_zoom(state);
+        self.zoom += (target_zoom - self.zoom) / self.zoom_wait.max(1) as f32;
+        if (target_zoom - self.zoom).abs() < 0.001 {
+            self.zoom = target_zoom;
         }
 
-        if (stage.map.height as usize).saturating_sub(1) * (tile_size as usize) < state.canvas_size.1 as usize {
-            self.y = -(((state.canvas_size.1 as i32 - (stage.map.height as i32 - 1) * tile_size) * 0x200) / 2);
-        } else {
-            self.y += (self.target_y - (state.canvas_size.1 as i32 * 0x200 / 2) - self.y) / self.wait;
+        let (view_width, view_height) = self.view_size(state);
+        let (target_x, target_y) = (self.target_x - view_width / 2, self.target_y - view_height / 2);
 
-            if self.y < 0 {
-                self.y = 0;
+        match self.custom_bounds(state, stage) {
+            // the target can move between regions, so the view eases into their bounds instead of snapping to them
+            Some(bounds) => {
+                self.x += (fit_view(target_x, bounds.left, bounds.right, view_width) - self.x) / self.wait;
+                self.y += (fit_view(target_y, bounds.top, bounds.bottom, view_height) - self.y) / self.wait;
             }
+            None => {
+                let bounds = Frame::map_bounds(state, stage);
 
-            let max_y = (((stage.map.height as i32 - 1) * tile_size) - state.canvas_size.1 as i32) * 0x200;
-            if self.y > max_y {
-                self.y = max_y;
+                self.x = fit_view(self.x + (target_x - self.x) / self.wait, bounds.left, bounds.right, view_width);
+                self.y = fit_view(self.y + (target_y - self.y) / self.wait, bounds.top, bounds.bottom, view_height);
             }
         }
 
@@ -112,3 +206,69 @@ impl Frame {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCK: i32 = 16 * 0x200;
+
+    #[test]
+    fn view_is_kept_in_bounds() {
+        // 40 tiles wide map, bounds end at center of the last tile
+        let (min, max, view) = (0, 39 * BLOCK, 320 * 0x200);
+
+        assert_eq!(fit_view(100 * 0x200, min, max, view), 100 * 0x200);
+        assert_eq!(fit_view(-30 * 0x200, min, max, view), min);
+        assert_eq!(fit_view(max, min, max, view), max - view);
+        assert_eq!(fit_view(max - view + 1, min, max, view), max - view);
+    }
+
+    #[test]
+    fn small_maps_are_centered() {
+        // 11 tiles wide map with bounds 160 pixels wide, in a 320 pixels wide view
+        let (min, max, view) = (0, 10 * BLOCK, 320 * 0x200);
+
+        for pos in [-BLOCK, 0, 5 * BLOCK, 20 * BLOCK].iter() {
+            assert_eq!(fit_view(*pos, min, max, view), -80 * 0x200);
+        }
+
+        // exactly as large as the view
+        assert_eq!(fit_view(BLOCK, 0, view, view), 0);
+    }
+
+    #[test]
+    fn look_ahead_distance() {
+        let (x, camera_target) = (50 * BLOCK, 0x8000);
+        let target = x + camera_target;
+
+        assert_eq!(lead_ahead(target, camera_target, 64), target);
+        assert_eq!(lead_ahead(target, camera_target, 96), target + 0x4000);
+        assert_eq!(lead_ahead(target, camera_target, 0), x);
+        assert_eq!(lead_ahead(x - camera_target, -camera_target, 96), x - camera_target - 0x4000);
+    }
+
+    #[test]
+    fn zoom_fits_area_with_margin() {
+        assert_eq!(zoom_to_fit((320.0, 240.0), 48, 0, 0), 2.5);
+        assert_eq!(zoom_to_fit((320.0, 240.0), 48, 544 * 0x200, 0), 0.5);
+        assert_eq!(zoom_to_fit((320.0, 240.0), 48, 0, 384 * 0x200), 0.5);
+        assert_eq!(zoom_to_fit((320.0, 240.0), 0, 0, 0), 240.0);
+    }
+
+    #[test]
+    fn both_players_fit() {
+        let a = (100 * 0x200, 200 * 0x200);
+        let b = (500 * 0x200, 100 * 0x200);
+        let ((x, y), zoom) = fit_targets((320.0, 240.0), 48, a, b);
+
+        assert_eq!((x, y), (300 * 0x200, 150 * 0x200));
+        assert_eq!(zoom, 320.0 / 496.0);
+        assert_eq!(fit_targets((320.0, 240.0), 48, b, a), ((x, y), zoom));
+
+        // distance between them and the margins fill the width of the view
+        let (view_width, view_height) = (320.0 / zoom, 240.0 / zoom);
+        assert!((view_width - (400.0 + 96.0)).abs() < 0.01);
+        assert!(view_height > 100.0 + 96.0);
+    }
+}
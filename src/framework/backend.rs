@@ -2,7 +2,7 @@ use imgui::DrawData;
 
 use crate::common::{Color, Rect};
 use crate::framework::context::Context;
-use crate::framework::error::GameResult;
+use crate::framework::error::{GameError, GameResult};
 use crate::framework::graphics::BlendMode;
 use crate::Game;
 
@@ -62,6 +62,36 @@ pub trait BackendRenderer {
     }
 
     fn draw_triangle_list(&mut self, vertices: Vec<VertexData>, texture: Option<&Box<dyn BackendTexture>>, shader: BackendShader) -> GameResult;
+
+    /// Restricts drawing to the screen to given area in screen pixels, which following frames treat as the whole screen.
+    /// Rest of the screen is left black. None resets it to the whole screen.
+    fn set_screen_viewport(&mut self, _viewport: Option<Rect<f32>>) {}
+
+    /// Replaces the chain of post-processing fragment shaders applied to the frame, given as GLSL sources.
+    /// An empty chain draws the game directly to the screen.
+    fn set_post_process_chain(&mut self, shaders: &[String]) -> GameResult {
+        if shaders.is_empty() {
+            Ok(())
+        } else {
+            Err(GameError::RenderError("Post-processing is not supported by this renderer.".to_string()))
+        }
+    }
+
+    /// Runs the post-processing chain over the frame drawn since last `prepare_draw` and outputs it to the screen,
+    /// `scale` being the size of a game pixel in screen pixels. Anything drawn afterwards isn't post-processed.
+    fn apply_post_process(&mut self, _scale: f32) -> GameResult {
+        Ok(())
+    }
+
+    /// Reads back contents of the screen drawn since last `prepare_draw` as RGBA pixels, rows going top to bottom.
+    fn read_screen(&mut self) -> GameResult<(u16, u16, Vec<u8>)> {
+        Err(GameError::RenderError("Screen read-back is not supported by this renderer.".to_string()))
+    }
+
+    /// Reads back contents of a texture created with `create_texture_mutable` as RGBA pixels, rows going top to bottom.
+    fn read_texture(&mut self, _texture: &Box<dyn BackendTexture>) -> GameResult<(u16, u16, Vec<u8>)> {
+        Err(GameError::RenderError("Texture read-back is not supported by this renderer.".to_string()))
+    }
 }
 
 pub trait BackendTexture {
@@ -72,6 +102,30 @@ pub trait BackendTexture {
     fn clear(&mut self);
 
     fn draw(&mut self) -> GameResult;
+
+    /// Replaces given area of the texture with RGBA pixels, rows going top to bottom.
+    fn update(&mut self, _x: u16, _y: u16, _width: u16, _height: u16, _data: &[u8]) -> GameResult {
+        Err(GameError::RenderError("Updating textures is not supported by this renderer.".to_string()))
+    }
+
+    /// Returns true if queued commands can be kept in a retained buffer with `store_static`.
+    fn supports_static(&self) -> bool {
+        false
+    }
+
+    /// Moves commands queued since last draw into a retained buffer identified by `slot`, replacing its contents.
+    fn store_static(&mut self, _slot: usize) -> GameResult {
+        Err(GameError::RenderError("Static batches are not supported by this renderer.".to_string()))
+    }
+
+    fn has_static(&self, _slot: usize) -> bool {
+        false
+    }
+
+    /// Draws contents of a retained buffer, moved by given offset in screen pixels.
+    fn draw_static(&mut self, _slot: usize, _offset: (f32, f32)) -> GameResult {
+        Err(GameError::RenderError("Static batches are not supported by this renderer.".to_string()))
+    }
 }
 
 #[allow(unreachable_code)]
@@ -7,7 +7,8 @@ use std::sync::Arc;
 use glutin::{Api, ContextBuilder, GlProfile, GlRequest, PossiblyCurrent, WindowedContext};
 use glutin::event::{ElementState, Event, TouchPhase, VirtualKeyCode, WindowEvent};
 use glutin::event_loop::{ControlFlow, EventLoop};
-use glutin::window::WindowBuilder;
+use glutin::dpi::PhysicalSize;
+use glutin::window::{Fullscreen, Window, WindowBuilder};
 use imgui::{DrawCmdParams, DrawData, DrawIdx, DrawVert};
 
 use crate::{Game, GAME_SUSPENDED};
@@ -19,6 +20,7 @@ use crate::framework::gl;
 use crate::framework::keyboard::ScanCode;
 use crate::framework::render_opengl::{GLContext, OpenGLRenderer};
 use crate::input::touch_controls::TouchPoint;
+use crate::settings::{DisplaySettings, WindowMode};
 
 pub struct GlutinBackend;
 
@@ -50,7 +52,11 @@ pub struct GlutinEventLoop {
 }
 
 impl GlutinEventLoop {
-    fn get_context(&self, event_loop: &EventLoop<()>) -> &mut WindowedContext<PossiblyCurrent> {
+    fn get_context(
+        &self,
+        event_loop: &EventLoop<()>,
+        settings: &DisplaySettings,
+    ) -> &mut WindowedContext<PossiblyCurrent> {
         let mut refs = unsafe { &mut *self.refs.get() };
 
         if refs.is_none() {
@@ -63,7 +69,7 @@ impl GlutinEventLoop {
             let windowed_context = windowed_context.with_gl_profile(GlProfile::Core)
                 .with_gl_debug_flag(false)
                 .with_pixel_format(24, 8)
-                .with_vsync(true);
+                .with_vsync(settings.vsync);
 
             #[cfg(target_os = "windows")]
             {
@@ -71,7 +77,8 @@ impl GlutinEventLoop {
                 window = window.with_drag_and_drop(false);
             }
 
-            window = window.with_title("doukutsu-rs");
+            let (width, height) = settings.window_size();
+            window = window.with_title("doukutsu-rs").with_inner_size(PhysicalSize::new(width, height));
 
             let windowed_context = windowed_context.build_windowed(window, event_loop).unwrap();
 
@@ -91,6 +98,37 @@ impl GlutinEventLoop {
     }
 }
 
+/// Applies window mode and size settings which differ from ones applied previously.
+/// Vsync can be only set when the window is created, so changing it needs a restart.
+fn apply_display_settings(window: &Window, settings: &DisplaySettings, previous: &mut Option<DisplaySettings>) {
+    let previous = previous.replace(settings.clone());
+    let mode_changed = previous.as_ref().map_or(true, |p| p.window_mode != settings.window_mode);
+    let size_changed = previous.as_ref().map_or(true, |p| p.window_size() != settings.window_size());
+
+    if mode_changed {
+        let fullscreen = match settings.window_mode {
+            WindowMode::Windowed => None,
+            WindowMode::Fullscreen => window
+                .current_monitor()
+                .and_then(|monitor| {
+                    monitor.video_modes().max_by_key(|mode| {
+                        (mode.size().width * mode.size().height, mode.bit_depth(), mode.refresh_rate())
+                    })
+                })
+                .map(Fullscreen::Exclusive)
+                .or(Some(Fullscreen::Borderless(None))),
+            WindowMode::Borderless => Some(Fullscreen::Borderless(None)),
+        };
+
+        window.set_fullscreen(fullscreen);
+    }
+
+    if settings.window_mode == WindowMode::Windowed && (mode_changed || size_changed) {
+        let (width, height) = settings.window_size();
+        window.set_inner_size(PhysicalSize::new(width, height));
+    }
+}
+
 #[cfg(target_os = "android")]
 fn request_android_redraw() {
     match ndk_glue::native_window().as_ref() {
@@ -133,9 +171,14 @@ impl BackendEventLoop for GlutinEventLoop {
         let event_loop = EventLoop::new();
         let state_ref = unsafe { &mut *game.state.get() };
         let window: &'static mut WindowedContext<PossiblyCurrent> =
-            unsafe { std::mem::transmute(self.get_context(&event_loop)) };
+            unsafe { std::mem::transmute(self.get_context(&event_loop, &state_ref.settings.display)) };
+        #[cfg(not(target_os = "android"))]
+        let mut display = None;
 
         {
+            #[cfg(not(target_os = "android"))]
+            apply_display_settings(window.window(), &state_ref.settings.display, &mut display);
+
             let size = window.window().inner_size();
             ctx.screen_size = (size.width.max(1) as f32, size.height.max(1) as f32);
             state_ref.handle_resize(ctx).unwrap();
@@ -199,19 +242,25 @@ impl BackendEventLoop for GlutinEventLoop {
                 {
                     let mut controls = &mut state_ref.touch_controls;
                     let scale = state_ref.scale as f64;
+                    let (offset_x, offset_y) =
+                        ctx.screen_viewport.map_or((0.0, 0.0), |viewport| (viewport.left as f64, viewport.top as f64));
 
                     match touch.phase {
                         TouchPhase::Started | TouchPhase::Moved => {
                             if let Some(point) = controls.points.iter_mut().find(|p| p.id == touch.id) {
                                 point.last_position = point.position;
-                                point.position = (touch.location.x / scale, touch.location.y / scale);
+                                point.position =
+                                    ((touch.location.x - offset_x) / scale, (touch.location.y - offset_y) / scale);
                             } else {
                                 controls.touch_id_counter = controls.touch_id_counter.wrapping_add(1);
 
                                 let point = TouchPoint {
                                     id: touch.id,
                                     touch_id: controls.touch_id_counter,
-                                    position: (touch.location.x / scale, touch.location.y / scale),
+                                    position: (
+                                        (touch.location.x - offset_x) / scale,
+                                        (touch.location.y - offset_y) / scale,
+                                    ),
                                     last_position: (0.0, 0.0),
                                 };
                                 controls.points.push(point);
@@ -237,6 +286,10 @@ impl BackendEventLoop for GlutinEventLoop {
                                 ElementState::Released => false,
                             };
 
+                            if key_state && !ctx.keyboard_context.is_key_pressed(drs_scan) {
+                                state_ref.process_debug_keys(drs_scan);
+                            }
+
                             ctx.keyboard_context.set_key(drs_scan, key_state);
                         }
                     }
@@ -262,6 +315,12 @@ impl BackendEventLoop for GlutinEventLoop {
                     request_android_redraw();
                 }
                 Event::MainEventsCleared => {
+                    #[cfg(not(target_os = "android"))]
+                    if state_ref.display_changed {
+                        state_ref.display_changed = false;
+                        apply_display_settings(window.window(), &state_ref.settings.display, &mut display);
+                    }
+
                     if state_ref.shutdown {
                         log::info!("Shutting down...");
                         *control_flow = ControlFlow::Exit;
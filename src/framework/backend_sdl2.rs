@@ -12,7 +12,7 @@ use sdl2::keyboard::Scancode;
 use sdl2::mouse::{Cursor, SystemCursor};
 use sdl2::pixels::PixelFormatEnum;
 use sdl2::render::{Texture, TextureCreator, WindowCanvas};
-use sdl2::video::WindowContext;
+use sdl2::video::{FullscreenType, SwapInterval, WindowContext, WindowPos};
 use sdl2::{keyboard, pixels, EventPump, Sdl, VideoSubsystem};
 
 use crate::common::{Color, Rect};
@@ -25,6 +25,7 @@ use crate::framework::graphics::BlendMode;
 use crate::framework::keyboard::ScanCode;
 use crate::framework::render_opengl::{GLContext, OpenGLRenderer};
 use crate::framework::ui::init_imgui;
+use crate::settings::{DisplaySettings, WindowMode};
 use crate::Game;
 use crate::GAME_SUSPENDED;
 
@@ -52,6 +53,8 @@ struct SDL2EventLoop {
     event_pump: EventPump,
     refs: Rc<RefCell<SDL2Context>>,
     opengl_available: RefCell<bool>,
+    /// Display settings last applied to the window.
+    display: Option<DisplaySettings>,
 }
 
 struct SDL2Context {
@@ -94,12 +97,56 @@ impl SDL2EventLoop {
                 blend_mode: sdl2::render::BlendMode::Blend,
             })),
             opengl_available: RefCell::new(opengl_available),
+            display: None,
         };
 
         Ok(Box::new(event_loop))
     }
 }
 
+impl SDL2EventLoop {
+    /// Applies window mode, size and vsync settings which differ from ones applied previously.
+    /// Vsync can be only changed for OpenGL renderer, the fallback renderer always waits for it.
+    fn apply_display_settings(&mut self, settings: &DisplaySettings) {
+        let previous = self.display.replace(settings.clone());
+        let mode_changed = previous.as_ref().map_or(true, |p| p.window_mode != settings.window_mode);
+        let size_changed = previous.as_ref().map_or(true, |p| p.window_size() != settings.window_size());
+        let vsync_changed = previous.as_ref().map_or(true, |p| p.vsync != settings.vsync);
+
+        let mut refs = self.refs.borrow_mut();
+
+        if mode_changed {
+            let fullscreen = match settings.window_mode {
+                WindowMode::Windowed => FullscreenType::Off,
+                WindowMode::Fullscreen => FullscreenType::True,
+                WindowMode::Borderless => FullscreenType::Desktop,
+            };
+
+            if let Err(err) = refs.canvas.window_mut().set_fullscreen(fullscreen) {
+                log::warn!("Failed to change window mode: {}", err);
+            }
+        }
+
+        if settings.window_mode == WindowMode::Windowed && (mode_changed || size_changed) {
+            let (width, height) = settings.window_size();
+            let window = refs.canvas.window_mut();
+
+            if let Err(err) = window.set_size(width, height) {
+                log::warn!("Failed to resize window: {}", err);
+            }
+            window.set_position(WindowPos::Centered, WindowPos::Centered);
+        }
+
+        if vsync_changed && refs.gl_context.is_some() {
+            let interval = if settings.vsync { SwapInterval::VSync } else { SwapInterval::Immediate };
+
+            if let Err(err) = refs.video.gl_set_swap_interval(interval) {
+                log::warn!("Failed to change vsync mode: {}", err);
+            }
+        }
+    }
+}
+
 impl BackendEventLoop for SDL2EventLoop {
     fn run(&mut self, game: &mut Game, ctx: &mut Context) {
         let state = unsafe { &mut *game.state.get() };
@@ -111,6 +158,8 @@ impl BackendEventLoop for SDL2EventLoop {
         };
 
         {
+            self.apply_display_settings(&state.settings.display);
+
             let (width, height) = self.refs.borrow().canvas.window().size();
             ctx.screen_size = (width.max(1) as f32, height.max(1) as f32);
 
@@ -172,6 +221,11 @@ impl BackendEventLoop for SDL2EventLoop {
                 }
             }
 
+            if state.display_changed {
+                state.display_changed = false;
+                self.apply_display_settings(&state.settings.display);
+            }
+
             if state.shutdown {
                 log::info!("Shutting down...");
                 break;
@@ -266,6 +320,7 @@ impl BackendEventLoop for SDL2EventLoop {
 
 struct SDL2Renderer {
     refs: Rc<RefCell<SDL2Context>>,
+    viewport: Option<Rect<f32>>,
     imgui: Rc<RefCell<imgui::Context>>,
     imgui_event: Rc<RefCell<ImguiSdl2>>,
     imgui_textures: HashMap<TextureId, SDL2Texture>,
@@ -326,6 +381,7 @@ impl SDL2Renderer {
 
         Ok(Box::new(SDL2Renderer {
             refs,
+            viewport: None,
             imgui: Rc::new(RefCell::new(imgui)),
             imgui_event: Rc::new(RefCell::new(imgui_sdl2)),
             imgui_textures,
@@ -378,8 +434,16 @@ impl BackendRenderer for SDL2Renderer {
         let mut refs = self.refs.borrow_mut();
 
         refs.canvas.set_draw_color(to_sdl(color));
+
+        if self.viewport.is_some() {
+            // clearing ignores the viewport and would paint over the letterbox
+            refs.canvas.set_blend_mode(sdl2::render::BlendMode::None);
+            let _ = refs.canvas.fill_rect(None);
+        } else {
+            refs.canvas.clear();
+        }
+
         refs.canvas.set_blend_mode(sdl2::render::BlendMode::Blend);
-        refs.canvas.clear();
     }
 
     fn present(&mut self) -> GameResult {
@@ -393,12 +457,35 @@ impl BackendRenderer for SDL2Renderer {
     fn prepare_draw(&mut self, width: f32, height: f32) -> GameResult {
         let mut refs = self.refs.borrow_mut();
 
+        refs.canvas.set_viewport(None);
+        refs.canvas.set_clip_rect(None);
+        refs.canvas.set_draw_color(pixels::Color::RGB(0, 0, 0));
+        refs.canvas.clear();
+
+        let (width, height) = match self.viewport {
+            Some(viewport) => {
+                refs.canvas.set_viewport(Some(sdl2::rect::Rect::new(
+                    viewport.left as i32,
+                    viewport.top as i32,
+                    viewport.width() as u32,
+                    viewport.height() as u32,
+                )));
+
+                (viewport.width(), viewport.height())
+            }
+            None => (width, height),
+        };
+
         refs.canvas.set_clip_rect(Some(sdl2::rect::Rect::new(0, 0, width as u32, height as u32, )));
         //refs.canvas.set_clip_rect(None);
 
         Ok(())
     }
 
+    fn set_screen_viewport(&mut self, viewport: Option<Rect<f32>>) {
+        self.viewport = viewport;
+    }
+
     fn create_texture_mutable(&mut self, width: u16, height: u16) -> GameResult<Box<dyn BackendTexture>> {
         let refs = self.refs.borrow_mut();
 
@@ -675,6 +762,45 @@ impl BackendRenderer for SDL2Renderer {
     ) -> GameResult<()> {
         Err(GameError::RenderError("Unsupported operation".to_string()))
     }
+
+    fn read_screen(&mut self) -> GameResult<(u16, u16, Vec<u8>)> {
+        let refs = self.refs.borrow();
+
+        unsafe {
+            set_raw_target(refs.canvas.raw(), std::ptr::null_mut())?;
+        }
+
+        // pixels are read from the viewport only
+        let viewport = refs.canvas.viewport();
+        let (width, height) = (viewport.width(), viewport.height());
+        let data = refs
+            .canvas
+            .read_pixels(None, PixelFormatEnum::RGBA32)
+            .map_err(|e| GameError::RenderError(e.to_string()))?;
+
+        Ok((width as u16, height as u16, data))
+    }
+
+    fn read_texture(&mut self, texture: &Box<dyn BackendTexture>) -> GameResult<(u16, u16, Vec<u8>)> {
+        let refs = self.refs.borrow();
+        let sdl2_texture: &Box<SDL2Texture> = unsafe { std::mem::transmute(texture) };
+        let target = sdl2_texture
+            .texture
+            .as_ref()
+            .ok_or_else(|| GameError::RenderError("Texture has no pixels to read back.".to_string()))?;
+
+        unsafe {
+            let renderer = refs.canvas.raw();
+            let current = sdl2::sys::SDL_GetRenderTarget(renderer);
+
+            set_raw_target(renderer, target.raw())?;
+            let data = refs.canvas.read_pixels(None, PixelFormatEnum::RGBA32);
+            set_raw_target(renderer, current)?;
+
+            let data = data.map_err(|e| GameError::RenderError(e.to_string()))?;
+            Ok((sdl2_texture.width, sdl2_texture.height, data))
+        }
+    }
 }
 
 struct SDL2Texture {
@@ -698,6 +824,19 @@ impl BackendTexture for SDL2Texture {
         self.commands.clear();
     }
 
+    fn update(&mut self, x: u16, y: u16, width: u16, height: u16, data: &[u8]) -> GameResult {
+        match self.texture.as_mut() {
+            None => Err(GameError::RenderError("Texture has no storage.".to_string())),
+            Some(texture) => texture
+                .update(
+                    Some(sdl2::rect::Rect::new(x as i32, y as i32, width as u32, height as u32)),
+                    data,
+                    width as usize * 4,
+                )
+                .map_err(|e| GameError::RenderError(e.to_string())),
+        }
+    }
+
     fn draw(&mut self) -> GameResult {
         match self.texture.as_mut() {
             None => Ok(()),
@@ -1,4 +1,5 @@
-use crate::framework::backend::{init_backend, BackendRenderer};
+use crate::common::Rect;
+use crate::framework::backend::{init_backend, BackendRenderer, BackendTexture};
 use crate::framework::error::GameResult;
 use crate::framework::filesystem::Filesystem;
 use crate::framework::keyboard::KeyboardContext;
@@ -10,6 +11,9 @@ pub struct Context {
     pub(crate) keyboard_context: KeyboardContext,
     pub(crate) screen_size: (f32, f32),
     pub(crate) screen_insets: (f32, f32, f32, f32),
+    pub(crate) screen_viewport: Option<Rect<f32>>,
+    /// Texture standing in for the screen while a frame is rendered offscreen.
+    pub(crate) screen_target: Option<Box<dyn BackendTexture>>,
 }
 
 impl Context {
@@ -20,6 +24,8 @@ impl Context {
             keyboard_context: KeyboardContext::new(),
             screen_size: (320.0, 240.0),
             screen_insets: (0.0, 0.0, 0.0, 0.0),
+            screen_viewport: None,
+            screen_target: None,
         }
     }
 
@@ -100,6 +100,13 @@ impl From<serde_yaml::Error> for GameError {
     }
 }
 
+impl From<serde_json::Error> for GameError {
+    fn from(e: serde_json::Error) -> Self {
+        let errstr = format!("JSON error: {:?}", e);
+        GameError::ParseError(errstr)
+    }
+}
+
 #[cfg(target_os = "android")]
 impl From<jni::errors::Error> for GameError {
     fn from(e: jni::errors::Error) -> GameError {
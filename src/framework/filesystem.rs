@@ -188,6 +188,19 @@ impl Filesystem {
         Ok(Box::new(itr))
     }
 
+    /// Resolves the given path in the user directory to a location
+    /// on the physical filesystem, if there's one.
+    pub(crate) fn user_physical_path<P: AsRef<path::Path>>(&self, path: P) -> Option<path::PathBuf> {
+        let relative = path.as_ref().strip_prefix("/").unwrap_or_else(|_| path.as_ref());
+
+        self.user_vfs
+            .roots()
+            .iter()
+            .filter(|vfs| vfs.exists(path.as_ref()))
+            .find_map(|vfs| vfs.to_path_buf())
+            .map(|root| root.join(relative))
+    }
+
     /// Returns a list of all files and directories in the resource directory,
     /// in no particular order.
     ///
@@ -240,6 +253,14 @@ impl Filesystem {
         self.vfs.push_back(vfs);
     }
 
+    pub fn mount_vfs_front(&mut self, vfs: Box<dyn vfs::VFS>) {
+        self.vfs.push_front(vfs);
+    }
+
+    pub fn unmount_vfs_front(&mut self) -> Option<Box<dyn vfs::VFS>> {
+        self.vfs.pop_front()
+    }
+
     pub fn mount_user_vfs(&mut self, vfs: Box<dyn vfs::VFS>) {
         self.user_vfs.push_back(vfs);
     }
@@ -319,6 +340,12 @@ pub fn user_read_dir<P: AsRef<path::Path>>(
     ctx.filesystem.user_read_dir(path)
 }
 
+/// Resolves the given path in the user directory to a location
+/// on the physical filesystem, if there's one.
+pub fn user_physical_path<P: AsRef<path::Path>>(ctx: &Context, path: P) -> Option<path::PathBuf> {
+    ctx.filesystem.user_physical_path(path)
+}
+
 /// Check whether a file or directory exists.
 pub fn exists<P: AsRef<path::Path>>(ctx: &Context, path: P) -> bool {
     ctx.filesystem.exists(path.as_ref())
@@ -361,6 +388,17 @@ pub fn mount_vfs(ctx: &mut Context, vfs: Box<dyn vfs::VFS>) {
     ctx.filesystem.mount_vfs(vfs)
 }
 
+/// Adds a VFS to the front of the list of resource search locations,
+/// so its files take precedence over everything mounted before.
+pub fn mount_vfs_front(ctx: &mut Context, vfs: Box<dyn vfs::VFS>) {
+    ctx.filesystem.mount_vfs_front(vfs)
+}
+
+/// Removes the VFS at the front of the list of resource search locations.
+pub fn unmount_vfs_front(ctx: &mut Context) -> Option<Box<dyn vfs::VFS>> {
+    ctx.filesystem.unmount_vfs_front()
+}
+
 /// Adds a VFS to the list of user data search locations.
 pub fn mount_user_vfs(ctx: &mut Context, vfs: Box<dyn vfs::VFS>) {
     ctx.filesystem.mount_user_vfs(vfs)
@@ -70,7 +70,24 @@ pub fn screen_insets_scaled(ctx: &mut Context, scale: f32) -> (f32, f32, f32, f3
     (ctx.screen_insets.0 / scale, ctx.screen_insets.1 / scale, ctx.screen_insets.2 / scale, ctx.screen_insets.3 / scale)
 }
 
+/// Area of the screen the game is drawn to, the whole screen if None.
+pub fn screen_viewport(ctx: &mut Context) -> Option<Rect<f32>> {
+    ctx.screen_viewport
+}
+
+/// Restricts drawing to given area of the screen, see `BackendRenderer::set_screen_viewport`.
+pub fn set_screen_viewport(ctx: &mut Context, viewport: Option<Rect<f32>>) {
+    ctx.screen_viewport = viewport;
+
+    if let Some(renderer) = ctx.renderer.as_mut() {
+        renderer.set_screen_viewport(viewport);
+    }
+}
+
+/// Sets the texture following draws go to, None being the screen or the texture set with `set_screen_target`.
 pub fn set_render_target(ctx: &mut Context, texture: Option<&Box<dyn BackendTexture>>) -> GameResult {
+    let texture = texture.or(ctx.screen_target.as_ref());
+
     if let Some(renderer) = ctx.renderer.as_mut() {
         return renderer.set_render_target(texture);
     }
@@ -78,6 +95,18 @@ pub fn set_render_target(ctx: &mut Context, texture: Option<&Box<dyn BackendText
     Err(GameError::RenderError("Rendering backend hasn't been initialized yet.".to_string()))
 }
 
+/// Redirects everything drawn to the screen to given texture and binds it, so whole frames can be rendered offscreen.
+/// None binds the real screen again. Returns the texture set previously.
+pub fn set_screen_target(
+    ctx: &mut Context,
+    texture: Option<Box<dyn BackendTexture>>,
+) -> GameResult<Option<Box<dyn BackendTexture>>> {
+    let previous = std::mem::replace(&mut ctx.screen_target, texture);
+    set_render_target(ctx, None)?;
+
+    Ok(previous)
+}
+
 pub fn set_blend_mode(ctx: &mut Context, blend: BlendMode) -> GameResult {
     if let Some(renderer) = ctx.renderer.as_mut() {
         return renderer.set_blend_mode(blend);
@@ -147,3 +176,38 @@ pub fn draw_triangle_list(
 
     Err(GameError::RenderError("Rendering backend hasn't been initialized yet.".to_string()))
 }
+
+/// Returns the current contents of the screen as (width, height, RGBA pixels).
+pub fn read_screen(ctx: &mut Context) -> GameResult<(u16, u16, Vec<u8>)> {
+    if let Some(renderer) = ctx.renderer.as_mut() {
+        return renderer.read_screen();
+    }
+
+    Err(GameError::RenderError("Rendering backend hasn't been initialized yet.".to_string()))
+}
+
+/// Returns contents of a texture used as render target as (width, height, RGBA pixels).
+pub fn read_texture(ctx: &mut Context, texture: &Box<dyn BackendTexture>) -> GameResult<(u16, u16, Vec<u8>)> {
+    if let Some(renderer) = ctx.renderer.as_mut() {
+        return renderer.read_texture(texture);
+    }
+
+    Err(GameError::RenderError("Rendering backend hasn't been initialized yet.".to_string()))
+}
+
+/// Replaces the chain of post-processing shaders, see `BackendRenderer::set_post_process_chain`.
+pub fn set_post_process_chain(ctx: &mut Context, shaders: &[String]) -> GameResult {
+    if let Some(renderer) = ctx.renderer.as_mut() {
+        return renderer.set_post_process_chain(shaders);
+    }
+
+    Err(GameError::RenderError("Rendering backend hasn't been initialized yet.".to_string()))
+}
+
+pub fn apply_post_process(ctx: &mut Context, scale: f32) -> GameResult {
+    if let Some(renderer) = ctx.renderer.as_mut() {
+        return renderer.apply_post_process(scale);
+    }
+
+    Err(GameError::RenderError("Rendering backend hasn't been initialized yet.".to_string()))
+}
@@ -1,4 +1,5 @@
 use std::cell::{RefCell, UnsafeCell};
+use std::collections::HashMap;
 use std::ffi::{c_void, CStr};
 use std::mem;
 use std::mem::MaybeUninit;
@@ -30,9 +31,57 @@ pub struct OpenGLTexture {
     program: GLuint,
     vbo: GLuint,
     vertices: Vec<VertexData>,
+    /// Retained buffers by slot, holding their own vertex buffer object and vertex count.
+    static_buffers: HashMap<usize, (GLuint, usize)>,
     context_active: Arc<RefCell<bool>>,
 }
 
+impl OpenGLTexture {
+    unsafe fn bind(&self, gl: &Gl, vbo: GLuint) {
+        if gl.gl.BindSampler.is_loaded() {
+            gl.gl.BindSampler(0, 0);
+        }
+
+        gl.gl.Enable(gl::TEXTURE_2D);
+        gl.gl.Enable(gl::BLEND);
+        gl.gl.Disable(gl::DEPTH_TEST);
+
+        gl.gl.BindTexture(gl::TEXTURE_2D, self.texture_id);
+        gl.gl.BindBuffer(gl::ARRAY_BUFFER, vbo);
+        gl.gl.UseProgram(self.program);
+        gl.gl.EnableVertexAttribArray(self.locs.position);
+        gl.gl.EnableVertexAttribArray(self.locs.uv);
+        gl.gl.EnableVertexAttribArray(self.locs.color);
+
+        gl.gl.VertexAttribPointer(
+            self.locs.position,
+            2,
+            gl::FLOAT,
+            gl::FALSE,
+            mem::size_of::<VertexData>() as _,
+            field_offset::<VertexData, _, _>(|v| &v.position) as _,
+        );
+
+        gl.gl.VertexAttribPointer(
+            self.locs.uv,
+            2,
+            gl::FLOAT,
+            gl::FALSE,
+            mem::size_of::<VertexData>() as _,
+            field_offset::<VertexData, _, _>(|v| &v.uv) as _,
+        );
+
+        gl.gl.VertexAttribPointer(
+            self.locs.color,
+            4,
+            gl::UNSIGNED_BYTE,
+            gl::TRUE,
+            mem::size_of::<VertexData>() as _,
+            field_offset::<VertexData, _, _>(|v| &v.color) as _,
+        );
+    }
+}
+
 impl BackendTexture for OpenGLTexture {
     fn dimensions(&self) -> (u16, u16) {
         (self.width, self.height)
@@ -170,57 +219,116 @@ impl BackendTexture for OpenGLTexture {
                     return Ok(());
                 }
 
-                if gl.gl.BindSampler.is_loaded() {
-                    gl.gl.BindSampler(0, 0);
-                }
+                self.bind(gl, self.vbo);
+                gl.gl.BufferData(
+                    gl::ARRAY_BUFFER,
+                    (self.vertices.len() * mem::size_of::<VertexData>()) as _,
+                    self.vertices.as_ptr() as _,
+                    gl::STREAM_DRAW,
+                );
 
-                gl.gl.Enable(gl::TEXTURE_2D);
-                gl.gl.Enable(gl::BLEND);
-                gl.gl.Disable(gl::DEPTH_TEST);
+                gl.gl.DrawArrays(gl::TRIANGLES, 0, self.vertices.len() as _);
 
-                gl.gl.BindTexture(gl::TEXTURE_2D, self.texture_id);
-                gl.gl.BindBuffer(gl::ARRAY_BUFFER, self.vbo);
-                gl.gl.UseProgram(self.program);
-                gl.gl.EnableVertexAttribArray(self.locs.position);
-                gl.gl.EnableVertexAttribArray(self.locs.uv);
-                gl.gl.EnableVertexAttribArray(self.locs.color);
+                gl.gl.BindTexture(gl::TEXTURE_2D, 0);
+                gl.gl.BindBuffer(gl::ARRAY_BUFFER, 0);
 
-                gl.gl.VertexAttribPointer(
-                    self.locs.position,
-                    2,
-                    gl::FLOAT,
-                    gl::FALSE,
-                    mem::size_of::<VertexData>() as _,
-                    field_offset::<VertexData, _, _>(|v| &v.position) as _,
-                );
+                Ok(())
+            } else {
+                Err(RenderError("No OpenGL context available!".to_string()))
+            }
+        }
+    }
 
-                gl.gl.VertexAttribPointer(
-                    self.locs.uv,
-                    2,
-                    gl::FLOAT,
-                    gl::FALSE,
-                    mem::size_of::<VertexData>() as _,
-                    field_offset::<VertexData, _, _>(|v| &v.uv) as _,
-                );
+    fn update(&mut self, x: u16, y: u16, width: u16, height: u16, data: &[u8]) -> GameResult {
+        unsafe {
+            if let Some(gl) = GL_PROC.as_ref() {
+                if self.texture_id == 0 {
+                    return Err(RenderError("Texture has no storage.".to_string()));
+                }
 
-                gl.gl.VertexAttribPointer(
-                    self.locs.color,
-                    4,
+                let current_texture_id = return_param(|x| gl.gl.GetIntegerv(gl::TEXTURE_BINDING_2D, x)) as u32;
+                gl.gl.BindTexture(gl::TEXTURE_2D, self.texture_id);
+                gl.gl.TexSubImage2D(
+                    gl::TEXTURE_2D,
+                    0,
+                    x as _,
+                    y as _,
+                    width as _,
+                    height as _,
+                    gl::RGBA,
                     gl::UNSIGNED_BYTE,
-                    gl::TRUE,
-                    mem::size_of::<VertexData>() as _,
-                    field_offset::<VertexData, _, _>(|v| &v.color) as _,
+                    data.as_ptr() as _,
                 );
+                gl.gl.BindTexture(gl::TEXTURE_2D, current_texture_id);
+
+                Ok(())
+            } else {
+                Err(RenderError("No OpenGL context available!".to_string()))
+            }
+        }
+    }
+
+    fn supports_static(&self) -> bool {
+        self.texture_id != 0
+    }
+
+    fn store_static(&mut self, slot: usize) -> GameResult {
+        unsafe {
+            if let Some(gl) = GL_PROC.as_ref() {
+                let vbo = match self.static_buffers.get(&slot) {
+                    Some((vbo, _)) => *vbo,
+                    None => return_param(|x| gl.gl.GenBuffers(1, x)),
+                };
 
-                gl.gl.BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+                gl.gl.BindBuffer(gl::ARRAY_BUFFER, vbo);
                 gl.gl.BufferData(
                     gl::ARRAY_BUFFER,
                     (self.vertices.len() * mem::size_of::<VertexData>()) as _,
                     self.vertices.as_ptr() as _,
-                    gl::STREAM_DRAW,
+                    gl::STATIC_DRAW,
                 );
+                gl.gl.BindBuffer(gl::ARRAY_BUFFER, 0);
 
-                gl.gl.DrawArrays(gl::TRIANGLES, 0, self.vertices.len() as _);
+                self.static_buffers.insert(slot, (vbo, self.vertices.len()));
+                self.vertices.clear();
+
+                Ok(())
+            } else {
+                Err(RenderError("No OpenGL context available!".to_string()))
+            }
+        }
+    }
+
+    fn has_static(&self, slot: usize) -> bool {
+        self.static_buffers.contains_key(&slot)
+    }
+
+    fn draw_static(&mut self, slot: usize, (x, y): (f32, f32)) -> GameResult {
+        let (vbo, count) = match self.static_buffers.get(&slot) {
+            Some(buffer) => *buffer,
+            None => return Ok(()),
+        };
+
+        unsafe {
+            if let Some(gl) = GL_PROC.as_ref() {
+                if self.texture_id == 0 || count == 0 {
+                    return Ok(());
+                }
+
+                self.bind(gl, vbo);
+
+                // the projection is set up by the renderer for current render target, append translation to it
+                let mut matrix = [0.0f32; 16];
+                gl.gl.GetUniformfv(self.program, self.locs.proj_mtx, matrix.as_mut_ptr());
+                let mut translated = matrix;
+                translated[12] = matrix[0] * x + matrix[4] * y + matrix[12];
+                translated[13] = matrix[1] * x + matrix[5] * y + matrix[13];
+                translated[14] = matrix[2] * x + matrix[6] * y + matrix[14];
+                translated[15] = matrix[3] * x + matrix[7] * y + matrix[15];
+
+                gl.gl.UniformMatrix4fv(self.locs.proj_mtx, 1, gl::FALSE, translated.as_ptr());
+                gl.gl.DrawArrays(gl::TRIANGLES, 0, count as _);
+                gl.gl.UniformMatrix4fv(self.locs.proj_mtx, 1, gl::FALSE, matrix.as_ptr());
 
                 gl.gl.BindTexture(gl::TEXTURE_2D, 0);
                 gl.gl.BindBuffer(gl::ARRAY_BUFFER, 0);
@@ -244,6 +352,10 @@ impl Drop for OpenGLTexture {
                     }
 
                     if self.framebuffer_id != 0 {}
+
+                    for (vbo, _) in self.static_buffers.values() {
+                        gl.gl.DeleteBuffers(1, vbo as *const _);
+                    }
                 }
             }
         }
@@ -278,6 +390,55 @@ fn check_shader_compile_status(shader: u32, gl: &Gl) -> bool {
     true
 }
 
+/// Compiles and links a shader program, returns None and logs the error if either step fails.
+fn create_program(gl: &Gl, vertex_source: &str, fragment_source: &str) -> Option<GLuint> {
+    unsafe {
+        let vert_shader = gl.gl.CreateShader(gl::VERTEX_SHADER);
+        let frag_shader = gl.gl.CreateShader(gl::FRAGMENT_SHADER);
+        let vert_sources = [vertex_source.as_ptr() as *const GLchar];
+        let frag_sources = [fragment_source.as_ptr() as *const GLchar];
+        let vert_sources_len = [vertex_source.len() as GLint];
+        let frag_sources_len = [fragment_source.len() as GLint];
+
+        gl.gl.ShaderSource(vert_shader, 1, vert_sources.as_ptr(), vert_sources_len.as_ptr());
+        gl.gl.ShaderSource(frag_shader, 1, frag_sources.as_ptr(), frag_sources_len.as_ptr());
+        gl.gl.CompileShader(vert_shader);
+        gl.gl.CompileShader(frag_shader);
+
+        let mut program = None;
+
+        if check_shader_compile_status(vert_shader, gl) && check_shader_compile_status(frag_shader, gl) {
+            let id = gl.gl.CreateProgram();
+            gl.gl.AttachShader(id, vert_shader);
+            gl.gl.AttachShader(id, frag_shader);
+            gl.gl.LinkProgram(id);
+
+            let mut status: GLint = 0;
+            gl.gl.GetProgramiv(id, gl::LINK_STATUS, (&mut status) as *mut _);
+
+            if status == (gl::FALSE as GLint) {
+                let mut max_length: GLint = 0;
+                let mut msg_length: GLsizei = 0;
+                gl.gl.GetProgramiv(id, gl::INFO_LOG_LENGTH, (&mut max_length) as *mut _);
+
+                let mut data: Vec<u8> = vec![0; max_length as usize];
+                gl.gl.GetProgramInfoLog(id, max_length, (&mut msg_length) as *mut _, data.as_mut_ptr() as *mut _);
+
+                log::error!("Failed to link shader program: {}", String::from_utf8_lossy(&data));
+                gl.gl.DeleteProgram(id);
+            } else {
+                program = Some(id);
+            }
+        }
+
+        // only flagged for deletion until the program is deleted
+        gl.gl.DeleteShader(vert_shader);
+        gl.gl.DeleteShader(frag_shader);
+
+        program
+    }
+}
+
 const VERTEX_SHADER_BASIC: &str = r"
 #version 100
 
@@ -465,6 +626,93 @@ impl ImguiData {
     }
 }
 
+/// Fragment shader run over the whole frame, with uniforms describing the frame in addition to `Locs`.
+struct PostProcessPass {
+    program: GLuint,
+    locs: Locs,
+    source_size: GLint,
+    output_size: GLint,
+    scale: GLint,
+    frame_count: GLint,
+}
+
+impl PostProcessPass {
+    fn new(gl: &Gl, program: GLuint) -> PostProcessPass {
+        unsafe {
+            PostProcessPass {
+                program,
+                locs: Locs {
+                    texture: gl.gl.GetUniformLocation(program, b"Texture\0".as_ptr() as _),
+                    proj_mtx: gl.gl.GetUniformLocation(program, b"ProjMtx\0".as_ptr() as _),
+                    position: gl.gl.GetAttribLocation(program, b"Position\0".as_ptr() as _) as _,
+                    uv: gl.gl.GetAttribLocation(program, b"UV\0".as_ptr() as _) as _,
+                    color: gl.gl.GetAttribLocation(program, b"Color\0".as_ptr() as _) as _,
+                },
+                source_size: gl.gl.GetUniformLocation(program, b"SourceSize\0".as_ptr() as _),
+                output_size: gl.gl.GetUniformLocation(program, b"OutputSize\0".as_ptr() as _),
+                scale: gl.gl.GetUniformLocation(program, b"Scale\0".as_ptr() as _),
+                frame_count: gl.gl.GetUniformLocation(program, b"FrameCount\0".as_ptr() as _),
+            }
+        }
+    }
+}
+
+struct PostProcessTarget {
+    texture_id: GLuint,
+    framebuffer_id: GLuint,
+}
+
+impl PostProcessTarget {
+    fn new(gl: &Gl, width: u16, height: u16) -> PostProcessTarget {
+        unsafe {
+            let current_texture_id = return_param(|x| gl.gl.GetIntegerv(gl::TEXTURE_BINDING_2D, x)) as u32;
+            let texture_id = return_param(|x| gl.gl.GenTextures(1, x));
+
+            gl.gl.BindTexture(gl::TEXTURE_2D, texture_id);
+            gl.gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as _);
+            gl.gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as _);
+            gl.gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as _);
+            gl.gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as _);
+            gl.gl.TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGBA as _,
+                width as _,
+                height as _,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                std::ptr::null(),
+            );
+            gl.gl.BindTexture(gl::TEXTURE_2D, current_texture_id);
+
+            let framebuffer_id = return_param(|x| gl.gl.GenFramebuffers(1, x));
+            gl.gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer_id);
+            gl.gl.FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, texture_id, 0);
+            gl.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+
+            PostProcessTarget { texture_id, framebuffer_id }
+        }
+    }
+
+    fn delete(&self, gl: &Gl) {
+        unsafe {
+            gl.gl.DeleteFramebuffers(1, &self.framebuffer_id as *const _);
+            gl.gl.DeleteTextures(1, &self.texture_id as *const _);
+        }
+    }
+}
+
+/// Projection for drawing to the screen, with the origin in the top left corner.
+fn screen_matrix(width: f32, height: f32) -> [[f32; 4]; 4] {
+    [[2.0 / width, 0.0, 0.0, 0.0], [0.0, 2.0 / -height, 0.0, 0.0], [0.0, 0.0, -1.0, 0.0], [-1.0, 1.0, 0.0, 1.0]]
+}
+
+/// Projection for drawing to a texture, so its first row ends up at the top when it's drawn.
+fn texture_matrix(width: f32, height: f32) -> [[f32; 4]; 4] {
+    [[2.0 / width, 0.0, 0.0, 0.0], [0.0, 2.0 / height, 0.0, 0.0], [0.0, 0.0, -1.0, 0.0], [-1.0, -1.0, 0.0, 1.0]]
+}
+
 pub struct Gl {
     pub gl: gl::Gles2,
 }
@@ -503,6 +751,18 @@ pub struct OpenGLRenderer {
     context_active: Arc<RefCell<bool>>,
     def_matrix: [[f32; 4]; 4],
     curr_matrix: [[f32; 4]; 4],
+    /// Area of the screen the game is drawn to, the whole screen if None.
+    screen_viewport: Option<Rect<f32>>,
+    /// The same area in OpenGL window coordinates, with origin in the bottom left corner.
+    gl_viewport: (GLint, GLint, GLsizei, GLsizei),
+    post_process: Vec<PostProcessPass>,
+    /// The frame is drawn to the first one, passes draw to them in turns, except for the last one.
+    post_process_targets: Vec<PostProcessTarget>,
+    post_process_size: (u16, u16),
+    /// Whether the frame is being drawn offscreen and hasn't been post-processed yet.
+    post_process_pending: bool,
+    post_process_scale: f32,
+    frame_count: u32,
 }
 
 impl OpenGLRenderer {
@@ -514,6 +774,14 @@ impl OpenGLRenderer {
             context_active: Arc::new(RefCell::new(true)),
             def_matrix: [[0.0; 4]; 4],
             curr_matrix: [[0.0; 4]; 4],
+            screen_viewport: None,
+            gl_viewport: (0, 0, 0, 0),
+            post_process: Vec::new(),
+            post_process_targets: Vec::new(),
+            post_process_size: (0, 0),
+            post_process_pending: false,
+            post_process_scale: 1.0,
+            frame_count: 0,
         }
     }
 
@@ -528,6 +796,65 @@ impl OpenGLRenderer {
 
         Some((&mut self.refs, gl))
     }
+
+    /// Framebuffer standing for the screen, which is offscreen while the frame awaits post-processing.
+    fn screen_framebuffer(&self) -> GLuint {
+        match self.post_process_targets.first() {
+            Some(target) if self.post_process_pending => target.framebuffer_id,
+            _ => 0,
+        }
+    }
+
+    /// Binds the framebuffer standing for the screen, restricting drawing to the viewport if it's the real one.
+    fn bind_screen(&self, gl: &Gl) {
+        let (x, y, width, height) = self.gl_viewport;
+
+        unsafe {
+            gl.gl.BindFramebuffer(gl::FRAMEBUFFER, self.screen_framebuffer());
+
+            if self.post_process_pending {
+                gl.gl.Viewport(0, 0, width, height);
+                gl.gl.Disable(gl::SCISSOR_TEST);
+            } else {
+                gl.gl.Viewport(x, y, width, height);
+                gl.gl.Scissor(x, y, width, height);
+                gl.gl.Enable(gl::SCISSOR_TEST);
+            }
+        }
+    }
+
+    fn set_projection(&mut self, gl: &Gl, matrix: [[f32; 4]; 4]) {
+        self.curr_matrix = matrix;
+
+        unsafe {
+            gl.gl.UseProgram(self.imgui_data.program_fill);
+            gl.gl.UniformMatrix4fv(self.imgui_data.fill_locs.proj_mtx, 1, gl::FALSE, matrix.as_ptr() as _);
+            gl.gl.UseProgram(self.imgui_data.program_tex);
+            gl.gl.Uniform1i(self.imgui_data.tex_locs.texture, 0);
+            gl.gl.UniformMatrix4fv(self.imgui_data.tex_locs.proj_mtx, 1, gl::FALSE, matrix.as_ptr() as _);
+        }
+    }
+
+    /// Makes sure there are enough offscreen targets of screen size for current post-processing chain.
+    fn update_post_process_targets(&mut self, gl: &Gl, width: u16, height: u16) {
+        let count = self.post_process.len().min(2);
+
+        if self.post_process_targets.len() == count && self.post_process_size == (width, height) {
+            return;
+        }
+
+        self.delete_post_process_targets(gl);
+        self.post_process_targets = (0..count).map(|_| PostProcessTarget::new(gl, width, height)).collect();
+        self.post_process_size = (width, height);
+    }
+
+    fn delete_post_process_targets(&mut self, gl: &Gl) {
+        for target in self.post_process_targets.drain(..) {
+            target.delete(gl);
+        }
+
+        self.post_process_pending = false;
+    }
 }
 
 fn field_offset<T, U, F: for<'a> FnOnce(&'a T) -> &'a U>(f: F) -> usize {
@@ -580,6 +907,10 @@ impl BackendRenderer for OpenGLRenderer {
             }
         }
 
+        if self.post_process_pending {
+            self.apply_post_process(self.post_process_scale)?;
+        }
+
         if let Some((context, gl)) = self.get_context() {
             unsafe {
                 gl.gl.Finish();
@@ -598,16 +929,28 @@ impl BackendRenderer for OpenGLRenderer {
                 gl.gl.BlendEquation(gl::FUNC_ADD);
                 gl.gl.BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
 
-                gl.gl.Viewport(0, 0, width as _, height as _);
+                let viewport = self.screen_viewport.unwrap_or_else(|| Rect::new(0.0, 0.0, width, height));
+                let (view_width, view_height) = (viewport.width().max(1.0), viewport.height().max(1.0));
+                self.gl_viewport =
+                    (viewport.left as _, (height - viewport.bottom) as _, view_width as _, view_height as _);
 
-                self.def_matrix = [
-                    [2.0 / width, 0.0, 0.0, 0.0],
-                    [0.0, 2.0 / -height, 0.0, 0.0],
-                    [0.0, 0.0, -1.0, 0.0],
-                    [-1.0, 1.0, 0.0, 1.0],
-                ];
+                // clear the letterbox, the game can only draw inside of the viewport
+                gl.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+                gl.gl.Disable(gl::SCISSOR_TEST);
+                gl.gl.ClearColor(0.0, 0.0, 0.0, 1.0);
+                gl.gl.Clear(gl::COLOR_BUFFER_BIT);
+
+                if self.post_process.is_empty() {
+                    self.def_matrix = screen_matrix(view_width, view_height);
+                } else {
+                    self.update_post_process_targets(gl, view_width as u16, view_height as u16);
+                    self.post_process_pending = true;
+                    self.def_matrix = texture_matrix(view_width, view_height);
+                }
                 self.curr_matrix = self.def_matrix;
 
+                self.bind_screen(gl);
+
                 gl.gl.BindBuffer(gl::ARRAY_BUFFER, 0);
                 gl.gl.BindBuffer(gl::ELEMENT_ARRAY_BUFFER, 0);
                 gl.gl.UseProgram(self.imgui_data.program_fill);
@@ -666,6 +1009,7 @@ impl BackendRenderer for OpenGLRenderer {
                     width,
                     height,
                     vertices: Vec::new(),
+                    static_buffers: HashMap::new(),
                     locs: self.imgui_data.tex_locs,
                     program: self.imgui_data.program_tex,
                     vbo: self.imgui_data.vbo,
@@ -706,6 +1050,7 @@ impl BackendRenderer for OpenGLRenderer {
                     width,
                     height,
                     vertices: Vec::new(),
+                    static_buffers: HashMap::new(),
                     locs: self.imgui_data.tex_locs,
                     program: self.imgui_data.program_tex,
                     vbo: self.imgui_data.vbo,
@@ -760,6 +1105,8 @@ impl BackendRenderer for OpenGLRenderer {
                     gl.gl.UniformMatrix4fv(self.imgui_data.tex_locs.proj_mtx, 1, gl::FALSE, self.curr_matrix.as_ptr() as _);
 
                     gl.gl.BindFramebuffer(gl::FRAMEBUFFER, gl_texture.framebuffer_id);
+                    gl.gl.Viewport(0, 0, gl_texture.width as _, gl_texture.height as _);
+                    gl.gl.Disable(gl::SCISSOR_TEST);
                 } else {
                     self.curr_matrix = self.def_matrix;
 
@@ -778,7 +1125,7 @@ impl BackendRenderer for OpenGLRenderer {
                         gl::FALSE,
                         self.def_matrix.as_ptr() as _,
                     );
-                    gl.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+                    self.bind_screen(gl);
                 }
             }
 
@@ -872,6 +1219,11 @@ impl BackendRenderer for OpenGLRenderer {
     }
 
     fn render_imgui(&mut self, draw_data: &DrawData) -> GameResult {
+        // the UI is drawn on top of post-processed frame
+        if self.post_process_pending {
+            self.apply_post_process(self.post_process_scale)?;
+        }
+
         // https://github.com/michaelfairley/rust-imgui-opengl-renderer
         if let Some((_, gl)) = self.get_context() {
             unsafe {
@@ -1011,6 +1363,206 @@ impl BackendRenderer for OpenGLRenderer {
     fn supports_vertex_draw(&self) -> bool {
         true
     }
+
+    fn set_post_process_chain(&mut self, shaders: &[String]) -> GameResult {
+        if let Some((_, gl)) = self.get_context() {
+            let mut passes = Vec::with_capacity(shaders.len());
+
+            for source in shaders.iter() {
+                match create_program(gl, VERTEX_SHADER_BASIC, source) {
+                    Some(program) => passes.push(PostProcessPass::new(gl, program)),
+                    None => {
+                        for pass in passes.iter() {
+                            unsafe { gl.gl.DeleteProgram(pass.program) };
+                        }
+
+                        return Err(RenderError("Failed to compile post-processing shader.".to_string()));
+                    }
+                }
+            }
+
+            for pass in self.post_process.iter() {
+                unsafe { gl.gl.DeleteProgram(pass.program) };
+            }
+
+            self.post_process = passes;
+            // recreated by the next prepare_draw
+            self.delete_post_process_targets(gl);
+
+            Ok(())
+        } else {
+            Err(RenderError("No OpenGL context available!".to_string()))
+        }
+    }
+
+    fn apply_post_process(&mut self, scale: f32) -> GameResult {
+        self.post_process_scale = scale;
+
+        if !self.post_process_pending {
+            return Ok(());
+        }
+
+        self.post_process_pending = false;
+        self.frame_count = self.frame_count.wrapping_add(1);
+
+        if let Some((_, gl)) = self.get_context() {
+            let (width, height) = self.post_process_size;
+            let (w, h) = (width as f32, height as f32);
+            let color = (255, 255, 255, 255);
+            let vertices = [
+                VertexData { position: (0.0, h), uv: (0.0, 1.0), color },
+                VertexData { position: (0.0, 0.0), uv: (0.0, 0.0), color },
+                VertexData { position: (w, 0.0), uv: (1.0, 0.0), color },
+                VertexData { position: (0.0, h), uv: (0.0, 1.0), color },
+                VertexData { position: (w, 0.0), uv: (1.0, 0.0), color },
+                VertexData { position: (w, h), uv: (1.0, 1.0), color },
+            ];
+
+            unsafe {
+                gl.gl.BlendFunc(gl::ONE, gl::ZERO);
+                gl.gl.BindBuffer(gl::ARRAY_BUFFER, self.imgui_data.vbo);
+                gl.gl.BufferData(
+                    gl::ARRAY_BUFFER,
+                    (vertices.len() * mem::size_of::<VertexData>()) as _,
+                    vertices.as_ptr() as _,
+                    gl::STREAM_DRAW,
+                );
+
+                let last = self.post_process.len() - 1;
+                for (i, pass) in self.post_process.iter().enumerate() {
+                    let source = &self.post_process_targets[i % self.post_process_targets.len()];
+                    let (framebuffer, matrix) = if i == last {
+                        (0, screen_matrix(w, h))
+                    } else {
+                        (self.post_process_targets[(i + 1) % 2].framebuffer_id, texture_matrix(w, h))
+                    };
+
+                    gl.gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer);
+                    if i == last {
+                        let (x, y, width, height) = self.gl_viewport;
+                        gl.gl.Viewport(x, y, width, height);
+                        gl.gl.Scissor(x, y, width, height);
+                        gl.gl.Enable(gl::SCISSOR_TEST);
+                    } else {
+                        gl.gl.Viewport(0, 0, width as _, height as _);
+                        gl.gl.Disable(gl::SCISSOR_TEST);
+                    }
+                    gl.gl.UseProgram(pass.program);
+                    gl.gl.Uniform1i(pass.locs.texture, 0);
+                    gl.gl.UniformMatrix4fv(pass.locs.proj_mtx, 1, gl::FALSE, matrix.as_ptr() as _);
+                    gl.gl.Uniform2f(pass.source_size, w, h);
+                    gl.gl.Uniform2f(pass.output_size, w, h);
+                    gl.gl.Uniform1f(pass.scale, scale);
+                    gl.gl.Uniform1f(pass.frame_count, self.frame_count as f32);
+
+                    gl.gl.EnableVertexAttribArray(pass.locs.position);
+                    gl.gl.EnableVertexAttribArray(pass.locs.uv);
+                    gl.gl.EnableVertexAttribArray(pass.locs.color);
+
+                    gl.gl.VertexAttribPointer(
+                        pass.locs.position,
+                        2,
+                        gl::FLOAT,
+                        gl::FALSE,
+                        mem::size_of::<VertexData>() as _,
+                        field_offset::<VertexData, _, _>(|v| &v.position) as _,
+                    );
+
+                    gl.gl.VertexAttribPointer(
+                        pass.locs.uv,
+                        2,
+                        gl::FLOAT,
+                        gl::FALSE,
+                        mem::size_of::<VertexData>() as _,
+                        field_offset::<VertexData, _, _>(|v| &v.uv) as _,
+                    );
+
+                    gl.gl.VertexAttribPointer(
+                        pass.locs.color,
+                        4,
+                        gl::UNSIGNED_BYTE,
+                        gl::TRUE,
+                        mem::size_of::<VertexData>() as _,
+                        field_offset::<VertexData, _, _>(|v| &v.color) as _,
+                    );
+
+                    gl.gl.BindTexture(gl::TEXTURE_2D, source.texture_id);
+                    gl.gl.DrawArrays(gl::TRIANGLES, 0, vertices.len() as _);
+                }
+
+                gl.gl.BindTexture(gl::TEXTURE_2D, 0);
+                gl.gl.BindBuffer(gl::ARRAY_BUFFER, 0);
+                gl.gl.BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            }
+
+            // anything drawn afterwards goes straight to the screen
+            self.def_matrix = screen_matrix(w, h);
+            self.set_projection(gl, self.def_matrix);
+            self.bind_screen(gl);
+
+            Ok(())
+        } else {
+            Err(RenderError("No OpenGL context available!".to_string()))
+        }
+    }
+
+    fn set_screen_viewport(&mut self, viewport: Option<Rect<f32>>) {
+        self.screen_viewport = viewport;
+    }
+
+    fn read_screen(&mut self) -> GameResult<(u16, u16, Vec<u8>)> {
+        let (x, y, width, height) = self.gl_viewport;
+        let (width, height) = (width as u16, height as u16);
+
+        if let Some((_, gl)) = self.get_context() {
+            unsafe {
+                let mut data = vec![0u8; width as usize * height as usize * 4];
+                let current_fb = return_param(|x| gl.gl.GetIntegerv(gl::FRAMEBUFFER_BINDING, x)) as u32;
+
+                gl.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+                gl.gl.PixelStorei(gl::PACK_ALIGNMENT, 1);
+                gl.gl.ReadPixels(x, y, width as _, height as _, gl::RGBA, gl::UNSIGNED_BYTE, data.as_mut_ptr() as _);
+                gl.gl.BindFramebuffer(gl::FRAMEBUFFER, current_fb);
+
+                // OpenGL returns rows bottom to top
+                let stride = width as usize * 4;
+                let mut flipped = Vec::with_capacity(data.len());
+                for row in data.chunks_exact(stride).rev() {
+                    flipped.extend_from_slice(row);
+                }
+
+                // the default framebuffer might not have an alpha channel
+                for pixel in flipped.chunks_exact_mut(4) {
+                    pixel[3] = 0xff;
+                }
+
+                Ok((width, height, flipped))
+            }
+        } else {
+            Err(RenderError("No OpenGL context available!".to_string()))
+        }
+    }
+
+    fn read_texture(&mut self, texture: &Box<dyn BackendTexture>) -> GameResult<(u16, u16, Vec<u8>)> {
+        if let Some((_, gl)) = self.get_context() {
+            unsafe {
+                let gl_texture: &Box<OpenGLTexture> = std::mem::transmute(texture);
+                let (width, height) = (gl_texture.width, gl_texture.height);
+                let mut data = vec![0u8; width as usize * height as usize * 4];
+                let current_fb = return_param(|x| gl.gl.GetIntegerv(gl::FRAMEBUFFER_BINDING, x)) as u32;
+
+                // textures are drawn with their first row at the bottom, which is also read first
+                gl.gl.BindFramebuffer(gl::FRAMEBUFFER, gl_texture.framebuffer_id);
+                gl.gl.PixelStorei(gl::PACK_ALIGNMENT, 1);
+                gl.gl.ReadPixels(0, 0, width as _, height as _, gl::RGBA, gl::UNSIGNED_BYTE, data.as_mut_ptr() as _);
+                gl.gl.BindFramebuffer(gl::FRAMEBUFFER, current_fb);
+
+                Ok((width, height, data))
+            }
+        } else {
+            Err(RenderError("No OpenGL context available!".to_string()))
+        }
+    }
 }
 
 impl OpenGLRenderer {
@@ -0,0 +1,831 @@
+use std::cell::{RefCell, UnsafeCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use imgui::{DrawCmd, DrawCmdParams, DrawData, DrawVert, TextureId};
+
+use crate::common::{Color, Rect};
+use crate::framework::backend::{BackendRenderer, BackendShader, BackendTexture, SpriteBatchCommand, VertexData};
+use crate::framework::error::GameResult;
+use crate::framework::graphics::BlendMode;
+
+/// RGBA8 pixel buffer, rows are stored from top to bottom.
+pub struct Surface {
+    pub width: u16,
+    pub height: u16,
+    pub data: Vec<u8>,
+}
+
+impl Surface {
+    pub fn new(width: u16, height: u16) -> Surface {
+        Surface { width, height, data: vec![0; width as usize * height as usize * 4] }
+    }
+
+    pub fn from_rgba(width: u16, height: u16, data: &[u8]) -> Surface {
+        let mut surface = Surface::new(width, height);
+        let len = surface.data.len().min(data.len());
+        surface.data[..len].copy_from_slice(&data[..len]);
+
+        surface
+    }
+
+    /// Copies contents of another surface, resizing this one to match it.
+    pub fn copy_from(&mut self, other: &Surface) {
+        self.width = other.width;
+        self.height = other.height;
+        self.data.clear();
+        self.data.extend_from_slice(&other.data);
+    }
+
+    fn fill(&mut self, color: [u8; 4]) {
+        for pixel in self.data.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&color);
+        }
+    }
+
+    #[inline]
+    fn sample(&self, u: f32, v: f32) -> [u8; 4] {
+        let x = ((u * self.width as f32) as isize).max(0).min(self.width as isize - 1) as usize;
+        let y = ((v * self.height as f32) as isize).max(0).min(self.height as isize - 1) as usize;
+        let idx = (y * self.width as usize + x) * 4;
+
+        [self.data[idx], self.data[idx + 1], self.data[idx + 2], self.data[idx + 3]]
+    }
+
+    #[inline]
+    fn blend(&mut self, x: usize, y: usize, src: [u8; 4], mode: BlendMode) {
+        let idx = (y * self.width as usize + x) * 4;
+        let dst = &mut self.data[idx..idx + 4];
+
+        // matches the blend functions used by the OpenGL renderer
+        match mode {
+            BlendMode::Alpha => {
+                let a = src[3] as u32;
+                for (d, &s) in dst.iter_mut().zip(src.iter()) {
+                    *d = ((s as u32 * a + *d as u32 * (255 - a) + 127) / 255) as u8;
+                }
+            }
+            BlendMode::Add => {
+                for (d, &s) in dst.iter_mut().zip(src.iter()) {
+                    *d = d.saturating_add(s);
+                }
+            }
+            BlendMode::Multiply => {
+                for (d, &s) in dst.iter_mut().zip(src.iter()) {
+                    *d = mul8(*d, s) as u8;
+                }
+            }
+        }
+    }
+}
+
+#[inline]
+fn mul8(a: u8, b: u8) -> u32 {
+    (a as u32 * b as u32 + 127) / 255
+}
+
+/// Vertex attributes shared by game and imgui geometry.
+#[derive(Copy, Clone)]
+struct Vertex {
+    x: f32,
+    y: f32,
+    u: f32,
+    v: f32,
+    color: [f32; 4],
+}
+
+impl From<&VertexData> for Vertex {
+    fn from(vertex: &VertexData) -> Vertex {
+        let (r, g, b, a) = vertex.color;
+        Vertex {
+            x: vertex.position.0,
+            y: vertex.position.1,
+            u: vertex.uv.0,
+            v: vertex.uv.1,
+            color: [r as f32, g as f32, b as f32, a as f32],
+        }
+    }
+}
+
+impl From<&DrawVert> for Vertex {
+    fn from(vertex: &DrawVert) -> Vertex {
+        let [r, g, b, a] = vertex.col;
+        Vertex {
+            x: vertex.pos[0],
+            y: vertex.pos[1],
+            u: vertex.uv[0],
+            v: vertex.uv[1],
+            color: [r as f32, g as f32, b as f32, a as f32],
+        }
+    }
+}
+
+/// Pixel bounds drawing is limited to, right and bottom edges are exclusive.
+#[derive(Copy, Clone)]
+struct Clip {
+    left: isize,
+    top: isize,
+    right: isize,
+    bottom: isize,
+}
+
+impl Clip {
+    fn surface(surface: &Surface) -> Clip {
+        Clip { left: 0, top: 0, right: surface.width as isize, bottom: surface.height as isize }
+    }
+
+    fn intersect(self, other: Clip) -> Clip {
+        Clip {
+            left: self.left.max(other.left),
+            top: self.top.max(other.top),
+            right: self.right.min(other.right),
+            bottom: self.bottom.min(other.bottom),
+        }
+    }
+}
+
+#[inline]
+fn edge(a: &Vertex, b: &Vertex, x: f32, y: f32) -> f32 {
+    (b.x - a.x) * (y - a.y) - (b.y - a.y) * (x - a.x)
+}
+
+/// Top-left fill rule, so pixels on edges shared by two triangles are drawn exactly once.
+#[inline]
+fn is_top_left(a: &Vertex, b: &Vertex) -> bool {
+    (a.y == b.y && b.x > a.x) || b.y < a.y
+}
+
+#[inline]
+fn is_inside(w: f32, top_left: bool) -> bool {
+    w > 0.0 || (w == 0.0 && top_left)
+}
+
+/// Rasterizes a triangle sampling pixel centers, with the texture sampled using nearest filtering
+/// and multiplied by interpolated vertex color.
+fn draw_triangle(target: &mut Surface, clip: Clip, vertices: [Vertex; 3], texture: Option<&Surface>, mode: BlendMode) {
+    let [v0, mut v1, mut v2] = vertices;
+    let mut area = edge(&v0, &v1, v2.x, v2.y);
+    if area.abs() < f32::EPSILON {
+        return;
+    }
+
+    // there's no culling, make all triangles wind the same way
+    if area < 0.0 {
+        std::mem::swap(&mut v1, &mut v2);
+        area = -area;
+    }
+
+    let clip = clip.intersect(Clip::surface(target));
+    let left = (v0.x.min(v1.x).min(v2.x).floor() as isize).max(clip.left);
+    let top = (v0.y.min(v1.y).min(v2.y).floor() as isize).max(clip.top);
+    let right = (v0.x.max(v1.x).max(v2.x).ceil() as isize).min(clip.right);
+    let bottom = (v0.y.max(v1.y).max(v2.y).ceil() as isize).min(clip.bottom);
+
+    if left >= right || top >= bottom {
+        return;
+    }
+
+    let top_left0 = is_top_left(&v1, &v2);
+    let top_left1 = is_top_left(&v2, &v0);
+    let top_left2 = is_top_left(&v0, &v1);
+
+    let inv_area = 1.0 / area;
+    let flat_color = v1.color == v0.color && v2.color == v0.color;
+
+    for y in top..bottom {
+        let py = y as f32 + 0.5;
+
+        for x in left..right {
+            let px = x as f32 + 0.5;
+            let w0 = edge(&v1, &v2, px, py);
+            let w1 = edge(&v2, &v0, px, py);
+            let w2 = edge(&v0, &v1, px, py);
+
+            if !is_inside(w0, top_left0) || !is_inside(w1, top_left1) || !is_inside(w2, top_left2) {
+                continue;
+            }
+
+            let (b0, b1, b2) = (w0 * inv_area, w1 * inv_area, w2 * inv_area);
+
+            let color = if flat_color {
+                v0.color
+            } else {
+                let mut color = [0.0; 4];
+                for (i, c) in color.iter_mut().enumerate() {
+                    *c = v0.color[i] * b0 + v1.color[i] * b1 + v2.color[i] * b2;
+                }
+                color
+            };
+
+            let src = match texture {
+                Some(texture) => {
+                    let texel = texture.sample(v0.u * b0 + v1.u * b1 + v2.u * b2, v0.v * b0 + v1.v * b1 + v2.v * b2);
+                    [
+                        (texel[0] as f32 * color[0] / 255.0) as u8,
+                        (texel[1] as f32 * color[1] / 255.0) as u8,
+                        (texel[2] as f32 * color[2] / 255.0) as u8,
+                        (texel[3] as f32 * color[3] / 255.0) as u8,
+                    ]
+                }
+                None => [color[0] as u8, color[1] as u8, color[2] as u8, color[3] as u8],
+            };
+
+            target.blend(x as usize, y as usize, src, mode);
+        }
+    }
+}
+
+fn draw_triangles<I: Iterator<Item = Vertex>>(
+    target: &mut Surface,
+    clip: Clip,
+    mut vertices: I,
+    texture: Option<&Surface>,
+    mode: BlendMode,
+) {
+    while let (Some(v0), Some(v1), Some(v2)) = (vertices.next(), vertices.next(), vertices.next()) {
+        draw_triangle(target, clip, [v0, v1, v2], texture, mode);
+    }
+}
+
+struct RenderState {
+    screen: Rc<RefCell<Surface>>,
+    target: Rc<RefCell<Surface>>,
+    blend_mode: BlendMode,
+}
+
+impl RenderState {
+    /// Draws triangles sampled from given surface to the current render target.
+    fn draw(&self, vertices: &[VertexData], texture: Option<&Rc<RefCell<Surface>>>) {
+        let mut target = self.target.borrow_mut();
+        let clip = Clip::surface(&target);
+        let vertices = vertices.iter().map(Vertex::from);
+
+        match texture {
+            // drawing a texture onto itself, sample from a copy
+            Some(texture) if Rc::ptr_eq(texture, &self.target) => {
+                let copy = Surface::from_rgba(target.width, target.height, &target.data);
+                draw_triangles(&mut target, clip, vertices, Some(&copy), self.blend_mode);
+            }
+            Some(texture) => draw_triangles(&mut target, clip, vertices, Some(&texture.borrow()), self.blend_mode),
+            None => draw_triangles(&mut target, clip, vertices, None, self.blend_mode),
+        }
+    }
+}
+
+pub struct SoftwareTexture {
+    width: u16,
+    height: u16,
+    surface: Rc<RefCell<Surface>>,
+    vertices: Vec<VertexData>,
+    static_vertices: HashMap<usize, Vec<VertexData>>,
+    state: Rc<RefCell<RenderState>>,
+}
+
+impl BackendTexture for SoftwareTexture {
+    fn dimensions(&self) -> (u16, u16) {
+        (self.width, self.height)
+    }
+
+    fn add(&mut self, command: SpriteBatchCommand) {
+        let (tex_scale_x, tex_scale_y) = (1.0 / self.width as f32, 1.0 / self.height as f32);
+
+        let (mut src, dest, color) = match command {
+            SpriteBatchCommand::DrawRect(src, dest) => (src, dest, (255, 255, 255, 255)),
+            SpriteBatchCommand::DrawRectFlip(mut src, dest, flip_x, flip_y) => {
+                if flip_x {
+                    std::mem::swap(&mut src.left, &mut src.right);
+                }
+
+                if flip_y {
+                    std::mem::swap(&mut src.top, &mut src.bottom);
+                }
+
+                (src, dest, (255, 255, 255, 255))
+            }
+            SpriteBatchCommand::DrawRectTinted(src, dest, color) => (src, dest, color.to_rgba()),
+        };
+
+        src.left *= tex_scale_x;
+        src.right *= tex_scale_x;
+        src.top *= tex_scale_y;
+        src.bottom *= tex_scale_y;
+
+        let vertices = [
+            VertexData { position: (dest.left, dest.bottom), uv: (src.left, src.bottom), color },
+            VertexData { position: (dest.left, dest.top), uv: (src.left, src.top), color },
+            VertexData { position: (dest.right, dest.top), uv: (src.right, src.top), color },
+            VertexData { position: (dest.left, dest.bottom), uv: (src.left, src.bottom), color },
+            VertexData { position: (dest.right, dest.top), uv: (src.right, src.top), color },
+            VertexData { position: (dest.right, dest.bottom), uv: (src.right, src.bottom), color },
+        ];
+        self.vertices.extend_from_slice(&vertices);
+    }
+
+    fn clear(&mut self) {
+        self.vertices.clear();
+    }
+
+    fn draw(&mut self) -> GameResult {
+        self.state.borrow().draw(&self.vertices, Some(&self.surface));
+
+        Ok(())
+    }
+
+    fn update(&mut self, x: u16, y: u16, width: u16, height: u16, data: &[u8]) -> GameResult {
+        let mut surface = self.surface.borrow_mut();
+        let (x, y, width) = (x as usize, y as usize, width as usize);
+        let stride = surface.width as usize * 4;
+
+        for (row, src) in data.chunks_exact(width * 4).take(height as usize).enumerate() {
+            let start = (y + row) * stride + x * 4;
+            if let Some(dst) = surface.data.get_mut(start..start + src.len()) {
+                dst.copy_from_slice(src);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn supports_static(&self) -> bool {
+        true
+    }
+
+    fn store_static(&mut self, slot: usize) -> GameResult {
+        self.static_vertices.insert(slot, std::mem::take(&mut self.vertices));
+
+        Ok(())
+    }
+
+    fn has_static(&self, slot: usize) -> bool {
+        self.static_vertices.contains_key(&slot)
+    }
+
+    fn draw_static(&mut self, slot: usize, (x, y): (f32, f32)) -> GameResult {
+        if let Some(vertices) = self.static_vertices.get(&slot) {
+            let moved: Vec<VertexData> =
+                vertices.iter().map(|v| VertexData { position: (v.position.0 + x, v.position.1 + y), ..*v }).collect();
+
+            self.state.borrow().draw(&moved, Some(&self.surface));
+        }
+
+        Ok(())
+    }
+}
+
+/// Renderer rasterizing everything on the CPU, used where there's no GPU or no window at all.
+pub struct SoftwareRenderer {
+    state: Rc<RefCell<RenderState>>,
+    imgui: UnsafeCell<imgui::Context>,
+    imgui_textures: HashMap<TextureId, Surface>,
+    /// Receives a copy of the screen on every `present`.
+    output: Option<Rc<RefCell<Surface>>>,
+}
+
+impl SoftwareRenderer {
+    pub fn new(mut imgui: imgui::Context, width: u16, height: u16) -> SoftwareRenderer {
+        let mut imgui_textures = HashMap::new();
+
+        {
+            let mut fonts = imgui.fonts();
+            let id = fonts.tex_id;
+            let font_tex = fonts.build_rgba32_texture();
+
+            imgui_textures.insert(id, Surface::from_rgba(font_tex.width as u16, font_tex.height as u16, font_tex.data));
+        }
+
+        let screen = Rc::new(RefCell::new(Surface::new(width, height)));
+
+        SoftwareRenderer {
+            state: Rc::new(RefCell::new(RenderState {
+                screen: screen.clone(),
+                target: screen,
+                blend_mode: BlendMode::Alpha,
+            })),
+            imgui: UnsafeCell::new(imgui),
+            imgui_textures,
+            output: None,
+        }
+    }
+
+    /// Creates a renderer which isn't shown anywhere, presented frames can be read from the returned surface.
+    #[allow(dead_code)]
+    pub fn offscreen(imgui: imgui::Context, width: u16, height: u16) -> (SoftwareRenderer, Rc<RefCell<Surface>>) {
+        let output = Rc::new(RefCell::new(Surface::new(width, height)));
+        let mut renderer = SoftwareRenderer::new(imgui, width, height);
+        renderer.output = Some(output.clone());
+
+        (renderer, output)
+    }
+
+    fn fill_rect(&mut self, rect: Rect<isize>, color: Color) {
+        let state = self.state.borrow();
+        let mut target = state.target.borrow_mut();
+        let clip = Clip::surface(&target);
+        let color = color.to_rgba();
+        let color = [color.0, color.1, color.2, color.3];
+
+        for y in rect.top.max(clip.top)..rect.bottom.min(clip.bottom) {
+            for x in rect.left.max(clip.left)..rect.right.min(clip.right) {
+                target.blend(x as usize, y as usize, color, state.blend_mode);
+            }
+        }
+    }
+}
+
+impl BackendRenderer for SoftwareRenderer {
+    fn renderer_name(&self) -> String {
+        "Software".to_owned()
+    }
+
+    fn clear(&mut self, color: Color) {
+        let color = color.to_rgba();
+        self.state.borrow().target.borrow_mut().fill([color.0, color.1, color.2, color.3]);
+    }
+
+    fn present(&mut self) -> GameResult {
+        if let Some(output) = self.output.as_ref() {
+            output.borrow_mut().copy_from(&self.state.borrow().screen.borrow());
+        }
+
+        Ok(())
+    }
+
+    fn prepare_draw(&mut self, width: f32, height: f32) -> GameResult {
+        let mut state = self.state.borrow_mut();
+        let (width, height) = (width as u16, height as u16);
+
+        let resized = {
+            let screen = state.screen.borrow();
+            screen.width != width || screen.height != height
+        };
+
+        if resized {
+            state.screen = Rc::new(RefCell::new(Surface::new(width, height)));
+        }
+
+        state.target = state.screen.clone();
+        state.blend_mode = BlendMode::Alpha;
+
+        Ok(())
+    }
+
+    fn create_texture_mutable(&mut self, width: u16, height: u16) -> GameResult<Box<dyn BackendTexture>> {
+        Ok(Box::new(SoftwareTexture {
+            width,
+            height,
+            surface: Rc::new(RefCell::new(Surface::new(width, height))),
+            vertices: Vec::new(),
+            static_vertices: HashMap::new(),
+            state: self.state.clone(),
+        }))
+    }
+
+    fn create_texture(&mut self, width: u16, height: u16, data: &[u8]) -> GameResult<Box<dyn BackendTexture>> {
+        Ok(Box::new(SoftwareTexture {
+            width,
+            height,
+            surface: Rc::new(RefCell::new(Surface::from_rgba(width, height, data))),
+            vertices: Vec::new(),
+            static_vertices: HashMap::new(),
+            state: self.state.clone(),
+        }))
+    }
+
+    fn set_blend_mode(&mut self, blend: BlendMode) -> GameResult {
+        self.state.borrow_mut().blend_mode = blend;
+
+        Ok(())
+    }
+
+    fn set_render_target(&mut self, texture: Option<&Box<dyn BackendTexture>>) -> GameResult {
+        let mut state = self.state.borrow_mut();
+
+        state.target = if let Some(texture) = texture {
+            let sw_texture: &Box<SoftwareTexture> = unsafe { std::mem::transmute(texture) };
+            sw_texture.surface.clone()
+        } else {
+            state.screen.clone()
+        };
+
+        Ok(())
+    }
+
+    fn draw_rect(&mut self, rect: Rect<isize>, color: Color) -> GameResult {
+        self.fill_rect(rect, color);
+
+        Ok(())
+    }
+
+    fn draw_outline_rect(&mut self, rect: Rect<isize>, line_width: usize, color: Color) -> GameResult {
+        let width = line_width as isize;
+
+        self.fill_rect(Rect::new(rect.left, rect.top, rect.right, rect.top + width), color);
+        self.fill_rect(Rect::new(rect.left, rect.bottom - width, rect.right, rect.bottom), color);
+        self.fill_rect(Rect::new(rect.left, rect.top + width, rect.left + width, rect.bottom - width), color);
+        self.fill_rect(Rect::new(rect.right - width, rect.top + width, rect.right, rect.bottom - width), color);
+
+        Ok(())
+    }
+
+    fn imgui(&self) -> GameResult<&mut imgui::Context> {
+        unsafe { Ok(&mut *self.imgui.get()) }
+    }
+
+    fn render_imgui(&mut self, draw_data: &DrawData) -> GameResult {
+        let state = self.state.borrow();
+        let mut screen = state.screen.borrow_mut();
+        let [offset_x, offset_y] = draw_data.display_pos;
+
+        for draw_list in draw_data.draw_lists() {
+            let vtx_buffer = draw_list.vtx_buffer();
+            let idx_buffer = draw_list.idx_buffer();
+
+            for cmd in draw_list.commands() {
+                match cmd {
+                    DrawCmd::Elements {
+                        count,
+                        cmd_params: DrawCmdParams { clip_rect: [x, y, z, w], texture_id, vtx_offset, idx_offset },
+                    } => {
+                        let clip = Clip {
+                            left: (x - offset_x).floor() as isize,
+                            top: (y - offset_y).floor() as isize,
+                            right: (z - offset_x).ceil() as isize,
+                            bottom: (w - offset_y).ceil() as isize,
+                        };
+
+                        let vertices = idx_buffer[idx_offset..idx_offset + count].iter().map(|&idx| {
+                            let mut vertex = Vertex::from(&vtx_buffer[vtx_offset + idx as usize]);
+                            vertex.x -= offset_x;
+                            vertex.y -= offset_y;
+                            vertex
+                        });
+
+                        draw_triangles(
+                            &mut screen,
+                            clip,
+                            vertices,
+                            self.imgui_textures.get(&texture_id),
+                            BlendMode::Alpha,
+                        );
+                    }
+                    DrawCmd::ResetRenderState => {}
+                    DrawCmd::RawCallback { .. } => {}
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn supports_vertex_draw(&self) -> bool {
+        true
+    }
+
+    fn draw_triangle_list(
+        &mut self,
+        vertices: Vec<VertexData>,
+        texture: Option<&Box<dyn BackendTexture>>,
+        shader: BackendShader,
+    ) -> GameResult {
+        let texture = match (texture, shader) {
+            (Some(texture), BackendShader::Texture) => {
+                let sw_texture: &Box<SoftwareTexture> = unsafe { std::mem::transmute(texture) };
+                Some(sw_texture.surface.clone())
+            }
+            _ => None,
+        };
+
+        self.state.borrow().draw(&vertices, texture.as_ref());
+
+        Ok(())
+    }
+
+    fn read_screen(&mut self) -> GameResult<(u16, u16, Vec<u8>)> {
+        let state = self.state.borrow();
+        let screen = state.screen.borrow();
+
+        Ok((screen.width, screen.height, screen.data.clone()))
+    }
+
+    fn read_texture(&mut self, texture: &Box<dyn BackendTexture>) -> GameResult<(u16, u16, Vec<u8>)> {
+        let sw_texture: &Box<SoftwareTexture> = unsafe { std::mem::transmute(texture) };
+        let surface = sw_texture.surface.borrow();
+
+        Ok((surface.width, surface.height, surface.data.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::framework::ui::lock_imgui;
+
+    use super::*;
+
+    fn vertex(x: f32, y: f32) -> Vertex {
+        Vertex { x, y, u: 0.0, v: 0.0, color: [255.0; 4] }
+    }
+
+    /// Two triangles covering given rectangle, the same way sprite batches draw quads.
+    fn quad(left: f32, top: f32, right: f32, bottom: f32) -> Vec<Vertex> {
+        vec![
+            vertex(left, bottom),
+            vertex(left, top),
+            vertex(right, top),
+            vertex(left, bottom),
+            vertex(right, top),
+            vertex(right, bottom),
+        ]
+    }
+
+    fn pixel(surface: &Surface, x: usize, y: usize) -> [u8; 4] {
+        let idx = (y * surface.width as usize + x) * 4;
+        [surface.data[idx], surface.data[idx + 1], surface.data[idx + 2], surface.data[idx + 3]]
+    }
+
+    /// Returns how many times each pixel was drawn, given everything was drawn with additive blending at 1.
+    fn coverage(surface: &Surface) -> Vec<Vec<u8>> {
+        (0..surface.height as usize)
+            .map(|y| (0..surface.width as usize).map(|x| pixel(surface, x, y)[0]).collect())
+            .collect()
+    }
+
+    fn draw_counted(surface: &mut Surface, clip: Clip, vertices: Vec<Vertex>) {
+        let vertices = vertices.into_iter().map(|v| Vertex { color: [1.0; 4], ..v });
+        draw_triangles(surface, clip, vertices, None, BlendMode::Add);
+    }
+
+    fn render_state(width: u16, height: u16) -> Rc<RefCell<RenderState>> {
+        let screen = Rc::new(RefCell::new(Surface::new(width, height)));
+        Rc::new(RefCell::new(RenderState { screen: screen.clone(), target: screen, blend_mode: BlendMode::Alpha }))
+    }
+
+    fn texture(state: &Rc<RefCell<RenderState>>, width: u16, height: u16, data: &[u8]) -> SoftwareTexture {
+        SoftwareTexture {
+            width,
+            height,
+            surface: Rc::new(RefCell::new(Surface::from_rgba(width, height, data))),
+            vertices: Vec::new(),
+            static_vertices: HashMap::new(),
+            state: state.clone(),
+        }
+    }
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+    #[test]
+    fn shared_edges_are_drawn_once() {
+        let mut surface = Surface::new(4, 4);
+        let clip = Clip::surface(&surface);
+
+        // quad split along the diagonal, plus a second quad sharing its right edge
+        draw_counted(&mut surface, clip, quad(0.0, 0.0, 2.0, 4.0));
+        draw_counted(&mut surface, clip, quad(2.0, 0.0, 4.0, 4.0));
+
+        assert_eq!(coverage(&surface), vec![vec![1; 4]; 4]);
+    }
+
+    #[test]
+    fn edges_through_pixel_centers() {
+        let mut surface = Surface::new(5, 5);
+        let clip = Clip::surface(&surface);
+
+        // centers on the left and top edges are inside, on the right and bottom ones outside
+        draw_counted(&mut surface, clip, quad(0.5, 0.5, 3.5, 2.5));
+
+        assert_eq!(
+            coverage(&surface),
+            vec![
+                vec![1, 1, 1, 0, 0],
+                vec![1, 1, 1, 0, 0],
+                vec![0, 0, 0, 0, 0],
+                vec![0, 0, 0, 0, 0],
+                vec![0, 0, 0, 0, 0],
+            ]
+        );
+
+        // a triangle winding the other way, centers on its diagonal are on a bottom-right edge and left out,
+        // and a degenerate one which draws nothing
+        let mut surface = Surface::new(2, 2);
+        draw_counted(&mut surface, clip, vec![vertex(0.0, 0.0), vertex(0.0, 2.0), vertex(2.0, 0.0)]);
+        draw_counted(&mut surface, clip, vec![vertex(0.0, 0.0), vertex(1.0, 1.0), vertex(2.0, 2.0)]);
+        assert_eq!(coverage(&surface), vec![vec![1, 0], vec![0, 0]]);
+    }
+
+    #[test]
+    fn blend_modes() {
+        let dst = [100, 100, 100, 255];
+        let src = [200, 0, 50, 128];
+
+        for &(mode, expected) in [
+            (BlendMode::Alpha, [150, 50, 75, 191]),
+            (BlendMode::Add, [255, 100, 150, 255]),
+            (BlendMode::Multiply, [78, 0, 20, 128]),
+        ]
+        .iter()
+        {
+            let mut surface = Surface::from_rgba(1, 1, &dst);
+            surface.blend(0, 0, src, mode);
+
+            assert_eq!(pixel(&surface, 0, 0), expected, "{:?}", mode);
+        }
+
+        // fully opaque and fully transparent alpha blending
+        let mut surface = Surface::from_rgba(1, 1, &dst);
+        surface.blend(0, 0, [1, 2, 3, 0], BlendMode::Alpha);
+        assert_eq!(pixel(&surface, 0, 0), dst);
+        surface.blend(0, 0, [1, 2, 3, 255], BlendMode::Alpha);
+        assert_eq!(pixel(&surface, 0, 0), [1, 2, 3, 255]);
+    }
+
+    #[test]
+    fn flip_flags() {
+        let row = [RED, BLUE].concat();
+        let dest = Rect::new(0.0, 0.0, 2.0, 1.0);
+        let src = Rect::new(0.0, 0.0, 2.0, 1.0);
+
+        for &(flip_x, expected) in [(false, [RED, BLUE]), (true, [BLUE, RED])].iter() {
+            let state = render_state(2, 1);
+            let mut batch = texture(&state, 2, 1, &row);
+            batch.add(SpriteBatchCommand::DrawRectFlip(src, dest, flip_x, false));
+            batch.draw().unwrap();
+
+            let state = state.borrow();
+            let screen = state.screen.borrow();
+            assert_eq!([pixel(&screen, 0, 0), pixel(&screen, 1, 0)], expected, "flip_x: {}", flip_x);
+        }
+
+        let dest = Rect::new(0.0, 0.0, 1.0, 2.0);
+        let src = Rect::new(0.0, 0.0, 1.0, 2.0);
+
+        for &(flip_y, expected) in [(false, [RED, BLUE]), (true, [BLUE, RED])].iter() {
+            let state = render_state(1, 2);
+            let mut batch = texture(&state, 1, 2, &row);
+            batch.add(SpriteBatchCommand::DrawRectFlip(src, dest, false, flip_y));
+            batch.draw().unwrap();
+
+            let state = state.borrow();
+            let screen = state.screen.borrow();
+            assert_eq!([pixel(&screen, 0, 0), pixel(&screen, 0, 1)], expected, "flip_y: {}", flip_y);
+        }
+    }
+
+    #[test]
+    fn clipping() {
+        let mut surface = Surface::new(4, 4);
+        let full = Clip::surface(&surface);
+
+        // geometry past the surface edges is cut off
+        draw_counted(&mut surface, full, quad(-10.0, -10.0, 2.0, 20.0));
+        assert_eq!(coverage(&surface), vec![vec![1, 1, 0, 0]; 4]);
+
+        // and so is anything outside the clip rectangle
+        let mut surface = Surface::new(4, 4);
+        let clip = Clip { left: 1, top: 1, right: 3, bottom: 10 };
+        draw_counted(&mut surface, clip, quad(0.0, 0.0, 4.0, 4.0));
+        assert_eq!(coverage(&surface), vec![vec![0, 0, 0, 0], vec![0, 1, 1, 0], vec![0, 1, 1, 0], vec![0, 1, 1, 0]]);
+
+        // triangles completely outside don't draw anything
+        let mut surface = Surface::new(4, 4);
+        draw_counted(&mut surface, full, quad(5.0, 5.0, 8.0, 8.0));
+        draw_counted(&mut surface, full, quad(-8.0, 0.0, -1.0, 4.0));
+        assert_eq!(coverage(&surface), vec![vec![0; 4]; 4]);
+    }
+
+    #[test]
+    fn render_target_switching() {
+        let _lock = lock_imgui();
+        let mut renderer = SoftwareRenderer::new(imgui::Context::create(), 4, 4);
+        let mut target = renderer.create_texture_mutable(2, 2).unwrap();
+        let green = [0, 255, 0, 255];
+
+        renderer.clear(Color::from_rgba(0, 0, 255, 255));
+        renderer.set_render_target(Some(&target)).unwrap();
+        renderer.clear(Color::from_rgba(255, 0, 0, 255));
+        // clipped to the 2x2 texture
+        renderer.draw_rect(Rect::new(1, 1, 4, 4), Color::from_rgba(0, 255, 0, 255)).unwrap();
+
+        let (_, _, screen) = renderer.read_screen().unwrap();
+        assert!(screen.chunks_exact(4).all(|p| p == BLUE), "drawing to a texture changed the screen");
+
+        // draw the texture to the bottom right corner of the screen
+        renderer.set_render_target(None).unwrap();
+        target.add(SpriteBatchCommand::DrawRect(Rect::new(0.0, 0.0, 2.0, 2.0), Rect::new(2.0, 2.0, 4.0, 4.0)));
+        target.draw().unwrap();
+        renderer.draw_rect(Rect::new(0, 0, 1, 1), Color::from_rgba(255, 0, 0, 255)).unwrap();
+
+        let (width, height, screen) = renderer.read_screen().unwrap();
+        let screen = Surface::from_rgba(width, height, &screen);
+        assert_eq!(pixel(&screen, 0, 0), RED);
+        assert_eq!(pixel(&screen, 1, 1), BLUE);
+        assert_eq!([pixel(&screen, 2, 2), pixel(&screen, 3, 2), pixel(&screen, 2, 3)], [RED; 3]);
+        assert_eq!(pixel(&screen, 3, 3), green);
+
+        // a new frame always starts drawing on the screen
+        renderer.set_render_target(Some(&target)).unwrap();
+        renderer.prepare_draw(4.0, 4.0).unwrap();
+        renderer.clear(Color::from_rgba(0, 255, 0, 255));
+        let (_, _, screen) = renderer.read_screen().unwrap();
+        assert!(screen.chunks_exact(4).all(|p| p == green));
+    }
+}
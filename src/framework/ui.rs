@@ -19,6 +19,18 @@ pub struct Components {
     pub live_debugger: LiveDebugger,
 }
 
+#[cfg(test)]
+lazy_static::lazy_static! {
+    static ref IMGUI_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+}
+
+/// imgui allows only one context at a time, tests which create one hold this lock until it's dropped.
+#[cfg(test)]
+pub fn lock_imgui() -> std::sync::MutexGuard<'static, ()> {
+    // a test which panicked while holding it has dropped its context anyway
+    IMGUI_LOCK.lock().unwrap_or_else(|err| err.into_inner())
+}
+
 pub fn init_imgui() -> GameResult<imgui::Context> {
     let mut imgui = imgui::Context::create();
     imgui.set_ini_filename(None);
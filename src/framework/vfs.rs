@@ -407,13 +407,15 @@ impl OverlayFS {
     }
 
     /// Adds a new VFS to the front of the list.
-    /// Currently unused, I suppose, but good to
-    /// have at least for tests.
-    #[allow(dead_code)]
     pub fn push_front(&mut self, fs: Box<dyn VFS>) {
         self.roots.push_front(fs);
     }
 
+    /// Removes the VFS at the front of the list.
+    pub fn pop_front(&mut self) -> Option<Box<dyn VFS>> {
+        self.roots.pop_front()
+    }
+
     /// Adds a new VFS to the end of the list.
     pub fn push_back(&mut self, fs: Box<dyn VFS>) {
         self.roots.push_back(fs);
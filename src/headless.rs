@@ -0,0 +1,327 @@
+use std::cell::RefCell;
+use std::mem;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::MutexGuard;
+
+use image::RgbaImage;
+
+use crate::builtin_fs::BuiltinFS;
+use crate::common::FadeState;
+use crate::framework::context::Context;
+use crate::framework::error::GameError::{InvalidValue, RenderError};
+use crate::framework::error::GameResult;
+use crate::framework::filesystem::{mount_user_vfs, mount_vfs};
+use crate::framework::keyboard::ScanCode;
+use crate::framework::render_software::{SoftwareRenderer, Surface};
+use crate::framework::ui::{init_imgui, lock_imgui};
+use crate::framework::vfs::PhysicalFS;
+use crate::scene::game_scene::GameScene;
+use crate::scene::loading_scene::LoadingScene;
+use crate::shared_game_state::{SharedGameState, TimingMode};
+use crate::sound::{AudioClock, AudioOutput};
+use crate::text_script::TextScriptExecutionState;
+use crate::Game;
+
+/// Key press or release applied at the start of given frame of `HeadlessGame::run`.
+#[derive(Copy, Clone, Debug)]
+pub struct InputEvent {
+    pub frame: usize,
+    pub key: ScanCode,
+    pub pressed: bool,
+}
+
+impl InputEvent {
+    pub const fn press(frame: usize, key: ScanCode) -> InputEvent {
+        InputEvent { frame, key, pressed: true }
+    }
+
+    pub const fn release(frame: usize, key: ScanCode) -> InputEvent {
+        InputEvent { frame, key, pressed: false }
+    }
+}
+
+/// Runs the engine without a window on the software renderer, stepping it one tick at a time.
+/// Audio is mixed to nowhere with a simulated clock, so runs are deterministic.
+pub struct HeadlessGame {
+    // boxed, scripting keeps raw pointers to both
+    ctx: Box<Context>,
+    game: Box<Game>,
+    frame: Rc<RefCell<Surface>>,
+    // released after the context owning imgui is dropped
+    _imgui_lock: MutexGuard<'static, ()>,
+}
+
+impl HeadlessGame {
+    /// Boots the engine with game data from `data_dir` and user data (settings, saves) in `user_dir`,
+    /// rendering at the native canvas resolution.
+    pub fn new(data_dir: &Path, user_dir: &Path, width: u16, height: u16) -> GameResult<HeadlessGame> {
+        let imgui_lock = lock_imgui();
+        let mut ctx = Box::new(Context::new());
+        mount_vfs(&mut ctx, Box::new(BuiltinFS::new()));
+        mount_vfs(&mut ctx, Box::new(PhysicalFS::new(data_dir, true)));
+
+        std::fs::create_dir_all(user_dir)?;
+        mount_user_vfs(&mut ctx, Box::new(PhysicalFS::new(user_dir, false)));
+
+        let mut imgui = init_imgui()?;
+        imgui.io_mut().display_size = [width as f32, height as f32];
+
+        let (renderer, frame) = SoftwareRenderer::offscreen(imgui, width, height);
+        ctx.renderer = Some(Box::new(renderer));
+        ctx.screen_size = (width as f32, height as f32);
+
+        let mut state = SharedGameState::with_audio_output(AudioOutput::Null(AudioClock::Simulated), &mut ctx)?;
+        // interpolation depends on wall clock time
+        state.settings.motion_interpolation = false;
+        state.timing_mode = TimingMode::_50Hz;
+        state.handle_resize(&mut ctx)?;
+
+        let mut game = Box::new(Game::with_state(state, &mut ctx)?);
+        game.scene = Some(Box::new(LoadingScene::new()));
+
+        #[cfg(feature = "scripting")]
+        {
+            let state_ref = unsafe { &mut *game.state.get() };
+            state_ref.lua.update_refs(game.state.get(), ctx.as_mut() as *mut Context);
+        }
+
+        let mut headless = HeadlessGame { ctx, game, frame, _imgui_lock: imgui_lock };
+
+        // the loading scene loads stage and NPC tables and then starts the intro
+        for _ in 0..2 {
+            headless.tick()?;
+        }
+
+        Ok(headless)
+    }
+
+    /// Replaces current scene with given stage, running an event the same way as `<TRA` does.
+    pub fn warp(&mut self, stage_id: usize, event_num: u16, x: i32, y: i32) -> GameResult {
+        let ctx = &mut self.ctx;
+        let state = unsafe { &mut *self.game.state.get() };
+
+        if stage_id >= state.stages.len() {
+            return Err(InvalidValue(format!("Stage {} doesn't exist.", stage_id)));
+        }
+
+        state.reset();
+        state.reset_map_flags();
+
+        let mut scene = GameScene::new(state, ctx, stage_id)?;
+        let tile_size = scene.stage.map.tile_size.as_int() * 0x200;
+        scene.player1.cond.set_alive(true);
+        scene.player1.x = x * tile_size;
+        scene.player1.y = y * tile_size;
+
+        state.fade_state = FadeState::Visible;
+        state.textscript_vm.state = TextScriptExecutionState::Running(event_num, 0);
+        state.next_scene = Some(Box::new(scene));
+
+        self.switch_scene()
+    }
+
+    fn switch_scene(&mut self) -> GameResult {
+        let state = unsafe { &mut *self.game.state.get() };
+
+        if state.next_scene.is_some() {
+            mem::swap(&mut self.game.scene, &mut state.next_scene);
+            state.next_scene = None;
+
+            if let Some(scene) = self.game.scene.as_mut() {
+                scene.init(state, &mut self.ctx)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn tick(&mut self) -> GameResult {
+        let state = unsafe { &mut *self.game.state.get() };
+        let tick_length = state.timing_mode.get_delta_millis() / 1000.0;
+
+        if let Some(scene) = self.game.scene.as_mut() {
+            scene.draw_tick(state)?;
+            scene.tick(state, &mut self.ctx)?;
+            state.sound_manager.advance_time(tick_length)?;
+        }
+
+        self.switch_scene()
+    }
+
+    /// Ticks the game given amount of frames, applying input events scheduled for them.
+    /// Frame numbers of events are relative to this call.
+    pub fn run(&mut self, frames: usize, inputs: &[InputEvent]) -> GameResult {
+        for frame in 0..frames {
+            for input in inputs.iter().filter(|input| input.frame == frame) {
+                self.ctx.keyboard_context.set_key(input.key, input.pressed);
+            }
+
+            self.tick()?;
+        }
+
+        Ok(())
+    }
+
+    /// Draws the current frame and returns it as an image.
+    pub fn capture(&mut self) -> GameResult<RgbaImage> {
+        self.game.draw(&mut self.ctx)?;
+
+        let frame = self.frame.borrow();
+        RgbaImage::from_raw(frame.width as u32, frame.height as u32, frame.data.clone())
+            .ok_or_else(|| RenderError("Captured frame has invalid size.".to_string()))
+    }
+}
+
+/// Counts pixels which differ in any channel by more than `tolerance` between two images.
+pub fn compare_images(expected: &RgbaImage, actual: &RgbaImage, tolerance: u8) -> GameResult<usize> {
+    if expected.dimensions() != actual.dimensions() {
+        return Err(InvalidValue(format!(
+            "Image size mismatch, expected {:?}, got {:?}.",
+            expected.dimensions(),
+            actual.dimensions()
+        )));
+    }
+
+    Ok(expected
+        .pixels()
+        .zip(actual.pixels())
+        .filter(|(a, b)| a.0.iter().zip(b.0.iter()).any(|(&a, &b)| (a as i16 - b as i16).abs() > tolerance as i16))
+        .count())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::path::PathBuf;
+
+    use super::*;
+
+    struct GoldenCase {
+        name: &'static str,
+        stage: usize,
+        event: u16,
+        pos: (i32, i32),
+        frames: usize,
+        inputs: &'static [InputEvent],
+    }
+
+    /// Scenes from the data in `tests/fixtures/data`, textures and maps made from scratch for these tests.
+    static FIXTURE_CASES: [GoldenCase; 3] = [
+        GoldenCase { name: "fixture_map_name", stage: 0, event: 90, pos: (8, 12), frames: 100, inputs: &[] },
+        GoldenCase {
+            name: "fixture_walk",
+            stage: 0,
+            event: 90,
+            pos: (8, 12),
+            frames: 100,
+            inputs: &[InputEvent::press(50, ScanCode::Left), InputEvent::release(80, ScanCode::Left)],
+        },
+        GoldenCase { name: "fixture_message", stage: 0, event: 91, pos: (15, 12), frames: 100, inputs: &[] },
+    ];
+
+    /// Scenes from the freeware release of Cave Story.
+    static GAME_CASES: [GoldenCase; 3] = [
+        GoldenCase { name: "start_point", stage: 13, event: 200, pos: (10, 8), frames: 100, inputs: &[] },
+        GoldenCase {
+            name: "start_point_walk",
+            stage: 13,
+            event: 200,
+            pos: (10, 8),
+            frames: 100,
+            inputs: &[InputEvent::press(50, ScanCode::Left), InputEvent::release(80, ScanCode::Left)],
+        },
+        GoldenCase { name: "first_cave", stage: 12, event: 90, pos: (37, 11), frames: 100, inputs: &[] },
+    ];
+
+    /// Maximum difference of a channel which isn't counted as a mismatch.
+    const TOLERANCE: u8 = 2;
+
+    fn golden_tests_enabled() -> bool {
+        ["CAVESTORY_GOLDEN_TESTS", "CAVESTORY_UPDATE_GOLDEN"]
+            .iter()
+            .any(|name| env::var(name).map_or(false, |v| v == "1"))
+    }
+
+    /// Renders given scenes and compares them against PNGs in `golden_dir`, a missing image is an error
+    /// unless the goldens are being updated.
+    fn check_golden_images(data_dir: &Path, golden_dir: &Path, cases: &[GoldenCase]) {
+        let update = env::var("CAVESTORY_UPDATE_GOLDEN").map_or(false, |v| v == "1");
+        // both tests run in parallel, so they don't share the settings either
+        let user_dir = env::temp_dir().join("doukutsu-rs-golden").join(golden_dir.file_name().unwrap());
+
+        let mut failures = Vec::new();
+
+        for case in cases.iter() {
+            let mut game = HeadlessGame::new(data_dir, &user_dir, 320, 240).unwrap();
+            game.warp(case.stage, case.event, case.pos.0, case.pos.1).unwrap();
+            game.run(case.frames, case.inputs).unwrap();
+            let actual = game.capture().unwrap();
+
+            let golden_path = golden_dir.join(format!("{}.png", case.name));
+            if update {
+                std::fs::create_dir_all(golden_dir).unwrap();
+                actual.save(&golden_path).unwrap();
+                eprintln!("Saved golden image {:?}", golden_path);
+                continue;
+            }
+
+            let actual_path = user_dir.join(format!("{}.actual.png", case.name));
+            if !golden_path.exists() {
+                actual.save(&actual_path).unwrap();
+                failures.push(format!(
+                    "{}: {:?} is missing, run with CAVESTORY_UPDATE_GOLDEN=1 to create it, rendered {:?}",
+                    case.name, golden_path, actual_path
+                ));
+                continue;
+            }
+
+            let expected = image::open(&golden_path).unwrap().to_rgba8();
+            let mismatched = compare_images(&expected, &actual, TOLERANCE).unwrap();
+
+            if mismatched > 0 {
+                actual.save(&actual_path).unwrap();
+                failures.push(format!("{}: {} pixels differ, see {:?}", case.name, mismatched, actual_path));
+            }
+        }
+
+        assert!(failures.is_empty(), "Golden image mismatch:\n{}", failures.join("\n"));
+    }
+
+    /// Renders scenes of the fixture data and compares them against PNGs in `tests/golden`.
+    /// The goldens aren't part of the repository and have to be rendered locally first, so the test is opt-in:
+    /// create them with `CAVESTORY_UPDATE_GOLDEN=1` and compare against them later with `CAVESTORY_GOLDEN_TESTS=1`,
+    /// both with `cargo test golden -- --ignored`.
+    #[test]
+    #[ignore]
+    fn golden_images() {
+        if !golden_tests_enabled() {
+            eprintln!(
+                "Neither CAVESTORY_GOLDEN_TESTS nor CAVESTORY_UPDATE_GOLDEN is set, skipping golden image tests."
+            );
+            return;
+        }
+
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests");
+
+        check_golden_images(&root.join("fixtures").join("data"), &root.join("golden"), &FIXTURE_CASES);
+    }
+
+    /// Same as `golden_images`, but for the game itself. Its data is taken from `CAVESTORY_TEST_DATA_DIR`
+    /// and the test is skipped if it's not set. Renders of the game can't be redistributed, so their
+    /// goldens are kept out of the repository in `tests/golden/game`.
+    #[test]
+    #[ignore]
+    fn game_golden_images() {
+        let data_dir = match env::var("CAVESTORY_TEST_DATA_DIR") {
+            Ok(dir) => PathBuf::from(dir),
+            Err(_) => {
+                eprintln!("CAVESTORY_TEST_DATA_DIR isn't set, skipping golden image tests of the game.");
+                return;
+            }
+        };
+        let golden_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join("game");
+
+        check_golden_images(&data_dir, &golden_dir, &GAME_CASES);
+    }
+}
@@ -24,10 +24,18 @@ use crate::framework::filesystem::{mount_user_vfs, mount_vfs};
 use crate::framework::graphics;
 use crate::framework::ui::UI;
 use crate::framework::vfs::PhysicalFS;
+use crate::recorder::{RecordOptions, Recorder};
+use crate::scene::benchmark_scene::BenchmarkOptions;
 use crate::scene::loading_scene::LoadingScene;
 use crate::scene::Scene;
 use crate::shared_game_state::{SharedGameState, TimingMode};
+use crate::sound::midi::{read_midi, write_midi};
+#[cfg(feature = "ogg-export")]
+use crate::sound::org_render::render_org_to_ogg;
+use crate::sound::org_render::{render_org_to_wav, OrgRenderOptions};
+use crate::sound::organya::Song;
 use crate::texture_set::{G_MAG, I_MAG};
+use crate::validator::AssetValidator;
 
 mod bmfont;
 mod bmfont_renderer;
@@ -39,8 +47,11 @@ mod difficulty_modifier;
 mod encoding;
 mod engine_constants;
 mod entity;
+mod font_renderer;
 mod frame;
 mod framework;
+#[cfg(test)]
+mod headless;
 mod input;
 mod inventory;
 #[cfg(feature = "hooks")]
@@ -49,14 +60,20 @@ mod live_debugger;
 mod macros;
 mod map;
 mod menu;
+mod mod_loader;
 #[cfg(feature = "netplay")]
 mod netplay;
 mod npc;
+mod org_tracker;
 mod physics;
+mod pixtone_designer;
 mod player;
+mod post_process;
 mod profile;
+mod recorder;
 mod rng;
 mod scene;
+mod screenshot;
 #[cfg(feature = "scripting")]
 mod scripting;
 mod settings;
@@ -66,7 +83,11 @@ mod shared_game_state;
 mod sound;
 mod stage;
 mod text_script;
+mod texture_atlas;
 mod texture_set;
+mod tiled;
+mod ttf_renderer;
+mod validator;
 mod weapon;
 
 lazy_static! {
@@ -81,18 +102,26 @@ pub struct Game {
     last_tick: u128,
     next_tick: u128,
     loops: u64,
+    recorder: Option<Recorder>,
 }
 
 impl Game {
     fn new(ctx: &mut Context) -> GameResult<Game> {
+        let state = SharedGameState::new(ctx)?;
+
+        Game::with_state(state, ctx)
+    }
+
+    fn with_state(state: SharedGameState, ctx: &mut Context) -> GameResult<Game> {
         let s = Game {
             scene: None,
             ui: UI::new(ctx)?,
-            state: UnsafeCell::new(SharedGameState::new(ctx)?),
+            state: UnsafeCell::new(state),
             start_time: Instant::now(),
             last_tick: 0,
             next_tick: 0,
             loops: 0,
+            recorder: None,
         };
 
         Ok(s)
@@ -102,6 +131,18 @@ impl Game {
         if let Some(scene) = self.scene.as_mut() {
             let state_ref = unsafe { &mut *self.state.get() };
 
+            // recordings advance exactly one tick per drawn frame, regardless of how long it takes to write
+            if let Some(recorder) = self.recorder.as_ref() {
+                state_ref.timing_mode = recorder.timing_mode();
+                let tick_length = state_ref.timing_mode.get_delta_millis() / 1000.0;
+
+                scene.draw_tick(state_ref)?;
+                scene.tick(state_ref, ctx)?;
+                state_ref.sound_manager.advance_time(tick_length)?;
+
+                return Ok(());
+            }
+
             match state_ref.timing_mode {
                 TimingMode::_50Hz | TimingMode::_60Hz => {
                     let last_tick = self.next_tick;
@@ -129,8 +170,12 @@ impl Game {
                         self.last_tick = last_tick;
                     }
 
+                    // simulated audio clocks follow game ticks, so recordings are identical between runs
+                    let tick_length = state_ref.timing_mode.get_delta_millis() / 1000.0 / state_ref.settings.speed;
+
                     for _ in 0..self.loops {
                         scene.tick(state_ref, ctx)?;
+                        state_ref.sound_manager.advance_time(tick_length)?;
                     }
                 }
                 TimingMode::FrameSynchronized => {
@@ -144,7 +189,9 @@ impl Game {
     fn draw(&mut self, ctx: &mut Context) -> GameResult {
         let state_ref = unsafe { &mut *self.state.get() };
 
-        if state_ref.timing_mode != TimingMode::FrameSynchronized {
+        if self.recorder.is_some() {
+            state_ref.frame_time = 1.0;
+        } else if state_ref.timing_mode != TimingMode::FrameSynchronized {
             let mut elapsed = self.start_time.elapsed().as_nanos();
 
             // Even with the non-monotonic Instant mitigation at the start of the event loop, there's still a chance of it not working.
@@ -167,6 +214,14 @@ impl Game {
         graphics::clear(ctx, [0.0, 0.0, 0.0, 1.0].into());
 
         if let Some(scene) = self.scene.as_mut() {
+            // recorded frames are drawn offscreen, with their own game pixel size
+            let (scale, canvas_size) = (state_ref.scale, state_ref.canvas_size);
+            if let Some(recorder) = self.recorder.as_mut() {
+                let render_scale = recorder.render_scale(scale);
+                recorder.begin_frame(canvas_size, render_scale, ctx)?;
+                state_ref.set_draw_scale(render_scale, canvas_size);
+            }
+
             scene.draw(state_ref, ctx)?;
             if state_ref.settings.touch_controls {
                 state_ref.touch_controls.draw(
@@ -178,6 +233,29 @@ impl Game {
                 )?;
             }
 
+            // finished before the debug UI is drawn so it doesn't end up in the picture
+            if let Some(recorder) = self.recorder.as_mut() {
+                state_ref.set_draw_scale(scale, canvas_size);
+                recorder.end_frame(state_ref.screen_size, ctx)?;
+                state_ref.sound_manager.flush()?;
+
+                if recorder.is_finished() {
+                    recorder.finish()?;
+                    state_ref.sound_manager.finish_output()?;
+                    log::info!("Recording finished.");
+                    state_ref.shutdown();
+                }
+            }
+
+            graphics::apply_post_process(ctx, state_ref.scale)?;
+
+            if let Some(scale) = state_ref.screenshot_request.take() {
+                match screenshot::save_screenshot(scale, state_ref, ctx) {
+                    Ok(path) => log::info!("Saved screenshot to {}", path),
+                    Err(err) => log::error!("Failed to save screenshot: {}", err),
+                }
+            }
+
             self.ui.draw(state_ref, ctx, scene)?;
         }
 
@@ -252,15 +330,198 @@ pub fn init() -> GameResult {
             }
         }
 
-    let game = UnsafeCell::new(Game::new(&mut context)?);
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--validate") {
+        return validate_data(&mut context);
+    }
+
+    if let Some(pos) = args.iter().position(|arg| arg == "--render-org") {
+        return render_org(&mut context, &args[pos + 1..]);
+    }
+
+    if let Some(pos) = args.iter().position(|arg| arg == "--convert-org") {
+        return convert_org(&args[pos + 1..]);
+    }
+
+    let game = if let Some(pos) = args.iter().position(|arg| arg == "--record") {
+        let recorder = Recorder::new(record_options(&args[pos + 1..])?)?;
+        let state = SharedGameState::with_audio_output(recorder.audio_output(), &mut context)?;
+        let mut game = Game::with_state(state, &mut context)?;
+        game.recorder = Some(recorder);
+
+        UnsafeCell::new(game)
+    } else {
+        UnsafeCell::new(Game::new(&mut context)?)
+    };
     let state_ref = unsafe { &mut *((&mut *game.get()).state.get()) };
     #[cfg(feature = "scripting")]
     {
         state_ref.lua.update_refs(unsafe { (&*game.get()).state.get() }, &mut context as *mut Context);
     }
 
-    state_ref.next_scene = Some(Box::new(LoadingScene::new()));
+    state_ref.next_scene = Some(Box::new(match args.iter().position(|arg| arg == "--benchmark") {
+        Some(pos) => LoadingScene::with_benchmark(benchmark_options(&args[pos + 1..])?),
+        None => LoadingScene::new(),
+    }));
     context.run(unsafe { &mut *game.get() })?;
 
     Ok(())
 }
+
+/// Checks mounted game data for broken references and logs every problem found, used by `--validate`.
+fn validate_data(ctx: &mut Context) -> GameResult {
+    log::info!("Validating game data...");
+
+    let state = SharedGameState::new(ctx)?;
+    let errors = AssetValidator::new(&state).validate(ctx)?;
+
+    for error in errors.iter() {
+        log::error!("{}", error);
+    }
+
+    if errors.is_empty() {
+        log::info!("No problems found.");
+        Ok(())
+    } else {
+        Err(GameError::ResourceLoadError(format!("Found {} problems in game data.", errors.len())))
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(name: &str, value: Option<&String>) -> GameResult<T> {
+    let value = value.ok_or_else(|| GameError::InvalidValue(format!("Missing value for {}.", name)))?;
+
+    value.parse().map_err(|_| GameError::ParseError(format!("Invalid value for {}: {}", name, value)))
+}
+
+static RECORD_USAGE: &str = "Usage: --record <output dir> [--format png|y4m] [--fps 50|60] [--scale canvas|window] \
+[--frames N]";
+
+/// Parses options of `--record`, which dumps every tick of the game as a video frame along with its audio.
+fn record_options(args: &[String]) -> GameResult<RecordOptions> {
+    let mut args = args.iter();
+    let output = match args.next() {
+        Some(output) if !output.starts_with("--") => PathBuf::from(output),
+        _ => return Err(GameError::InvalidValue(RECORD_USAGE.to_string())),
+    };
+
+    let mut options = RecordOptions::new(output);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => options.format = parse_value(arg, args.next())?,
+            "--scale" => options.scale = parse_value(arg, args.next())?,
+            "--frames" => options.frames = Some(parse_value(arg, args.next())?),
+            "--fps" => {
+                options.timing_mode = match parse_value(arg, args.next())? {
+                    50 => TimingMode::_50Hz,
+                    60 => TimingMode::_60Hz,
+                    _ => return Err(GameError::InvalidValue(RECORD_USAGE.to_string())),
+                }
+            }
+            _ => return Err(GameError::InvalidValue(RECORD_USAGE.to_string())),
+        }
+    }
+
+    Ok(options)
+}
+
+static BENCHMARK_USAGE: &str = "Usage: --benchmark [--stage N] [--npcs N] [--frames N]";
+
+/// Parses options of `--benchmark`, which compares frame times of the renderer with and without batching optimizations.
+fn benchmark_options(args: &[String]) -> GameResult<BenchmarkOptions> {
+    let mut options = BenchmarkOptions::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--stage" => options.stage_id = Some(parse_value(arg, args.next())?),
+            "--npcs" => options.npc_count = parse_value(arg, args.next())?,
+            "--frames" => options.frames = parse_value(arg, args.next())?,
+            _ => return Err(GameError::InvalidValue(BENCHMARK_USAGE.to_string())),
+        }
+    }
+
+    Ok(options)
+}
+
+#[cfg(feature = "ogg-export")]
+static RENDER_ORG_USAGE: &str = "Usage: --render-org <input.org> <output.wav|output.ogg> [--loops N] \
+[--fade-out SECONDS] [--sample-rate HZ] [--wavetable doukutsu|org]";
+#[cfg(not(feature = "ogg-export"))]
+static RENDER_ORG_USAGE: &str = "Usage: --render-org <input.org> <output.wav> [--loops N] \
+[--fade-out SECONDS] [--sample-rate HZ] [--wavetable doukutsu|org]";
+
+/// Renders an Organya song to a WAV or Ogg Vorbis file, picked by extension of the output, used by `--render-org`.
+/// Ogg Vorbis output is only available with the `ogg-export` feature.
+fn render_org(ctx: &mut Context, args: &[String]) -> GameResult {
+    let mut options = OrgRenderOptions::default();
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--loops" => options.loops = parse_value(arg, args.next())?,
+            "--fade-out" => options.fade_out = parse_value(arg, args.next())?,
+            "--sample-rate" => options.sample_rate = parse_value(arg, args.next())?,
+            "--wavetable" => options.wavetable = parse_value(arg, args.next())?,
+            path => paths.push(path),
+        }
+    }
+
+    let (input, output) = match paths.as_slice() {
+        [input, output] => (input, output),
+        _ => return Err(GameError::InvalidValue(RENDER_ORG_USAGE.to_string())),
+    };
+
+    log::info!("Rendering {} to {} ({:?})...", input, output, options);
+
+    let ogg = output.to_lowercase().ends_with(".ogg");
+    #[cfg(not(feature = "ogg-export"))]
+    if ogg {
+        return Err(GameError::InvalidValue("Ogg export requires the ogg-export feature.".to_string()));
+    }
+
+    let org = std::io::BufReader::new(std::fs::File::open(input)?);
+    let out = std::io::BufWriter::new(std::fs::File::create(output)?);
+
+    if ogg {
+        #[cfg(feature = "ogg-export")]
+        render_org_to_ogg(org, out, &options, ctx)?;
+    } else {
+        render_org_to_wav(org, out, &options, ctx)?;
+    }
+
+    log::info!("Done.");
+
+    Ok(())
+}
+
+/// Converts between Organya and MIDI files, used by `--convert-org <input> <output>`.
+/// The direction is picked by file extensions, `.org` for Organya and `.mid` or `.midi` for MIDI.
+fn convert_org(args: &[String]) -> GameResult {
+    fn is_midi(path: &str) -> bool {
+        let path = path.to_lowercase();
+        path.ends_with(".mid") || path.ends_with(".midi")
+    }
+
+    let (input, output) = match args {
+        [input, output] => (input, output),
+        _ => return Err(GameError::InvalidValue("Usage: --convert-org <input> <output>".to_string())),
+    };
+
+    log::info!("Converting {} to {}...", input, output);
+
+    let reader = std::io::BufReader::new(std::fs::File::open(input)?);
+    let song = if is_midi(input) { read_midi(reader)? } else { Song::load_from(reader)? };
+
+    let writer = std::io::BufWriter::new(std::fs::File::create(output)?);
+    if is_midi(output) {
+        write_midi(&song, writer)?;
+    } else {
+        song.write_to(writer)?;
+    }
+
+    log::info!("Done.");
+
+    Ok(())
+}
@@ -3,6 +3,8 @@ use itertools::Itertools;
 
 use crate::framework::context::Context;
 use crate::framework::error::GameResult;
+use crate::org_tracker::OrgTracker;
+use crate::pixtone_designer::PixToneDesigner;
 use crate::scene::game_scene::GameScene;
 use crate::shared_game_state::SharedGameState;
 use crate::text_script::TextScriptExecutionState;
@@ -28,6 +30,8 @@ pub struct LiveDebugger {
     event_ids: Vec<(ScriptType, u16)>,
     selected_event: i32,
     text_windows: Vec<(u32, ImString, ImString)>,
+    org_tracker: OrgTracker,
+    pixtone_designer: PixToneDesigner,
     error: Option<ImString>,
 }
 
@@ -45,6 +49,8 @@ impl LiveDebugger {
             event_ids: Vec::new(),
             selected_event: -1,
             text_windows: Vec::new(),
+            org_tracker: OrgTracker::new(),
+            pixtone_designer: PixToneDesigner::new(),
             error: None,
         }
     }
@@ -83,6 +89,13 @@ impl LiveDebugger {
                     game_scene.frame.wait
                 ));
 
+                ui.text(format!(
+                    "zoom: {:.2} -> {:.2} / {}",
+                    game_scene.frame.zoom,
+                    game_scene.frame.target_zoom,
+                    game_scene.frame.zoom_wait
+                ));
+
                 ui.text(format!(
                     "NPC Count: {}/{}/{} Booster fuel: {}",
                     game_scene.npc_list.iter_alive().count(),
@@ -144,6 +157,16 @@ impl LiveDebugger {
                 if ui.button(im_str!("NPC Inspector"), [0.0, 0.0]) {
                     self.npc_inspector_visible = !self.npc_inspector_visible;
                 }
+                ui.same_line(0.0);
+
+                if ui.button(im_str!("Tracker"), [0.0, 0.0]) {
+                    self.org_tracker.visible = !self.org_tracker.visible;
+                }
+                ui.same_line(0.0);
+
+                if ui.button(im_str!("Sound Effects"), [0.0, 0.0]) {
+                    self.pixtone_designer.visible = !self.pixtone_designer.visible;
+                }
             });
 
         if self.map_selector_visible {
@@ -354,6 +377,9 @@ impl LiveDebugger {
                 });
         }
 
+        self.org_tracker.run(state, ctx, ui)?;
+        self.pixtone_designer.run(state, ctx, ui)?;
+
         let mut remove = -1;
         for (idx, (_, title, contents)) in self.text_windows.iter().enumerate() {
             let mut opened = true;
@@ -10,7 +10,7 @@ use crate::encoding::read_cur_shift_jis;
 use crate::framework::error::GameError::ResourceLoadError;
 use crate::framework::error::{GameError, GameResult};
 use crate::shared_game_state::TileSize;
-use crate::stage::{StageData, PxPackScroll, PxPackStageData};
+use crate::stage::{StageData, PxPackScroll, PxPackStageData, PxPackUnit};
 use crate::str;
 use crate::framework::filesystem;
 use crate::framework::context::Context;
@@ -97,21 +97,12 @@ impl Map {
             Ok(chars.iter().collect())
         }
 
-        fn skip_string<R: io::Read>(map_data: &mut R) -> GameResult {
-            let bytes = map_data.read_u8()? as u32;
-            for _ in 0..bytes {
-                map_data.read_u8()?;
-            }
-
-            Ok(())
-        }
-
         let map_name = read_string(&mut map_data)?;
-        skip_string(&mut map_data)?; // left, right, up, down
-        skip_string(&mut map_data)?;
-        skip_string(&mut map_data)?;
-        skip_string(&mut map_data)?;
-        skip_string(&mut map_data)?; // spritesheet
+        let map_left = read_string(&mut map_data)?;
+        let map_right = read_string(&mut map_data)?;
+        let map_up = read_string(&mut map_data)?;
+        let map_down = read_string(&mut map_data)?;
+        let spritesheet = read_string(&mut map_data)?;
 
         map_data.read_u16::<LE>()?;
         map_data.read_u16::<LE>()?;
@@ -192,6 +183,46 @@ impl Map {
             map_data.read_exact(&mut tiles[(size_fg as usize + size_mg as usize)..(size_fg as usize + size_mg as usize + size_bg as usize)])?;
         }
 
+        // There's no specification of the unit list, the record layout below is an assumption based on maps
+        // saved by pxEdit: flags (u8), unit type (u8), unknown (u8), x and y in tiles (u16 each), unknown (u16)
+        // and a length-prefixed name. Records are kept as they are, see `PxPackUnit`.
+        fn read_units<R: io::Read>(map_data: &mut R) -> GameResult<Vec<PxPackUnit>> {
+            let count = map_data.read_u16::<LE>()? as usize;
+            let mut units = Vec::with_capacity(count);
+
+            log::debug!("Unit count: {}", count);
+
+            for _ in 0..count {
+                let flags = map_data.read_u8()?;
+                let unit_type = map_data.read_u8()?;
+                map_data.read_u8()?; // unknown
+                let x = map_data.read_u16::<LE>()?;
+                let y = map_data.read_u16::<LE>()?;
+                map_data.read_u16::<LE>()?; // unknown
+                read_string(map_data)?; // unit name, unused by the engine
+
+                units.push(PxPackUnit { flags, unit_type, x, y });
+            }
+
+            Ok(units)
+        }
+
+        // The layout is a guess, so units are read on a best-effort basis and a map with a unit list
+        // we can't make sense of still loads, just without units.
+        let units = match map_data.read_exact(&mut magic) {
+            Ok(()) if magic.starts_with(b"pxUNIT") => match read_units(&mut map_data) {
+                Ok(units) => units,
+                Err(err) => {
+                    log::warn!("Failed to read PxPack unit list, units will be skipped: {}", err);
+                    Vec::new()
+                }
+            },
+            _ => {
+                log::warn!("PxPack map has no unit list.");
+                Vec::new()
+            }
+        };
+
         if let Ok(mut attrib_data) = filesystem::open(ctx, [root, "Stage/", &tileset_fg, ".pxa"].join("")) {
             if attrib_data.read_exact(&mut attrib).is_err() {
                 log::warn!("Map attribute data is shorter than 256 bytes!");
@@ -269,7 +300,14 @@ impl Map {
             size_mg: (width_mg, height_mg),
             size_bg: (width_bg, height_bg),
             offset_mg: size_fg,
-            offset_bg: size_fg + size_mg
+            offset_bg: size_fg + size_mg,
+            map_left,
+            map_right,
+            map_up,
+            map_down,
+            spritesheet,
+            background_color: bg_color,
+            units,
         });
 
         Ok(Map { width: width_fg, height: height_fg, tiles, attrib, tile_size: TileSize::Tile8x8 })
@@ -439,7 +477,7 @@ impl Map {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct NPCData {
     pub id: u16,
     pub x: i16,
@@ -252,6 +252,28 @@ impl Menu {
                         ctx,
                     )?;
                 }
+                MenuEntry::Options(name, index, options) => {
+                    let value_text = options.get(*index).map(|s| s.as_str()).unwrap_or("");
+                    let val_text_len = state.font.text_width(value_text.chars(), &state.constants);
+
+                    state.font.draw_text(
+                        name.chars(),
+                        self.x as f32 + 20.0,
+                        y,
+                        &state.constants,
+                        &mut state.texture_set,
+                        ctx,
+                    )?;
+
+                    state.font.draw_text(
+                        value_text.chars(),
+                        self.x as f32 + self.width as f32 - val_text_len,
+                        y,
+                        &state.constants,
+                        &mut state.texture_set,
+                        ctx,
+                    )?;
+                }
                 MenuEntry::Hidden => {}
                 _ => {}
             }
@@ -295,6 +317,9 @@ impl Menu {
                         MenuEntry::Toggle(_, _) => {
                             break;
                         }
+                        MenuEntry::Options(_, _, _) => {
+                            break;
+                        }
                         _ => {}
                     }
                 } else {
@@ -320,13 +345,13 @@ impl Menu {
                     state.sound_manager.play_sfx(18);
                     return MenuSelectionResult::Selected(idx, entry);
                 }
-                MenuEntry::Options(_, _, _) if controller.trigger_left() => {
+                MenuEntry::Options(_, _, _) if self.selected == idx && controller.trigger_left() => {
                     state.sound_manager.play_sfx(1);
-                    return MenuSelectionResult::Left(self.selected, entry);
+                    return MenuSelectionResult::Left(idx, entry);
                 }
-                MenuEntry::Options(_, _, _) if controller.trigger_right() => {
+                MenuEntry::Options(_, _, _) if self.selected == idx && controller.trigger_right() => {
                     state.sound_manager.play_sfx(1);
-                    return MenuSelectionResult::Right(self.selected, entry);
+                    return MenuSelectionResult::Right(idx, entry);
                 }
                 _ => {}
             }
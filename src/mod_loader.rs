@@ -0,0 +1,187 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::engine_constants::EngineConstants;
+use crate::framework::context::Context;
+use crate::framework::error::{GameError, GameResult};
+use crate::framework::filesystem;
+use crate::framework::vfs::PhysicalFS;
+use crate::settings::Settings;
+
+/// Base game data a mod can be made for, as detected by `BaseGame::detect`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BaseGame {
+    #[serde(rename = "freeware")]
+    Freeware,
+    #[serde(rename = "csplus")]
+    CSPlus,
+    #[serde(rename = "switch")]
+    Switch,
+}
+
+impl BaseGame {
+    pub fn detect(ctx: &Context) -> BaseGame {
+        if filesystem::exists(ctx, "/base/Nicalis.bmp") {
+            BaseGame::CSPlus
+        } else if filesystem::exists(ctx, "/base/lighting.tbl") {
+            BaseGame::Switch
+        } else {
+            BaseGame::Freeware
+        }
+    }
+}
+
+/// Contents of `mod.yml` placed in the root directory of a mod.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModManifest {
+    pub name: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub author: String,
+    /// Base games this mod works with, any base game is accepted if empty.
+    #[serde(default)]
+    pub requires: Vec<BaseGame>,
+    /// Mods with higher load order are mounted later and take precedence over the ones loaded before.
+    #[serde(default)]
+    pub load_order: i32,
+    /// Lua scripts loaded after the base game scripts, relative to mod directory.
+    #[serde(default)]
+    pub scripts: Vec<String>,
+    /// Engine constants overrides, in the same format as `engine_constants.yml`.
+    #[serde(default)]
+    pub constants: Value,
+}
+
+pub struct ModInfo {
+    /// Name of the directory the mod is located in, used to refer to the mod in settings.
+    pub id: String,
+    pub path: PathBuf,
+    pub manifest: ModManifest,
+}
+
+impl ModInfo {
+    pub fn is_compatible(&self, base_game: BaseGame) -> bool {
+        self.manifest.requires.is_empty() || self.manifest.requires.contains(&base_game)
+    }
+
+    /// Returns the path to given file inside mod directory, relative to user data directory.
+    pub fn user_path(&self, file: &str) -> String {
+        format!("/mods/{}/{}", self.id, file.trim_start_matches('/'))
+    }
+}
+
+pub struct ModList {
+    pub mods: Vec<ModInfo>,
+    mounted: usize,
+}
+
+impl ModList {
+    pub fn new() -> ModList {
+        ModList { mods: Vec::new(), mounted: 0 }
+    }
+
+    /// Looks up all mods in `mods` directory of user data, sorted by their load order.
+    pub fn scan(&mut self, ctx: &Context) -> GameResult {
+        self.mods.clear();
+
+        if !filesystem::user_is_dir(ctx, "/mods/") {
+            return Ok(());
+        }
+
+        for dir in filesystem::user_read_dir(ctx, "/mods/")? {
+            let id = match dir.file_name() {
+                Some(name) => name.to_string_lossy().to_string(),
+                None => continue,
+            };
+
+            let manifest_path = dir.join("mod.yml");
+            let file = match filesystem::user_open(ctx, &manifest_path) {
+                Ok(file) => file,
+                Err(_) => continue,
+            };
+
+            let manifest = match serde_yaml::from_reader::<_, ModManifest>(file) {
+                Ok(manifest) => manifest,
+                Err(err) => {
+                    log::warn!("Failed to parse manifest of mod {}: {}", id, err);
+                    continue;
+                }
+            };
+
+            let path = match filesystem::user_physical_path(ctx, &dir) {
+                Some(path) => path,
+                None => {
+                    log::warn!("Mod {} is not located on a physical filesystem, skipping.", id);
+                    continue;
+                }
+            };
+
+            self.mods.push(ModInfo { id, path, manifest });
+        }
+
+        self.mods.sort_by(|a, b| a.manifest.load_order.cmp(&b.manifest.load_order).then_with(|| a.id.cmp(&b.id)));
+
+        Ok(())
+    }
+
+    /// Iterates over mods that are enabled in settings and compatible with detected base game, in load order.
+    pub fn enabled<'a>(&'a self, settings: &'a Settings, base_game: BaseGame) -> impl Iterator<Item = &'a ModInfo> {
+        self.mods.iter().filter(move |m| m.is_compatible(base_game) && settings.enabled_mods.contains(&m.id))
+    }
+
+    /// Mounts enabled mods ahead of base game data, replacing previously mounted ones.
+    pub fn mount(&mut self, ctx: &mut Context, settings: &Settings, base_game: BaseGame) {
+        self.unmount(ctx);
+
+        let paths: Vec<PathBuf> = self
+            .enabled(settings, base_game)
+            .map(|info| {
+                log::info!("Loading mod: {} {} ({})", info.manifest.name, info.manifest.version, info.id);
+                info.path.clone()
+            })
+            .collect();
+
+        for path in paths {
+            filesystem::mount_vfs_front(ctx, Box::new(PhysicalFS::new(&path, true)));
+            self.mounted += 1;
+        }
+    }
+
+    pub fn unmount(&mut self, ctx: &mut Context) {
+        while self.mounted > 0 {
+            filesystem::unmount_vfs_front(ctx);
+            self.mounted -= 1;
+        }
+    }
+
+    /// Applies engine constants overrides of enabled mods, both the ones defined in manifest
+    /// and in `engine_constants.json` / `engine_constants.yml` placed in mod directory.
+    pub fn apply_constants(
+        &self,
+        constants: &mut EngineConstants,
+        ctx: &Context,
+        settings: &Settings,
+        base_game: BaseGame,
+    ) {
+        for info in self.enabled(settings, base_game) {
+            if let Err(err) = constants.apply_overrides(&info.manifest.constants) {
+                log::warn!("Failed to apply engine constants of mod {}: {}", info.id, err);
+            }
+
+            let overrides = if let Ok(file) = filesystem::user_open(ctx, info.user_path("engine_constants.json")) {
+                serde_json::from_reader::<_, Value>(file).map_err(GameError::from)
+            } else if let Ok(file) = filesystem::user_open(ctx, info.user_path("engine_constants.yml")) {
+                serde_yaml::from_reader::<_, Value>(file).map_err(GameError::from)
+            } else {
+                continue;
+            };
+
+            if let Err(err) = overrides.and_then(|overrides| constants.apply_overrides(&overrides)) {
+                log::warn!("Failed to apply engine constants of mod {}: {}", info.id, err);
+            }
+        }
+    }
+}
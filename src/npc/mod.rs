@@ -680,6 +680,10 @@ impl NPCTable {
         self.entries.get(npc_type as usize)
     }
 
+    pub fn entries(&self) -> &[NPCTableEntry] {
+        &self.entries
+    }
+
     pub fn get_display_bounds(&self, npc_type: u16) -> Rect<u32> {
         if let Some(npc) = self.entries.get(npc_type as usize) {
             Rect {
@@ -0,0 +1,479 @@
+use std::io::BufWriter;
+
+use imgui::{im_str, ChildWindow, Condition, ImString, MouseButton, Slider, Window};
+
+use crate::framework::context::Context;
+use crate::framework::error::GameResult;
+use crate::framework::filesystem;
+use crate::shared_game_state::SharedGameState;
+use crate::sound::midi::write_midi;
+use crate::sound::organya::{Note, Song};
+use crate::sound::SoundManager;
+
+const KEY_COUNT: i32 = 96;
+const KEY_HEIGHT: f32 = 6.0;
+const RULER_HEIGHT: f32 = 12.0;
+/// Pan value of newly placed notes, the middle of 0-12 range.
+const DEFAULT_PAN: u8 = 6;
+
+/// Piano roll editor for Organya songs, edits are heard immediately if the song is playing.
+pub struct OrgTracker {
+    pub visible: bool,
+    song: Option<Song>,
+    song_id: usize,
+    selected_track: usize,
+    muted_tracks: u16,
+    solo_tracks: u16,
+    sent_muted_tracks: u16,
+    follow_playhead: bool,
+    zoom: f32,
+    note_length: i32,
+    note_volume: i32,
+    modified: bool,
+    status: Option<ImString>,
+}
+
+impl OrgTracker {
+    pub fn new() -> OrgTracker {
+        OrgTracker {
+            visible: false,
+            song: None,
+            song_id: usize::MAX,
+            selected_track: 0,
+            muted_tracks: 0,
+            solo_tracks: 0,
+            sent_muted_tracks: 0,
+            follow_playhead: true,
+            zoom: 4.0,
+            note_length: 1,
+            note_volume: 200,
+            modified: false,
+            status: None,
+        }
+    }
+
+    fn muted_mask(&self) -> u16 {
+        if self.solo_tracks != 0 {
+            !self.solo_tracks
+        } else {
+            self.muted_tracks
+        }
+    }
+
+    fn song_name(&self, state: &SharedGameState) -> String {
+        match state.constants.music_table.get(self.song_id) {
+            Some(name) if !name.is_empty() => name.clone(),
+            _ => format!("song{}", self.song_id),
+        }
+    }
+
+    fn load_current_song(&mut self, state: &mut SharedGameState, ctx: &mut Context) {
+        self.song_id = state.sound_manager.current_song();
+        self.modified = false;
+        self.status = None;
+
+        match SoundManager::load_organya_song(self.song_id, &state.constants, &state.settings, ctx) {
+            Ok(Some(song)) => self.song = Some(song),
+            Ok(None) => {
+                self.song = None;
+                self.status = Some(ImString::new(format!("Song #{} isn't played from an .org file.", self.song_id)));
+            }
+            Err(err) => {
+                self.song = None;
+                self.status = Some(ImString::new(format!("Failed to load song #{}: {}", self.song_id, err)));
+            }
+        }
+    }
+
+    fn song_edited(&mut self, state: &mut SharedGameState) {
+        self.modified = true;
+
+        if state.sound_manager.current_song() != self.song_id {
+            return;
+        }
+
+        if let Some(song) = &self.song {
+            if let Err(err) = state.sound_manager.update_organya_song(song) {
+                log::warn!("Failed to update Organya song: {}", err);
+            }
+        }
+    }
+
+    fn save(&mut self, state: &SharedGameState, ctx: &mut Context) -> GameResult<String> {
+        let song = match &self.song {
+            Some(song) => song,
+            None => return Ok("Nothing to save.".to_string()),
+        };
+
+        let name = self.song_name(state);
+        let org_path = format!("/organya/{}.org", name);
+        let mid_path = format!("/organya/{}.mid", name);
+
+        filesystem::user_create_dir(ctx, "/organya")?;
+        song.write_to(BufWriter::new(filesystem::user_create(ctx, &org_path)?))?;
+        write_midi(song, BufWriter::new(filesystem::user_create(ctx, &mid_path)?))?;
+
+        self.modified = false;
+
+        Ok(match filesystem::user_physical_path(ctx, &org_path) {
+            Some(path) => {
+                format!("Saved {}.org and {}.mid to {}", name, name, path.parent().unwrap_or(&path).display())
+            }
+            None => format!("Saved to {} and {}", org_path, mid_path),
+        })
+    }
+
+    pub fn run(&mut self, state: &mut SharedGameState, ctx: &mut Context, ui: &imgui::Ui) -> GameResult {
+        let muted_tracks = if self.visible { self.muted_mask() } else { 0 };
+        if muted_tracks != self.sent_muted_tracks {
+            self.sent_muted_tracks = muted_tracks;
+            state.sound_manager.set_organya_muted_tracks(muted_tracks)?;
+        }
+
+        if !self.visible {
+            return Ok(());
+        }
+
+        // follow the game unless there are unsaved edits
+        if !self.modified && state.sound_manager.current_song() != self.song_id {
+            self.load_current_song(state, ctx);
+        }
+
+        let mut visible = self.visible;
+        let mut edited = false;
+        let mut reload = false;
+        let mut save = false;
+        let mut seek = None;
+
+        let playing = state.sound_manager.current_song() == self.song_id;
+        let play_pos = if playing { state.sound_manager.organya_position() } else { None };
+        let drum_count = state.sound_manager.organya_drum_count();
+        let song_name = self.song_name(state);
+        let OrgTracker {
+            song,
+            song_id,
+            selected_track,
+            muted_tracks,
+            solo_tracks,
+            follow_playhead,
+            zoom,
+            note_length,
+            note_volume,
+            modified,
+            status,
+            ..
+        } = self;
+
+        Window::new(im_str!("Organya Tracker"))
+            .position([100.0, 100.0], Condition::FirstUseEver)
+            .size([640.0, 480.0], Condition::FirstUseEver)
+            .opened(&mut visible)
+            .build(ui, || {
+                ui.text(format!(
+                    "Song #{}: {}{}{}",
+                    *song_id,
+                    song_name,
+                    if *modified { " (modified)" } else { "" },
+                    if playing { "" } else { " (not playing)" }
+                ));
+
+                if ui.button(im_str!("Reload"), [0.0, 0.0]) {
+                    reload = true;
+                }
+
+                ui.same_line(0.0);
+                if ui.button(im_str!("Save .org/.mid"), [0.0, 0.0]) {
+                    save = true;
+                }
+
+                if let Some(status) = status {
+                    ui.text_wrapped(status);
+                }
+
+                let song = match song {
+                    Some(song) => song,
+                    None => return,
+                };
+
+                let mut wait = song.time.wait as i32;
+                ui.set_next_item_width(100.0);
+                if ui.input_int(im_str!("Wait (ms)"), &mut wait).build() {
+                    song.time.wait = wait.max(1).min(2000) as u16;
+                    edited = true;
+                }
+
+                ui.same_line(0.0);
+                let mut loop_start = song.time.loop_range.start;
+                ui.set_next_item_width(100.0);
+                if ui.input_int(im_str!("Loop start"), &mut loop_start).build() {
+                    song.time.loop_range.start = loop_start.max(0).min(song.time.loop_range.end - 1);
+                    edited = true;
+                }
+
+                ui.same_line(0.0);
+                let mut loop_end = song.time.loop_range.end;
+                ui.set_next_item_width(100.0);
+                if ui.input_int(im_str!("Loop end"), &mut loop_end).build() {
+                    song.time.loop_range.end = loop_end.max(song.time.loop_range.start + 1);
+                    edited = true;
+                }
+
+                ui.checkbox(im_str!("Follow playhead"), follow_playhead);
+                ui.same_line(0.0);
+                ui.set_next_item_width(100.0);
+                Slider::new(im_str!("Zoom")).range(1.0..=16.0).build(ui, zoom);
+                ui.same_line(0.0);
+                ui.set_next_item_width(80.0);
+                if ui.input_int(im_str!("Length"), note_length).build() {
+                    *note_length = (*note_length).max(1).min(255);
+                }
+                ui.same_line(0.0);
+                ui.set_next_item_width(80.0);
+                if ui.input_int(im_str!("Volume"), note_volume).build() {
+                    *note_volume = (*note_volume).max(0).min(254);
+                }
+
+                let mut drum_edit = None;
+                ChildWindow::new(im_str!("tracks")).size([0.0, 160.0]).border(true).build(ui, || {
+                    for (i, track) in song.tracks.iter_mut().enumerate() {
+                        let id = ui.push_id(i as i32);
+                        let label = if i < 8 {
+                            ImString::new(format!("Melody {}", i + 1))
+                        } else {
+                            ImString::new(format!("Drum {}", i - 7))
+                        };
+
+                        if ui.radio_button_bool(&label, *selected_track == i) {
+                            *selected_track = i;
+                        }
+
+                        ui.same_line(100.0);
+                        ui.checkbox_flags(im_str!("M"), muted_tracks, 1 << i);
+                        ui.same_line(0.0);
+                        ui.checkbox_flags(im_str!("S"), solo_tracks, 1 << i);
+
+                        ui.same_line(0.0);
+                        let mut inst = track.inst.inst as i32;
+                        ui.set_next_item_width(80.0);
+                        if ui.input_int(im_str!("Inst"), &mut inst).build() {
+                            if i < 8 {
+                                track.inst.inst = inst.max(0).min(99) as u8;
+                            } else {
+                                // applied after the loop, the song has to be upgraded first
+                                drum_edit = Some((i, inst.max(0).min(drum_count.max(1) as i32 - 1).min(255) as u8));
+                            }
+                            edited = true;
+                        }
+
+                        ui.same_line(0.0);
+                        let mut freq = track.inst.freq as i32;
+                        ui.set_next_item_width(80.0);
+                        if ui.input_int(im_str!("Freq"), &mut freq).build() {
+                            track.inst.freq = freq.max(100).min(1900) as u16;
+                            edited = true;
+                        }
+
+                        if i < 8 {
+                            ui.same_line(0.0);
+                            let mut pipi = track.inst.pipi != 0;
+                            if ui.checkbox(im_str!("Pi"), &mut pipi) {
+                                track.inst.pipi = pipi as u8;
+                                edited = true;
+                            }
+                        }
+
+                        ui.same_line(0.0);
+                        ui.text(format!("{} notes", track.notes.len()));
+
+                        id.pop(ui);
+                    }
+                });
+
+                // only extended songs pick their own drum samples, others would ignore the edit
+                if let Some((i, inst)) = drum_edit {
+                    song.upgrade_to_extended();
+                    song.tracks[i].inst.inst = inst;
+                }
+
+                let measure = (song.display.beats.max(1) as i32) * (song.display.steps.max(1) as i32);
+                let last_note = song.tracks.iter().filter_map(|t| t.notes.last()).map(|n| n.pos + n.len as i32).max();
+                let length = (last_note.unwrap_or(0).max(song.time.loop_range.end) / measure + 2) * measure;
+
+                ChildWindow::new(im_str!("piano_roll")).size([0.0, 0.0]).border(true).horizontal_scrollbar(true).build(
+                    ui,
+                    || {
+                        let zoom = *zoom;
+                        let origin = ui.cursor_screen_pos();
+                        let roll_y = origin[1] + RULER_HEIGHT;
+                        let width = length as f32 * zoom;
+                        let view_x = ui.scroll_x();
+                        let view_width = ui.window_size()[0];
+
+                        if let (true, Some(pos)) = (*follow_playhead, play_pos) {
+                            let x = pos as f32 * zoom;
+                            if x < view_x || x > view_x + view_width * 0.75 {
+                                ui.set_scroll_x((x - view_width * 0.25).max(0.0));
+                            }
+                        }
+
+                        ui.invisible_button(im_str!("canvas"), [width, RULER_HEIGHT + KEY_COUNT as f32 * KEY_HEIGHT]);
+
+                        if ui.is_item_hovered() {
+                            let [mouse_x, mouse_y] = ui.io().mouse_pos;
+                            let tick = ((mouse_x - origin[0]) / zoom).floor() as i32;
+                            let key = KEY_COUNT - 1 - ((mouse_y - roll_y) / KEY_HEIGHT).floor() as i32;
+                            let notes = &mut song.tracks[*selected_track].notes;
+
+                            if mouse_y < roll_y {
+                                if ui.is_mouse_clicked(MouseButton::Left) {
+                                    seek = Some(tick.max(0));
+                                }
+                            } else if (0..KEY_COUNT).contains(&key) && tick >= 0 {
+                                if ui.is_mouse_clicked(MouseButton::Left) {
+                                    let note = Note {
+                                        pos: tick,
+                                        key: key as u8,
+                                        len: *note_length as u8,
+                                        vol: *note_volume as u8,
+                                        pan: DEFAULT_PAN,
+                                    };
+
+                                    // one event per tick, keep them sorted like OrgMaker does
+                                    match notes.binary_search_by_key(&tick, |n| n.pos) {
+                                        Ok(idx) => notes[idx] = note,
+                                        Err(idx) => notes.insert(idx, note),
+                                    }
+                                    edited = true;
+                                } else if ui.is_mouse_clicked(MouseButton::Right) {
+                                    if let Some(idx) = notes.iter().position(|n| {
+                                        n.key as i32 == key && tick >= n.pos && tick < n.pos + (n.len as i32).max(1)
+                                    }) {
+                                        notes.remove(idx);
+                                        edited = true;
+                                    }
+                                }
+                            }
+                        }
+
+                        let draw_list = ui.get_window_draw_list();
+                        let left = origin[0] + view_x;
+                        let right = (left + view_width).min(origin[0] + width);
+                        let tick_x = |tick: i32| origin[0] + tick as f32 * zoom;
+
+                        for key in 0..KEY_COUNT {
+                            let y = roll_y + (KEY_COUNT - 1 - key) as f32 * KEY_HEIGHT;
+                            let color = match key % 12 {
+                                1 | 3 | 6 | 8 | 10 => [0.10, 0.10, 0.12, 1.0],
+                                _ => [0.16, 0.16, 0.18, 1.0],
+                            };
+                            draw_list.add_rect([left, y], [right, y + KEY_HEIGHT], color).filled(true).build();
+
+                            if key % 12 == 0 {
+                                draw_list
+                                    .add_line([left, y + KEY_HEIGHT], [right, y + KEY_HEIGHT], [0.35, 0.35, 0.40, 1.0])
+                                    .build();
+                            }
+                        }
+
+                        let first_tick = (view_x / zoom) as i32;
+                        let last_tick = ((view_x + view_width) / zoom) as i32 + 1;
+                        let beat = song.display.steps.max(1) as i32;
+                        for tick in (first_tick - first_tick % beat..last_tick.min(length)).step_by(beat as usize) {
+                            let color =
+                                if tick % measure == 0 { [0.5, 0.5, 0.55, 1.0] } else { [0.25, 0.25, 0.3, 1.0] };
+                            draw_list
+                                .add_line(
+                                    [tick_x(tick), origin[1]],
+                                    [tick_x(tick), roll_y + KEY_COUNT as f32 * KEY_HEIGHT],
+                                    color,
+                                )
+                                .build();
+
+                            if tick % measure == 0 {
+                                draw_list.add_text(
+                                    [tick_x(tick) + 2.0, origin[1]],
+                                    [0.8, 0.8, 0.8, 1.0],
+                                    format!("{}", tick / measure),
+                                );
+                            }
+                        }
+
+                        for (i, track) in song.tracks.iter().enumerate() {
+                            let selected = i == *selected_track;
+                            for note in track.notes.iter() {
+                                if note.key == 255 || note.pos > last_tick || note.pos + (note.len as i32) < first_tick
+                                {
+                                    continue;
+                                }
+
+                                let y = roll_y + (KEY_COUNT - 1 - note.key as i32) as f32 * KEY_HEIGHT;
+                                let x2 = tick_x(note.pos + (note.len as i32).max(1));
+                                let color = if selected {
+                                    let vol = if note.vol == 255 { 1.0 } else { 0.4 + note.vol as f32 / 254.0 * 0.6 };
+                                    [1.0 * vol, 0.45 * vol, 0.2 * vol, 1.0]
+                                } else {
+                                    [0.45, 0.45, 0.5, 0.5]
+                                };
+
+                                draw_list
+                                    .add_rect([tick_x(note.pos), y], [x2 - 1.0, y + KEY_HEIGHT - 1.0], color)
+                                    .filled(true)
+                                    .build();
+                            }
+                        }
+
+                        let bottom = roll_y + KEY_COUNT as f32 * KEY_HEIGHT;
+                        let loop_range = song.time.loop_range;
+                        draw_list
+                            .add_line(
+                                [tick_x(loop_range.start), origin[1]],
+                                [tick_x(loop_range.start), bottom],
+                                [0.2, 0.9, 0.3, 1.0],
+                            )
+                            .thickness(2.0)
+                            .build();
+                        draw_list
+                            .add_line(
+                                [tick_x(loop_range.end), origin[1]],
+                                [tick_x(loop_range.end), bottom],
+                                [0.9, 0.2, 0.2, 1.0],
+                            )
+                            .thickness(2.0)
+                            .build();
+
+                        if let Some(pos) = play_pos {
+                            draw_list
+                                .add_line([tick_x(pos), origin[1]], [tick_x(pos), bottom], [1.0, 0.9, 0.2, 1.0])
+                                .thickness(2.0)
+                                .build();
+                        }
+                    },
+                );
+            });
+
+        self.visible = visible;
+
+        if reload {
+            self.load_current_song(state, ctx);
+        }
+
+        if edited {
+            self.song_edited(state);
+        }
+
+        if let Some(pos) = seek {
+            if playing {
+                state.sound_manager.set_organya_position(pos)?;
+            }
+        }
+
+        if save {
+            self.status = Some(ImString::new(match self.save(state, ctx) {
+                Ok(message) => message,
+                Err(err) => format!("Failed to save the song: {}", err),
+            }));
+        }
+
+        Ok(())
+    }
+}
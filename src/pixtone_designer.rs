@@ -0,0 +1,237 @@
+use std::io::BufWriter;
+
+use imgui::{im_str, CollapsingHeader, Condition, ImStr, ImString, Slider, Window};
+
+use crate::framework::context::Context;
+use crate::framework::error::GameResult;
+use crate::framework::filesystem;
+use crate::shared_game_state::SharedGameState;
+use crate::sound::pixtone::{Channel, PixToneParameters, Waveform};
+
+static WAVEFORM_NAMES: [&str; 6] = ["Sine", "Triangle", "Saw up", "Saw down", "Square", "Noise"];
+
+/// Number of points the synthesized sound is reduced to for the preview plot.
+const PLOT_POINTS: usize = 512;
+
+/// Editor for PixTone sound effects, changes replace the sound in game until it's reverted or data is reloaded.
+pub struct PixToneDesigner {
+    pub visible: bool,
+    sound_id: i32,
+    loaded_id: i32,
+    params: PixToneParameters,
+    original: PixToneParameters,
+    live_preview: bool,
+    plot: Vec<f32>,
+    status: Option<ImString>,
+}
+
+impl PixToneDesigner {
+    pub fn new() -> PixToneDesigner {
+        PixToneDesigner {
+            visible: false,
+            sound_id: 1,
+            loaded_id: -1,
+            params: PixToneParameters::empty(),
+            original: PixToneParameters::empty(),
+            live_preview: true,
+            plot: Vec::new(),
+            status: None,
+        }
+    }
+
+    fn load(&mut self, state: &SharedGameState) {
+        self.loaded_id = self.sound_id;
+        self.params = state.sound_manager.sample_params(self.sound_id as u8);
+        self.original = self.params;
+        self.status = None;
+        self.update_plot();
+    }
+
+    fn update_plot(&mut self) {
+        let samples = self.params.synth();
+        let step = (samples.len() / PLOT_POINTS).max(1);
+
+        self.plot = samples
+            .chunks(step)
+            .map(|chunk| chunk.iter().map(|&s| (s as f32).abs()).fold(0.0, f32::max) / 32768.0)
+            .collect();
+    }
+
+    fn apply(&self, state: &mut SharedGameState, play: bool) -> GameResult {
+        state.sound_manager.set_sample_params(self.sound_id as u8, self.params)?;
+
+        if play {
+            state.sound_manager.play_sfx(self.sound_id as u8);
+        }
+
+        Ok(())
+    }
+
+    fn export(&self, ctx: &mut Context) -> GameResult<String> {
+        let pxt_path = format!("/pxt/fx{:02x}.pxt", self.sound_id);
+        let wav_path = format!("/pxt/fx{:02x}.wav", self.sound_id);
+
+        filesystem::user_create_dir(ctx, "/pxt")?;
+        self.params.write_to(BufWriter::new(filesystem::user_create(ctx, &pxt_path)?))?;
+        self.params.write_wav(BufWriter::new(filesystem::user_create(ctx, &wav_path)?))?;
+
+        Ok(match filesystem::user_physical_path(ctx, &pxt_path) {
+            Some(path) => format!("Exported to {} and .wav", path.display()),
+            None => format!("Exported to {} and {}", pxt_path, wav_path),
+        })
+    }
+
+    pub fn run(&mut self, state: &mut SharedGameState, ctx: &mut Context, ui: &imgui::Ui) -> GameResult {
+        if !self.visible {
+            return Ok(());
+        }
+
+        if self.loaded_id != self.sound_id {
+            self.load(state);
+        }
+
+        let mut visible = self.visible;
+        let mut edited = false;
+        let mut play = false;
+        let mut revert = false;
+        let mut export = false;
+
+        let PixToneDesigner { sound_id, params, live_preview, plot, status, .. } = self;
+
+        Window::new(im_str!("PixTone Designer"))
+            .position([120.0, 120.0], Condition::FirstUseEver)
+            .size([420.0, 480.0], Condition::FirstUseEver)
+            .opened(&mut visible)
+            .build(ui, || {
+                ui.set_next_item_width(100.0);
+                if ui.input_int(im_str!("Sound ID"), sound_id).build() {
+                    *sound_id = (*sound_id).max(0).min(255);
+                }
+
+                ui.same_line(0.0);
+                if ui.button(im_str!("Play"), [0.0, 0.0]) {
+                    play = true;
+                }
+
+                ui.same_line(0.0);
+                if ui.button(im_str!("Revert"), [0.0, 0.0]) {
+                    revert = true;
+                }
+
+                ui.same_line(0.0);
+                if ui.button(im_str!("Export"), [0.0, 0.0]) {
+                    export = true;
+                }
+
+                ui.checkbox(im_str!("Play on change"), live_preview);
+
+                if let Some(status) = status {
+                    ui.text_wrapped(status);
+                }
+
+                ui.plot_lines(im_str!(""), &plot[..]).scale_min(0.0).scale_max(1.0).graph_size([0.0, 60.0]).build();
+
+                for (i, channel) in params.channels.iter_mut().enumerate() {
+                    let id = ui.push_id(i as i32);
+                    let label = ImString::new(format!("Channel {}", i + 1));
+
+                    if CollapsingHeader::new(&label).default_open(i == 0).build(ui) {
+                        edited |= channel_editor(ui, channel);
+                    }
+
+                    id.pop(ui);
+                }
+            });
+
+        self.visible = visible;
+
+        if revert {
+            self.params = self.original;
+            edited = true;
+        }
+
+        if edited {
+            self.update_plot();
+        }
+
+        if edited || play {
+            self.apply(state, play || (edited && self.live_preview))?;
+        }
+
+        if export {
+            self.status = Some(ImString::new(match self.export(ctx) {
+                Ok(message) => message,
+                Err(err) => format!("Failed to export the sound: {}", err),
+            }));
+        }
+
+        Ok(())
+    }
+}
+
+fn channel_editor(ui: &imgui::Ui, channel: &mut Channel) -> bool {
+    let mut edited = ui.checkbox(im_str!("Enabled"), &mut channel.enabled);
+
+    let mut length = channel.length as i32;
+    ui.set_next_item_width(120.0);
+    if ui.input_int(im_str!("Length"), &mut length).build() {
+        channel.length = length.max(0).min(1_000_000) as u32;
+        edited = true;
+    }
+
+    edited |= waveform_editor(ui, im_str!("Carrier"), &mut channel.carrier);
+    edited |= waveform_editor(ui, im_str!("Frequency"), &mut channel.frequency);
+    edited |= waveform_editor(ui, im_str!("Amplitude"), &mut channel.amplitude);
+
+    ui.text("Envelope");
+    let envelope = &mut channel.envelope;
+    edited |= Slider::new(im_str!("Initial")).range(0..=127).build(ui, &mut envelope.initial);
+    for (name, time, value) in [
+        (im_str!("A"), &mut envelope.time_a, &mut envelope.value_a),
+        (im_str!("B"), &mut envelope.time_b, &mut envelope.value_b),
+        (im_str!("C"), &mut envelope.time_c, &mut envelope.value_c),
+    ]
+    .iter_mut()
+    {
+        let id = ui.push_id(*name);
+        ui.set_next_item_width(120.0);
+        edited |= Slider::new(im_str!("Time")).range(0..=255).build(ui, *time);
+        ui.same_line(0.0);
+        ui.set_next_item_width(120.0);
+        edited |= Slider::new(*name).range(0..=127).build(ui, *value);
+        id.pop(ui);
+    }
+
+    edited
+}
+
+fn waveform_editor(ui: &imgui::Ui, name: &ImStr, waveform: &mut Waveform) -> bool {
+    let mut edited = false;
+    let id = ui.push_id(name);
+
+    ui.text(name);
+    for (i, waveform_name) in WAVEFORM_NAMES.iter().enumerate() {
+        if i != 0 {
+            ui.same_line(0.0);
+        }
+
+        edited |= ui.radio_button(&ImString::new(*waveform_name), &mut waveform.waveform_type, i as u8);
+    }
+
+    ui.set_next_item_width(100.0);
+    edited |= ui.input_float(im_str!("Pitch"), &mut waveform.pitch).build();
+    ui.same_line(0.0);
+    ui.set_next_item_width(100.0);
+    edited |= ui.input_int(im_str!("Level"), &mut waveform.level).build();
+    ui.same_line(0.0);
+    ui.set_next_item_width(100.0);
+    edited |= ui.input_int(im_str!("Offset"), &mut waveform.offset).build();
+
+    if edited {
+        waveform.offset = waveform.offset.max(0).min(255);
+    }
+
+    id.pop(ui);
+
+    edited
+}
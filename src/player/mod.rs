@@ -2,6 +2,7 @@ use std::clone::Clone;
 
 use num_derive::FromPrimitive;
 use num_traits::clamp;
+use serde::{Deserialize, Serialize};
 
 use crate::caret::CaretType;
 use crate::common::{Condition, Direction, Equipment, Flag, interpolate_fix9_scale, Rect};
@@ -22,7 +23,7 @@ use crate::components::number_popup::NumberPopup;
 mod player_hit;
 pub mod skin;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum ControlMode {
     Normal = 0,
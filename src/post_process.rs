@@ -0,0 +1,76 @@
+use std::io::Read;
+
+use crate::framework::context::Context;
+use crate::framework::error::GameResult;
+use crate::framework::{filesystem, graphics};
+use crate::settings::Settings;
+
+/// Shaders in the data directory take precedence over the built-in ones with the same name.
+const SHADER_DIRS: [&str; 2] = ["/shaders/postprocess/", "/builtin/shaders/postprocess/"];
+
+/// Prefix of shaders simulating color vision deficiencies, which are selected separately from other filters.
+pub const COLOR_FILTER_PREFIX: &str = "colorblind_";
+
+/// Returns sorted names of all post-processing shaders available, without the `.glsl` extension.
+pub fn list_shaders(ctx: &Context) -> Vec<String> {
+    let mut names = Vec::new();
+
+    for dir in SHADER_DIRS.iter() {
+        if !filesystem::exists(ctx, dir) {
+            continue;
+        }
+
+        if let Ok(files) = filesystem::read_dir(ctx, dir) {
+            for file in files {
+                if let Some(name) = file.file_name().map(|name| name.to_string_lossy().to_string()) {
+                    if let Some(name) = name.strip_suffix(".glsl") {
+                        names.push(name.to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    names.sort();
+    names.dedup();
+    names
+}
+
+pub fn is_color_filter(name: &str) -> bool {
+    name.starts_with(COLOR_FILTER_PREFIX)
+}
+
+fn load_shader(ctx: &Context, name: &str) -> GameResult<String> {
+    let mut last_err = None;
+
+    for dir in SHADER_DIRS.iter() {
+        match filesystem::open(ctx, format!("{}{}.glsl", dir, name)) {
+            Ok(mut file) => {
+                let mut source = String::new();
+                file.read_to_string(&mut source)?;
+                return Ok(source);
+            }
+            Err(err) => last_err = Some(err),
+        }
+    }
+
+    Err(last_err.unwrap())
+}
+
+/// Loads the post-processing chain selected in settings and passes it to the renderer.
+/// Failures are logged and leave the frame unprocessed, so a broken shader can't make the game unplayable.
+pub fn apply_settings(settings: &Settings, ctx: &mut Context) {
+    let mut sources = Vec::new();
+
+    for name in settings.post_process_chain.iter() {
+        match load_shader(ctx, name) {
+            Ok(source) => sources.push(source),
+            Err(err) => log::warn!("Failed to load post-processing shader {}: {}", name, err),
+        }
+    }
+
+    if let Err(err) = graphics::set_post_process_chain(ctx, &sources) {
+        log::warn!("Failed to set up post-processing: {}", err);
+        let _ = graphics::set_post_process_chain(ctx, &[]);
+    }
+}
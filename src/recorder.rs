@@ -0,0 +1,235 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use image::imageops::FilterType;
+use image::RgbaImage;
+
+use crate::common::Rect;
+use crate::framework::backend::{BackendTexture, SpriteBatchCommand};
+use crate::framework::context::Context;
+use crate::framework::error::GameError::{ParseError, RenderError};
+use crate::framework::error::{GameError, GameResult};
+use crate::framework::graphics;
+use crate::screenshot::ScreenshotScale;
+use crate::shared_game_state::TimingMode;
+use crate::sound::{AudioClock, AudioOutput};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VideoFormat {
+    /// Numbered PNG files, `frame_000000.png` and so on.
+    Png,
+    /// Single uncompressed YUV4MPEG2 stream in 4:4:4 format, `video.y4m`.
+    Y4m,
+}
+
+impl FromStr for VideoFormat {
+    type Err = GameError;
+
+    fn from_str(s: &str) -> GameResult<VideoFormat> {
+        match s {
+            "png" => Ok(VideoFormat::Png),
+            "y4m" => Ok(VideoFormat::Y4m),
+            _ => Err(ParseError(format!("Unknown video format: {}, expected png or y4m.", s))),
+        }
+    }
+}
+
+impl FromStr for ScreenshotScale {
+    type Err = GameError;
+
+    fn from_str(s: &str) -> GameResult<ScreenshotScale> {
+        match s {
+            "canvas" => Ok(ScreenshotScale::Canvas),
+            "window" => Ok(ScreenshotScale::Window),
+            _ => Err(ParseError(format!("Unknown scale: {}, expected canvas or window.", s))),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RecordOptions {
+    /// Directory the frames and `audio.wav` are written to.
+    pub output: PathBuf,
+    pub format: VideoFormat,
+    /// Tick rate the game is locked to, which is also the frame rate of the video.
+    pub timing_mode: TimingMode,
+    pub scale: ScreenshotScale,
+    /// Amount of frames after which the game quits, records until it's closed if None.
+    pub frames: Option<usize>,
+}
+
+impl RecordOptions {
+    pub fn new(output: PathBuf) -> RecordOptions {
+        RecordOptions {
+            output,
+            format: VideoFormat::Png,
+            timing_mode: TimingMode::_50Hz,
+            scale: ScreenshotScale::Canvas,
+            frames: None,
+        }
+    }
+}
+
+/// Dumps every game tick as a video frame. The game runs as fast as frames can be written instead of
+/// in real time and the audio is rendered on a simulated clock advanced once per tick, so both stay in sync.
+/// Frames are rendered offscreen, so their size doesn't depend on the window and the post-processing chain
+/// only applies to what's shown in it.
+pub struct Recorder {
+    options: RecordOptions,
+    frame: usize,
+    /// Size of the first frame, the rest is scaled to it if the window is resized.
+    size: Option<(u32, u32)>,
+    y4m: Option<BufWriter<File>>,
+    /// Texture frames are rendered to, kept between frames of the same size.
+    target: Option<Box<dyn BackendTexture>>,
+}
+
+impl Recorder {
+    pub fn new(options: RecordOptions) -> GameResult<Recorder> {
+        std::fs::create_dir_all(&options.output)?;
+
+        if options.timing_mode == TimingMode::FrameSynchronized {
+            return Err(GameError::InvalidValue("Recording needs a fixed tick rate.".to_string()));
+        }
+
+        log::info!("Recording {:?} frames to {:?}", options.format, options.output);
+
+        Ok(Recorder { options, frame: 0, size: None, y4m: None, target: None })
+    }
+
+    /// Output mixing audio to a WAV file next to the frames.
+    pub fn audio_output(&self) -> AudioOutput {
+        AudioOutput::Wav(self.options.output.join("audio.wav"), AudioClock::Simulated)
+    }
+
+    pub fn timing_mode(&self) -> TimingMode {
+        self.options.timing_mode
+    }
+
+    /// Size of a game pixel in recorded frames, given its size in the window.
+    pub fn render_scale(&self, window_scale: f32) -> f32 {
+        match self.options.scale {
+            ScreenshotScale::Canvas => 1.0,
+            ScreenshotScale::Window => window_scale,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.options.frames.map_or(false, |frames| self.frame >= frames)
+    }
+
+    /// Redirects everything drawn to the screen to an offscreen texture, covering canvas of given size
+    /// with game pixels of given scale.
+    pub fn begin_frame(&mut self, canvas_size: (f32, f32), scale: f32, ctx: &mut Context) -> GameResult {
+        let width = (canvas_size.0 * scale).round().max(1.0) as u16;
+        let height = (canvas_size.1 * scale).round().max(1.0) as u16;
+
+        let target = match self.target.take() {
+            Some(target) if target.dimensions() == (width, height) => target,
+            _ => graphics::create_texture_mutable(ctx, width, height)?,
+        };
+
+        graphics::set_screen_target(ctx, Some(target))?;
+        graphics::clear(ctx, [0.0, 0.0, 0.0, 1.0].into());
+
+        Ok(())
+    }
+
+    /// Writes the frame rendered since `begin_frame` and draws it to the screen, stretched over `screen_size`.
+    pub fn end_frame(&mut self, screen_size: (f32, f32), ctx: &mut Context) -> GameResult {
+        let mut target = graphics::set_screen_target(ctx, None)?
+            .ok_or_else(|| RenderError("Recorded frame hasn't been started.".to_string()))?;
+        let (width, height, mut data) = graphics::read_texture(ctx, &target)?;
+
+        // translucent sprites leave holes in the alpha channel of the texture
+        for pixel in data.chunks_exact_mut(4) {
+            pixel[3] = 0xff;
+        }
+
+        let image = RgbaImage::from_raw(width as u32, height as u32, data)
+            .ok_or_else(|| RenderError("Texture read-back returned invalid amount of data.".to_string()))?;
+
+        let (width, height) = (width as f32, height as f32);
+        target.clear();
+        target.add(SpriteBatchCommand::DrawRect(
+            Rect::new(0.0, 0.0, width, height),
+            Rect::new(0.0, 0.0, screen_size.0, screen_size.1),
+        ));
+        target.draw()?;
+        self.target = Some(target);
+
+        self.write_frame(image)
+    }
+
+    /// Flushes frames buffered by the video stream.
+    pub fn finish(&mut self) -> GameResult {
+        if let Some(writer) = self.y4m.as_mut() {
+            writer.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn write_frame(&mut self, image: RgbaImage) -> GameResult {
+        let (width, height) = *self.size.get_or_insert(image.dimensions());
+        let image = if image.dimensions() != (width, height) {
+            image::imageops::resize(&image, width, height, FilterType::Nearest)
+        } else {
+            image
+        };
+
+        match self.options.format {
+            VideoFormat::Png => {
+                image.save(self.options.output.join(format!("frame_{:06}.png", self.frame)))?;
+            }
+            VideoFormat::Y4m => {
+                if self.y4m.is_none() {
+                    let fps = self.options.timing_mode.get_tps();
+                    let mut writer = BufWriter::new(File::create(self.options.output.join("video.y4m"))?);
+                    writeln!(writer, "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444", width, height, fps)?;
+                    self.y4m = Some(writer);
+                }
+
+                if let Some(writer) = self.y4m.as_mut() {
+                    write_y4m_frame(writer, &image)?;
+                }
+            }
+        }
+
+        self.frame += 1;
+
+        Ok(())
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Err(err) = self.finish() {
+            log::error!("Failed to finish video output: {}", err);
+        }
+    }
+}
+
+/// Writes a frame converted to limited range BT.601 YCbCr, one full resolution plane per component.
+fn write_y4m_frame<W: Write>(writer: &mut W, image: &RgbaImage) -> GameResult {
+    let pixels = image.width() as usize * image.height() as usize;
+    let mut planes = vec![0u8; pixels * 3];
+    let (y_plane, chroma) = planes.split_at_mut(pixels);
+    let (cb_plane, cr_plane) = chroma.split_at_mut(pixels);
+
+    for (i, pixel) in image.pixels().enumerate() {
+        let [r, g, b, _] = pixel.0;
+        let (r, g, b) = (r as i32, g as i32, b as i32);
+
+        y_plane[i] = (16 + ((66 * r + 129 * g + 25 * b + 128) >> 8)) as u8;
+        cb_plane[i] = (128 + ((-38 * r - 74 * g + 112 * b + 128) >> 8)) as u8;
+        cr_plane[i] = (128 + ((112 * r - 94 * g - 18 * b + 128) >> 8)) as u8;
+    }
+
+    writer.write_all(b"FRAME\n")?;
+    writer.write_all(&planes)?;
+
+    Ok(())
+}
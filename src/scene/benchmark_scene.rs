@@ -0,0 +1,302 @@
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+
+use image::RgbaImage;
+
+use crate::common::FadeState;
+use crate::framework::context::Context;
+use crate::framework::error::GameResult;
+use crate::framework::graphics;
+use crate::framework::ui::Components;
+use crate::npc::NPC;
+use crate::scene::game_scene::GameScene;
+use crate::scene::Scene;
+use crate::screenshot::{capture_frame, save_image, ScreenshotScale};
+use crate::shared_game_state::SharedGameState;
+use crate::text_script::TextScriptExecutionState;
+
+/// NPC types spawned by the benchmark, a mix of sprites from different sheets and ones with lights attached.
+static NPC_TYPES: [u16; 16] = [17, 18, 20, 21, 22, 32, 38, 60, 61, 62, 70, 74, 75, 77, 87, 211];
+
+/// Frames drawn right after switching passes, which mostly measure loading textures, are left out of the results.
+const WARMUP_FRAMES: usize = 30;
+
+/// Speed of the camera sweep across the map, in pixels per tick.
+const SWEEP_SPEED: i32 = 4;
+
+pub struct BenchmarkOptions {
+    /// Stage to draw, starting stage of a new game if not set.
+    pub stage_id: Option<usize>,
+    pub npc_count: usize,
+    /// Number of frames measured in each pass.
+    pub frames: usize,
+}
+
+impl BenchmarkOptions {
+    pub fn new() -> BenchmarkOptions {
+        BenchmarkOptions { stage_id: None, npc_count: 500, frames: 1000 }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Pass {
+    Baseline,
+    Optimized,
+}
+
+impl Pass {
+    fn name(self) -> &'static str {
+        match self {
+            Pass::Baseline => "baseline",
+            Pass::Optimized => "atlas + static tiles",
+        }
+    }
+}
+
+#[derive(Default)]
+struct FrameStats {
+    frames: usize,
+    draw_time: Duration,
+    max_draw_time: Duration,
+    frame_time: Duration,
+    last_frame: Option<Instant>,
+}
+
+impl FrameStats {
+    fn average_draw_ms(&self) -> f64 {
+        self.draw_time.as_secs_f64() * 1000.0 / self.frames.max(1) as f64
+    }
+
+    fn average_frame_ms(&self) -> f64 {
+        self.frame_time.as_secs_f64() * 1000.0 / self.frames.saturating_sub(1).max(1) as f64
+    }
+}
+
+/// Sweeps the camera over a stage crowded with NPCs, drawing it once the old way and once with texture atlas
+/// and cached tile layers enabled, then logs frame times of both passes and exits. Between the passes
+/// a single frame is drawn both ways and compared, as the optimizations mustn't change the picture.
+/// Used by `--benchmark`.
+pub struct BenchmarkScene {
+    game_scene: GameScene,
+    options: BenchmarkOptions,
+    pass: Pass,
+    warmup: RefCell<usize>,
+    stats: RefCell<FrameStats>,
+    baseline: Option<(f64, f64)>,
+    sweep: (i32, i32),
+}
+
+impl BenchmarkScene {
+    pub fn new(state: &mut SharedGameState, ctx: &mut Context, options: BenchmarkOptions) -> GameResult<Self> {
+        let stage_id = options.stage_id.unwrap_or(state.constants.game.new_game_stage as usize);
+        let mut game_scene = GameScene::new(state, ctx, stage_id)?;
+        game_scene.player1.cond.set_hidden(true);
+
+        Ok(Self {
+            game_scene,
+            options,
+            pass: Pass::Baseline,
+            warmup: RefCell::new(WARMUP_FRAMES),
+            stats: RefCell::new(FrameStats::default()),
+            baseline: None,
+            sweep: (SWEEP_SPEED, SWEEP_SPEED),
+        })
+    }
+
+    fn configure(&mut self, state: &mut SharedGameState, pass: Pass) {
+        state.reload_textures();
+        state.texture_set.use_atlas = pass == Pass::Optimized;
+        self.game_scene.static_tiles = pass == Pass::Optimized;
+    }
+
+    fn start_pass(&mut self, state: &mut SharedGameState, pass: Pass) {
+        log::info!("Benchmark pass: {}, {} frames.", pass.name(), self.options.frames);
+
+        self.configure(state, pass);
+
+        self.pass = pass;
+        *self.warmup.borrow_mut() = WARMUP_FRAMES;
+        *self.stats.borrow_mut() = FrameStats::default();
+    }
+
+    fn spawn_npcs(&mut self, state: &mut SharedGameState) {
+        let tile_size = self.game_scene.stage.map.tile_size.as_int();
+        let width = self.game_scene.stage.map.width.max(1) as usize;
+        let height = self.game_scene.stage.map.height.max(1) as usize;
+
+        for i in 0..self.options.npc_count {
+            let mut npc = NPC::create(NPC_TYPES[i % NPC_TYPES.len()], &state.npc_table);
+            npc.cond.set_alive(true);
+            // spread evenly over the map, stepping by a prime so rows and columns don't line up
+            let tile = i * 7919 % (width * height);
+            npc.x = (tile % width) as i32 * tile_size * 0x200;
+            npc.y = (tile / width) as i32 * tile_size * 0x200;
+
+            let _ = self.game_scene.npc_list.spawn(0x100, npc);
+        }
+    }
+
+    /// Moves the camera diagonally, bouncing off the map edges.
+    fn sweep_camera(&mut self, state: &SharedGameState) {
+        let tile_size = self.game_scene.stage.map.tile_size.as_int();
+        let max_x = ((self.game_scene.stage.map.width as i32 - 1) * tile_size - state.canvas_size.0 as i32).max(0);
+        let max_y = ((self.game_scene.stage.map.height as i32 - 1) * tile_size - state.canvas_size.1 as i32).max(0);
+
+        let frame = &mut self.game_scene.frame;
+        let (mut x, mut y) = (frame.x / 0x200 + self.sweep.0, frame.y / 0x200 + self.sweep.1);
+
+        if x <= 0 || x >= max_x {
+            self.sweep.0 = -self.sweep.0;
+            x = x.max(0).min(max_x);
+        }
+
+        if y <= 0 || y >= max_y {
+            self.sweep.1 = -self.sweep.1;
+            y = y.max(0).min(max_y);
+        }
+
+        frame.x = x * 0x200;
+        frame.y = y * 0x200;
+    }
+
+    /// Draws current state of the scene the way given pass does and reads the frame back.
+    fn capture_pass(&mut self, state: &mut SharedGameState, ctx: &mut Context, pass: Pass) -> GameResult<RgbaImage> {
+        self.configure(state, pass);
+
+        graphics::prepare_draw(ctx)?;
+        graphics::clear(ctx, [0.0, 0.0, 0.0, 1.0].into());
+        self.game_scene.draw(state, ctx)?;
+
+        capture_frame(ScreenshotScale::Canvas, state, ctx)
+    }
+
+    /// Draws the same frame with both passes and compares them, both frames are saved if they differ.
+    fn check_passes_match(&mut self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
+        let baseline = self.capture_pass(state, ctx, Pass::Baseline)?;
+        let optimized = self.capture_pass(state, ctx, Pass::Optimized)?;
+
+        if baseline.dimensions() == optimized.dimensions() && baseline.as_raw() == optimized.as_raw() {
+            log::info!("Benchmark check passed, both passes draw the same frame.");
+            return Ok(());
+        }
+
+        let mismatched = baseline.pixels().zip(optimized.pixels()).filter(|(a, b)| a != b).count();
+        let baseline_path = save_image(baseline, "benchmark_baseline", ctx)?;
+        let optimized_path = save_image(optimized, "benchmark_optimized", ctx)?;
+
+        log::error!(
+            "Benchmark check failed, {} pixels differ between the passes, see {} and {}.",
+            mismatched,
+            baseline_path,
+            optimized_path
+        );
+
+        Ok(())
+    }
+
+    fn finish_pass(&mut self, state: &mut SharedGameState, ctx: &mut Context) {
+        let (draw_ms, frame_ms, max_draw_ms) = {
+            let stats = self.stats.borrow();
+            (stats.average_draw_ms(), stats.average_frame_ms(), stats.max_draw_time.as_secs_f64() * 1000.0)
+        };
+
+        log::info!(
+            "Benchmark pass {} finished: average draw {:.3} ms (max {:.3} ms), average frame {:.3} ms.",
+            self.pass.name(),
+            draw_ms,
+            max_draw_ms,
+            frame_ms
+        );
+
+        match self.baseline {
+            None => {
+                self.baseline = Some((draw_ms, frame_ms));
+
+                if let Err(err) = self.check_passes_match(state, ctx) {
+                    log::error!("Benchmark check couldn't be done: {}", err);
+                }
+
+                self.start_pass(state, Pass::Optimized);
+            }
+            Some((baseline_draw_ms, baseline_frame_ms)) => {
+                log::info!(
+                    "Benchmark results: draw {:.3} ms -> {:.3} ms ({:+.1}%), frame {:.3} ms -> {:.3} ms ({:+.1}%).",
+                    baseline_draw_ms,
+                    draw_ms,
+                    (draw_ms / baseline_draw_ms - 1.0) * 100.0,
+                    baseline_frame_ms,
+                    frame_ms,
+                    (frame_ms / baseline_frame_ms - 1.0) * 100.0
+                );
+
+                state.shutdown();
+            }
+        }
+    }
+}
+
+impl Scene for BenchmarkScene {
+    fn init(&mut self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
+        self.game_scene.init(state, ctx)?;
+        self.spawn_npcs(state);
+
+        state.reset_map_flags();
+        state.fade_state = FadeState::Visible;
+        state.textscript_vm.state = TextScriptExecutionState::Ended;
+        state.control_flags.set_tick_world(true);
+        state.control_flags.set_control_enabled(false);
+
+        self.start_pass(state, Pass::Baseline);
+
+        Ok(())
+    }
+
+    fn tick(&mut self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
+        self.game_scene.tick(state, ctx)?;
+        self.sweep_camera(state);
+
+        if self.stats.borrow().frames >= self.options.frames {
+            self.finish_pass(state, ctx);
+        }
+
+        Ok(())
+    }
+
+    fn draw_tick(&mut self, state: &mut SharedGameState) -> GameResult {
+        self.game_scene.draw_tick(state)
+    }
+
+    fn draw(&self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
+        let start = Instant::now();
+        self.game_scene.draw(state, ctx)?;
+        let end = Instant::now();
+
+        let mut warmup = self.warmup.borrow_mut();
+        if *warmup > 0 {
+            *warmup -= 1;
+            return Ok(());
+        }
+
+        let mut stats = self.stats.borrow_mut();
+        let draw_time = end - start;
+        stats.frames += 1;
+        stats.draw_time += draw_time;
+        stats.max_draw_time = stats.max_draw_time.max(draw_time);
+        if let Some(last_frame) = stats.last_frame {
+            stats.frame_time += start - last_frame;
+        }
+        stats.last_frame = Some(start);
+
+        Ok(())
+    }
+
+    fn debug_overlay_draw(
+        &mut self,
+        game_ui: &mut Components,
+        state: &mut SharedGameState,
+        ctx: &mut Context,
+        frame: &mut imgui::Ui,
+    ) -> GameResult {
+        self.game_scene.debug_overlay_draw(game_ui, state, ctx, frame)
+    }
+}
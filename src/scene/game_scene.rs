@@ -1,3 +1,5 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::ops::Range;
 
 use log::info;
@@ -33,7 +35,7 @@ use crate::scene::Scene;
 use crate::shared_game_state::{SharedGameState, TileSize};
 use crate::stage::{BackgroundType, Stage};
 use crate::text_script::{ConfirmSelection, ScriptMode, TextScriptExecutionState, TextScriptLine, TextScriptVM};
-use crate::texture_set::SizedBatch;
+use crate::texture_set::{SizedBatch, G_MAG};
 use crate::weapon::bullet::BulletManager;
 use crate::weapon::{Weapon, WeaponType};
 
@@ -58,6 +60,11 @@ pub struct GameScene {
     pub boss: BossNPC,
     pub bullet_manager: BulletManager,
     pub intro_mode: bool,
+    pub lights: Vec<DynamicLight>,
+    /// Keeps tile layers in retained buffers instead of rebuilding them every frame, if the renderer supports it.
+    pub static_tiles: bool,
+    /// Tiles revision, scale and pixel grid scale each of the retained tile layers was built with.
+    tile_cache: RefCell<HashMap<TileLayer, (u32, f32, f32)>>,
     tex_background_name: String,
     tex_tileset_name: String,
     tex_tileset_name_mg: String,
@@ -67,6 +74,16 @@ pub struct GameScene {
     inventory_dim: f32,
 }
 
+/// Light added at runtime by scripts, positioned in world coordinates.
+#[derive(Debug, Copy, Clone)]
+pub struct DynamicLight {
+    pub id: u16,
+    pub x: i32,
+    pub y: i32,
+    pub size: f32,
+    pub color: (u8, u8, u8),
+}
+
 #[derive(Debug, EnumIter, PartialEq, Eq, Hash, Copy, Clone)]
 pub enum TileLayer {
     Background,
@@ -136,12 +153,23 @@ impl GameScene {
                 target_x: 0,
                 target_y: 0,
                 wait: 16,
+                zoom: 1.0,
+                prev_zoom: 1.0,
+                target_zoom: 1.0,
+                zoom_wait: state.constants.camera.zoom_wait,
+                look_ahead: state.constants.camera.look_ahead,
+                bounds: None,
+                multi_target: state.constants.camera.multi_target,
+                fit_zoom: None,
             },
             stage_id: id,
             npc_list: NPCList::new(),
             boss: BossNPC::new(),
             bullet_manager: BulletManager::new(),
             intro_mode: false,
+            lights: Vec::new(),
+            static_tiles: true,
+            tile_cache: RefCell::new(HashMap::new()),
             tex_background_name,
             tex_tileset_name,
             tex_tileset_name_mg,
@@ -152,6 +180,21 @@ impl GameScene {
         })
     }
 
+    /// Adds a light drawn on the light map, replacing the one with the same id.
+    pub fn set_light(&mut self, light: DynamicLight) {
+        self.lights.retain(|l| l.id != light.id);
+        self.lights.push(light);
+    }
+
+    /// Removes light with given id, or all of them if it's 0.
+    pub fn remove_light(&mut self, id: u16) {
+        if id == 0 {
+            self.lights.clear();
+        } else {
+            self.lights.retain(|light| light.id != id);
+        }
+    }
+
     pub fn display_map_name(&mut self, ticks: u16) {
         self.map_name_counter = ticks;
     }
@@ -252,6 +295,9 @@ impl GameScene {
     }
 
     fn draw_npc_layer(&self, state: &mut SharedGameState, ctx: &mut Context, layer: NPCLayer) -> GameResult {
+        // NPCs draw only sprites, so ones from sheets packed together can be drawn at once
+        state.texture_set.begin_deferred_draws();
+
         for npc in self.npc_list.iter_alive() {
             if npc.layer != layer
                 || npc.x < (self.frame.x - 128 * 0x200 - npc.display_bounds.width() as i32 * 0x200)
@@ -271,7 +317,7 @@ impl GameScene {
             npc.draw(state, ctx, &self.frame)?;
         }
 
-        Ok(())
+        state.texture_set.end_deferred_draws()
     }
 
     fn draw_bullets(&self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
@@ -779,7 +825,9 @@ impl GameScene {
         graphics::set_render_target(ctx, Some(canvas))?;
         graphics::set_blend_mode(ctx, BlendMode::Add)?;
 
-        graphics::clear(ctx, Color::from_rgb(100, 100, 110));
+        let (r, g, b) =
+            state.constants.lighting.stage(&self.stage.data.map).ambient.unwrap_or(state.constants.lighting.ambient);
+        graphics::clear(ctx, Color::from_rgb(r, g, b));
         {
             let batch = state.texture_set.get_or_load_batch(ctx, &state.constants, "builtin/lightmap/spot")?;
 
@@ -862,217 +910,29 @@ impl GameScene {
                     continue;
                 }
 
-                match npc.npc_type {
-                    1 => {
-                        self.draw_light(
-                            interpolate_fix9_scale(
-                                npc.prev_x - self.frame.prev_x,
-                                npc.x - self.frame.x,
-                                state.frame_time,
-                            ),
-                            interpolate_fix9_scale(
-                                npc.prev_y - self.frame.prev_y,
-                                npc.y - self.frame.y,
-                                state.frame_time,
-                            ),
-                            0.4,
-                            (255, 255, 0),
-                            batch,
-                        );
-                    }
-                    4 if npc.direction == Direction::Up => self.draw_light(
-                        interpolate_fix9_scale(npc.prev_x - self.frame.prev_x, npc.x - self.frame.x, state.frame_time),
-                        interpolate_fix9_scale(npc.prev_y - self.frame.prev_y, npc.y - self.frame.y, state.frame_time),
-                        1.0,
-                        (200, 100, 0),
-                        batch,
-                    ),
-                    7 => self.draw_light(
-                        interpolate_fix9_scale(npc.prev_x - self.frame.prev_x, npc.x - self.frame.x, state.frame_time),
-                        interpolate_fix9_scale(npc.prev_y - self.frame.prev_y, npc.y - self.frame.y, state.frame_time),
-                        1.0,
-                        (100, 100, 100),
-                        batch,
-                    ),
-                    17 if npc.anim_num == 0 => {
-                        self.draw_light(
-                            interpolate_fix9_scale(
-                                npc.prev_x - self.frame.prev_x,
-                                npc.x - self.frame.x,
-                                state.frame_time,
-                            ),
-                            interpolate_fix9_scale(
-                                npc.prev_y - self.frame.prev_y,
-                                npc.y - self.frame.y,
-                                state.frame_time,
-                            ),
-                            2.0,
-                            (160, 0, 0),
-                            batch,
-                        );
-                        self.draw_light(
-                            interpolate_fix9_scale(
-                                npc.prev_x - self.frame.prev_x,
-                                npc.x - self.frame.x,
-                                state.frame_time,
-                            ),
-                            interpolate_fix9_scale(
-                                npc.prev_y - self.frame.prev_y,
-                                npc.y - self.frame.y,
-                                state.frame_time,
-                            ),
-                            0.5,
-                            (255, 0, 0),
-                            batch,
-                        );
-                    }
-                    20 if npc.direction == Direction::Right => {
-                        self.draw_light(
-                            interpolate_fix9_scale(
-                                npc.prev_x - self.frame.prev_x,
-                                npc.x - self.frame.x,
-                                state.frame_time,
-                            ),
-                            interpolate_fix9_scale(
-                                npc.prev_y - self.frame.prev_y,
-                                npc.y - self.frame.y,
-                                state.frame_time,
-                            ),
-                            2.0,
-                            (0, 0, 150),
-                            batch,
-                        );
+                if let Some(lights) = state.constants.lighting.npc_lights.get(&npc.npc_type) {
+                    let x =
+                        interpolate_fix9_scale(npc.prev_x - self.frame.prev_x, npc.x - self.frame.x, state.frame_time);
+                    let y =
+                        interpolate_fix9_scale(npc.prev_y - self.frame.prev_y, npc.y - self.frame.y, state.frame_time);
 
-                        if npc.anim_num < 2 {
-                            self.draw_light(
-                                interpolate_fix9_scale(
-                                    npc.prev_x - self.frame.prev_x,
-                                    npc.x - self.frame.x,
-                                    state.frame_time,
-                                ),
-                                interpolate_fix9_scale(
-                                    npc.prev_y - self.frame.prev_y,
-                                    npc.y - self.frame.y,
-                                    state.frame_time,
-                                ),
-                                2.1,
-                                (0, 0, 30),
-                                batch,
-                            );
-                        }
+                    for light in lights.iter().filter(|light| light.matches(npc)) {
+                        let color = light.color_for(npc);
+                        self.draw_light(x + light.offset.0, y + light.offset.1, light.size, color, batch);
                     }
-                    22 if npc.action_num == 1 && npc.anim_num == 1 => self.draw_light(
-                        interpolate_fix9_scale(npc.prev_x - self.frame.prev_x, npc.x - self.frame.x, state.frame_time),
-                        interpolate_fix9_scale(npc.prev_y - self.frame.prev_y, npc.y - self.frame.y, state.frame_time),
-                        3.0,
-                        (0, 0, 255),
-                        batch,
-                    ),
-                    32 | 87 | 211 => {
-                        self.draw_light(
-                            interpolate_fix9_scale(
-                                npc.prev_x - self.frame.prev_x,
-                                npc.x - self.frame.x,
-                                state.frame_time,
-                            ),
-                            interpolate_fix9_scale(
-                                npc.prev_y - self.frame.prev_y,
-                                npc.y - self.frame.y,
-                                state.frame_time,
-                            ),
-                            2.0,
-                            (255, 30, 30),
-                            batch,
-                        );
-                    }
-                    38 => {
-                        let flicker = (npc.anim_num ^ 5 & 3) as u8 * 15;
-                        self.draw_light(
-                            interpolate_fix9_scale(
-                                npc.prev_x - self.frame.prev_x,
-                                npc.x - self.frame.x,
-                                state.frame_time,
-                            ),
-                            interpolate_fix9_scale(
-                                npc.prev_y - self.frame.prev_y,
-                                npc.y - self.frame.y,
-                                state.frame_time,
-                            ),
-                            3.5,
-                            (130 + flicker, 40 + flicker, 0),
-                            batch,
-                        );
-                    }
-                    70 => {
-                        let flicker = 50 + npc.anim_num as u8 * 15;
-                        self.draw_light(
-                            interpolate_fix9_scale(
-                                npc.prev_x - self.frame.prev_x,
-                                npc.x - self.frame.x,
-                                state.frame_time,
-                            ),
-                            interpolate_fix9_scale(
-                                npc.prev_y - self.frame.prev_y,
-                                npc.y - self.frame.y,
-                                state.frame_time,
-                            ),
-                            2.0,
-                            (flicker, flicker, flicker),
-                            batch,
-                        );
-                    }
-                    75 | 77 => self.draw_light(
-                        interpolate_fix9_scale(npc.prev_x - self.frame.prev_x, npc.x - self.frame.x, state.frame_time),
-                        interpolate_fix9_scale(npc.prev_y - self.frame.prev_y, npc.y - self.frame.y, state.frame_time),
-                        3.0,
-                        (255, 100, 0),
-                        batch,
-                    ),
-                    85 if npc.action_num == 1 => {
-                        let (color, color2) = if npc.direction == Direction::Left {
-                            ((0, 150, 100), (0, 50, 30))
-                        } else {
-                            ((150, 0, 0), (50, 0, 0))
-                        };
-
-                        self.draw_light(
-                            interpolate_fix9_scale(
-                                npc.prev_x - self.frame.prev_x,
-                                npc.x - self.frame.x,
-                                state.frame_time,
-                            ),
-                            interpolate_fix9_scale(
-                                npc.prev_y - self.frame.prev_y,
-                                npc.y - self.frame.y,
-                                state.frame_time,
-                            ),
-                            1.5,
-                            color,
-                            batch,
-                        );
-
-                        if npc.anim_num < 2 {
-                            self.draw_light(
-                                interpolate_fix9_scale(
-                                    npc.prev_x - self.frame.prev_x,
-                                    npc.x - self.frame.x,
-                                    state.frame_time,
-                                ),
-                                interpolate_fix9_scale(
-                                    npc.prev_y - self.frame.prev_y,
-                                    npc.y - self.frame.y,
-                                    state.frame_time,
-                                ) - 8.0,
-                                2.1,
-                                color2,
-                                batch,
-                            );
-                        }
-                    }
-                    _ => {}
                 }
             }
 
+            for light in self.lights.iter() {
+                self.draw_light(
+                    interpolate_fix9_scale(light.x - self.frame.prev_x, light.x - self.frame.x, state.frame_time),
+                    interpolate_fix9_scale(light.y - self.frame.prev_y, light.y - self.frame.y, state.frame_time),
+                    light.size,
+                    light.color,
+                    batch,
+                );
+            }
+
             batch.draw_filtered(FilterMode::Linear, ctx)?;
         }
 
@@ -1089,6 +949,48 @@ impl GameScene {
         Ok(())
     }
 
+    /// Draws the stage and everything in it, in coordinates relative to the camera.
+    fn draw_world(&self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
+        self.draw_background(state, ctx)?;
+        self.draw_tiles(state, ctx, TileLayer::Background)?;
+        self.draw_npc_layer(state, ctx, NPCLayer::Background)?;
+        self.draw_tiles(state, ctx, TileLayer::Middleground)?;
+
+        let lighting_enabled = state.constants.lighting.stage(&self.stage.data.map).enabled.unwrap_or_else(|| {
+            self.stage.data.background_type != BackgroundType::Black
+                && self.stage.data.background_type != BackgroundType::Outside
+                && self.stage.data.background_type != BackgroundType::OutsideWind
+                && self.stage.data.background.name() != "bkBlack"
+        });
+
+        if state.settings.shader_effects && lighting_enabled {
+            self.draw_light_map(state, ctx)?;
+        }
+
+        self.boss.draw(state, ctx, &self.frame)?;
+        self.draw_npc_layer(state, ctx, NPCLayer::Middleground)?;
+        self.draw_bullets(state, ctx)?;
+        self.player2.draw(state, ctx, &self.frame)?;
+        self.player1.draw(state, ctx, &self.frame)?;
+
+        self.water_renderer.draw(state, ctx, &self.frame)?;
+        self.draw_tiles(state, ctx, TileLayer::Foreground)?;
+        self.draw_tiles(state, ctx, TileLayer::Snack)?;
+        self.draw_carets(state, ctx)?;
+        self.player1.popup.draw(state, ctx, &self.frame)?;
+        self.player2.popup.draw(state, ctx, &self.frame)?;
+
+        // if !self.intro_mode && state.settings.shader_effects
+        //     && (self.stage.data.background_type == BackgroundType::Black
+        //         || self.stage.data.background.name() == "bkBlack")
+        // {
+        //     self.draw_light_map(state, ctx)?;
+        // }
+        self.flash.draw(state, ctx, &self.frame)?;
+
+        Ok(())
+    }
+
     fn draw_tiles(&self, state: &mut SharedGameState, ctx: &mut Context, layer: TileLayer) -> GameResult {
         if state.tile_size == TileSize::Tile8x8 && layer == TileLayer::Snack {
             return Ok(());
@@ -1131,16 +1033,36 @@ impl GameScene {
                 _ => pxpack_data.scroll_fg.transform_camera_pos(frame_x, frame_y),
             };
 
-            frame_x = fx;
-            frame_y = fy;
+            // scroll factors move the layer off the pixel grid the camera position is snapped to
+            let g_mag = unsafe { G_MAG };
+            frame_x = (fx * g_mag).floor() / g_mag;
+            frame_y = (fy * g_mag).floor() / g_mag;
+        }
+
+        // whole layer is kept in a retained buffer, which is rebuilt only after tiles or scale change,
+        // so it's not used while zooming as the scale changes every frame
+        let static_tiles = self.static_tiles && batch.supports_static() && !self.frame.is_zooming();
+        let slot = layer as usize;
+        let cache_key = (self.stage.tiles_revision(), state.scale, unsafe { G_MAG });
+
+        if static_tiles && batch.has_static(slot) && self.tile_cache.borrow().get(&layer) == Some(&cache_key) {
+            batch.draw_static(slot, -frame_x, -frame_y)?;
+            return Ok(());
         }
 
-        let tile_start_x = (frame_x as i32 / tile_size).clamp(0, layer_width as i32) as usize;
-        let tile_start_y = (frame_y as i32 / tile_size).clamp(0, layer_height as i32) as usize;
-        let tile_end_x = ((frame_x as i32 + 8 + state.canvas_size.0 as i32) / tile_size + 1)
-            .clamp(0, layer_width as i32) as usize;
-        let tile_end_y = ((frame_y as i32 + halft + state.canvas_size.1 as i32) / tile_size + 1)
-            .clamp(0, layer_height as i32) as usize;
+        let (tile_start_x, tile_start_y, tile_end_x, tile_end_y) = if static_tiles {
+            (0, 0, layer_width as usize, layer_height as usize)
+        } else {
+            (
+                (frame_x as i32 / tile_size).clamp(0, layer_width as i32) as usize,
+                (frame_y as i32 / tile_size).clamp(0, layer_height as i32) as usize,
+                ((frame_x as i32 + 8 + state.canvas_size.0 as i32) / tile_size + 1).clamp(0, layer_width as i32)
+                    as usize,
+                ((frame_y as i32 + halft + state.canvas_size.1 as i32) / tile_size + 1).clamp(0, layer_height as i32)
+                    as usize,
+            )
+        };
+        let (offset_x, offset_y) = if static_tiles { (0.0, 0.0) } else { (frame_x, frame_y) };
 
         if layer == TileLayer::Snack {
             rect = state.constants.world.snack_rect;
@@ -1192,14 +1114,20 @@ impl GameScene {
                 }
 
                 batch.add_rect(
-                    (x as f32 * tile_sizef - halftf) - frame_x,
-                    (y as f32 * tile_sizef - halftf) - frame_y,
+                    (x as f32 * tile_sizef - halftf) - offset_x,
+                    (y as f32 * tile_sizef - halftf) - offset_y,
                     &rect,
                 );
             }
         }
 
-        batch.draw(ctx)?;
+        if static_tiles {
+            batch.store_static(slot)?;
+            self.tile_cache.borrow_mut().insert(layer, cache_key);
+            batch.draw_static(slot, -frame_x, -frame_y)?;
+        } else {
+            batch.draw(ctx)?;
+        }
 
         Ok(())
     }
@@ -1237,7 +1165,7 @@ impl GameScene {
                     } else {
                         if npc.shock < 14 {
                             if let Some(table_entry) = state.npc_table.get_entry(npc.npc_type) {
-                                state.sound_manager.play_sfx(table_entry.hurt_sound);
+                                state.sound_manager.play_sfx_at(table_entry.hurt_sound, npc.x, npc.y);
                             }
 
                             npc.shock = 16;
@@ -1332,7 +1260,7 @@ impl GameScene {
                             state.control_flags.set_interactions_disabled(true);
                             state.textscript_vm.start_script(npc.event_num);
                         } else {
-                            state.sound_manager.play_sfx(self.boss.death_sound[idx]);
+                            state.sound_manager.play_sfx_at(self.boss.death_sound[idx], npc.x, npc.y);
 
                             let destroy_count = 4usize * (2usize).pow((npc.size as u32).saturating_sub(1));
 
@@ -1351,7 +1279,7 @@ impl GameScene {
                             for _ in 0..3 {
                                 state.create_caret(bullet.x, bullet.y, CaretType::HurtParticles, Direction::Left);
                             }
-                            state.sound_manager.play_sfx(self.boss.hurt_sound[idx]);
+                            state.sound_manager.play_sfx_at(self.boss.hurt_sound[idx], npc.x, npc.y);
                         }
 
                         npc.shock = 8;
@@ -1480,21 +1408,27 @@ impl GameScene {
         self.bullet_manager.tick_bullets(state, [&self.player1, &self.player2], &self.npc_list, &mut self.stage);
         state.tick_carets();
 
+        self.frame.fit_zoom = None;
         match self.frame.update_target {
             UpdateTarget::Player => {
-                if self.player2.cond.alive()
-                    && !self.player2.cond.hidden()
+                let (target1_x, target1_y) = self.frame.player_target(&self.player1);
+                let (target2_x, target2_y) = self.frame.player_target(&self.player2);
+                let player2_visible = self.player2.cond.alive() && !self.player2.cond.hidden();
+
+                if player2_visible && self.frame.multi_target {
+                    self.frame.target_both(state, (target1_x, target1_y), (target2_x, target2_y));
+                } else if player2_visible
                     && (self.player1.x - self.player2.x).abs() < 240 * 0x200
                     && (self.player1.y - self.player2.y).abs() < 200 * 0x200
                 {
-                    self.frame.target_x = (self.player1.target_x * 2 + self.player2.target_x) / 3;
-                    self.frame.target_y = (self.player1.target_y * 2 + self.player2.target_y) / 3;
+                    self.frame.target_x = (target1_x * 2 + target2_x) / 3;
+                    self.frame.target_y = (target1_y * 2 + target2_y) / 3;
 
                     self.frame.target_x = self.frame.target_x.clamp(self.player1.x - 0x8000, self.player1.x + 0x8000);
                     self.frame.target_y = self.frame.target_y.clamp(self.player1.y, self.player1.y);
                 } else {
-                    self.frame.target_x = self.player1.target_x;
-                    self.frame.target_y = self.player1.target_y;
+                    self.frame.target_x = target1_x;
+                    self.frame.target_y = target1_y;
                 }
             }
             UpdateTarget::NPC(npc_id) => {
@@ -1515,6 +1449,7 @@ impl GameScene {
             }
         }
         self.frame.update(state, &self.stage);
+        state.sound_manager.set_listener(&self.frame, self.frame.viewport_size(state), state.settings.positional_sfx);
 
         if state.control_flags.control_enabled() {
             self.inventory_player1.tick_weapons(
@@ -1649,7 +1584,7 @@ impl Scene for GameScene {
         self.player1.controller = state.settings.create_player1_controller();
         self.player2.controller = state.settings.create_player2_controller();
 
-        let npcs = self.stage.load_npcs(&state.base_path, ctx)?;
+        let npcs = self.stage.load_npcs(&state.base_path, &state.constants, ctx)?;
         for npc_data in npcs.iter() {
             log::info!("creating npc: {:?}", npc_data);
 
@@ -1693,6 +1628,7 @@ impl Scene for GameScene {
         self.frame.target_x = self.player1.x;
         self.frame.target_y = self.player1.y;
         self.frame.immediate_update(state, &self.stage);
+        state.sound_manager.set_listener(&self.frame, self.frame.viewport_size(state), state.settings.positional_sfx);
 
         Ok(())
     }
@@ -1791,6 +1727,7 @@ impl Scene for GameScene {
     fn draw_tick(&mut self, state: &mut SharedGameState) -> GameResult {
         self.frame.prev_x = self.frame.x;
         self.frame.prev_y = self.frame.y;
+        self.frame.prev_zoom = self.frame.zoom;
         self.player1.prev_x = self.player1.x;
         self.player1.prev_y = self.player1.y;
         self.player1.popup.prev_x = self.player1.popup.x;
@@ -1835,40 +1772,13 @@ impl Scene for GameScene {
 
     fn draw(&self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
         //graphics::set_canvas(ctx, Some(&state.game_canvas));
-        self.draw_background(state, ctx)?;
-        self.draw_tiles(state, ctx, TileLayer::Background)?;
-        self.draw_npc_layer(state, ctx, NPCLayer::Background)?;
-        self.draw_tiles(state, ctx, TileLayer::Middleground)?;
-
-        if state.settings.shader_effects
-            && self.stage.data.background_type != BackgroundType::Black
-            && self.stage.data.background_type != BackgroundType::Outside
-            && self.stage.data.background_type != BackgroundType::OutsideWind
-            && self.stage.data.background.name() != "bkBlack"
-        {
-            self.draw_light_map(state, ctx)?;
-        }
-
-        self.boss.draw(state, ctx, &self.frame)?;
-        self.draw_npc_layer(state, ctx, NPCLayer::Middleground)?;
-        self.draw_bullets(state, ctx)?;
-        self.player2.draw(state, ctx, &self.frame)?;
-        self.player1.draw(state, ctx, &self.frame)?;
-
-        self.water_renderer.draw(state, ctx, &self.frame)?;
-        self.draw_tiles(state, ctx, TileLayer::Foreground)?;
-        self.draw_tiles(state, ctx, TileLayer::Snack)?;
-        self.draw_carets(state, ctx)?;
-        self.player1.popup.draw(state, ctx, &self.frame)?;
-        self.player2.popup.draw(state, ctx, &self.frame)?;
-
-        // if !self.intro_mode && state.settings.shader_effects
-        //     && (self.stage.data.background_type == BackgroundType::Black
-        //         || self.stage.data.background.name() == "bkBlack")
-        // {
-        //     self.draw_light_map(state, ctx)?;
-        // }
-        self.flash.draw(state, ctx, &self.frame)?;
+        // stage is drawn with camera zoom applied, by making game pixels larger and the canvas smaller
+        let (scale, canvas_size) = (state.scale, state.canvas_size);
+        let zoom = self.frame.zoom_interpolated(state.frame_time);
+        state.set_draw_scale(scale * zoom, (canvas_size.0 / zoom, canvas_size.1 / zoom));
+        let result = self.draw_world(state, ctx);
+        state.set_draw_scale(scale, canvas_size);
+        result?;
 
         /*graphics::set_canvas(ctx, None);
         state.game_canvas.draw(ctx, DrawParam::new()
@@ -1886,7 +1796,7 @@ impl Scene for GameScene {
                         self.player2.prev_y - self.frame.prev_y,
                         self.player2.y - self.frame.y,
                         state.frame_time,
-                    );
+                    ) * zoom;
                     let y = y.clamp(8.0, state.canvas_size.1 - 8.0 - state.font.line_height(&state.constants));
 
                     if self.player2.x + 0x1000 < self.frame.x {
@@ -1909,7 +1819,9 @@ impl Scene for GameScene {
                             &mut state.texture_set,
                             ctx,
                         )?;
-                    } else if self.player2.x - 0x1000 > self.frame.x + state.canvas_size.0 as i32 * 0x200 {
+                    } else if self.player2.x - 0x1000
+                        > self.frame.x + self.frame.viewport_size(state).0 as i32 * 0x200
+                    {
                         let width = state.font.text_width(P2_RIGHT_TEXT.chars(), &state.constants);
 
                         state.font.draw_colored_text(
@@ -2,6 +2,8 @@ use crate::framework::context::Context;
 use crate::framework::error::GameResult;
 use crate::framework::filesystem;
 use crate::npc::NPCTable;
+use crate::post_process;
+use crate::scene::benchmark_scene::{BenchmarkOptions, BenchmarkScene};
 use crate::scene::no_data_scene::NoDataScene;
 use crate::scene::Scene;
 use crate::shared_game_state::SharedGameState;
@@ -10,12 +12,22 @@ use crate::text_script::TextScript;
 
 pub struct LoadingScene {
     tick: usize,
+    benchmark: Option<BenchmarkOptions>,
 }
 
 impl LoadingScene {
     pub fn new() -> Self {
         Self {
             tick: 0,
+            benchmark: None,
+        }
+    }
+
+    /// Runs the rendering benchmark instead of the game once loading is done.
+    pub fn with_benchmark(options: BenchmarkOptions) -> Self {
+        Self {
+            tick: 0,
+            benchmark: Some(options),
         }
     }
 
@@ -37,7 +49,13 @@ impl LoadingScene {
         let stage_select_script = TextScript::load_from(stage_select_tsc, &state.constants)?;
         state.textscript_vm.set_stage_select_script(stage_select_script);
 
-        state.start_intro(ctx)?;
+        post_process::apply_settings(&state.settings, ctx);
+
+        if let Some(options) = self.benchmark.take() {
+            state.next_scene = Some(Box::new(BenchmarkScene::new(state, ctx, options)?));
+        } else {
+            state.start_intro(ctx)?;
+        }
 
         Ok(())
     }
@@ -4,6 +4,7 @@ use crate::framework::error::GameResult;
 use crate::shared_game_state::SharedGameState;
 use crate::framework::ui::Components;
 
+pub mod benchmark_scene;
 pub mod game_scene;
 pub mod loading_scene;
 pub mod no_data_scene;
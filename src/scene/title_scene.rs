@@ -5,7 +5,9 @@ use crate::framework::graphics;
 use crate::input::combined_menu_controller::CombinedMenuController;
 use crate::input::touch_controls::TouchControlType;
 use crate::menu::{Menu, MenuEntry, MenuSelectionResult};
+use crate::post_process;
 use crate::scene::Scene;
+use crate::settings::{AspectRatio, WindowMode};
 use crate::shared_game_state::{SharedGameState, TimingMode};
 
 #[derive(PartialEq, Eq, Copy, Clone)]
@@ -14,6 +16,9 @@ use crate::shared_game_state::{SharedGameState, TimingMode};
 enum CurrentMenu {
     MainMenu,
     OptionMenu,
+    SoundMenu,
+    DisplayMenu,
+    ModsMenu,
     SaveSelectMenu,
     ChallengesMenu,
     StartGame,
@@ -26,7 +31,14 @@ pub struct TitleScene {
     current_menu: CurrentMenu,
     main_menu: Menu,
     option_menu: Menu,
+    sound_menu: Menu,
+    display_menu: Menu,
+    mods_menu: Menu,
+    mods_changed: bool,
     save_select_menu: Menu,
+    /// Post-processing shaders selectable in options, screen filters and color vision filters.
+    screen_filters: Vec<String>,
+    color_filters: Vec<String>,
 }
 
 impl TitleScene {
@@ -37,10 +49,70 @@ impl TitleScene {
             current_menu: CurrentMenu::MainMenu,
             main_menu: Menu::new(0, 0, 100, 0),
             option_menu: Menu::new(0, 0, 180, 0),
+            sound_menu: Menu::new(0, 0, 180, 0),
+            display_menu: Menu::new(0, 0, 180, 0),
+            mods_menu: Menu::new(0, 0, 200, 0),
+            mods_changed: false,
             save_select_menu: Menu::new(0, 0, 200, 0),
+            screen_filters: Vec::new(),
+            color_filters: Vec::new(),
         }
     }
 
+    /// Replaces the screen filter (or color vision filter if `color` is set) in the post-processing chain,
+    /// `value` is an index into `filters` shifted by one for the "off" option.
+    fn apply_filter_option(
+        state: &mut SharedGameState,
+        ctx: &mut Context,
+        filters: &[String],
+        value: usize,
+        color: bool,
+    ) {
+        let chain = &mut state.settings.post_process_chain;
+        chain.retain(|name| post_process::is_color_filter(name) != color);
+
+        if let Some(name) = value.checked_sub(1).and_then(|i| filters.get(i)) {
+            if color {
+                chain.push(name.clone());
+            } else {
+                chain.insert(0, name.clone());
+            }
+        }
+
+        post_process::apply_settings(&state.settings, ctx);
+        let _ = state.settings.save(ctx);
+    }
+
+    fn apply_sound_option(state: &mut SharedGameState, idx: usize, value: usize) {
+        let volume = value as f32 / 10.0;
+
+        match idx {
+            0 => state.settings.master_volume = volume,
+            1 => state.settings.bgm_volume = volume,
+            2 => state.settings.sfx_volume = volume,
+            3 => state.settings.music_crossfade = CROSSFADE_TIMES.get(value).copied().unwrap_or(0.0),
+            _ => {}
+        }
+
+        let _ = state.sound_manager.update_volume(&state.settings);
+    }
+
+    fn apply_display_option(state: &mut SharedGameState, ctx: &mut Context, idx: usize, value: usize) {
+        let display = &mut state.settings.display;
+
+        match idx {
+            0 => display.window_mode = WINDOW_MODES.get(value).copied().unwrap_or(WindowMode::Windowed),
+            1 => display.window_scale = value as u8 + 1,
+            4 => display.aspect_ratio = ASPECT_RATIOS.get(value).copied().unwrap_or(AspectRatio::Standard),
+            _ => {}
+        }
+
+        if let Err(err) = state.apply_display_settings(ctx) {
+            log::warn!("Failed to apply display settings: {}", err);
+        }
+        let _ = state.settings.save(ctx);
+    }
+
     fn draw_background(&self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
         let batch = state.texture_set.get_or_load_batch(ctx, &state.constants, "bkMoon")?;
         let offset = (self.tick % 640) as isize;
@@ -86,6 +158,11 @@ impl TitleScene {
 // asset copyright for freeware version
 static COPYRIGHT_PIXEL: &str = "2004.12  Studio Pixel";
 static DISCORD_LINK: &str = "https://discord.gg/fbRsNNB";
+// music crossfade durations selectable in sound options, in seconds
+static CROSSFADE_TIMES: [f32; 5] = [0.0, 0.5, 1.0, 2.0, 3.0];
+static WINDOW_MODES: [WindowMode; 3] = [WindowMode::Windowed, WindowMode::Fullscreen, WindowMode::Borderless];
+static ASPECT_RATIOS: [AspectRatio; 2] = [AspectRatio::Standard, AspectRatio::Widescreen];
+static MAX_WINDOW_SCALE: u8 = 6;
 
 impl Scene for TitleScene {
     fn init(&mut self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
@@ -96,6 +173,7 @@ impl Scene for TitleScene {
         self.main_menu.push_entry(MenuEntry::Active("New game".to_string()));
         self.main_menu.push_entry(MenuEntry::Active("Load game".to_string()));
         self.main_menu.push_entry(MenuEntry::Active("Options".to_string()));
+        self.main_menu.push_entry(MenuEntry::Active("Mods".to_string()));
         if cfg!(feature = "editor") {
             self.main_menu.push_entry(MenuEntry::Active("Editor".to_string()));
         } else {
@@ -121,10 +199,112 @@ impl Scene for TitleScene {
         } else {
             self.option_menu.push_entry(MenuEntry::Disabled("Seasonal textures".to_string()));
         }
+
+        let (color_filters, screen_filters): (Vec<String>, Vec<String>) =
+            post_process::list_shaders(ctx).into_iter().partition(|name| post_process::is_color_filter(name));
+        self.color_filters = color_filters;
+        self.screen_filters = screen_filters;
+
+        let filter_index = |filters: &[String]| {
+            state
+                .settings
+                .post_process_chain
+                .iter()
+                .find_map(|name| filters.iter().position(|f| f == name))
+                .map_or(0, |i| i + 1)
+        };
+        let filter_name = |name: &str| {
+            let name = name.trim_start_matches(post_process::COLOR_FILTER_PREFIX).replace('_', " ");
+            let mut chars = name.chars();
+            chars.next().map_or(String::new(), |first| first.to_uppercase().chain(chars).collect())
+        };
+        self.option_menu.push_entry(MenuEntry::Options(
+            "Screen filter".to_string(),
+            filter_index(&self.screen_filters),
+            std::iter::once("Off".to_string())
+                .chain(self.screen_filters.iter().map(|name| filter_name(name)))
+                .collect(),
+        ));
+        self.option_menu.push_entry(MenuEntry::Options(
+            "Color vision".to_string(),
+            filter_index(&self.color_filters),
+            std::iter::once("Normal".to_string())
+                .chain(self.color_filters.iter().map(|name| filter_name(name)))
+                .collect(),
+        ));
+        self.option_menu.push_entry(MenuEntry::Active("Display...".to_string()));
+        self.option_menu.push_entry(MenuEntry::Active("Sound...".to_string()));
         self.option_menu.push_entry(MenuEntry::Active(DISCORD_LINK.to_owned()));
         self.option_menu.push_entry(MenuEntry::Disabled(["Renderer: ", &ctx.renderer.as_ref().unwrap().renderer_name()].join("")));
         self.option_menu.push_entry(MenuEntry::Active("Back".to_string()));
 
+        let volume_levels: Vec<String> = (0..=10).map(|i| format!("{}%", i * 10)).collect();
+        let volume_index = |volume: f32| (volume.max(0.0).min(1.0) * 10.0).round() as usize;
+        self.sound_menu.push_entry(MenuEntry::Options(
+            "Master volume".to_string(),
+            volume_index(state.settings.master_volume),
+            volume_levels.clone(),
+        ));
+        self.sound_menu.push_entry(MenuEntry::Options(
+            "Music volume".to_string(),
+            volume_index(state.settings.bgm_volume),
+            volume_levels.clone(),
+        ));
+        self.sound_menu.push_entry(MenuEntry::Options(
+            "Effects volume".to_string(),
+            volume_index(state.settings.sfx_volume),
+            volume_levels,
+        ));
+        let crossfade_index = CROSSFADE_TIMES
+            .iter()
+            .rposition(|&time| time <= state.settings.music_crossfade)
+            .unwrap_or(0);
+        self.sound_menu.push_entry(MenuEntry::Options(
+            "Music crossfade".to_string(),
+            crossfade_index,
+            CROSSFADE_TIMES
+                .iter()
+                .map(|&time| if time == 0.0 { "Off".to_string() } else { format!("{}s", time) })
+                .collect(),
+        ));
+        self.sound_menu
+            .push_entry(MenuEntry::Toggle("Positional effects".to_string(), state.settings.positional_sfx));
+        self.sound_menu.push_entry(MenuEntry::Active("Back".to_string()));
+
+        let display = &state.settings.display;
+        self.display_menu.push_entry(MenuEntry::Options(
+            "Window mode".to_string(),
+            WINDOW_MODES.iter().position(|&mode| mode == display.window_mode).unwrap_or(0),
+            vec!["Windowed".to_string(), "Fullscreen".to_string(), "Borderless".to_string()],
+        ));
+        self.display_menu.push_entry(MenuEntry::Options(
+            "Window size".to_string(),
+            (display.window_scale.max(1).min(MAX_WINDOW_SCALE) - 1) as usize,
+            (1..=MAX_WINDOW_SCALE).map(|scale| format!("{}x", scale)).collect(),
+        ));
+        self.display_menu.push_entry(MenuEntry::Toggle("Integer scaling".to_string(), display.integer_scaling));
+        self.display_menu.push_entry(MenuEntry::Toggle("V-Sync".to_string(), display.vsync));
+        self.display_menu.push_entry(MenuEntry::Options(
+            "Aspect ratio".to_string(),
+            ASPECT_RATIOS.iter().position(|&ratio| ratio == display.aspect_ratio).unwrap_or(0),
+            vec!["4:3".to_string(), "16:9".to_string()],
+        ));
+        self.display_menu.push_entry(MenuEntry::Active("Back".to_string()));
+
+        for info in state.mod_list.mods.iter() {
+            let title = format!("{} {}", info.manifest.name, info.manifest.version).trim_end().to_string();
+
+            if info.is_compatible(state.base_game) {
+                self.mods_menu.push_entry(MenuEntry::Toggle(title, state.settings.enabled_mods.contains(&info.id)));
+            } else {
+                self.mods_menu.push_entry(MenuEntry::Disabled(title));
+            }
+        }
+        if state.mod_list.mods.is_empty() {
+            self.mods_menu.push_entry(MenuEntry::Disabled("No mods installed".to_string()));
+        }
+        self.mods_menu.push_entry(MenuEntry::Active("Back".to_string()));
+
         self.save_select_menu.push_entry(MenuEntry::NewSave);
         self.save_select_menu.push_entry(MenuEntry::NewSave);
         self.save_select_menu.push_entry(MenuEntry::NewSave);
@@ -150,6 +330,18 @@ impl Scene for TitleScene {
         self.option_menu.x = ((state.canvas_size.0 - self.option_menu.width as f32) / 2.0).floor() as isize;
         self.option_menu.y = ((state.canvas_size.1 + 70.0 - self.option_menu.height as f32) / 2.0).floor() as isize;
 
+        self.sound_menu.update_height();
+        self.sound_menu.x = ((state.canvas_size.0 - self.sound_menu.width as f32) / 2.0).floor() as isize;
+        self.sound_menu.y = ((state.canvas_size.1 + 70.0 - self.sound_menu.height as f32) / 2.0).floor() as isize;
+
+        self.display_menu.update_height();
+        self.display_menu.x = ((state.canvas_size.0 - self.display_menu.width as f32) / 2.0).floor() as isize;
+        self.display_menu.y = ((state.canvas_size.1 + 70.0 - self.display_menu.height as f32) / 2.0).floor() as isize;
+
+        self.mods_menu.update_height();
+        self.mods_menu.x = ((state.canvas_size.0 - self.mods_menu.width as f32) / 2.0).floor() as isize;
+        self.mods_menu.y = ((state.canvas_size.1 + 70.0 - self.mods_menu.height as f32) / 2.0).floor() as isize;
+
         match self.current_menu {
             CurrentMenu::MainMenu => match self.main_menu.tick(&mut self.controller, state) {
                 MenuSelectionResult::Selected(0, _) => {
@@ -166,7 +358,10 @@ impl Scene for TitleScene {
                 MenuSelectionResult::Selected(2, _) => {
                     self.current_menu = CurrentMenu::OptionMenu;
                 }
-                MenuSelectionResult::Selected(4, _) => {
+                MenuSelectionResult::Selected(3, _) => {
+                    self.current_menu = CurrentMenu::ModsMenu;
+                }
+                MenuSelectionResult::Selected(5, _) => {
                     state.shutdown();
                 }
                 _ => {}
@@ -210,16 +405,139 @@ impl Scene for TitleScene {
                         *value = state.settings.seasonal_textures;
                     }
                 }
-                MenuSelectionResult::Selected(4, _) => {
+                MenuSelectionResult::Left(idx @ 4..=5, MenuEntry::Options(_, value, _)) => {
+                    *value = value.saturating_sub(1);
+
+                    let filters = if idx == 4 { &self.screen_filters } else { &self.color_filters };
+                    TitleScene::apply_filter_option(state, ctx, filters, *value, idx == 5);
+                }
+                MenuSelectionResult::Right(idx @ 4..=5, MenuEntry::Options(_, value, options)) => {
+                    if *value + 1 < options.len() {
+                        *value += 1;
+                    }
+
+                    let filters = if idx == 4 { &self.screen_filters } else { &self.color_filters };
+                    TitleScene::apply_filter_option(state, ctx, filters, *value, idx == 5);
+                }
+                MenuSelectionResult::Selected(6, _) => {
+                    self.current_menu = CurrentMenu::DisplayMenu;
+                }
+                MenuSelectionResult::Selected(7, _) => {
+                    self.current_menu = CurrentMenu::SoundMenu;
+                }
+                MenuSelectionResult::Selected(8, _) => {
                     if let Err(e) = webbrowser::open(DISCORD_LINK) {
                         log::warn!("Error opening web browser: {}", e);
                     }
                 }
-                MenuSelectionResult::Selected(6, _) | MenuSelectionResult::Canceled => {
+                MenuSelectionResult::Selected(10, _) | MenuSelectionResult::Canceled => {
                     self.current_menu = CurrentMenu::MainMenu;
                 }
                 _ => {}
             },
+            CurrentMenu::SoundMenu => match self.sound_menu.tick(&mut self.controller, state) {
+                MenuSelectionResult::Left(idx, MenuEntry::Options(_, value, _)) => {
+                    *value = value.saturating_sub(1);
+
+                    TitleScene::apply_sound_option(state, idx, *value);
+                    let _ = state.settings.save(ctx);
+                }
+                MenuSelectionResult::Right(idx, MenuEntry::Options(_, value, options)) => {
+                    if *value + 1 < options.len() {
+                        *value += 1;
+                    }
+
+                    TitleScene::apply_sound_option(state, idx, *value);
+                    let _ = state.settings.save(ctx);
+                }
+                MenuSelectionResult::Selected(4, toggle) => {
+                    if let MenuEntry::Toggle(_, value) = toggle {
+                        state.settings.positional_sfx = !state.settings.positional_sfx;
+                        let _ = state.settings.save(ctx);
+
+                        *value = state.settings.positional_sfx;
+                    }
+                }
+                MenuSelectionResult::Selected(5, _) | MenuSelectionResult::Canceled => {
+                    self.current_menu = CurrentMenu::OptionMenu;
+                }
+                _ => {}
+            },
+            CurrentMenu::DisplayMenu => match self.display_menu.tick(&mut self.controller, state) {
+                MenuSelectionResult::Left(idx, MenuEntry::Options(_, value, _)) => {
+                    *value = value.saturating_sub(1);
+
+                    TitleScene::apply_display_option(state, ctx, idx, *value);
+                }
+                MenuSelectionResult::Right(idx, MenuEntry::Options(_, value, options)) => {
+                    if *value + 1 < options.len() {
+                        *value += 1;
+                    }
+
+                    TitleScene::apply_display_option(state, ctx, idx, *value);
+                }
+                MenuSelectionResult::Selected(2, toggle) => {
+                    if let MenuEntry::Toggle(_, value) = toggle {
+                        state.settings.display.integer_scaling = !state.settings.display.integer_scaling;
+                        let _ = state.handle_resize(ctx);
+                        let _ = state.settings.save(ctx);
+
+                        *value = state.settings.display.integer_scaling;
+                    }
+                }
+                MenuSelectionResult::Selected(3, toggle) => {
+                    if let MenuEntry::Toggle(_, value) = toggle {
+                        state.settings.display.vsync = !state.settings.display.vsync;
+                        state.display_changed = true;
+                        let _ = state.settings.save(ctx);
+
+                        *value = state.settings.display.vsync;
+                    }
+                }
+                MenuSelectionResult::Selected(5, _) | MenuSelectionResult::Canceled => {
+                    self.current_menu = CurrentMenu::OptionMenu;
+                }
+                _ => {}
+            },
+            CurrentMenu::ModsMenu => {
+                let back_idx = self.mods_menu.entries.len() - 1;
+                let mut leave = false;
+
+                match self.mods_menu.tick(&mut self.controller, state) {
+                    MenuSelectionResult::Selected(idx, _) if idx == back_idx => {
+                        leave = true;
+                    }
+                    MenuSelectionResult::Canceled => {
+                        leave = true;
+                    }
+                    MenuSelectionResult::Selected(idx, toggle) => {
+                        if let (MenuEntry::Toggle(_, value), Some(info)) = (toggle, state.mod_list.mods.get(idx)) {
+                            let id = info.id.clone();
+
+                            if let Some(pos) = state.settings.enabled_mods.iter().position(|m| *m == id) {
+                                state.settings.enabled_mods.remove(pos);
+                            } else {
+                                state.settings.enabled_mods.push(id);
+                            }
+                            let _ = state.settings.save(ctx);
+
+                            *value = !*value;
+                            self.mods_changed = true;
+                        }
+                    }
+                    _ => {}
+                }
+
+                if leave {
+                    if self.mods_changed {
+                        self.mods_changed = false;
+                        state.sound_manager.play_song(0, &state.constants, &state.settings, ctx)?;
+                        state.reload_mods(ctx)?;
+                    }
+
+                    self.current_menu = CurrentMenu::MainMenu;
+                }
+            }
             CurrentMenu::StartGame => {
                 if self.tick == 10 {
                     state.reset_skip_flags();
@@ -268,6 +586,15 @@ impl Scene for TitleScene {
             CurrentMenu::OptionMenu => {
                 self.option_menu.draw(state, ctx)?;
             }
+            CurrentMenu::SoundMenu => {
+                self.sound_menu.draw(state, ctx)?;
+            }
+            CurrentMenu::DisplayMenu => {
+                self.display_menu.draw(state, ctx)?;
+            }
+            CurrentMenu::ModsMenu => {
+                self.mods_menu.draw(state, ctx)?;
+            }
             _ => {}
         }
 
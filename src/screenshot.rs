@@ -0,0 +1,60 @@
+use std::io::BufWriter;
+
+use chrono::Local;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageOutputFormat, RgbaImage};
+
+use crate::framework::context::Context;
+use crate::framework::error::GameError::RenderError;
+use crate::framework::error::GameResult;
+use crate::framework::{filesystem, graphics};
+use crate::shared_game_state::SharedGameState;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ScreenshotScale {
+    /// Downscaled to the game canvas, one pixel per in-game pixel.
+    Canvas,
+    /// As drawn to the window.
+    Window,
+}
+
+/// Reads back the frame drawn so far, scaled according to `scale`.
+pub fn capture_frame(scale: ScreenshotScale, state: &SharedGameState, ctx: &mut Context) -> GameResult<RgbaImage> {
+    let (width, height, data) = graphics::read_screen(ctx)?;
+    let image = RgbaImage::from_raw(width as u32, height as u32, data)
+        .ok_or_else(|| RenderError("Screen read-back returned invalid amount of data.".to_string()))?;
+
+    let canvas_width = state.canvas_size.0.round().max(1.0) as u32;
+    let canvas_height = state.canvas_size.1.round().max(1.0) as u32;
+
+    Ok(match scale {
+        ScreenshotScale::Canvas if image.dimensions() != (canvas_width, canvas_height) => {
+            image::imageops::resize(&image, canvas_width, canvas_height, FilterType::Nearest)
+        }
+        _ => image,
+    })
+}
+
+/// Saves the frame drawn so far as a timestamped PNG in the `screenshots` directory of user data,
+/// returns the path it has been written to.
+pub fn save_screenshot(scale: ScreenshotScale, state: &SharedGameState, ctx: &mut Context) -> GameResult<String> {
+    let image = capture_frame(scale, state, ctx)?;
+    let name = Local::now().format("%Y-%m-%d_%H-%M-%S_%3f").to_string();
+
+    save_image(image, &name, ctx)
+}
+
+/// Saves an image as PNG with given name in the `screenshots` directory of user data,
+/// returns the path it has been written to.
+pub fn save_image(image: RgbaImage, name: &str, ctx: &mut Context) -> GameResult<String> {
+    let path = format!("/screenshots/{}.png", name);
+
+    filesystem::user_create_dir(ctx, "/screenshots")?;
+    let mut file = BufWriter::new(filesystem::user_create(ctx, &path)?);
+    DynamicImage::ImageRgba8(image).write_to(&mut file, ImageOutputFormat::Png)?;
+
+    Ok(match filesystem::user_physical_path(ctx, &path) {
+        Some(physical) => physical.display().to_string(),
+        None => path,
+    })
+}
@@ -346,6 +346,47 @@ function doukutsu.playSong(id)
     __doukutsu_rs:playSong(id)
 end
 
+function doukutsu.setVolume(master, music, sfx)
+    __doukutsu_rs:setVolume(master, music, sfx)
+end
+
+function doukutsu.getVolume()
+    local master, music, sfx = __doukutsu_rs:getVolume()
+    return { master, music, sfx }
+end
+
+function doukutsu.screenshot(native)
+    __doukutsu_rs:screenshot(native ~= false)
+end
+
+function doukutsu.addLight(id, x, y, size, r, g, b)
+    __doukutsu_rs:addLight(id, x, y, size, r, g, b)
+end
+
+function doukutsu.removeLight(id)
+    __doukutsu_rs:removeLight(id)
+end
+
+function doukutsu.setCameraZoom(zoom, ticks)
+    __doukutsu_rs:setCameraZoom(zoom, ticks)
+end
+
+function doukutsu.getCameraZoom()
+    return __doukutsu_rs:getCameraZoom()
+end
+
+function doukutsu.setCameraLookAhead(x, y)
+    __doukutsu_rs:setCameraLookAhead(x, y)
+end
+
+function doukutsu.setCameraBounds(left, top, right, bottom)
+    __doukutsu_rs:setCameraBounds(left, top, right, bottom)
+end
+
+function doukutsu.setCameraMultiTarget(enabled)
+    __doukutsu_rs:setCameraMultiTarget(enabled)
+end
+
 function doukutsu.players()
     return { __doukutsu_rs_runtime_dont_touch._playerRef0, __doukutsu_rs_runtime_dont_touch._playerRef1 }
 end
@@ -204,6 +204,75 @@ declare namespace doukutsu {
      */
     function playMusic(id: number): void;
 
+    /**
+     * Sets master, music and sound effect volume in 0.0 - 1.0 range and saves them to settings.
+     * Passing nil leaves the respective volume unchanged.
+     */
+    function setVolume(master?: number, music?: number, sfx?: number): void;
+
+    /**
+     * Returns master, music and sound effect volume in 0.0 - 1.0 range.
+     */
+    function getVolume(): [number, number, number];
+
+    /**
+     * Saves a screenshot of the current frame as a PNG in the screenshots directory of user data.
+     * @param native true (default) to save at game canvas resolution, false to save at window scale.
+     */
+    function screenshot(native?: boolean): void;
+
+    /**
+     * Adds a light to the light map of current stage, replacing the one with the same ID.
+     * Lights are removed when the stage changes.
+     * @param id the light ID, shared with lights added by <LIA in TSC
+     * @param x the x position in pixels
+     * @param y the y position in pixels
+     * @param size the light size, 1.0 being 64 pixels wide
+     * @param r red component, 0-255, 200 if not set
+     * @param g green component, 0-255, 200 if not set
+     * @param b blue component, 0-255, 200 if not set
+     */
+    function addLight(id: number, x: number, y: number, size: number, r?: number, g?: number, b?: number): void;
+
+    /**
+     * Removes a light with specified ID, or all lights added by scripts if ID equals 0.
+     */
+    function removeLight(id: number): void;
+
+    /**
+     * Smoothly zooms the camera, limited by min_zoom and max_zoom engine constants.
+     * @param zoom the zoom level, 1.0 being the normal view and 2.0 showing half as much of the stage
+     * @param ticks how slowly the zoom changes, the same way as ticks of <FOM, camera.zoom_wait constant if not set
+     */
+    function setCameraZoom(zoom: number, ticks?: number): void;
+
+    /**
+     * Returns the zoom level the camera is zooming to, or nil if not in game.
+     */
+    function getCameraZoom(): number | null;
+
+    /**
+     * Sets how far the camera leads ahead of the player in the direction they're facing or looking.
+     * @param x the horizontal distance in pixels, 64 by default
+     * @param y the vertical distance in pixels, 64 by default
+     */
+    function setCameraLookAhead(x: number, y: number): void;
+
+    /**
+     * Keeps the camera view within specified area, overriding camera regions of the stage.
+     * Called without arguments, restores stage bounds. Bounds are reset when the stage changes.
+     * @param left the left edge in pixels
+     * @param top the top edge in pixels
+     * @param right the right edge in pixels
+     * @param bottom the bottom edge in pixels
+     */
+    function setCameraBounds(left?: number, top?: number, right?: number, bottom?: number): void;
+
+    /**
+     * Enables or disables keeping both players in view in co-op, zooming out if they're far apart.
+     */
+    function setCameraMultiTarget(enabled: boolean): void;
+
     /**
      * Returns the value of a certain TSC flag.
      * @param id the flag number
@@ -6,6 +6,8 @@ use lua_ffi::{c_int, LuaObject, State};
 use crate::common::{Direction, Rect};
 use crate::framework::filesystem;
 use crate::rng::RNG;
+use crate::scene::game_scene::DynamicLight;
+use crate::screenshot::ScreenshotScale;
 use crate::scripting::{check_status, LuaScriptingState, DRS_RUNTIME_GLOBAL};
 
 pub struct Doukutsu {
@@ -50,6 +52,168 @@ impl Doukutsu {
         0
     }
 
+    unsafe fn lua_set_volume(&self, state: &mut State) -> c_int {
+        let game_state = &mut (*(*self.ptr).state_ptr);
+        let ctx = &mut (*(*self.ptr).ctx_ptr);
+
+        if let Some(volume) = state.to_float(2) {
+            game_state.settings.master_volume = volume.max(0.0).min(1.0);
+        }
+
+        if let Some(volume) = state.to_float(3) {
+            game_state.settings.bgm_volume = volume.max(0.0).min(1.0);
+        }
+
+        if let Some(volume) = state.to_float(4) {
+            game_state.settings.sfx_volume = volume.max(0.0).min(1.0);
+        }
+
+        let _ = game_state.sound_manager.update_volume(&game_state.settings);
+        let _ = game_state.settings.save(ctx);
+
+        0
+    }
+
+    unsafe fn lua_get_volume(&self, state: &mut State) -> c_int {
+        let game_state = &mut (*(*self.ptr).state_ptr);
+
+        state.push(game_state.settings.master_volume);
+        state.push(game_state.settings.bgm_volume);
+        state.push(game_state.settings.sfx_volume);
+
+        3
+    }
+
+    unsafe fn lua_screenshot(&self, state: &mut State) -> c_int {
+        let game_state = &mut (*(*self.ptr).state_ptr);
+
+        game_state.screenshot_request = Some(if state.to_bool(2).unwrap_or(true) {
+            ScreenshotScale::Canvas
+        } else {
+            ScreenshotScale::Window
+        });
+
+        0
+    }
+
+    unsafe fn lua_add_light(&self, state: &mut State) -> c_int {
+        if (*self.ptr).game_scene.is_null() {
+            return 0;
+        }
+
+        if let (Some(id), Some(x), Some(y), Some(size)) =
+            (state.to_int(2), state.to_float(3), state.to_float(4), state.to_float(5))
+        {
+            let game_scene = &mut *(*self.ptr).game_scene;
+            let r = state.to_int(6).unwrap_or(200).max(0).min(255) as u8;
+            let g = state.to_int(7).unwrap_or(200).max(0).min(255) as u8;
+            let b = state.to_int(8).unwrap_or(200).max(0).min(255) as u8;
+
+            game_scene.set_light(DynamicLight {
+                id: id.max(0) as u16,
+                x: (x * 512.0) as i32,
+                y: (y * 512.0) as i32,
+                size,
+                color: (r, g, b),
+            });
+        }
+
+        0
+    }
+
+    unsafe fn lua_remove_light(&self, state: &mut State) -> c_int {
+        if (*self.ptr).game_scene.is_null() {
+            return 0;
+        }
+
+        if let Some(id) = state.to_int(2) {
+            let game_scene = &mut *(*self.ptr).game_scene;
+
+            game_scene.remove_light(id.max(0) as u16);
+        }
+
+        0
+    }
+
+    unsafe fn lua_set_camera_zoom(&self, state: &mut State) -> c_int {
+        if (*self.ptr).game_scene.is_null() {
+            return 0;
+        }
+
+        if let Some(zoom) = state.to_float(2) {
+            let game_state = &mut (*(*self.ptr).state_ptr);
+            let game_scene = &mut *(*self.ptr).game_scene;
+            let ticks = state.to_int(3).map_or(game_state.constants.camera.zoom_wait, |ticks| ticks as i32);
+
+            game_scene.frame.set_zoom(zoom, ticks);
+        }
+
+        0
+    }
+
+    unsafe fn lua_get_camera_zoom(&self, state: &mut State) -> c_int {
+        if (*self.ptr).game_scene.is_null() {
+            state.push_nil();
+        } else {
+            let game_scene = &mut *(*self.ptr).game_scene;
+
+            state.push(game_scene.frame.target_zoom);
+        }
+
+        1
+    }
+
+    unsafe fn lua_set_camera_look_ahead(&self, state: &mut State) -> c_int {
+        if (*self.ptr).game_scene.is_null() {
+            return 0;
+        }
+
+        if let (Some(x), Some(y)) = (state.to_int(2), state.to_int(3)) {
+            let game_scene = &mut *(*self.ptr).game_scene;
+
+            game_scene.frame.look_ahead = (x as i32, y as i32);
+        }
+
+        0
+    }
+
+    unsafe fn lua_set_camera_bounds(&self, state: &mut State) -> c_int {
+        if (*self.ptr).game_scene.is_null() {
+            return 0;
+        }
+
+        let game_scene = &mut *(*self.ptr).game_scene;
+
+        game_scene.frame.bounds = if let (Some(left), Some(top), Some(right), Some(bottom)) =
+            (state.to_float(2), state.to_float(3), state.to_float(4), state.to_float(5))
+        {
+            Some(Rect {
+                left: (left * 512.0) as i32,
+                top: (top * 512.0) as i32,
+                right: (right * 512.0) as i32,
+                bottom: (bottom * 512.0) as i32,
+            })
+        } else {
+            None
+        };
+
+        0
+    }
+
+    unsafe fn lua_set_camera_multi_target(&self, state: &mut State) -> c_int {
+        if (*self.ptr).game_scene.is_null() {
+            return 0;
+        }
+
+        if let Some(enabled) = state.to_bool(2) {
+            let game_scene = &mut *(*self.ptr).game_scene;
+
+            game_scene.frame.multi_target = enabled;
+        }
+
+        0
+    }
+
     unsafe fn lua_set_setting(&self, state: &mut State) -> c_int {
         if let Some(index) = state.to_int(2) {
             let game_state = &mut (*(*self.ptr).state_ptr);
@@ -471,6 +635,16 @@ impl LuaObject for Doukutsu {
         vec![
             lua_method!("playSfx", Doukutsu, Doukutsu::lua_play_sfx),
             lua_method!("playSong", Doukutsu, Doukutsu::lua_play_song),
+            lua_method!("setVolume", Doukutsu, Doukutsu::lua_set_volume),
+            lua_method!("getVolume", Doukutsu, Doukutsu::lua_get_volume),
+            lua_method!("screenshot", Doukutsu, Doukutsu::lua_screenshot),
+            lua_method!("addLight", Doukutsu, Doukutsu::lua_add_light),
+            lua_method!("removeLight", Doukutsu, Doukutsu::lua_remove_light),
+            lua_method!("setCameraZoom", Doukutsu, Doukutsu::lua_set_camera_zoom),
+            lua_method!("getCameraZoom", Doukutsu, Doukutsu::lua_get_camera_zoom),
+            lua_method!("setCameraLookAhead", Doukutsu, Doukutsu::lua_set_camera_look_ahead),
+            lua_method!("setCameraBounds", Doukutsu, Doukutsu::lua_set_camera_bounds),
+            lua_method!("setCameraMultiTarget", Doukutsu, Doukutsu::lua_set_camera_multi_target),
             lua_method!("getFlag", Doukutsu, Doukutsu::lua_get_flag),
             lua_method!("setFlag", Doukutsu, Doukutsu::lua_set_flag),
             lua_method!("getSkipFlag", Doukutsu, Doukutsu::lua_get_skip_flag),
@@ -164,6 +164,25 @@ impl LuaScriptingState {
             }
         }
 
+        if !self.state_ptr.is_null() {
+            let game_state = unsafe { &*self.state_ptr };
+
+            for info in game_state.mod_list.enabled(&game_state.settings, game_state.base_game) {
+                for script_path in info.manifest.scripts.iter() {
+                    let path = info.user_path(script_path);
+
+                    match filesystem::user_open(ctx, &path) {
+                        Ok(script) => {
+                            LuaScriptingState::load_script(&mut state, &path, script);
+                        }
+                        Err(err) => {
+                            log::warn!("Error opening script {:?} of mod {}: {}", path, info.id, err);
+                        }
+                    }
+                }
+            }
+        }
+
         self.state = Some(state);
 
         Ok(())
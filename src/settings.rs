@@ -22,6 +22,26 @@ pub struct Settings {
     pub player1_key_map: PlayerKeyMap,
     #[serde(default = "p2_default_keymap")]
     pub player2_key_map: PlayerKeyMap,
+    #[serde(default)]
+    pub enabled_mods: Vec<String>,
+    /// Duration of crossfade between songs in seconds, songs are switched instantly if it's zero.
+    #[serde(default)]
+    pub music_crossfade: f32,
+    /// Volume levels in 0.0 - 1.0 range, music and sound effect volume are scaled by master volume.
+    #[serde(default = "default_volume")]
+    pub master_volume: f32,
+    #[serde(default = "default_volume")]
+    pub bgm_volume: f32,
+    #[serde(default = "default_volume")]
+    pub sfx_volume: f32,
+    /// Pans and attenuates sound effects of NPCs and bullets by where they are relative to the camera.
+    #[serde(default)]
+    pub positional_sfx: bool,
+    /// Names of shaders in `/shaders/postprocess` without extension, applied to the frame in order.
+    #[serde(default)]
+    pub post_process_chain: Vec<String>,
+    #[serde(default)]
+    pub display: DisplaySettings,
     #[serde(skip, default = "default_speed")]
     pub speed: f64,
     #[serde(skip)]
@@ -36,6 +56,10 @@ fn default_speed() -> f64 {
     1.0
 }
 
+fn default_volume() -> f32 {
+    1.0
+}
+
 impl Settings {
     pub fn load(ctx: &Context) -> GameResult<Settings> {
         if let Ok(file) = user_open(ctx, "/settings.yml") {
@@ -79,6 +103,14 @@ impl Default for Settings {
             soundtrack: "".to_string(),
             player1_key_map: p1_default_keymap(),
             player2_key_map: p2_default_keymap(),
+            enabled_mods: Vec::new(),
+            music_crossfade: 0.0,
+            master_volume: 1.0,
+            bgm_volume: 1.0,
+            sfx_volume: 1.0,
+            positional_sfx: false,
+            post_process_chain: Vec::new(),
+            display: DisplaySettings::default(),
             speed: 1.0,
             god_mode: false,
             infinite_booster: false,
@@ -87,6 +119,68 @@ impl Default for Settings {
     }
 }
 
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WindowMode {
+    Windowed,
+    /// Exclusive fullscreen in the current video mode of the monitor.
+    Fullscreen,
+    /// Borderless window covering the whole monitor.
+    Borderless,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AspectRatio {
+    /// 4:3, as in the original game.
+    Standard,
+    /// 16:9
+    Widescreen,
+}
+
+impl AspectRatio {
+    /// Size of the area of the world the game is designed to show, in game pixels.
+    pub fn viewport_size(self) -> (f32, f32) {
+        match self {
+            AspectRatio::Standard => (320.0, 240.0),
+            AspectRatio::Widescreen => (426.0, 240.0),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct DisplaySettings {
+    pub window_mode: WindowMode,
+    /// Size of the window in windowed mode as a multiple of the viewport size.
+    pub window_scale: u8,
+    /// Keeps the canvas at viewport size and scales it by whole numbers only, centering it in the window with
+    /// black bars around. Otherwise the canvas grows to fill the window and shows more of the world.
+    pub integer_scaling: bool,
+    pub vsync: bool,
+    pub aspect_ratio: AspectRatio,
+}
+
+impl DisplaySettings {
+    /// Size of the window in windowed mode.
+    pub fn window_size(&self) -> (u32, u32) {
+        let (width, height) = self.aspect_ratio.viewport_size();
+        let scale = self.window_scale.max(1) as f32;
+
+        ((width * scale) as u32, (height * scale) as u32)
+    }
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        DisplaySettings {
+            window_mode: WindowMode::Windowed,
+            window_scale: 2,
+            integer_scaling: false,
+            vsync: true,
+            aspect_ratio: AspectRatio::Standard,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct PlayerKeyMap {
     pub left: ScanCode,
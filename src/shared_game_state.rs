@@ -4,10 +4,10 @@ use bitvec::array::BitArray;
 use bitvec::vec::BitVec;
 use chrono::{Datelike, Local};
 
-use crate::bmfont_renderer::BMFontRenderer;
 use crate::caret::{Caret, CaretType};
-use crate::common::{ControlFlags, Direction, FadeState};
+use crate::common::{ControlFlags, Direction, FadeState, Rect};
 use crate::engine_constants::EngineConstants;
+use crate::font_renderer::FontRenderer;
 use crate::framework::backend::BackendTexture;
 use crate::framework::context::Context;
 use crate::framework::error::GameResult;
@@ -18,22 +18,25 @@ use crate::framework::{filesystem, graphics};
 #[cfg(feature = "hooks")]
 use crate::hooks::init_hooks;
 use crate::input::touch_controls::TouchControls;
+use crate::mod_loader::{BaseGame, ModList};
 use crate::npc::NPCTable;
 use crate::profile::GameProfile;
 use crate::rng::XorShift;
 use crate::scene::game_scene::GameScene;
+use crate::scene::loading_scene::LoadingScene;
 use crate::scene::title_scene::TitleScene;
 use crate::scene::Scene;
+use crate::screenshot::ScreenshotScale;
 #[cfg(feature = "scripting")]
 use crate::scripting::LuaScriptingState;
 use crate::settings::Settings;
-use crate::sound::SoundManager;
+use crate::sound::{AudioOutput, SoundManager};
 use crate::stage::StageData;
 use crate::str;
 use crate::text_script::{ScriptMode, TextScriptExecutionState, TextScriptVM};
-use crate::texture_set::TextureSet;
+use crate::texture_set::{TextureSet, G_MAG, I_MAG};
 
-#[derive(PartialEq, Eq, Copy, Clone)]
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
 pub enum TimingMode {
     _50Hz,
     _60Hz,
@@ -135,6 +138,7 @@ pub struct SharedGameState {
     pub debugger: bool,
     pub scale: f32,
     pub canvas_size: (f32, f32),
+    /// Size of the area of the window the game is drawn to, smaller than the window if it's letterboxed.
     pub screen_size: (f32, f32),
     pub preferred_viewport_size: (f32, f32),
     pub next_scene: Option<Box<dyn Scene>>,
@@ -142,39 +146,63 @@ pub struct SharedGameState {
     pub lightmap_canvas: Option<Box<dyn BackendTexture>>,
     pub season: Season,
     pub constants: EngineConstants,
-    pub font: BMFontRenderer,
+    pub font: FontRenderer,
     pub texture_set: TextureSet,
     #[cfg(feature = "scripting")]
     pub lua: LuaScriptingState,
     pub sound_manager: SoundManager,
     pub settings: Settings,
+    pub base_game: BaseGame,
+    pub mod_list: ModList,
     pub shutdown: bool,
+    /// Screenshot to be taken once the current frame is drawn.
+    pub screenshot_request: Option<ScreenshotScale>,
+    /// Window mode, size or vsync settings have changed and have to be applied to the window by the backend.
+    pub display_changed: bool,
 }
 
 impl SharedGameState {
     pub fn new(ctx: &mut Context) -> GameResult<SharedGameState> {
+        SharedGameState::with_audio_output(AudioOutput::from_env(), ctx)
+    }
+
+    pub fn with_audio_output(audio_output: AudioOutput, ctx: &mut Context) -> GameResult<SharedGameState> {
         let mut constants = EngineConstants::defaults();
-        let sound_manager = SoundManager::new(ctx)?;
+        let mut sound_manager = SoundManager::with_output(audio_output, ctx)?;
         let mut base_path = "/";
         let settings = Settings::load(ctx)?;
+        sound_manager.update_volume(&settings)?;
+        let base_game = BaseGame::detect(ctx);
+
+        match base_game {
+            BaseGame::CSPlus => {
+                info!("Cave Story+ (PC) data files detected.");
+                constants.apply_csplus_patches(&mut sound_manager);
+                base_path = "/base/";
+            }
+            BaseGame::Switch => {
+                info!("Cave Story+ (Switch) data files detected.");
+                constants.apply_csplus_patches(&mut sound_manager);
+                constants.apply_csplus_nx_patches();
+                base_path = "/base/";
+            }
+            BaseGame::Freeware if filesystem::exists(ctx, "/mrmap.bin") => info!("CSE2E data files detected."),
+            BaseGame::Freeware if filesystem::exists(ctx, "/stage.dat") => info!("NXEngine-evo data files detected."),
+            BaseGame::Freeware => {}
+        }
 
-        if filesystem::exists(ctx, "/base/Nicalis.bmp") {
-            info!("Cave Story+ (PC) data files detected.");
-            constants.apply_csplus_patches(&sound_manager);
-            base_path = "/base/";
-        } else if filesystem::exists(ctx, "/base/lighting.tbl") {
-            info!("Cave Story+ (Switch) data files detected.");
-            constants.apply_csplus_patches(&sound_manager);
-            constants.apply_csplus_nx_patches();
-            base_path = "/base/";
-        } else if filesystem::exists(ctx, "/mrmap.bin") {
-            info!("CSE2E data files detected.");
-        } else if filesystem::exists(ctx, "/stage.dat") {
-            info!("NXEngine-evo data files detected.");
+        if let Err(err) = constants.load_overrides(base_path, ctx) {
+            log::warn!("Failed to load engine constants overrides: {}", err);
         }
 
-        let font = BMFontRenderer::load(base_path, &constants.font_path, ctx)
-            .or_else(|_| BMFontRenderer::load("/", "builtin/builtin_font.fnt", ctx))?;
+        let mut mod_list = ModList::new();
+        if let Err(err) = mod_list.scan(ctx) {
+            log::warn!("Failed to look up installed mods: {}", err);
+        }
+        mod_list.mount(ctx, &settings, base_game);
+        mod_list.apply_constants(&mut constants, ctx, &settings, base_game);
+
+        let font = FontRenderer::load(base_path, &constants, ctx)?;
         let season = Season::current();
         let mut texture_set = TextureSet::new(base_path);
 
@@ -182,31 +210,7 @@ impl SharedGameState {
             texture_set.apply_seasonal_content(season, &settings);
         }
 
-        for i in 0..0xffu8 {
-            let path = format!("{}/pxt/fx{:02x}.pxt", base_path, i);
-            if let Ok(file) = filesystem::open(ctx, path) {
-                sound_manager.set_sample_params_from_file(i, file)?;
-                continue;
-            }
-
-            let path = format!("/pxt/fx{:02x}.pxt", i);
-            if let Ok(file) = filesystem::open(ctx, path) {
-                sound_manager.set_sample_params_from_file(i, file)?;
-                continue;
-            }
-
-            let path = format!("{}/PixTone/{:03}.pxt", base_path, i);
-            if let Ok(file) = filesystem::open(ctx, path) {
-                sound_manager.set_sample_params_from_file(i, file)?;
-                continue;
-            }
-
-            let path = format!("/PixTone/{:03}.pxt", i);
-            if let Ok(file) = filesystem::open(ctx, path) {
-                sound_manager.set_sample_params_from_file(i, file)?;
-                continue;
-            }
-        }
+        SharedGameState::load_sample_params(&mut sound_manager, base_path, ctx)?;
 
         println!("lookup path: {:#?}", texture_set.paths);
 
@@ -238,7 +242,7 @@ impl SharedGameState {
             scale: 2.0,
             screen_size: (640.0, 480.0),
             canvas_size: (320.0, 240.0),
-            preferred_viewport_size: (320.0, 240.0),
+            preferred_viewport_size: settings.display.aspect_ratio.viewport_size(),
             next_scene: None,
             textscript_vm: TextScriptVM::new(),
             lightmap_canvas: None,
@@ -250,12 +254,48 @@ impl SharedGameState {
             lua: LuaScriptingState::new(),
             sound_manager,
             settings,
+            base_game,
+            mod_list,
             shutdown: false,
+            screenshot_request: None,
+            display_changed: false,
         })
     }
 
+    fn load_sample_params(sound_manager: &mut SoundManager, base_path: &str, ctx: &mut Context) -> GameResult {
+        for i in 0..0xffu8 {
+            let path = format!("{}/pxt/fx{:02x}.pxt", base_path, i);
+            if let Ok(file) = filesystem::open(ctx, path) {
+                sound_manager.set_sample_params_from_file(i, file)?;
+                continue;
+            }
+
+            let path = format!("/pxt/fx{:02x}.pxt", i);
+            if let Ok(file) = filesystem::open(ctx, path) {
+                sound_manager.set_sample_params_from_file(i, file)?;
+                continue;
+            }
+
+            let path = format!("{}/PixTone/{:03}.pxt", base_path, i);
+            if let Ok(file) = filesystem::open(ctx, path) {
+                sound_manager.set_sample_params_from_file(i, file)?;
+                continue;
+            }
+
+            let path = format!("/PixTone/{:03}.pxt", i);
+            if let Ok(file) = filesystem::open(ctx, path) {
+                sound_manager.set_sample_params_from_file(i, file)?;
+                continue;
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn process_debug_keys(&mut self, key_code: ScanCode) {
         match key_code {
+            ScanCode::F1 => self.screenshot_request = Some(ScreenshotScale::Window),
+            ScanCode::F2 => self.screenshot_request = Some(ScreenshotScale::Canvas),
             ScanCode::F3 => self.settings.god_mode = !self.settings.god_mode,
             ScanCode::F4 => self.settings.infinite_booster = !self.settings.infinite_booster,
             ScanCode::F5 => self.settings.subpixel_coords = !self.settings.subpixel_coords,
@@ -287,6 +327,41 @@ impl SharedGameState {
         self.texture_set = texture_set;
     }
 
+    /// Remounts mods enabled in settings and reloads all game data that might have been affected by them.
+    pub fn reload_mods(&mut self, ctx: &mut Context) -> GameResult {
+        self.mod_list.unmount(ctx);
+        // sound effects replaced by mods which have been disabled since would stay otherwise
+        self.sound_manager.reset_sample_params()?;
+
+        let mut constants = EngineConstants::defaults();
+        match self.base_game {
+            BaseGame::CSPlus => constants.apply_csplus_patches(&mut self.sound_manager),
+            BaseGame::Switch => {
+                constants.apply_csplus_patches(&mut self.sound_manager);
+                constants.apply_csplus_nx_patches();
+            }
+            BaseGame::Freeware => {}
+        }
+
+        if let Err(err) = constants.load_overrides(&self.base_path, ctx) {
+            log::warn!("Failed to load engine constants overrides: {}", err);
+        }
+
+        self.mod_list.scan(ctx)?;
+        self.mod_list.mount(ctx, &self.settings, self.base_game);
+        self.mod_list.apply_constants(&mut constants, ctx, &self.settings, self.base_game);
+
+        self.constants = constants;
+
+        self.font = FontRenderer::load(&self.base_path, &self.constants, ctx)?;
+        self.reload_textures();
+        SharedGameState::load_sample_params(&mut self.sound_manager, &self.base_path, ctx)?;
+
+        self.next_scene = Some(Box::new(LoadingScene::new()));
+
+        Ok(())
+    }
+
     pub fn graphics_reset(&mut self) {
         self.reload_textures();
     }
@@ -388,13 +463,41 @@ impl SharedGameState {
         self.textscript_vm.suspend = true;
     }
 
+    /// Changes size of game pixels on the screen for following draws, `canvas_size` being the canvas size matching it.
+    /// Used to zoom the camera, the original values have to be restored afterwards.
+    pub fn set_draw_scale(&mut self, scale: f32, canvas_size: (f32, f32)) {
+        self.scale = scale;
+        self.canvas_size = canvas_size;
+
+        unsafe {
+            G_MAG = if self.settings.subpixel_coords { scale } else { 1.0 };
+            I_MAG = scale;
+        }
+    }
+
     pub fn handle_resize(&mut self, ctx: &mut Context) -> GameResult {
-        self.screen_size = graphics::screen_size(ctx);
-        let scale_x = self.screen_size.1.div(self.preferred_viewport_size.1).floor().max(1.0);
-        let scale_y = self.screen_size.0.div(self.preferred_viewport_size.0).floor().max(1.0);
+        let window_size = graphics::screen_size(ctx);
+        let scale_x = window_size.1.div(self.preferred_viewport_size.1).floor().max(1.0);
+        let scale_y = window_size.0.div(self.preferred_viewport_size.0).floor().max(1.0);
 
         self.scale = f32::min(scale_x, scale_y);
-        self.canvas_size = (self.screen_size.0 / self.scale, self.screen_size.1 / self.scale);
+
+        if self.settings.display.integer_scaling {
+            self.canvas_size = (
+                self.preferred_viewport_size.0.min((window_size.0 / self.scale).floor()),
+                self.preferred_viewport_size.1.min((window_size.1 / self.scale).floor()),
+            );
+            self.screen_size = (self.canvas_size.0 * self.scale, self.canvas_size.1 * self.scale);
+
+            let left = ((window_size.0 - self.screen_size.0) / 2.0).floor();
+            let top = ((window_size.1 - self.screen_size.1) / 2.0).floor();
+            graphics::set_screen_viewport(ctx, Some(Rect::new_size(left, top, self.screen_size.0, self.screen_size.1)));
+        } else {
+            self.canvas_size = (window_size.0 / self.scale, window_size.1 / self.scale);
+            self.screen_size = window_size;
+
+            graphics::set_screen_viewport(ctx, None);
+        }
 
         let (width, height) = (self.screen_size.0 as u16, self.screen_size.1 as u16);
 
@@ -405,6 +508,14 @@ impl SharedGameState {
         Ok(())
     }
 
+    /// Applies changed display settings, the window itself is updated by the backend.
+    pub fn apply_display_settings(&mut self, ctx: &mut Context) -> GameResult {
+        self.preferred_viewport_size = self.settings.display.aspect_ratio.viewport_size();
+        self.display_changed = true;
+
+        self.handle_resize(ctx)
+    }
+
     pub fn tick_carets(&mut self) {
         for caret in self.carets.iter_mut() {
             caret.tick(&self.effect_rng, &self.constants);
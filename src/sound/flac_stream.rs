@@ -0,0 +1,174 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use claxon::FlacReader;
+
+use crate::framework::error::GameError::ResourceLoadError;
+use crate::framework::error::GameResult;
+use crate::sound::pcm_playback::PcmStream;
+use crate::sound::stuff::LoopPoints;
+
+/// Frames returned per `read` call when playing back from the loop cache.
+const CHUNK_FRAMES: usize = 4096;
+/// Upper bound of samples kept in the loop cache, 64 MiB worth of them.
+const MAX_CACHED_SAMPLES: usize = 32 * 1024 * 1024;
+
+/// Streams FLAC files. Loop points are read from `LOOPSTART`/`LOOPEND`/`LOOPLENGTH` Vorbis comments.
+pub struct FlacStream<R: Read + Seek + Send> {
+    reader: Option<FlacReader<R>>,
+    channels: u16,
+    sample_rate: u32,
+    bits_per_sample: u32,
+    /// Frames left to drop after seeking, as claxon can only decode from the start.
+    skip: u64,
+    /// Frames decoded since the start of stream.
+    decoded: u64,
+    /// Samples decoded from the loop start onwards, claxon can't seek so going back to the loop start would
+    /// otherwise mean decoding the whole intro again on the audio thread.
+    cache: Vec<i16>,
+    /// Frame the cache starts at, None if the loop turned out too long to be cached.
+    cache_start: Option<u64>,
+    /// Position in `cache` samples are currently read from, None if they come from the decoder.
+    cache_pos: Option<usize>,
+    block_buffer: Vec<i32>,
+    loop_points: Option<LoopPoints>,
+}
+
+impl<R: Read + Seek + Send> FlacStream<R> {
+    /// Parses the header of a FLAC file, `loop_points` override the ones in its tags.
+    pub fn new(reader: R, loop_points: Option<LoopPoints>) -> GameResult<FlacStream<R>> {
+        let reader = FlacReader::new(reader).map_err(|e| ResourceLoadError(e.to_string()))?;
+        let info = reader.streaminfo();
+        let loop_points = loop_points.or_else(|| LoopPoints::from_tags(reader.tags()));
+
+        Ok(FlacStream {
+            channels: info.channels as u16,
+            sample_rate: info.sample_rate,
+            bits_per_sample: info.bits_per_sample,
+            skip: 0,
+            decoded: 0,
+            cache: Vec::new(),
+            // songs without loop points are repeated from their start
+            cache_start: Some(loop_points.map_or(0, |points| points.start)),
+            cache_pos: None,
+            block_buffer: Vec::new(),
+            loop_points,
+            reader: Some(reader),
+        })
+    }
+
+    /// Frame right after the last cached one.
+    fn cache_end(&self) -> Option<u64> {
+        Some(self.cache_start? + (self.cache.len() / self.channels.max(1) as usize) as u64)
+    }
+}
+
+impl<R: Read + Seek + Send> PcmStream for FlacStream<R> {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn read(&mut self, buf: &mut Vec<i16>) -> GameResult<bool> {
+        if let Some(pos) = self.cache_pos {
+            if pos < self.cache.len() {
+                let len = (CHUNK_FRAMES * self.channels as usize).min(self.cache.len() - pos);
+                buf.extend_from_slice(&self.cache[pos..pos + len]);
+                self.cache_pos = Some(pos + len);
+
+                return Ok(true);
+            }
+
+            // the decoder is right where the cache ends
+            self.cache_pos = None;
+        }
+
+        let reader = match self.reader.as_mut() {
+            Some(reader) => reader,
+            None => return Ok(false),
+        };
+
+        loop {
+            let block = match reader.blocks().read_next_or_eof(std::mem::take(&mut self.block_buffer)) {
+                Ok(Some(block)) => block,
+                Ok(None) => return Ok(false),
+                Err(err) => return Err(ResourceLoadError(err.to_string())),
+            };
+
+            let frames = block.duration() as u64;
+            let skip = self.skip.min(frames);
+            self.skip -= skip;
+
+            // extend the cache if this block continues it, which also catches up after seeking back without it
+            let mut cache_from = match self.cache_end() {
+                Some(end) if self.decoded <= end && end < self.decoded + frames => Some(end - self.decoded),
+                _ => None,
+            };
+            let cached_len = self.cache.len() + frames as usize * self.channels as usize;
+
+            if cache_from.is_some() && cached_len > MAX_CACHED_SAMPLES {
+                log::warn!("Loop of the song is too long to be cached, looping will decode it from the start.");
+                self.cache = Vec::new();
+                self.cache_start = None;
+                cache_from = None;
+            }
+
+            for i in 0..frames as u32 {
+                for ch in 0..self.channels as u32 {
+                    let sample = block.sample(ch, i);
+                    let sample = if self.bits_per_sample > 16 {
+                        sample >> (self.bits_per_sample - 16)
+                    } else {
+                        sample << (16 - self.bits_per_sample)
+                    };
+
+                    if i as u64 >= skip {
+                        buf.push(sample as i16);
+                    }
+
+                    if cache_from.map_or(false, |from| i as u64 >= from) {
+                        self.cache.push(sample as i16);
+                    }
+                }
+            }
+
+            self.decoded += frames;
+            self.block_buffer = block.into_buffer();
+
+            if skip < frames {
+                return Ok(true);
+            }
+        }
+    }
+
+    fn seek(&mut self, frame: u64) -> GameResult {
+        if let (Some(start), Some(end)) = (self.cache_start, self.cache_end()) {
+            // the decoder has to be at the end of the cache to carry on once it runs out
+            if frame >= start && frame <= end && self.decoded == end {
+                self.cache_pos = Some((frame - start) as usize * self.channels as usize);
+                self.skip = 0;
+
+                return Ok(());
+            }
+        }
+
+        let mut inner = match self.reader.take() {
+            Some(reader) => reader.into_inner(),
+            None => return Ok(()),
+        };
+
+        inner.seek(SeekFrom::Start(0))?;
+        self.reader = Some(FlacReader::new(inner).map_err(|e| ResourceLoadError(e.to_string()))?);
+        self.skip = frame;
+        self.decoded = 0;
+        self.cache_pos = None;
+
+        Ok(())
+    }
+
+    fn loop_points(&self) -> Option<LoopPoints> {
+        self.loop_points
+    }
+}
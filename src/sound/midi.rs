@@ -0,0 +1,616 @@
+//! Conversion of Organya songs to and from Standard MIDI Files.
+//!
+//! Conventions used while converting:
+//! - melody tracks use MIDI channels 1-8, drum tracks are all on the General MIDI percussion channel 10,
+//!   each of them in its own MIDI track. Drum note keys are pitches of the Organya drum sample rather than
+//!   GM percussion keys, and since Organya instruments don't correspond to GM programs, no program changes
+//!   are written and the ones found on import are ignored,
+//! - one Organya tick is `TICKS_PER_STEP` MIDI ticks long, a beat of the song is a quarter note,
+//! - note volume becomes velocity, pan changes become CC10 and volume changes without a note become CC7,
+//! - loop range is stored as `loopStart` / `loopEnd` markers, CC111 is also accepted as loop start on import,
+//! - settings without a MIDI equivalent (instrument, frequency, pipi, grid) are kept in sequencer specific meta
+//!   events, so songs survive a round trip through a DAW that preserves them,
+//! - on import, channel 10 notes of each MIDI track go to the drum track named by its instrument meta event,
+//!   or to the next unused drum track. Notes on channels 9 and 11-16 have no Organya track and are skipped.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::io::Read;
+
+use byteorder::{ReadBytesExt, WriteBytesExt, BE};
+
+use crate::framework::error::GameError::ParseError;
+use crate::framework::error::GameResult;
+use crate::sound::organya::{Display, Instrument, LoopRange, Note, Song, Timing, Track, Version};
+
+/// MIDI ticks per Organya tick on export.
+const TICKS_PER_STEP: u16 = 24;
+/// Organya key 48 (C of 4th octave) is middle C.
+const KEY_OFFSET: i32 = 12;
+const DEFAULT_VOLUME: u8 = 200;
+/// Manufacturer ID reserved for non-commercial use, followed by `ORG` and the kind of data.
+const SEQUENCER_TAG: [u8; 4] = [0x7d, b'O', b'R', b'G'];
+const SEQUENCER_SONG: u8 = 0;
+const SEQUENCER_INSTRUMENT: u8 = 1;
+
+const META_TRACK_NAME: u8 = 0x03;
+const META_MARKER: u8 = 0x06;
+const META_END_OF_TRACK: u8 = 0x2f;
+const META_TEMPO: u8 = 0x51;
+const META_TIME_SIGNATURE: u8 = 0x58;
+const META_SEQUENCER: u8 = 0x7f;
+
+const CC_VOLUME: u8 = 7;
+const CC_PAN: u8 = 10;
+const CC_LOOP_START: u8 = 111;
+
+/// General MIDI percussion channel, used by all drum tracks.
+const DRUM_CHANNEL: u8 = 9;
+
+struct TrackWriter {
+    events: Vec<(u32, u8, Vec<u8>)>,
+}
+
+impl TrackWriter {
+    fn new() -> TrackWriter {
+        TrackWriter { events: Vec::new() }
+    }
+
+    /// Events at the same tick are ordered by priority, so notes are released before new ones start.
+    fn push(&mut self, tick: u32, priority: u8, data: Vec<u8>) {
+        self.events.push((tick, priority, data));
+    }
+
+    fn meta(&mut self, tick: u32, kind: u8, data: &[u8]) {
+        let mut event = vec![0xff, kind];
+        write_var_len(&mut event, data.len() as u32);
+        event.extend_from_slice(data);
+
+        self.push(tick, 1, event);
+    }
+
+    fn write_to<W: io::Write>(mut self, end_tick: u32, f: &mut W) -> GameResult {
+        self.events.sort_by_key(|(tick, priority, _)| (*tick, *priority));
+
+        let mut data = Vec::new();
+        let mut last_tick = 0;
+
+        for (tick, _, event) in self.events.iter() {
+            write_var_len(&mut data, tick - last_tick);
+            data.extend_from_slice(event);
+            last_tick = *tick;
+        }
+
+        write_var_len(&mut data, end_tick.saturating_sub(last_tick));
+        data.extend_from_slice(&[0xff, META_END_OF_TRACK, 0]);
+
+        f.write_all(b"MTrk")?;
+        f.write_u32::<BE>(data.len() as u32)?;
+        f.write_all(&data)?;
+
+        Ok(())
+    }
+}
+
+fn write_var_len(buf: &mut Vec<u8>, mut value: u32) {
+    let mut bytes = [0u8; 5];
+    let mut len = 0;
+
+    loop {
+        bytes[len] = (value & 0x7f) as u8;
+        len += 1;
+        value >>= 7;
+
+        if value == 0 {
+            break;
+        }
+    }
+
+    for i in (0..len).rev() {
+        buf.push(bytes[i] | if i != 0 { 0x80 } else { 0 });
+    }
+}
+
+/// Writes the song as a format 1 Standard MIDI File, with a conductor track followed by one track per Organya track.
+pub fn write_midi<W: io::Write>(song: &Song, mut f: W) -> GameResult {
+    let steps = song.display.steps.max(1) as u32;
+    let to_ticks = |pos: i32| pos.max(0) as u32 * TICKS_PER_STEP as u32;
+
+    let song_end = song
+        .tracks
+        .iter()
+        .flat_map(|track| track.notes.iter())
+        .map(|note| note.pos + note.len.max(1) as i32)
+        .chain(std::iter::once(song.time.loop_range.end))
+        .max()
+        .unwrap_or(0);
+    let end_tick = to_ticks(song_end);
+
+    f.write_all(b"MThd")?;
+    f.write_u32::<BE>(6)?;
+    f.write_u16::<BE>(1)?;
+    f.write_u16::<BE>(17)?;
+    f.write_u16::<BE>(steps as u16 * TICKS_PER_STEP)?;
+
+    let mut conductor = TrackWriter::new();
+    let tempo = (song.time.wait as u32 * steps * 1000).min(0xffffff);
+    conductor.meta(0, META_TIME_SIGNATURE, &[song.display.beats.max(1), 2, 24, 8]);
+    conductor.meta(0, META_TEMPO, &[(tempo >> 16) as u8, (tempo >> 8) as u8, tempo as u8]);
+    conductor.meta(
+        0,
+        META_SEQUENCER,
+        &[&SEQUENCER_TAG[..], &[SEQUENCER_SONG, song.version as u8, song.display.beats, song.display.steps]].concat(),
+    );
+    conductor.meta(to_ticks(song.time.loop_range.start), META_MARKER, b"loopStart");
+    conductor.meta(to_ticks(song.time.loop_range.end), META_MARKER, b"loopEnd");
+    conductor.write_to(end_tick, &mut f)?;
+
+    for (idx, track) in song.tracks.iter().enumerate() {
+        let channel = if idx < 8 { idx as u8 } else { DRUM_CHANNEL };
+        let mut writer = TrackWriter::new();
+
+        let name = if idx < 8 { format!("Melody {}", idx + 1) } else { format!("Drum {}", idx - 7) };
+        writer.meta(0, META_TRACK_NAME, name.as_bytes());
+
+        let inst = track.inst;
+        writer.meta(
+            0,
+            META_SEQUENCER,
+            &[
+                &SEQUENCER_TAG[..],
+                &[SEQUENCER_INSTRUMENT, idx as u8, inst.inst, (inst.freq >> 8) as u8, inst.freq as u8, inst.pipi],
+            ]
+            .concat(),
+        );
+
+        let mut notes: Vec<&Note> = track.notes.iter().collect();
+        notes.sort_by_key(|note| note.pos);
+
+        let mut volume = DEFAULT_VOLUME;
+        for (i, note) in notes.iter().enumerate() {
+            let tick = to_ticks(note.pos);
+
+            if note.pan != 255 {
+                let pan = (note.pan.min(12) as u32 * 127 + 6) / 12;
+                writer.push(tick, 1, vec![0xb0 | channel, CC_PAN, pan as u8]);
+            }
+
+            if note.vol != 255 {
+                volume = note.vol;
+            }
+
+            if note.key != 255 {
+                let key = (note.key as i32 + KEY_OFFSET).min(127) as u8;
+                let velocity = (volume / 2).max(1);
+
+                // a track plays one note at a time, so the next note cuts off current one
+                let mut end = note.pos + note.len.max(1) as i32;
+                if let Some(next) = notes[i + 1..].iter().find(|next| next.key != 255 && next.pos > note.pos) {
+                    end = end.min(next.pos);
+                }
+
+                writer.push(tick, 2, vec![0x90 | channel, key, velocity]);
+                writer.push(to_ticks(end), 0, vec![0x80 | channel, key, 0]);
+            } else if note.vol != 255 {
+                writer.push(tick, 1, vec![0xb0 | channel, CC_VOLUME, note.vol / 2]);
+            }
+        }
+
+        writer.write_to(end_tick, &mut f)?;
+    }
+
+    Ok(())
+}
+
+fn read_var_len<R: io::Read>(f: &mut R) -> GameResult<u32> {
+    let mut value = 0u32;
+
+    for _ in 0..4 {
+        let byte = f.read_u8()?;
+        value = (value << 7) | (byte & 0x7f) as u32;
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(ParseError("Invalid variable length quantity in MIDI file.".to_string()))
+}
+
+enum MidiEvent {
+    NoteOn { channel: u8, key: u8, velocity: u8 },
+    NoteOff { channel: u8, key: u8 },
+    Controller { channel: u8, controller: u8, value: u8 },
+    Meta { kind: u8, data: Vec<u8> },
+}
+
+fn read_track(data: &[u8]) -> GameResult<Vec<(u32, MidiEvent)>> {
+    let mut f = io::Cursor::new(data);
+    let mut events = Vec::new();
+    let mut tick = 0u32;
+    let mut running_status = 0u8;
+
+    while (f.position() as usize) < data.len() {
+        tick += read_var_len(&mut f)?;
+
+        let mut status = f.read_u8()?;
+        let first_byte = if status < 0x80 {
+            // running status, the byte we've just read is already the first data byte
+            let byte = status;
+            status = running_status;
+            Some(byte)
+        } else {
+            None
+        };
+
+        match status {
+            0xff => {
+                let kind = f.read_u8()?;
+                let len = read_var_len(&mut f)? as usize;
+                let mut data = vec![0; len];
+                f.read_exact(&mut data)?;
+
+                if kind == META_END_OF_TRACK {
+                    break;
+                }
+
+                events.push((tick, MidiEvent::Meta { kind, data }));
+            }
+            0xf0 | 0xf7 => {
+                let len = read_var_len(&mut f)? as u64;
+                f.set_position(f.position() + len);
+            }
+            0x80..=0xef => {
+                running_status = status;
+                let channel = status & 0x0f;
+                let a = match first_byte {
+                    Some(byte) => byte,
+                    None => f.read_u8()?,
+                };
+
+                match status & 0xf0 {
+                    // program changes and channel pressure
+                    0xc0 | 0xd0 => {}
+                    kind => {
+                        let b = f.read_u8()?;
+
+                        match kind {
+                            0x90 if b != 0 => events.push((tick, MidiEvent::NoteOn { channel, key: a, velocity: b })),
+                            0x80 | 0x90 => events.push((tick, MidiEvent::NoteOff { channel, key: a })),
+                            0xb0 => events.push((tick, MidiEvent::Controller { channel, controller: a, value: b })),
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            _ => return Err(ParseError(format!("Unexpected MIDI status byte: {:#x}", status))),
+        }
+    }
+
+    Ok(events)
+}
+
+/// Reads a Standard MIDI File of format 0 or 1 and converts it to an Organya song.
+pub fn read_midi<R: io::Read>(mut f: R) -> GameResult<Song> {
+    let mut magic = [0; 4];
+    f.read_exact(&mut magic)?;
+    if &magic != b"MThd" {
+        return Err(ParseError("Invalid MIDI file header.".to_string()));
+    }
+
+    let header_len = f.read_u32::<BE>()?;
+    let format = f.read_u16::<BE>()?;
+    let track_count = f.read_u16::<BE>()?;
+    let division = f.read_u16::<BE>()?;
+    io::copy(&mut (&mut f).take(header_len.saturating_sub(6) as u64), &mut io::sink())?;
+
+    if format > 1 {
+        return Err(ParseError(format!("MIDI format {} is not supported.", format)));
+    }
+
+    if division & 0x8000 != 0 || division == 0 {
+        return Err(ParseError("SMPTE timed MIDI files are not supported.".to_string()));
+    }
+
+    // events are tagged with index of the MIDI track they come from
+    let mut events = Vec::new();
+    for midi_track in 0..track_count as usize {
+        f.read_exact(&mut magic)?;
+        let len = f.read_u32::<BE>()? as usize;
+        let mut data = vec![0; len];
+        f.read_exact(&mut data)?;
+
+        if &magic == b"MTrk" {
+            events.extend(read_track(&data)?.into_iter().map(|(tick, event)| (tick, midi_track, event)));
+        }
+    }
+
+    // a stable sort keeps the original order of events at the same tick
+    events.sort_by_key(|(tick, _, _)| *tick);
+
+    let mut version = None;
+    let mut display = None;
+    let mut tempo = None;
+    let mut beats = 4;
+    let mut loop_start = None;
+    let mut loop_end = None;
+    let mut instruments: [Option<Instrument>; 16] = [None; 16];
+    // Organya drum track the percussion channel of each MIDI track goes to
+    let mut drum_tracks: HashMap<usize, usize> = HashMap::new();
+
+    for (tick, midi_track, event) in events.iter() {
+        match event {
+            MidiEvent::Meta { kind: META_TEMPO, data } if data.len() == 3 => {
+                if tempo.is_none() {
+                    tempo = Some((data[0] as u32) << 16 | (data[1] as u32) << 8 | data[2] as u32);
+                } else {
+                    log::warn!("MIDI file contains tempo changes, only the first tempo is used.");
+                }
+            }
+            MidiEvent::Meta { kind: META_TIME_SIGNATURE, data } if !data.is_empty() => {
+                beats = data[0].max(1);
+            }
+            MidiEvent::Meta { kind: META_MARKER, data } => {
+                let marker = String::from_utf8_lossy(data).to_lowercase();
+
+                if marker == "loopstart" {
+                    loop_start = Some(*tick);
+                } else if marker == "loopend" {
+                    loop_end = Some(*tick);
+                }
+            }
+            MidiEvent::Meta { kind: META_SEQUENCER, data } if data.starts_with(&SEQUENCER_TAG) => {
+                match &data[SEQUENCER_TAG.len()..] {
+                    &[SEQUENCER_SONG, song_version, song_beats, song_steps] => {
+                        version = match song_version {
+                            b'1' => Some(Version::Beta),
+                            b'2' => Some(Version::Main),
+                            b'3' => Some(Version::Extended),
+                            _ => None,
+                        };
+                        display = Some(Display { beats: song_beats, steps: song_steps });
+                    }
+                    &[SEQUENCER_INSTRUMENT, track, inst, freq_hi, freq_lo, pipi] if track < 16 => {
+                        let freq = (freq_hi as u16) << 8 | freq_lo as u16;
+                        instruments[track as usize] = Some(Instrument { freq, inst, pipi, notes: 0 });
+
+                        if track >= 8 {
+                            drum_tracks.insert(*midi_track, track as usize);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            MidiEvent::Controller { controller: CC_LOOP_START, .. } => {
+                loop_start = loop_start.or(Some(*tick));
+            }
+            _ => {}
+        }
+    }
+
+    // percussion of MIDI tracks without instrument meta events goes to drum tracks which are still free
+    let mut free_drums = (8..16).filter(|track| !drum_tracks.values().any(|used| used == track)).collect::<Vec<_>>();
+    for (_, midi_track, event) in events.iter() {
+        if let MidiEvent::NoteOn { channel: DRUM_CHANNEL, .. } = event {
+            if !drum_tracks.contains_key(midi_track) {
+                if free_drums.is_empty() {
+                    log::warn!("MIDI file has percussion in more than 8 tracks, skipping track {}.", midi_track);
+                    drum_tracks.insert(*midi_track, usize::MAX);
+                } else {
+                    drum_tracks.insert(*midi_track, free_drums.remove(0));
+                }
+            }
+        }
+    }
+
+    let mut skipped_channels = 0u16;
+    let mut org_track = |midi_track: usize, channel: u8| match channel {
+        0..=7 => Some(channel as usize),
+        DRUM_CHANNEL => drum_tracks.get(&midi_track).copied().filter(|&track| track < 16),
+        _ => {
+            if skipped_channels & (1 << channel) == 0 {
+                skipped_channels |= 1 << channel;
+                log::warn!("Channel {} has no Organya track, its notes are skipped.", channel + 1);
+            }
+
+            None
+        }
+    };
+
+    // MIDI files coming from elsewhere are quantized to 16th notes
+    let display = display.unwrap_or(Display { beats, steps: 4 });
+    let steps = display.steps.max(1) as u32;
+    let ticks_per_step = division as f64 / steps as f64;
+    let to_pos = |tick: u32| (tick as f64 / ticks_per_step).round() as i32;
+
+    let tempo = tempo.unwrap_or(500000);
+    let wait = (tempo / steps / 1000).max(1).min(u16::MAX as u32) as u16;
+
+    let mut tracks: Vec<BTreeMap<i32, Note>> = vec![BTreeMap::new(); 16];
+    let mut active_notes = HashMap::new();
+    let empty_note = |pos: i32| Note { pos, key: 255, len: 1, vol: 255, pan: 255 };
+
+    for (tick, midi_track, event) in events.iter() {
+        match *event {
+            MidiEvent::NoteOn { channel, key, velocity } => {
+                if let Some(track) = org_track(*midi_track, channel) {
+                    active_notes.insert((track, key), (*tick, velocity));
+                }
+            }
+            MidiEvent::NoteOff { channel, key } => {
+                let track = match org_track(*midi_track, channel) {
+                    Some(track) => track,
+                    None => continue,
+                };
+
+                if let Some((start, velocity)) = active_notes.remove(&(track, key)) {
+                    let org_key = key as i32 - KEY_OFFSET;
+                    if org_key < 0 || org_key > 95 {
+                        log::warn!("Note {} on channel {} is out of Organya range, skipping.", key, channel + 1);
+                        continue;
+                    }
+
+                    let pos = to_pos(start);
+                    let len = (to_pos(*tick) - pos).max(1).min(255) as u8;
+                    let note = tracks[track].entry(pos).or_insert_with(|| empty_note(pos));
+
+                    note.key = org_key as u8;
+                    note.len = len;
+                    note.vol = (velocity as u16 * 2).min(254) as u8;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for (tick, midi_track, event) in events.iter() {
+        if let MidiEvent::Controller { channel, controller, value } = *event {
+            let track = match org_track(*midi_track, channel) {
+                Some(track) => track,
+                None => continue,
+            };
+            let pos = to_pos(*tick);
+
+            match controller {
+                CC_PAN => {
+                    let note = tracks[track].entry(pos).or_insert_with(|| empty_note(pos));
+                    note.pan = ((value.min(127) as u32 * 12 + 63) / 127) as u8;
+                }
+                CC_VOLUME => {
+                    let note = tracks[track].entry(pos).or_insert_with(|| empty_note(pos));
+                    // velocity of a note starting at the same time takes precedence
+                    if note.vol == 255 {
+                        note.vol = (value as u16 * 2).min(254) as u8;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let song_end = tracks
+        .iter()
+        .flat_map(|notes| notes.values())
+        .map(|note| note.pos + if note.key != 255 { note.len as i32 } else { 1 })
+        .max()
+        .unwrap_or(0);
+    let measure = (display.beats.max(1) as i32) * steps as i32;
+
+    let start = loop_start.map(to_pos).unwrap_or(0);
+    let mut end = loop_end.map(to_pos).unwrap_or_else(|| ((song_end + measure - 1) / measure).max(1) * measure);
+    if end <= start {
+        end = start + measure;
+    }
+
+    let mut extended_drums = false;
+    let mut make_track = |idx: usize, notes: &BTreeMap<i32, Note>| {
+        let inst = instruments[idx].unwrap_or_else(|| {
+            // drums use sample of the same index unless the song uses extended drums
+            let default_inst = if idx < 8 { 0 } else { idx as u8 - 8 };
+            Instrument { freq: 1000, inst: default_inst, pipi: 0, notes: 0 }
+        });
+
+        if idx >= 8 && inst.inst != idx as u8 - 8 {
+            extended_drums = true;
+        }
+
+        Track { inst: Instrument { notes: notes.len() as u16, ..inst }, notes: notes.values().copied().collect() }
+    };
+
+    let tracks = [
+        make_track(0, &tracks[0]),
+        make_track(1, &tracks[1]),
+        make_track(2, &tracks[2]),
+        make_track(3, &tracks[3]),
+        make_track(4, &tracks[4]),
+        make_track(5, &tracks[5]),
+        make_track(6, &tracks[6]),
+        make_track(7, &tracks[7]),
+        make_track(8, &tracks[8]),
+        make_track(9, &tracks[9]),
+        make_track(10, &tracks[10]),
+        make_track(11, &tracks[11]),
+        make_track(12, &tracks[12]),
+        make_track(13, &tracks[13]),
+        make_track(14, &tracks[14]),
+        make_track(15, &tracks[15]),
+    ];
+
+    let version = version.unwrap_or(if extended_drums { Version::Extended } else { Version::Main });
+
+    Ok(Song { version, display, time: Timing { wait, loop_range: LoopRange { start, end } }, tracks })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sound::organya::tests::test_song;
+
+    use super::*;
+
+    #[test]
+    fn midi_round_trip() {
+        let song = test_song();
+        let mut data = Vec::new();
+        write_midi(&song, &mut data).unwrap();
+
+        assert_eq!(read_midi(&data[..]).unwrap(), song);
+    }
+
+    #[test]
+    fn midi_drums_use_percussion_channel() {
+        let mut data = Vec::new();
+        write_midi(&test_song(), &mut data).unwrap();
+
+        // skip the header and conductor track
+        let mut f = io::Cursor::new(&data[14..]);
+        let mut chunks = Vec::new();
+        while (f.position() as usize) < data.len() - 14 {
+            let mut magic = [0; 4];
+            f.read_exact(&mut magic).unwrap();
+            let mut chunk = vec![0; f.read_u32::<BE>().unwrap() as usize];
+            f.read_exact(&mut chunk).unwrap();
+            chunks.push(chunk);
+        }
+        assert_eq!(chunks.len(), 17);
+
+        for (idx, chunk) in chunks[1..].iter().enumerate() {
+            let expected_channel = if idx < 8 { idx as u8 } else { DRUM_CHANNEL };
+
+            for (_, event) in read_track(chunk).unwrap() {
+                match event {
+                    MidiEvent::NoteOn { channel, .. }
+                    | MidiEvent::NoteOff { channel, .. }
+                    | MidiEvent::Controller { channel, .. } => assert_eq!(channel, expected_channel),
+                    MidiEvent::Meta { .. } => {}
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn midi_import_skips_channels_without_tracks() {
+        let mut data = Vec::new();
+        data.extend_from_slice(b"MThd");
+        data.extend_from_slice(&[0, 0, 0, 6, 0, 0, 0, 1, 0, 96]);
+
+        // note on channel 12, then one on channel 10 and one on channel 1
+        let events = [
+            &[0x00, 0x9b, 60, 100, 0x18, 0x8b, 60, 0][..],
+            &[0x00, 0x99, 50, 100, 0x18, 0x89, 50, 0],
+            &[0x00, 0x90, 70, 100, 0x18, 0x80, 70, 0],
+            &[0x00, 0xff, META_END_OF_TRACK, 0],
+        ]
+        .concat();
+        data.extend_from_slice(b"MTrk");
+        data.extend_from_slice(&(events.len() as u32).to_be_bytes());
+        data.extend_from_slice(&events);
+
+        let song = read_midi(&data[..]).unwrap();
+
+        assert_eq!(song.tracks[0].notes.len(), 1);
+        assert_eq!(song.tracks[0].notes[0].key, 70 - KEY_OFFSET as u8);
+        assert_eq!(song.tracks[8].notes.len(), 1);
+        assert_eq!(song.tracks[8].notes[0].key, 50 - KEY_OFFSET as u8);
+        assert!(song.tracks.iter().all(|track| track.notes.iter().all(|note| note.key != 60 - KEY_OFFSET as u8)));
+    }
+}
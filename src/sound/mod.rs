@@ -1,45 +1,111 @@
+use std::collections::HashMap;
 use std::io;
+use std::sync::atomic::{AtomicI32, Ordering};
 use std::sync::mpsc;
 use std::sync::mpsc::{Receiver, Sender};
+use std::sync::Arc;
 use std::time::Duration;
 
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::Sample;
 #[cfg(feature = "ogg-playback")]
 use lewton::inside_ogg::OggStreamReader;
 use num_traits::clamp;
 
 use crate::engine_constants::EngineConstants;
+use crate::frame::Frame;
 use crate::framework::context::Context;
+#[cfg(feature = "ogg-playback")]
+use crate::framework::error::GameError;
 use crate::framework::error::GameError::{AudioError, InvalidValue};
-use crate::framework::error::{GameError, GameResult};
+use crate::framework::error::GameResult;
 use crate::framework::filesystem;
 use crate::framework::filesystem::File;
 use crate::settings::Settings;
+#[cfg(feature = "flac-playback")]
+use crate::sound::flac_stream::FlacStream;
 #[cfg(feature = "ogg-playback")]
 use crate::sound::ogg_playback::{OggPlaybackEngine, SavedOggPlaybackState};
 use crate::sound::org_playback::{OrgPlaybackEngine, SavedOrganyaPlaybackState};
 use crate::sound::organya::Song;
+pub use crate::sound::output::{AudioClock, AudioOutput};
+#[cfg(feature = "pcm-playback")]
+use crate::sound::pcm_playback::{PcmPlaybackEngine, PcmStream, SavedPcmPlaybackState};
 use crate::sound::pixtone::{PixToneParameters, PixTonePlayback};
+use crate::sound::pixtone_sfx::DEFAULT_PIXTONE_TABLE;
+#[cfg(feature = "pcm-playback")]
+use crate::sound::stuff::LoopPoints;
+#[cfg(feature = "tracker-playback")]
+use crate::sound::tracker_stream::TrackerStream;
+#[cfg(feature = "wav-playback")]
+use crate::sound::wav_stream::WavStream;
 use crate::sound::wave_bank::SoundBank;
 use crate::str;
-use std::io::{BufReader, BufRead, Lines};
-use std::str::FromStr;
 
+#[cfg(feature = "flac-playback")]
+mod flac_stream;
+pub mod midi;
 #[cfg(feature = "ogg-playback")]
 mod ogg_playback;
 mod org_playback;
-mod organya;
+pub mod org_render;
+pub mod organya;
+mod output;
+#[cfg(feature = "pcm-playback")]
+mod pcm_playback;
 pub mod pixtone;
 mod pixtone_sfx;
 mod stuff;
+#[cfg(feature = "tracker-playback")]
+mod tracker_stream;
 mod wav;
+#[cfg(feature = "wav-playback")]
+mod wav_stream;
 mod wave_bank;
 
 pub struct SoundManager {
     tx: Sender<PlaybackMessage>,
     prev_song_id: usize,
     current_song_id: usize,
+    crossfade: f32,
+    clock: AudioClock,
+    /// Whether the output writes into a file, which has to be finalized once the game exits.
+    writes_file: bool,
+    /// Amount of drum samples in the Organya sound bank used by the mixer.
+    org_drum_count: usize,
+    /// Position of currently playing Organya song in ticks, updated by the audio thread, -1 if there's none.
+    org_position: Arc<AtomicI32>,
+    /// Sound effects which have been replaced since startup, mirrors the table used by the mixer.
+    sample_params: HashMap<u8, PixToneParameters>,
+    /// Point positional sound effects are heard from, they're played centered if there's none.
+    listener: Option<Listener>,
+}
+
+/// Area of the world visible on screen, in the same fixed point units entities use.
+#[derive(Copy, Clone)]
+struct Listener {
+    x: i32,
+    y: i32,
+    half_width: i32,
+    half_height: i32,
+}
+
+impl Listener {
+    /// Maximum pan in hundredths of decibel, reached at the edge of the screen.
+    const MAX_PAN: f32 = 1500.0;
+    /// Volume of sounds two screens away or further.
+    const MIN_VOLUME: f32 = 0.3;
+
+    /// Returns volume and pan of a sound played at given position.
+    fn mix(&self, x: i32, y: i32) -> (f32, i32) {
+        let dx = (x - self.x) as f32 / self.half_width as f32;
+        let dy = (y - self.y) as f32 / self.half_height as f32;
+
+        // anything on screen is heard at full volume, then it fades out over the next screen
+        let distance = dx.abs().max(dy.abs());
+        let volume = 1.0 - (distance - 1.0).max(0.0).min(1.0) * (1.0 - Listener::MIN_VOLUME);
+        let pan = (dx.max(-1.0).min(1.0) * Listener::MAX_PAN) as i32;
+
+        (volume, pan)
+    }
 }
 
 enum SongFormat {
@@ -48,36 +114,125 @@ enum SongFormat {
     OggSinglePart,
     #[cfg(feature = "ogg-playback")]
     OggMultiPart,
+    #[cfg(feature = "flac-playback")]
+    Flac,
+    #[cfg(feature = "wav-playback")]
+    Wav,
+    #[cfg(feature = "tracker-playback")]
+    Tracker,
+}
+
+#[cfg(feature = "tracker-playback")]
+static TRACKER_EXTENSIONS: [&str; 4] = ["xm", "it", "s3m", "mod"];
+
+impl SongFormat {
+    #[allow(dead_code)]
+    fn name(&self) -> &'static str {
+        match self {
+            SongFormat::Organya => "Organya",
+            #[cfg(feature = "ogg-playback")]
+            SongFormat::OggSinglePart => "single part Ogg",
+            #[cfg(feature = "ogg-playback")]
+            SongFormat::OggMultiPart => "multi part Ogg",
+            #[cfg(feature = "flac-playback")]
+            SongFormat::Flac => "FLAC",
+            #[cfg(feature = "wav-playback")]
+            SongFormat::Wav => "WAV",
+            #[cfg(feature = "tracker-playback")]
+            SongFormat::Tracker => "tracker module",
+        }
+    }
 }
 
 impl SoundManager {
+    /// Creates a sound manager playing to the output selected by environment, see `AudioOutput::from_env`.
     pub fn new(ctx: &mut Context) -> GameResult<SoundManager> {
-        let (tx, rx): (Sender<PlaybackMessage>, Receiver<PlaybackMessage>) = mpsc::channel();
+        SoundManager::with_output(AudioOutput::from_env(), ctx)
+    }
 
-        let host = cpal::default_host();
-        let device =
-            host.default_output_device().ok_or_else(|| AudioError(str!("Error initializing audio device.")))?;
-        let config = device.default_output_config()?;
+    pub fn with_output(output: AudioOutput, ctx: &mut Context) -> GameResult<SoundManager> {
+        let (tx, rx): (Sender<PlaybackMessage>, Receiver<PlaybackMessage>) = mpsc::channel();
 
         let bnk = wave_bank::SoundBank::load_from(filesystem::open(ctx, "/builtin/organya-wavetable-doukutsu.bin")?)?;
+        let clock = output.clock();
+        let writes_file = matches!(output, AudioOutput::Wav(..));
+        let org_drum_count = bnk.samples.len();
+        let org_position = Arc::new(AtomicI32::new(-1));
+
+        output::start(output, rx, bnk, org_position.clone())?;
+
+        Ok(SoundManager {
+            tx: tx.clone(),
+            prev_song_id: 0,
+            current_song_id: 0,
+            crossfade: 0.0,
+            clock,
+            writes_file,
+            org_drum_count,
+            org_position,
+            sample_params: HashMap::new(),
+            listener: None,
+        })
+    }
 
-        std::thread::spawn(move || {
-            if let Err(err) = match config.sample_format() {
-                cpal::SampleFormat::F32 => run::<f32>(rx, bnk, &device, &config.into()),
-                cpal::SampleFormat::I16 => run::<i16>(rx, bnk, &device, &config.into()),
-                cpal::SampleFormat::U16 => run::<u16>(rx, bnk, &device, &config.into()),
-            } {
-                log::error!("Something went wrong in audio thread: {}", err);
-            }
-        });
+    /// Advances playback by given amount of seconds if the output uses a simulated clock, does nothing otherwise.
+    pub fn advance_time(&self, seconds: f64) -> GameResult {
+        if self.clock == AudioClock::Simulated {
+            self.tx.send(PlaybackMessage::AdvanceTime(seconds))?;
+        }
+
+        Ok(())
+    }
+
+    /// Waits until the audio thread catches up with everything sent so far, so audio recorded with a
+    /// simulated clock is written up to the current tick. Does nothing on realtime outputs.
+    pub fn flush(&self) -> GameResult {
+        if self.clock == AudioClock::Simulated {
+            let (tx, rx) = mpsc::channel();
+            self.tx.send(PlaybackMessage::Flush(tx))?;
+            rx.recv().map_err(|_| AudioError("Audio thread has stopped.".to_string()))?;
+        }
+
+        Ok(())
+    }
 
-        Ok(SoundManager { tx: tx.clone(), prev_song_id: 0, current_song_id: 0 })
+    /// Finalizes files written by headless outputs, like the header of a recorded WAV file.
+    /// Waits for the audio thread to do so for at most a few seconds.
+    pub fn finish_output(&self) -> GameResult {
+        let (tx, rx) = mpsc::channel();
+        self.tx.send(PlaybackMessage::FinishOutput(tx))?;
+        rx.recv_timeout(Duration::from_secs(5))
+            .map_err(|_| AudioError("Audio thread didn't finish the output in time.".to_string()))
     }
 
     pub fn play_sfx(&self, id: u8) {
         let _ = self.tx.send(PlaybackMessage::PlaySample(id));
     }
 
+    /// Plays a sound effect panned and attenuated by where it's coming from relative to the camera,
+    /// or centered like `play_sfx` if positional sound is disabled.
+    pub fn play_sfx_at(&self, id: u8, x: i32, y: i32) {
+        match self.listener {
+            Some(listener) => {
+                let (volume, pan) = listener.mix(x, y);
+                let _ = self.tx.send(PlaybackMessage::PlaySamplePanned(id, volume, pan));
+            }
+            None => self.play_sfx(id),
+        }
+    }
+
+    /// Places the listener of positional sound effects in the center of the screen, or removes it if disabled.
+    pub fn set_listener(&mut self, frame: &Frame, canvas_size: (f32, f32), enabled: bool) {
+        self.listener = if enabled {
+            let half_width = (canvas_size.0 * 0x200 as f32 / 2.0) as i32;
+            let half_height = (canvas_size.1 * 0x200 as f32 / 2.0) as i32;
+
+            Some(Listener { x: frame.x + half_width, y: frame.y + half_height, half_width, half_height })
+        } else {
+            None
+        };
+    }
+
     pub fn loop_sfx(&self, id: u8) {
         let _ = self.tx.send(PlaybackMessage::LoopSample(id));
     }
@@ -97,6 +252,11 @@ impl SoundManager {
             return Ok(());
         }
 
+        if self.crossfade != settings.music_crossfade {
+            self.crossfade = settings.music_crossfade;
+            self.tx.send(PlaybackMessage::SetCrossfade(settings.music_crossfade))?;
+        }
+
         if song_id == 0 {
             log::info!("Stopping BGM");
 
@@ -106,110 +266,110 @@ impl SoundManager {
             self.tx.send(PlaybackMessage::SaveState)?;
             self.tx.send(PlaybackMessage::Stop)?;
         } else if let Some(song_name) = constants.music_table.get(song_id) {
-            let mut paths = constants.organya_paths.clone();
-
-            paths.insert(0, "/Soundtracks/".to_owned() + &settings.soundtrack + "/");
-
-            if let Some(soundtrack) = constants.soundtracks.get(&settings.soundtrack) {
-                paths.insert(0, soundtrack.clone());
-            }
-
-            let songs_paths = paths.iter().map(|prefix| {
-                [
-                    #[cfg(feature = "ogg-playback")]
-                    (
-                        SongFormat::OggMultiPart,
-                        vec![format!("{}{}_intro.ogg", prefix, song_name), format!("{}{}_loop.ogg", prefix, song_name)],
-                    ),
+            let songs_paths = SoundManager::song_search_paths(song_name, constants, settings);
+
+            for (format, paths) in
+                songs_paths.iter().filter(|(_, paths)| paths.iter().all(|path| filesystem::exists(ctx, path)))
+            {
+                match format {
+                    SongFormat::Organya => {
+                        // we're sure that there's one element
+                        let path = unsafe { paths.get_unchecked(0) };
+
+                        match filesystem::open(ctx, path).map(|f| organya::Song::load_from(f)) {
+                            Ok(Ok(org)) => {
+                                log::info!("Playing Organya BGM: {} {}", song_id, path);
+
+                                self.prev_song_id = self.current_song_id;
+                                self.current_song_id = song_id;
+                                self.tx.send(PlaybackMessage::SaveState)?;
+                                self.tx.send(PlaybackMessage::PlayOrganyaSong(Box::new(org)))?;
+
+                                return Ok(());
+                            }
+                            Ok(Err(err)) | Err(err) => {
+                                log::warn!("Failed to load Organya BGM {}: {}", song_id, err);
+                            }
+                        }
+                    }
                     #[cfg(feature = "ogg-playback")]
-                    (SongFormat::OggSinglePart, vec![format!("{}{}.ogg", prefix, song_name)]),
-                    (SongFormat::Organya, vec![format!("{}{}.org", prefix, song_name)]),
-                ]
-            });
-
-            for songs in songs_paths {
-                for (format, paths) in
-                    songs.iter().filter(|(_, paths)| paths.iter().all(|path| filesystem::exists(ctx, path)))
-                {
-                    match format {
-                        SongFormat::Organya => {
-                            // we're sure that there's one element
-                            let path = unsafe { paths.get_unchecked(0) };
-
-                            match filesystem::open(ctx, path).map(|f| organya::Song::load_from(f)) {
-                                Ok(Ok(org)) => {
-                                    log::info!("Playing Organya BGM: {} {}", song_id, path);
-
-                                    self.prev_song_id = self.current_song_id;
-                                    self.current_song_id = song_id;
-                                    self.tx.send(PlaybackMessage::SaveState)?;
-                                    self.tx.send(PlaybackMessage::PlayOrganyaSong(Box::new(org)))?;
-
-                                    return Ok(());
-                                }
-                                Ok(Err(err)) | Err(err) => {
-                                    log::warn!("Failed to load Organya BGM {}: {}", song_id, err);
-                                }
+                    SongFormat::OggSinglePart => {
+                        // we're sure that there's one element
+                        let path = unsafe { paths.get_unchecked(0) };
+
+                        match filesystem::open(ctx, path).map(|f| {
+                            OggStreamReader::new(f).map_err(|e| GameError::ResourceLoadError(e.to_string()))
+                        }) {
+                            Ok(Ok(song)) => {
+                                log::info!("Playing single part Ogg BGM: {} {}", song_id, path);
+
+                                self.prev_song_id = self.current_song_id;
+                                self.current_song_id = song_id;
+                                self.tx.send(PlaybackMessage::SaveState)?;
+                                self.tx.send(PlaybackMessage::PlayOggSongSinglePart(Box::new(song)))?;
+
+                                return Ok(());
+                            }
+                            Ok(Err(err)) | Err(err) => {
+                                log::warn!("Failed to load single part Ogg BGM {}: {}", song_id, err);
                             }
                         }
-                        #[cfg(feature = "ogg-playback")]
-                        SongFormat::OggSinglePart => {
-                            // we're sure that there's one element
-                            let path = unsafe { paths.get_unchecked(0) };
+                    }
+                    #[cfg(feature = "ogg-playback")]
+                    SongFormat::OggMultiPart => {
+                        // we're sure that there are two elements
+                        let path_intro = unsafe { paths.get_unchecked(0) };
+                        let path_loop = unsafe { paths.get_unchecked(1) };
 
-                            match filesystem::open(ctx, path).map(|f| {
+                        match (
+                            filesystem::open(ctx, path_intro).map(|f| {
                                 OggStreamReader::new(f).map_err(|e| GameError::ResourceLoadError(e.to_string()))
-                            }) {
-                                Ok(Ok(song)) => {
-                                    log::info!("Playing single part Ogg BGM: {} {}", song_id, path);
-
-                                    self.prev_song_id = self.current_song_id;
-                                    self.current_song_id = song_id;
-                                    self.tx.send(PlaybackMessage::SaveState)?;
-                                    self.tx.send(PlaybackMessage::PlayOggSongSinglePart(Box::new(song)))?;
-
-                                    return Ok(());
-                                }
-                                Ok(Err(err)) | Err(err) => {
-                                    log::warn!("Failed to load single part Ogg BGM {}: {}", song_id, err);
-                                }
+                            }),
+                            filesystem::open(ctx, path_loop).map(|f| {
+                                OggStreamReader::new(f).map_err(|e| GameError::ResourceLoadError(e.to_string()))
+                            }),
+                        ) {
+                            (Ok(Ok(song_intro)), Ok(Ok(song_loop))) => {
+                                log::info!(
+                                    "Playing multi part Ogg BGM: {} {} + {}",
+                                    song_id,
+                                    path_intro,
+                                    path_loop
+                                );
+
+                                self.prev_song_id = self.current_song_id;
+                                self.current_song_id = song_id;
+                                self.tx.send(PlaybackMessage::SaveState)?;
+                                self.tx.send(PlaybackMessage::PlayOggSongMultiPart(
+                                    Box::new(song_intro),
+                                    Box::new(song_loop),
+                                ))?;
+
+                                return Ok(());
+                            }
+                            (Ok(Err(err)), _) | (Err(err), _) | (_, Ok(Err(err))) | (_, Err(err)) => {
+                                log::warn!("Failed to load multi part Ogg BGM {}: {}", song_id, err);
                             }
                         }
-                        #[cfg(feature = "ogg-playback")]
-                        SongFormat::OggMultiPart => {
-                            // we're sure that there are two elements
-                            let path_intro = unsafe { paths.get_unchecked(0) };
-                            let path_loop = unsafe { paths.get_unchecked(1) };
-
-                            match (
-                                filesystem::open(ctx, path_intro).map(|f| {
-                                    OggStreamReader::new(f).map_err(|e| GameError::ResourceLoadError(e.to_string()))
-                                }),
-                                filesystem::open(ctx, path_loop).map(|f| {
-                                    OggStreamReader::new(f).map_err(|e| GameError::ResourceLoadError(e.to_string()))
-                                }),
-                            ) {
-                                (Ok(Ok(song_intro)), Ok(Ok(song_loop))) => {
-                                    log::info!(
-                                        "Playing multi part Ogg BGM: {} {} + {}",
-                                        song_id,
-                                        path_intro,
-                                        path_loop
-                                    );
-
-                                    self.prev_song_id = self.current_song_id;
-                                    self.current_song_id = song_id;
-                                    self.tx.send(PlaybackMessage::SaveState)?;
-                                    self.tx.send(PlaybackMessage::PlayOggSongMultiPart(
-                                        Box::new(song_intro),
-                                        Box::new(song_loop),
-                                    ))?;
-
-                                    return Ok(());
-                                }
-                                (Ok(Err(err)), _) | (Err(err), _) | (_, Ok(Err(err))) | (_, Err(err)) => {
-                                    log::warn!("Failed to load multi part Ogg BGM {}: {}", song_id, err);
-                                }
+                    }
+                    #[cfg(feature = "pcm-playback")]
+                    format => {
+                        // we're sure that there's one element
+                        let path = unsafe { paths.get_unchecked(0) };
+
+                        match SoundManager::open_pcm_stream(format, path, ctx) {
+                            Ok(stream) => {
+                                log::info!("Playing {} BGM: {} {}", format.name(), song_id, path);
+
+                                self.prev_song_id = self.current_song_id;
+                                self.current_song_id = song_id;
+                                self.tx.send(PlaybackMessage::SaveState)?;
+                                self.tx.send(PlaybackMessage::PlayPcmSong(stream))?;
+
+                                return Ok(());
+                            }
+                            Err(err) => {
+                                log::warn!("Failed to load {} BGM {}: {}", format.name(), song_id, err);
                             }
                         }
                     }
@@ -220,6 +380,154 @@ impl SoundManager {
         Ok(())
     }
 
+    /// Opens a streamed song, loop points can be overridden by a `.loop` file next to it,
+    /// containing `LOOPSTART=<frame>` and `LOOPEND=<frame>` or `LOOPLENGTH=<frames>` lines.
+    #[cfg(feature = "pcm-playback")]
+    fn open_pcm_stream(format: &SongFormat, path: &str, ctx: &mut Context) -> GameResult<Box<dyn PcmStream>> {
+        let file = filesystem::open(ctx, path)?;
+
+        let sidecar_path = match path.rfind('.') {
+            Some(idx) => format!("{}.loop", &path[..idx]),
+            None => format!("{}.loop", path),
+        };
+        let loop_points = match filesystem::open(ctx, &sidecar_path) {
+            Ok(mut sidecar) => {
+                let mut text = String::new();
+                io::Read::read_to_string(&mut sidecar, &mut text)?;
+                LoopPoints::from_sidecar(&text)
+            }
+            Err(_) => None,
+        };
+
+        match format {
+            #[cfg(feature = "flac-playback")]
+            SongFormat::Flac => Ok(Box::new(FlacStream::new(file, loop_points)?)),
+            #[cfg(feature = "wav-playback")]
+            SongFormat::Wav => Ok(Box::new(WavStream::new(file, loop_points)?)),
+            #[cfg(feature = "tracker-playback")]
+            SongFormat::Tracker => Ok(Box::new(TrackerStream::new(file)?)),
+            _ => Err(InvalidValue(format!("{} isn't a streamed format.", format.name()))),
+        }
+    }
+
+    /// Returns candidate files for given song in the order they're looked up by `play_song`.
+    fn song_search_paths(
+        song_name: &str,
+        constants: &EngineConstants,
+        settings: &Settings,
+    ) -> Vec<(SongFormat, Vec<String>)> {
+        let mut paths = constants.organya_paths.clone();
+
+        paths.insert(0, "/Soundtracks/".to_owned() + &settings.soundtrack + "/");
+
+        if let Some(soundtrack) = constants.soundtracks.get(&settings.soundtrack) {
+            paths.insert(0, soundtrack.clone());
+        }
+
+        let mut songs_paths = Vec::new();
+        for prefix in paths.iter() {
+            #[cfg(feature = "ogg-playback")]
+            songs_paths.push((
+                SongFormat::OggMultiPart,
+                vec![format!("{}{}_intro.ogg", prefix, song_name), format!("{}{}_loop.ogg", prefix, song_name)],
+            ));
+            #[cfg(feature = "ogg-playback")]
+            songs_paths.push((SongFormat::OggSinglePart, vec![format!("{}{}.ogg", prefix, song_name)]));
+            #[cfg(feature = "flac-playback")]
+            songs_paths.push((SongFormat::Flac, vec![format!("{}{}.flac", prefix, song_name)]));
+            #[cfg(feature = "wav-playback")]
+            songs_paths.push((SongFormat::Wav, vec![format!("{}{}.wav", prefix, song_name)]));
+            #[cfg(feature = "tracker-playback")]
+            for ext in TRACKER_EXTENSIONS.iter() {
+                songs_paths.push((SongFormat::Tracker, vec![format!("{}{}.{}", prefix, song_name, ext)]));
+            }
+            songs_paths.push((SongFormat::Organya, vec![format!("{}{}.org", prefix, song_name)]));
+        }
+
+        songs_paths
+    }
+
+    /// Returns the files `play_song` would pick for given song, or None if it can't be found in any search path.
+    pub fn find_song_files(
+        song_name: &str,
+        constants: &EngineConstants,
+        settings: &Settings,
+        ctx: &mut Context,
+    ) -> Option<Vec<String>> {
+        SoundManager::song_search_paths(song_name, constants, settings)
+            .into_iter()
+            .map(|(_, paths)| paths)
+            .find(|paths| paths.iter().all(|path| filesystem::exists(ctx, path)))
+    }
+
+    /// Loads the Organya song `play_song` would pick for given song ID.
+    /// Returns None if the song doesn't exist or is played from a different format.
+    pub fn load_organya_song(
+        song_id: usize,
+        constants: &EngineConstants,
+        settings: &Settings,
+        ctx: &mut Context,
+    ) -> GameResult<Option<Song>> {
+        let song_name = match constants.music_table.get(song_id) {
+            Some(song_name) => song_name,
+            None => return Ok(None),
+        };
+
+        match SoundManager::find_song_files(song_name, constants, settings, ctx) {
+            Some(paths) if paths.len() == 1 && paths[0].ends_with(".org") => {
+                Ok(Some(Song::load_from(filesystem::open(ctx, &paths[0])?)?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Replaces currently playing Organya song with an edited version, without restarting it.
+    pub fn update_organya_song(&self, song: &Song) -> GameResult {
+        self.tx.send(PlaybackMessage::UpdateOrganyaSong(Box::new(song.clone())))?;
+
+        Ok(())
+    }
+
+    /// Silences Organya tracks which have their bit set in the mask, tracks 0-7 are melody and 8-15 are drums.
+    pub fn set_organya_muted_tracks(&self, muted_tracks: u16) -> GameResult {
+        self.tx.send(PlaybackMessage::SetOrganyaMutedTracks(muted_tracks))?;
+
+        Ok(())
+    }
+
+    pub fn set_organya_position(&self, position: i32) -> GameResult {
+        self.tx.send(PlaybackMessage::SetOrganyaPosition(position))?;
+
+        Ok(())
+    }
+
+    /// Returns position of currently playing Organya song in ticks.
+    pub fn organya_position(&self) -> Option<i32> {
+        match self.org_position.load(Ordering::Relaxed) {
+            -1 => None,
+            position => Some(position),
+        }
+    }
+
+    /// Applies master, music and sound effect volume levels from settings to the mixer.
+    pub fn update_volume(&self, settings: &Settings) -> GameResult {
+        let master_volume = settings.master_volume.max(0.0).min(1.0);
+        let bgm_volume = settings.bgm_volume.max(0.0).min(1.0);
+        let sfx_volume = settings.sfx_volume.max(0.0).min(1.0);
+
+        self.tx.send(PlaybackMessage::SetVolume(master_volume * bgm_volume, master_volume * sfx_volume))?;
+
+        Ok(())
+    }
+
+    /// Fades out current song like vanilla `<FMU` does. The song is still considered playing afterwards,
+    /// so `<RMU` and `<CMU` with a different song bring the music back at full volume.
+    pub fn fade_out_song(&self) -> GameResult {
+        self.tx.send(PlaybackMessage::FadeOutSong)?;
+
+        Ok(())
+    }
+
     pub fn save_state(&mut self) -> GameResult {
         self.tx.send(PlaybackMessage::SaveState)?;
         self.prev_song_id = self.current_song_id;
@@ -247,72 +555,51 @@ impl SoundManager {
         self.current_song_id
     }
 
-    pub fn set_sample_params_from_file<R: io::Read>(&self, id: u8, data: R) -> GameResult {
-        let mut reader = BufReader::new(data).lines();
-        let mut params = PixToneParameters::empty();
-
-        fn next_string<T: FromStr, R: io::Read>(reader: &mut Lines<BufReader<R>>) -> GameResult<T> {
-            loop {
-                if let Some(Ok(str)) = reader.next() {
-                    let str = str.trim();
-                    if str == "" || str.starts_with("#") {
-                        continue;
-                    }
-
-                    let mut splits = str.split(":");
-
-                    let _ = splits.next();
-                    if let Some(str) = splits.next() {
-                        return str.trim().parse::<T>().map_err(|_| GameError::ParseError("failed to parse the value as specified type.".to_string()))
-                    } else {
-                        break;
-                    }
-                } else {
-                    break;
-                }
-            }
+    /// Returns amount of drum samples extended Organya songs can pick their drum instruments from.
+    pub fn organya_drum_count(&self) -> usize {
+        self.org_drum_count
+    }
 
-            return Err(GameError::ParseError("unexpected end.".to_string()))
+    /// Returns parameters sound effect with given ID is currently synthesized from.
+    pub fn sample_params(&self, id: u8) -> PixToneParameters {
+        match self.sample_params.get(&id) {
+            Some(params) => *params,
+            None => DEFAULT_PIXTONE_TABLE.get(id as usize).copied().unwrap_or_else(PixToneParameters::empty),
         }
+    }
 
-        for channel in params.channels.iter_mut() {
-            channel.enabled = next_string::<u8, R>(&mut reader)? != 0;
-            channel.length = next_string::<u32, R>(&mut reader)?;
-
-            channel.carrier.waveform_type = next_string::<u8, R>(&mut reader)?;
-            channel.carrier.pitch = next_string::<f32, R>(&mut reader)?;
-            channel.carrier.level = next_string::<i32, R>(&mut reader)?;
-            channel.carrier.offset = next_string::<i32, R>(&mut reader)?;
-
-            channel.frequency.waveform_type = next_string::<u8, R>(&mut reader)?;
-            channel.frequency.pitch = next_string::<f32, R>(&mut reader)?;
-            channel.frequency.level = next_string::<i32, R>(&mut reader)?;
-            channel.frequency.offset = next_string::<i32, R>(&mut reader)?;
-
-            channel.amplitude.waveform_type = next_string::<u8, R>(&mut reader)?;
-            channel.amplitude.pitch = next_string::<f32, R>(&mut reader)?;
-            channel.amplitude.level = next_string::<i32, R>(&mut reader)?;
-            channel.amplitude.offset = next_string::<i32, R>(&mut reader)?;
-
-            channel.envelope.initial = next_string::<i32, R>(&mut reader)?;
-            channel.envelope.time_a = next_string::<i32, R>(&mut reader)?;
-            channel.envelope.value_a = next_string::<i32, R>(&mut reader)?;
-            channel.envelope.time_b = next_string::<i32, R>(&mut reader)?;
-            channel.envelope.value_b = next_string::<i32, R>(&mut reader)?;
-            channel.envelope.time_c = next_string::<i32, R>(&mut reader)?;
-            channel.envelope.value_c = next_string::<i32, R>(&mut reader)?;
+    pub fn set_sample_params_from_file<R: io::Read>(&mut self, id: u8, data: R) -> GameResult {
+        self.set_sample_params(id, PixToneParameters::load_from(data)?)
+    }
+
+    /// Reverts all replaced sound effects back to the built-in ones.
+    pub fn reset_sample_params(&mut self) -> GameResult {
+        for (id, _) in std::mem::take(&mut self.sample_params) {
+            let params = DEFAULT_PIXTONE_TABLE.get(id as usize).copied().unwrap_or_else(PixToneParameters::empty);
+            self.tx.send(PlaybackMessage::SetSampleParams(id, params))?;
         }
 
-        self.set_sample_params(id, params)
+        Ok(())
     }
 
-    pub fn set_sample_params(&self, id: u8, params: PixToneParameters) -> GameResult {
+    pub fn set_sample_params(&mut self, id: u8, params: PixToneParameters) -> GameResult {
+        self.sample_params.insert(id, params);
         self.tx.send(PlaybackMessage::SetSampleParams(id, params))?;
 
         Ok(())
     }
 }
 
+impl Drop for SoundManager {
+    fn drop(&mut self) {
+        if self.writes_file {
+            if let Err(err) = self.finish_output() {
+                log::error!("Failed to finish audio output: {}", err);
+            }
+        }
+    }
+}
+
 enum PlaybackMessage {
     Stop,
     PlayOrganyaSong(Box<Song>),
@@ -320,13 +607,32 @@ enum PlaybackMessage {
     PlayOggSongSinglePart(Box<OggStreamReader<File>>),
     #[cfg(feature = "ogg-playback")]
     PlayOggSongMultiPart(Box<OggStreamReader<File>>, Box<OggStreamReader<File>>),
+    #[cfg(feature = "pcm-playback")]
+    PlayPcmSong(Box<dyn PcmStream>),
     PlaySample(u8),
+    /// Plays a sound effect with given volume and pan in hundredths of decibel.
+    PlaySamplePanned(u8, f32, i32),
     LoopSample(u8),
     StopSample(u8),
     SetSpeed(f32),
     SaveState,
     RestoreState,
     SetSampleParams(u8, PixToneParameters),
+    /// Fades out current song the same way vanilla `<FMU` does, the song keeps playing silently afterwards.
+    FadeOutSong,
+    /// Sets for how many seconds the previous song keeps playing while fading into the next one.
+    SetCrossfade(f32),
+    /// Sets gain of music and sound effects in the mixer, with master volume already applied.
+    SetVolume(f32, f32),
+    /// Renders given amount of seconds on outputs driven by a simulated clock.
+    AdvanceTime(f64),
+    /// Replies once all messages sent before it have been handled.
+    Flush(Sender<()>),
+    /// Finalizes files written by headless outputs, then replies.
+    FinishOutput(Sender<()>),
+    UpdateOrganyaSong(Box<Song>),
+    SetOrganyaMutedTracks(u16),
+    SetOrganyaPosition(i32),
 }
 
 #[derive(PartialEq, Eq)]
@@ -335,6 +641,8 @@ enum PlaybackState {
     PlayingOrg,
     #[cfg(feature = "ogg-playback")]
     PlayingOgg,
+    #[cfg(feature = "pcm-playback")]
+    PlayingPcm,
 }
 
 enum PlaybackStateType {
@@ -342,286 +650,486 @@ enum PlaybackStateType {
     Organya(SavedOrganyaPlaybackState),
     #[cfg(feature = "ogg-playback")]
     Ogg(SavedOggPlaybackState),
+    #[cfg(feature = "pcm-playback")]
+    Pcm(SavedPcmPlaybackState),
 }
 
-fn run<T>(
-    rx: Receiver<PlaybackMessage>,
-    bank: SoundBank,
-    device: &cpal::Device,
-    config: &cpal::StreamConfig,
-) -> GameResult
-where
-    T: cpal::Sample,
-{
-    let sample_rate = config.sample_rate.0 as f32;
-    let channels = config.channels as usize;
-    let mut state = PlaybackState::Stopped;
-    let mut saved_state: PlaybackStateType = PlaybackStateType::None;
-    let mut speed = 1.0;
-    let mut org_engine = OrgPlaybackEngine::new();
+/// A song slot of the mixer. Two of them exist so the previous song can be faded out while the next one starts.
+struct BgmChannel {
+    state: PlaybackState,
+    org_engine: OrgPlaybackEngine,
     #[cfg(feature = "ogg-playback")]
-    let mut ogg_engine = OggPlaybackEngine::new();
-    let mut pixtone = PixTonePlayback::new();
-    pixtone.create_samples();
+    ogg_engine: OggPlaybackEngine,
+    #[cfg(feature = "pcm-playback")]
+    pcm_engine: PcmPlaybackEngine,
+    buf: Vec<u16>,
+    index: usize,
+    samples: usize,
+}
 
-    log::info!("Audio format: {} {}", sample_rate, channels);
-    org_engine.set_sample_rate(sample_rate as usize);
-    #[cfg(feature = "ogg-playback")]
-    {
-        org_engine.loops = usize::MAX;
-        ogg_engine.set_sample_rate(sample_rate as usize);
-    }
-
-    let buf_size = sample_rate as usize * 10 / 1000;
-    let mut bgm_buf = vec![0x8080; buf_size * 2];
-    let mut pxt_buf = vec![0x8000; buf_size];
-    let mut bgm_index = 0;
-    let mut pxt_index = 0;
-    let mut samples = 0;
-    pixtone.mix(&mut pxt_buf, sample_rate);
-
-    let err_fn = |err| eprintln!("an error occurred on stream: {}", err);
-
-    let stream = device.build_output_stream(
-        config,
-        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
-            loop {
-                match rx.try_recv() {
-                    Ok(PlaybackMessage::PlayOrganyaSong(song)) => {
-                        if state == PlaybackState::Stopped {
-                            saved_state = PlaybackStateType::None;
-                        }
+impl BgmChannel {
+    fn new(sample_rate: usize, buf_size: usize) -> BgmChannel {
+        let mut org_engine = OrgPlaybackEngine::new();
+        #[cfg(feature = "ogg-playback")]
+        let mut ogg_engine = OggPlaybackEngine::new();
+        #[cfg(feature = "pcm-playback")]
+        let mut pcm_engine = PcmPlaybackEngine::new();
+
+        org_engine.set_sample_rate(sample_rate);
+        #[cfg(feature = "ogg-playback")]
+        {
+            org_engine.loops = usize::MAX;
+            ogg_engine.set_sample_rate(sample_rate);
+        }
+        #[cfg(feature = "pcm-playback")]
+        pcm_engine.set_sample_rate(sample_rate);
+
+        BgmChannel {
+            state: PlaybackState::Stopped,
+            org_engine,
+            #[cfg(feature = "ogg-playback")]
+            ogg_engine,
+            #[cfg(feature = "pcm-playback")]
+            pcm_engine,
+            buf: vec![0x8080; buf_size * 2],
+            index: 0,
+            samples: 0,
+        }
+    }
 
-                        org_engine.start_song(*song, &bank);
+    fn set_sample_rate(&mut self, sample_rate: usize) {
+        #[cfg(feature = "ogg-playback")]
+        self.ogg_engine.set_sample_rate(sample_rate);
+        #[cfg(feature = "pcm-playback")]
+        self.pcm_engine.set_sample_rate(sample_rate);
+        self.org_engine.set_sample_rate(sample_rate);
+    }
 
-                        for i in &mut bgm_buf[0..samples] {
-                            *i = 0x8080
-                        }
-                        samples = org_engine.render_to(&mut bgm_buf);
-                        bgm_index = 0;
+    fn fade_volume(&mut self, target: f32, seconds: f32) {
+        match self.state {
+            PlaybackState::Stopped => {}
+            PlaybackState::PlayingOrg => self.org_engine.fade_volume(target, seconds),
+            #[cfg(feature = "ogg-playback")]
+            PlaybackState::PlayingOgg => self.ogg_engine.fade_volume(target, seconds),
+            #[cfg(feature = "pcm-playback")]
+            PlaybackState::PlayingPcm => self.pcm_engine.fade_volume(target, seconds),
+        }
+    }
 
-                        state = PlaybackState::PlayingOrg;
-                    }
-                    #[cfg(feature = "ogg-playback")]
-                    Ok(PlaybackMessage::PlayOggSongSinglePart(data)) => {
-                        if state == PlaybackState::Stopped {
-                            saved_state = PlaybackStateType::None;
-                        }
+    fn fade_out(&mut self) {
+        match self.state {
+            PlaybackState::Stopped => {}
+            PlaybackState::PlayingOrg => self.org_engine.fade_out(),
+            #[cfg(feature = "ogg-playback")]
+            PlaybackState::PlayingOgg => self.ogg_engine.fade_out(),
+            #[cfg(feature = "pcm-playback")]
+            PlaybackState::PlayingPcm => self.pcm_engine.fade_out(),
+        }
+    }
 
-                        ogg_engine.start_single(data);
+    fn is_silent(&self) -> bool {
+        match self.state {
+            PlaybackState::Stopped => true,
+            PlaybackState::PlayingOrg => self.org_engine.is_silent(),
+            #[cfg(feature = "ogg-playback")]
+            PlaybackState::PlayingOgg => self.ogg_engine.is_silent(),
+            #[cfg(feature = "pcm-playback")]
+            PlaybackState::PlayingPcm => self.pcm_engine.is_silent(),
+        }
+    }
 
-                        for i in &mut bgm_buf[0..samples] {
-                            *i = 0x8000
-                        }
-                        samples = ogg_engine.render_to(&mut bgm_buf);
-                        bgm_index = 0;
+    /// Switches the channel to given state and renders the first chunk of the song.
+    fn start(&mut self, state: PlaybackState) {
+        self.state = state;
+        self.render();
+    }
 
-                        state = PlaybackState::PlayingOgg;
-                    }
-                    #[cfg(feature = "ogg-playback")]
-                    Ok(PlaybackMessage::PlayOggSongMultiPart(data_intro, data_loop)) => {
-                        if state == PlaybackState::Stopped {
-                            saved_state = PlaybackStateType::None;
-                        }
+    fn render(&mut self) {
+        match self.state {
+            PlaybackState::Stopped => {}
+            PlaybackState::PlayingOrg => {
+                for i in self.buf.iter_mut() {
+                    *i = 0x8080
+                }
+                self.samples = self.org_engine.render_to(&mut self.buf);
+            }
+            #[cfg(feature = "ogg-playback")]
+            PlaybackState::PlayingOgg => {
+                for i in self.buf.iter_mut() {
+                    *i = 0x8000
+                }
+                self.samples = self.ogg_engine.render_to(&mut self.buf);
+            }
+            #[cfg(feature = "pcm-playback")]
+            PlaybackState::PlayingPcm => {
+                for i in self.buf.iter_mut() {
+                    *i = 0x8000
+                }
+                self.samples = self.pcm_engine.render_to(&mut self.buf);
+            }
+        }
 
-                        ogg_engine.start_multi(data_intro, data_loop);
+        self.index = 0;
+    }
 
-                        for i in &mut bgm_buf[0..samples] {
-                            *i = 0x8000
-                        }
-                        samples = ogg_engine.render_to(&mut bgm_buf);
-                        bgm_index = 0;
+    /// Returns next stereo frame as signed samples.
+    fn next_frame(&mut self) -> (isize, isize) {
+        if self.state == PlaybackState::Stopped {
+            return (0, 0);
+        }
 
-                        state = PlaybackState::PlayingOgg;
-                    }
-                    Ok(PlaybackMessage::PlaySample(id)) => {
-                        pixtone.play_sfx(id);
-                    }
+        if self.index >= self.samples {
+            self.render();
+        }
 
-                    Ok(PlaybackMessage::LoopSample(id)) => {
-                        pixtone.loop_sfx(id);
-                    }
-                    Ok(PlaybackMessage::StopSample(id)) => {
-                        pixtone.stop_sfx(id);
-                    }
-                    Ok(PlaybackMessage::Stop) => {
-                        if state == PlaybackState::Stopped {
-                            saved_state = PlaybackStateType::None;
-                        }
+        let (sample_l, sample_r) = match self.state {
+            PlaybackState::PlayingOrg => {
+                let sample = self.buf[self.index];
+                self.index += 1;
+                ((sample & 0xff) << 8, sample & 0xff00)
+            }
+            #[cfg(feature = "ogg-playback")]
+            PlaybackState::PlayingOgg => {
+                let samples = (self.buf[self.index], self.buf[self.index + 1]);
+                self.index += 2;
+                samples
+            }
+            #[cfg(feature = "pcm-playback")]
+            PlaybackState::PlayingPcm => {
+                let samples = (self.buf[self.index], self.buf[self.index + 1]);
+                self.index += 2;
+                samples
+            }
+            _ => unreachable!(),
+        };
 
-                        state = PlaybackState::Stopped;
-                    }
-                    Ok(PlaybackMessage::SetSpeed(new_speed)) => {
-                        assert!(new_speed > 0.0);
-                        speed = new_speed;
-                        #[cfg(feature = "ogg-playback")]
-                        ogg_engine.set_sample_rate((sample_rate / new_speed) as usize);
-                        org_engine.set_sample_rate((sample_rate / new_speed) as usize);
-                    }
-                    Ok(PlaybackMessage::SaveState) => {
-                        saved_state = match state {
-                            PlaybackState::Stopped => PlaybackStateType::None,
-                            PlaybackState::PlayingOrg => PlaybackStateType::Organya(org_engine.get_state()),
-                            #[cfg(feature = "ogg-playback")]
-                            PlaybackState::PlayingOgg => PlaybackStateType::Ogg(ogg_engine.get_state()),
-                        };
-                    }
-                    Ok(PlaybackMessage::RestoreState) => {
-                        let mut saved_state_loc = PlaybackStateType::None;
-                        std::mem::swap(&mut saved_state_loc, &mut saved_state);
-
-                        match saved_state_loc {
-                            PlaybackStateType::None => {}
-                            PlaybackStateType::Organya(playback_state) => {
-                                org_engine.set_state(playback_state, &bank);
-
-                                if state == PlaybackState::Stopped {
-                                    org_engine.rewind();
-                                }
-
-                                for i in &mut bgm_buf[0..samples] {
-                                    *i = 0x8080
-                                }
-                                samples = org_engine.render_to(&mut bgm_buf);
-                                bgm_index = 0;
-
-                                state = PlaybackState::PlayingOrg;
-                            }
-                            #[cfg(feature = "ogg-playback")]
-                            PlaybackStateType::Ogg(playback_state) => {
-                                ogg_engine.set_state(playback_state);
+        (((sample_l ^ 0x8000) as i16) as isize, ((sample_r ^ 0x8000) as i16) as isize)
+    }
+}
+/// Mixes music and sound effects into signed 16-bit stereo frames, independently of the output they end up in.
+struct Mixer {
+    bank: SoundBank,
+    sample_rate: f32,
+    saved_state: PlaybackStateType,
+    speed: f32,
+    crossfade: f32,
+    bgm_volume: f32,
+    sfx_volume: f32,
+    pixtone: PixTonePlayback,
+    bgm: BgmChannel,
+    bgm_prev: BgmChannel,
+    pxt_buf: Vec<u16>,
+    pxt_index: usize,
+    org_position: Arc<AtomicI32>,
+}
 
-                                if state == PlaybackState::Stopped {
-                                    ogg_engine.rewind();
-                                }
+impl Mixer {
+    fn new(bank: SoundBank, sample_rate: f32, org_position: Arc<AtomicI32>) -> Mixer {
+        let mut pixtone = PixTonePlayback::new();
+        pixtone.create_samples();
+
+        let buf_size = sample_rate as usize * 10 / 1000;
+        let mut pxt_buf = vec![0x8000; buf_size * 2];
+        pixtone.mix(&mut pxt_buf, sample_rate);
+
+        Mixer {
+            bank,
+            sample_rate,
+            saved_state: PlaybackStateType::None,
+            speed: 1.0,
+            crossfade: 0.0,
+            bgm_volume: 1.0,
+            sfx_volume: 1.0,
+            pixtone,
+            bgm: BgmChannel::new(sample_rate as usize, buf_size),
+            bgm_prev: BgmChannel::new(sample_rate as usize, buf_size),
+            pxt_buf,
+            pxt_index: 0,
+            org_position,
+        }
+    }
 
-                                for i in &mut bgm_buf[0..samples] {
-                                    *i = 0x8000
-                                }
-                                samples = ogg_engine.render_to(&mut bgm_buf);
-                                bgm_index = 0;
+    /// Moves currently playing song to the fading channel, so the next one can be started in its place.
+    fn begin_crossfade(&mut self) -> bool {
+        if self.crossfade <= 0.0 || self.bgm.state == PlaybackState::Stopped {
+            self.bgm_prev.state = PlaybackState::Stopped;
+            return false;
+        }
 
-                                state = PlaybackState::PlayingOgg;
-                            }
-                        }
-                    }
-                    Ok(PlaybackMessage::SetSampleParams(id, params)) => {
-                        pixtone.set_sample_parameters(id, params);
-                    }
-                    Err(_) => {
-                        break;
-                    }
+        std::mem::swap(&mut self.bgm, &mut self.bgm_prev);
+        self.bgm_prev.fade_volume(0.0, self.crossfade);
+        self.bgm.state = PlaybackState::Stopped;
+
+        true
+    }
+
+    fn handle_message(&mut self, message: PlaybackMessage) {
+        match message {
+            PlaybackMessage::PlayOrganyaSong(song) => {
+                if self.bgm.state == PlaybackState::Stopped {
+                    self.saved_state = PlaybackStateType::None;
+                }
+
+                let fade_in = self.begin_crossfade();
+
+                self.bgm.org_engine.start_song(*song, &self.bank);
+                if fade_in {
+                    self.bgm.org_engine.set_volume(0.0);
+                    self.bgm.org_engine.fade_volume(1.0, self.crossfade);
                 }
+
+                self.bgm.start(PlaybackState::PlayingOrg);
             }
+            #[cfg(feature = "ogg-playback")]
+            PlaybackMessage::PlayOggSongSinglePart(data) => {
+                if self.bgm.state == PlaybackState::Stopped {
+                    self.saved_state = PlaybackStateType::None;
+                }
 
-            for frame in data.chunks_mut(channels) {
-                let (bgm_sample_l, bgm_sample_r): (u16, u16) = {
-                    if state == PlaybackState::Stopped {
-                        (0x8000, 0x8000)
-                    } else if bgm_index < samples {
-                        match state {
-                            PlaybackState::PlayingOrg => {
-                                let sample = bgm_buf[bgm_index];
-                                bgm_index += 1;
-                                ((sample & 0xff) << 8, sample & 0xff00)
-                            }
-                            #[cfg(feature = "ogg-playback")]
-                            PlaybackState::PlayingOgg => {
-                                let samples = (bgm_buf[bgm_index], bgm_buf[bgm_index + 1]);
-                                bgm_index += 2;
-                                samples
-                            }
-                            _ => unreachable!(),
+                let fade_in = self.begin_crossfade();
+
+                self.bgm.ogg_engine.start_single(data);
+                if fade_in {
+                    self.bgm.ogg_engine.set_volume(0.0);
+                    self.bgm.ogg_engine.fade_volume(1.0, self.crossfade);
+                }
+
+                self.bgm.start(PlaybackState::PlayingOgg);
+            }
+            #[cfg(feature = "ogg-playback")]
+            PlaybackMessage::PlayOggSongMultiPart(data_intro, data_loop) => {
+                if self.bgm.state == PlaybackState::Stopped {
+                    self.saved_state = PlaybackStateType::None;
+                }
+
+                let fade_in = self.begin_crossfade();
+
+                self.bgm.ogg_engine.start_multi(data_intro, data_loop);
+                if fade_in {
+                    self.bgm.ogg_engine.set_volume(0.0);
+                    self.bgm.ogg_engine.fade_volume(1.0, self.crossfade);
+                }
+
+                self.bgm.start(PlaybackState::PlayingOgg);
+            }
+            #[cfg(feature = "pcm-playback")]
+            PlaybackMessage::PlayPcmSong(stream) => {
+                if self.bgm.state == PlaybackState::Stopped {
+                    self.saved_state = PlaybackStateType::None;
+                }
+
+                let fade_in = self.begin_crossfade();
+
+                self.bgm.pcm_engine.start(stream);
+                if fade_in {
+                    self.bgm.pcm_engine.set_volume(0.0);
+                    self.bgm.pcm_engine.fade_volume(1.0, self.crossfade);
+                }
+
+                self.bgm.start(PlaybackState::PlayingPcm);
+            }
+            PlaybackMessage::PlaySample(id) => {
+                self.pixtone.play_sfx(id);
+            }
+            PlaybackMessage::PlaySamplePanned(id, volume, pan) => {
+                self.pixtone.play_sfx_panned(id, volume, pan);
+            }
+            PlaybackMessage::LoopSample(id) => {
+                self.pixtone.loop_sfx(id);
+            }
+            PlaybackMessage::StopSample(id) => {
+                self.pixtone.stop_sfx(id);
+            }
+            PlaybackMessage::Stop => {
+                if self.bgm.state == PlaybackState::Stopped {
+                    self.saved_state = PlaybackStateType::None;
+                }
+
+                self.begin_crossfade();
+                self.bgm.state = PlaybackState::Stopped;
+            }
+            PlaybackMessage::SetSpeed(new_speed) => {
+                assert!(new_speed > 0.0);
+                self.speed = new_speed;
+                self.bgm.set_sample_rate((self.sample_rate / new_speed) as usize);
+                self.bgm_prev.set_sample_rate((self.sample_rate / new_speed) as usize);
+            }
+            PlaybackMessage::SaveState => {
+                self.saved_state = match self.bgm.state {
+                    PlaybackState::Stopped => PlaybackStateType::None,
+                    PlaybackState::PlayingOrg => PlaybackStateType::Organya(self.bgm.org_engine.get_state()),
+                    #[cfg(feature = "ogg-playback")]
+                    PlaybackState::PlayingOgg => PlaybackStateType::Ogg(self.bgm.ogg_engine.get_state()),
+                    #[cfg(feature = "pcm-playback")]
+                    PlaybackState::PlayingPcm => PlaybackStateType::Pcm(self.bgm.pcm_engine.get_state()),
+                };
+            }
+            PlaybackMessage::RestoreState => {
+                let mut saved_state_loc = PlaybackStateType::None;
+                std::mem::swap(&mut saved_state_loc, &mut self.saved_state);
+
+                let was_stopped = self.bgm.state == PlaybackState::Stopped;
+
+                match saved_state_loc {
+                    PlaybackStateType::None => {}
+                    PlaybackStateType::Organya(playback_state) => {
+                        let fade_in = self.begin_crossfade();
+
+                        // restored songs always resume at full volume, even if they were faded out
+                        self.bgm.org_engine.set_state(playback_state, &self.bank);
+                        if fade_in {
+                            self.bgm.org_engine.set_volume(0.0);
+                            self.bgm.org_engine.fade_volume(1.0, self.crossfade);
                         }
-                    } else {
-                        for i in &mut bgm_buf[0..samples] {
-                            *i = 0x8080
+
+                        if was_stopped {
+                            self.bgm.org_engine.rewind();
                         }
 
-                        match state {
-                            PlaybackState::PlayingOrg => {
-                                samples = org_engine.render_to(&mut bgm_buf);
-                                bgm_index = 1;
-                                let sample = bgm_buf[0];
-                                ((sample & 0xff) << 8, sample & 0xff00)
-                            }
-                            #[cfg(feature = "ogg-playback")]
-                            PlaybackState::PlayingOgg => {
-                                samples = ogg_engine.render_to(&mut bgm_buf);
-                                bgm_index = 2;
-                                (bgm_buf[0], bgm_buf[1])
-                            }
-                            _ => unreachable!(),
+                        self.bgm.start(PlaybackState::PlayingOrg);
+                    }
+                    #[cfg(feature = "ogg-playback")]
+                    PlaybackStateType::Ogg(playback_state) => {
+                        let fade_in = self.begin_crossfade();
+
+                        self.bgm.ogg_engine.set_state(playback_state);
+                        if fade_in {
+                            self.bgm.ogg_engine.set_volume(0.0);
+                            self.bgm.ogg_engine.fade_volume(1.0, self.crossfade);
                         }
+
+                        if was_stopped {
+                            self.bgm.ogg_engine.rewind();
+                        }
+
+                        self.bgm.start(PlaybackState::PlayingOgg);
                     }
-                };
+                    #[cfg(feature = "pcm-playback")]
+                    PlaybackStateType::Pcm(playback_state) => {
+                        let fade_in = self.begin_crossfade();
+
+                        self.bgm.pcm_engine.set_state(playback_state);
+                        if fade_in {
+                            self.bgm.pcm_engine.set_volume(0.0);
+                            self.bgm.pcm_engine.fade_volume(1.0, self.crossfade);
+                        }
 
-                let pxt_sample: u16 = pxt_buf[pxt_index];
+                        if was_stopped {
+                            self.bgm.pcm_engine.rewind();
+                        }
 
-                if pxt_index < (pxt_buf.len() - 1) {
-                    pxt_index += 1;
-                } else {
-                    pxt_index = 0;
-                    for i in pxt_buf.iter_mut() {
-                        *i = 0x8000
+                        self.bgm.start(PlaybackState::PlayingPcm);
                     }
-                    pixtone.mix(&mut pxt_buf, sample_rate / speed);
                 }
-
-                if frame.len() >= 2 {
-                    let sample_l = clamp(
-                        (((bgm_sample_l ^ 0x8000) as i16) as isize) + (((pxt_sample ^ 0x8000) as i16) as isize),
-                        -0x7fff,
-                        0x7fff,
-                    ) as u16
-                        ^ 0x8000;
-                    let sample_r = clamp(
-                        (((bgm_sample_r ^ 0x8000) as i16) as isize) + (((pxt_sample ^ 0x8000) as i16) as isize),
-                        -0x7fff,
-                        0x7fff,
-                    ) as u16
-                        ^ 0x8000;
-
-                    frame[0] = Sample::from::<u16>(&sample_l);
-                    frame[1] = Sample::from::<u16>(&sample_r);
-                } else {
-                    let sample = clamp(
-                        ((((bgm_sample_l ^ 0x8000) as i16) + ((bgm_sample_r ^ 0x8000) as i16)) / 2) as isize
-                            + (((pxt_sample ^ 0x8000) as i16) as isize),
-                        -0x7fff,
-                        0x7fff,
-                    ) as u16
-                        ^ 0x8000;
-
-                    frame[0] = Sample::from::<u16>(&sample);
+            }
+            PlaybackMessage::SetSampleParams(id, params) => {
+                self.pixtone.set_sample_parameters(id, params);
+            }
+            PlaybackMessage::FadeOutSong => {
+                self.bgm.fade_out();
+            }
+            PlaybackMessage::SetCrossfade(seconds) => {
+                self.crossfade = seconds;
+            }
+            PlaybackMessage::SetVolume(bgm_volume, sfx_volume) => {
+                self.bgm_volume = bgm_volume;
+                self.sfx_volume = sfx_volume;
+            }
+            PlaybackMessage::UpdateOrganyaSong(song) => {
+                if self.bgm.state == PlaybackState::PlayingOrg {
+                    self.bgm.org_engine.update_song(*song, &self.bank);
                 }
             }
-        },
-        err_fn,
-    )?;
+            PlaybackMessage::SetOrganyaMutedTracks(muted_tracks) => {
+                // muting applies to whatever song comes next too, so it's set on both channels
+                self.bgm.org_engine.set_muted_tracks(muted_tracks);
+                self.bgm_prev.org_engine.set_muted_tracks(muted_tracks);
+            }
+            PlaybackMessage::SetOrganyaPosition(position) => {
+                if self.bgm.state == PlaybackState::PlayingOrg {
+                    self.bgm.org_engine.set_position(position);
+                    self.bgm.render();
+                }
+            }
+            // only meaningful for outputs driven by a simulated clock, which handle it themselves
+            PlaybackMessage::AdvanceTime(_) => {}
+            // device outputs have nothing to finalize, headless ones handle it themselves
+            PlaybackMessage::Flush(reply) | PlaybackMessage::FinishOutput(reply) => {
+                let _ = reply.send(());
+            }
+        }
+    }
 
-    stream.play()?;
+    /// Returns next mixed stereo frame as signed samples.
+    fn next_frame(&mut self) -> (i16, i16) {
+        let (bgm_sample_l, bgm_sample_r) = self.bgm.next_frame();
 
-    let mut saved_state = true;
-    loop {
-        std::thread::sleep(Duration::from_millis(10));
+        let org_position =
+            if self.bgm.state == PlaybackState::PlayingOrg { self.bgm.org_engine.position() } else { -1 };
+        self.org_position.store(org_position, Ordering::Relaxed);
+        let (prev_sample_l, prev_sample_r) = self.bgm_prev.next_frame();
 
-        {
-            let mutex = crate::GAME_SUSPENDED.lock().unwrap();
-            let state = *mutex;
-            if saved_state != state {
-                saved_state = state;
-
-                if state {
-                    if let Err(e) = stream.pause() {
-                        log::error!("Failed to pause the stream: {}", e);
-                    }
-                } else {
-                    if let Err(e) = stream.play() {
-                        log::error!("Failed to unpause the stream: {}", e);
-                    }
-                }
+        if self.bgm_prev.state != PlaybackState::Stopped && self.bgm_prev.is_silent() {
+            self.bgm_prev.state = PlaybackState::Stopped;
+        }
+
+        let (pxt_sample_l, pxt_sample_r) = (self.pxt_buf[self.pxt_index], self.pxt_buf[self.pxt_index + 1]);
+
+        if self.pxt_index + 2 < self.pxt_buf.len() {
+            self.pxt_index += 2;
+        } else {
+            self.pxt_index = 0;
+            for i in self.pxt_buf.iter_mut() {
+                *i = 0x8000
             }
+            self.pixtone.mix(&mut self.pxt_buf, self.sample_rate / self.speed);
         }
+
+        let pxt_sample_l = ((((pxt_sample_l ^ 0x8000) as i16) as f32) * self.sfx_volume) as isize;
+        let pxt_sample_r = ((((pxt_sample_r ^ 0x8000) as i16) as f32) * self.sfx_volume) as isize;
+        let bgm_sample_l = ((bgm_sample_l + prev_sample_l) as f32 * self.bgm_volume) as isize;
+        let bgm_sample_r = ((bgm_sample_r + prev_sample_r) as f32 * self.bgm_volume) as isize;
+
+        (
+            clamp(bgm_sample_l + pxt_sample_l, -0x7fff, 0x7fff) as i16,
+            clamp(bgm_sample_r + pxt_sample_r, -0x7fff, 0x7fff) as i16,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_mix(listener: &Listener, pos: (i32, i32), volume: f32, pan: i32) {
+        let (actual_volume, actual_pan) = listener.mix(pos.0, pos.1);
+
+        assert!((actual_volume - volume).abs() < 1e-5, "volume {} != {} at {:?}", actual_volume, volume, pos);
+        assert_eq!(actual_pan, pan, "pan at {:?}", pos);
+    }
+
+    #[test]
+    fn listener_pans_across_the_screen() {
+        let listener = Listener { x: 1000, y: 500, half_width: 100, half_height: 50 };
+
+        assert_mix(&listener, (1000, 500), 1.0, 0);
+        assert_mix(&listener, (900, 500), 1.0, -1500);
+        assert_mix(&listener, (1100, 500), 1.0, 1500);
+        assert_mix(&listener, (1050, 500), 1.0, 750);
+        // vertical distance doesn't pan
+        assert_mix(&listener, (1000, 450), 1.0, 0);
+    }
+
+    #[test]
+    fn listener_attenuates_with_distance() {
+        let listener = Listener { x: 1000, y: 500, half_width: 100, half_height: 50 };
+
+        // fades out over the screen past the edge, pan stays at the maximum
+        assert_mix(&listener, (1150, 500), 0.65, 1500);
+        assert_mix(&listener, (850, 500), 0.65, -1500);
+        assert_mix(&listener, (1000, 575), 0.65, 0);
+        assert_mix(&listener, (1200, 500), Listener::MIN_VOLUME, 1500);
+        assert_mix(&listener, (-5000, 500), Listener::MIN_VOLUME, -1500);
+        assert_mix(&listener, (1000, 5000), Listener::MIN_VOLUME, 0);
     }
 }
@@ -4,25 +4,40 @@ use lewton::inside_ogg::OggStreamReader;
 use num_traits::clamp;
 
 use crate::framework::filesystem::File;
-use crate::sound::stuff::cubic_interp;
+use crate::sound::stuff::{cubic_interp, LoopPoints, VolumeEnvelope};
 use crate::sound::wav::WavFormat;
 
+/// Length of `<FMU` fade in seconds. Unlike Organya, Ogg songs have no tick rate to derive it from,
+/// so this roughly matches the vanilla fade of an average-tempo song.
+const FADE_OUT_TIME: f32 = 5.0;
+
 pub(crate) struct OggPlaybackEngine {
     intro_music: Option<Arc<RwLock<Box<OggStreamReader<File>>>>>,
     loop_music: Option<Arc<RwLock<Box<OggStreamReader<File>>>>>,
     output_format: WavFormat,
     playing_intro: bool,
+    /// Loop range of `loop_music` taken from its `LOOPSTART`/`LOOPEND`/`LOOPLENGTH` comments.
+    loop_points: Option<LoopPoints>,
     position: u64,
+    /// Frame the loop stream was seeked to, packets are held in `pending` until their position is known.
+    seek_target: Option<u64>,
+    pending: Vec<i16>,
     buffer: Vec<i16>,
+    volume: VolumeEnvelope,
 }
 
 pub struct SavedOggPlaybackState {
     intro_music: Option<Arc<RwLock<Box<OggStreamReader<File>>>>>,
     loop_music: Option<Arc<RwLock<Box<OggStreamReader<File>>>>>,
     playing_intro: bool,
+    loop_points: Option<LoopPoints>,
     position: u64,
 }
 
+fn read_loop_points(music: &OggStreamReader<File>) -> Option<LoopPoints> {
+    LoopPoints::from_tags(music.comment_hdr.comment_list.iter().map(|(name, value)| (name.as_str(), value.as_str())))
+}
+
 impl OggPlaybackEngine {
     pub fn new() -> OggPlaybackEngine {
         OggPlaybackEngine {
@@ -34,8 +49,12 @@ impl OggPlaybackEngine {
                 bit_depth: 16,
             },
             playing_intro: false,
+            loop_points: None,
             position: 0,
+            seek_target: None,
+            pending: Vec::new(),
             buffer: Vec::with_capacity(4096),
+            volume: VolumeEnvelope::new(1.0),
         }
     }
 
@@ -48,7 +67,8 @@ impl OggPlaybackEngine {
             intro_music: self.intro_music.clone(),
             loop_music: self.loop_music.clone(),
             playing_intro: self.playing_intro,
-            position: self.position,
+            loop_points: self.loop_points,
+            position: self.seek_target.unwrap_or(self.position),
         }
     }
 
@@ -56,14 +76,27 @@ impl OggPlaybackEngine {
         self.intro_music = state.intro_music;
         self.loop_music = state.loop_music;
         self.playing_intro = state.playing_intro;
+        self.loop_points = state.loop_points;
         self.position = state.position;
+        self.buffer.clear();
+        self.volume.set(1.0);
+
+        // the stream could have been moved by a pending seek or another engine, resume exactly where we left
+        if !self.playing_intro {
+            self.seek_loop(state.position);
+        }
     }
 
     pub fn start_single(&mut self, loop_music: Box<OggStreamReader<File>>) {
+        self.loop_points = read_loop_points(&loop_music);
         self.intro_music = None;
         self.loop_music = Some(Arc::new(RwLock::new(loop_music)));
         self.playing_intro = false;
         self.position = 0;
+        self.seek_target = None;
+        self.pending.clear();
+        self.buffer.clear();
+        self.volume.set(1.0);
     }
 
     pub fn start_multi(
@@ -71,10 +104,32 @@ impl OggPlaybackEngine {
         intro_music: Box<OggStreamReader<File>>,
         loop_music: Box<OggStreamReader<File>>,
     ) {
+        self.loop_points = read_loop_points(&loop_music);
         self.intro_music = Some(Arc::new(RwLock::new(intro_music)));
         self.loop_music = Some(Arc::new(RwLock::new(loop_music)));
         self.playing_intro = true;
         self.position = 0;
+        self.seek_target = None;
+        self.pending.clear();
+        self.buffer.clear();
+        self.volume.set(1.0);
+    }
+
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume.set(volume);
+    }
+
+    /// Fades song volume to target over given amount of seconds.
+    pub fn fade_volume(&mut self, target: f32, seconds: f32) {
+        self.volume.fade_to(target, seconds * self.output_format.sample_rate as f32);
+    }
+
+    pub fn fade_out(&mut self) {
+        self.fade_volume(0.0, FADE_OUT_TIME);
+    }
+
+    pub fn is_silent(&self) -> bool {
+        self.volume.is_silent()
     }
 
     pub fn rewind(&mut self) {
@@ -83,13 +138,58 @@ impl OggPlaybackEngine {
             self.position = 0;
             self.playing_intro = true;
         } else {
-            if let Some(music) = self.loop_music.as_ref() {
-                let _ = music.write().unwrap().seek_absgp_pg(0);
+            self.seek_loop(0);
+            self.playing_intro = false;
+        }
+    }
+
+    /// Seeks the loop stream to given frame. Vorbis can only seek by pages, so decoded samples are discarded
+    /// up to the target once the granule position of a packet tells where they are.
+    fn seek_loop(&mut self, frame: u64) {
+        self.position = frame;
+        self.pending.clear();
+        self.seek_target = None;
+
+        if let Some(music) = self.loop_music.as_ref() {
+            if music.write().unwrap().seek_absgp_pg(frame).is_ok() {
+                self.seek_target = Some(frame);
             }
+        }
+    }
 
-            self.position = 0;
-            self.playing_intro = false;
+    /// Passes a decoded packet of the loop stream through pending seek and loop end handling.
+    /// Returns samples that should be played.
+    fn advance_loop(&mut self, mut buf: Vec<i16>, channels: usize, absgp: Option<u64>) -> Vec<i16> {
+        if let Some(target) = self.seek_target {
+            self.pending.append(&mut buf);
+
+            // granule position is only known at page boundaries
+            let absgp = match absgp {
+                Some(absgp) => absgp,
+                None => return Vec::new(),
+            };
+
+            let pending_start = absgp.saturating_sub((self.pending.len() / channels) as u64);
+            let skip = (target.saturating_sub(pending_start) as usize * channels).min(self.pending.len());
+
+            self.seek_target = None;
+            self.position = target.max(pending_start);
+            buf = self.pending.split_off(skip);
+            self.pending.clear();
         }
+
+        let start = self.position;
+        self.position += (buf.len() / channels) as u64;
+
+        if let Some(LoopPoints { start: loop_start, end: Some(loop_end) }) = self.loop_points {
+            // an end before the start would seek back forever without playing anything, loop the whole song instead
+            if loop_end > loop_start && self.position >= loop_end {
+                buf.truncate(loop_end.saturating_sub(start) as usize * channels);
+                self.seek_loop(loop_start);
+            }
+        }
+
+        buf
     }
 
     fn decode(&mut self) {
@@ -100,7 +200,9 @@ impl OggPlaybackEngine {
                 let mut buf = match music.read_dec_packet_itl() {
                     Ok(Some(buf)) => buf,
                     Ok(None) | Err(_) => {
+                        // loop points are relative to the loop part
                         self.playing_intro = false;
+                        self.position = 0;
                         return;
                     }
                 };
@@ -117,28 +219,31 @@ impl OggPlaybackEngine {
             }
         } else {
             if let Some(music) = self.loop_music.as_ref() {
-                let mut music = music.write().unwrap();
+                let (buf, absgp, sample_rate, channels) = {
+                    let mut music = music.write().unwrap();
 
-                let mut buf = match music.read_dec_packet_itl() {
-                    Ok(Some(buf)) => buf,
-                    Ok(None) => {
-                        if let Err(_) = music.seek_absgp_pg(0) {
-                            vec![0, 1000]
-                        } else {
+                    let buf = match music.read_dec_packet_itl() {
+                        Ok(buf) => buf,
+                        Err(_) => Some(vec![0, 1000]),
+                    };
+
+                    (buf, music.get_last_absgp(), music.ident_hdr.audio_sample_rate, music.ident_hdr.audio_channels)
+                };
+
+                let buf = match buf {
+                    Some(buf) => self.advance_loop(buf, channels.max(1) as usize, absgp),
+                    None => {
+                        // end of stream, jump back to the loop start
+                        self.seek_loop(self.loop_points.map(|l| l.start).unwrap_or(0));
+                        if self.seek_target.is_some() {
                             return;
                         }
-                    }
-                    Err(_) => {
+
                         vec![0, 1000]
                     }
                 };
 
-                self.position = music.get_last_absgp().unwrap_or(0);
-                buf = self.resample_buffer(
-                    buf,
-                    music.ident_hdr.audio_sample_rate,
-                    music.ident_hdr.audio_channels,
-                );
+                let mut buf = self.resample_buffer(buf, sample_rate, channels);
                 self.buffer.append(&mut buf);
             } else {
                 let mut buf = vec![0; 1000];
@@ -184,12 +289,86 @@ impl OggPlaybackEngine {
             self.decode();
         }
 
-        self.buffer
-            .drain(0..buf.len())
-            .map(|n| n as u16 ^ 0x8000)
-            .zip(buf.iter_mut())
-            .for_each(|(n, tgt)| *tgt = n);
+        let mut volume = 1.0;
+        for (i, (n, tgt)) in self.buffer.drain(0..buf.len()).zip(buf.iter_mut()).enumerate() {
+            // samples are interleaved, so the envelope advances once per stereo frame
+            if i % 2 == 0 {
+                volume = self.volume.advance();
+            }
+
+            *tgt = (n as f32 * volume) as i16 as u16 ^ 0x8000;
+        }
 
         buf.len()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Engine without a stream, seeks only move the position then.
+    fn engine(loop_points: Option<LoopPoints>) -> OggPlaybackEngine {
+        let mut engine = OggPlaybackEngine::new();
+        engine.loop_points = loop_points;
+        engine
+    }
+
+    /// Stereo packet of given frame count, samples count up from `first` so cuts can be told apart.
+    fn packet(first: i16, frames: usize) -> Vec<i16> {
+        (0..frames as i16 * 2).map(|i| first + i).collect()
+    }
+
+    #[test]
+    fn loop_end_at_stream_end() {
+        let mut engine = engine(Some(LoopPoints { start: 100, end: Some(300) }));
+        engine.position = 200;
+
+        // the last packet ends right at the loop end, nothing is cut and the next one comes from the loop start
+        assert_eq!(engine.advance_loop(packet(0, 100), 2, Some(300)), packet(0, 100));
+        assert_eq!(engine.position, 100);
+
+        // loop end inside a packet, the rest of it is dropped
+        engine.position = 250;
+        assert_eq!(engine.advance_loop(packet(0, 100), 2, Some(350)), packet(0, 50));
+        assert_eq!(engine.position, 100);
+    }
+
+    #[test]
+    fn loop_start_at_zero() {
+        let mut engine = engine(Some(LoopPoints { start: 0, end: Some(150) }));
+
+        assert_eq!(engine.advance_loop(packet(0, 100), 2, Some(100)), packet(0, 100));
+        assert_eq!(engine.position, 100);
+        assert_eq!(engine.advance_loop(packet(0, 100), 2, Some(200)), packet(0, 50));
+        assert_eq!(engine.position, 0);
+    }
+
+    #[test]
+    fn loop_end_before_loop_start() {
+        // tag parsing already drops such ends, but loop points can also come from a saved state
+        assert_eq!(
+            LoopPoints::from_tags(vec![("LOOPSTART", "300"), ("LOOPEND", "100")]),
+            Some(LoopPoints { start: 300, end: None })
+        );
+
+        let mut engine = engine(Some(LoopPoints { start: 300, end: Some(100) }));
+        engine.position = 300;
+
+        // played through instead of being cut to nothing over and over
+        assert_eq!(engine.advance_loop(packet(0, 100), 2, Some(400)), packet(0, 100));
+        assert_eq!(engine.position, 400);
+    }
+
+    #[test]
+    fn pending_seek_skips_to_target() {
+        let mut engine = engine(Some(LoopPoints { start: 150, end: None }));
+        engine.seek_target = Some(150);
+
+        // granule position is only known at the end of a page, packets are held until then
+        assert_eq!(engine.advance_loop(packet(0, 60), 2, None), Vec::<i16>::new());
+        assert_eq!(engine.advance_loop(packet(120, 60), 2, Some(200)), packet(140, 50));
+        assert_eq!(engine.position, 200);
+        assert_eq!(engine.seek_target, None);
+    }
+}
@@ -1,6 +1,6 @@
 use std::mem::MaybeUninit;
 
-use crate::sound::organya::{Song as Organya, Version};
+use crate::sound::organya::Song as Organya;
 use crate::sound::stuff::*;
 use crate::sound::wav::*;
 use crate::sound::wave_bank::SoundBank;
@@ -23,7 +23,12 @@ pub(crate) struct OrgPlaybackEngine {
     play_pos: i32,
     frames_this_tick: usize,
     frames_per_tick: usize,
+    volume: VolumeEnvelope,
+    /// Bit mask of tracks which don't play any notes.
+    muted_tracks: u16,
     pub loops: usize,
+    /// Set once the last of `loops` has been played, until another song is started.
+    ended: bool,
 }
 
 pub struct SavedOrganyaPlaybackState {
@@ -65,7 +70,10 @@ impl OrgPlaybackEngine {
             },
             frames_this_tick: 0,
             frames_per_tick,
+            volume: VolumeEnvelope::new(1.0),
+            muted_tracks: 0,
             loops: 1,
+            ended: false,
         }
     }
 
@@ -112,20 +120,19 @@ impl OrgPlaybackEngine {
             }
         }
 
-        for (idx, (track, buf)) in song.tracks[8..]
-            .iter()
-            .zip(self.track_buffers[128..].iter_mut())
-            .enumerate()
-        {
-            if self.song.version == Version::Extended {
-                *buf = RenderBuffer::new(samples.samples[track.inst.inst as usize].clone());
-            } else {
-                *buf = RenderBuffer::new(samples.samples[idx].clone());
-            }
+        for (idx, buf) in self.track_buffers[128..].iter_mut().enumerate() {
+            // fall back to the default drum if the song refers to a sample the bank doesn't have
+            let sample = samples.samples.get(song.drum_sample(idx)).or_else(|| samples.samples.get(idx));
+
+            *buf = match sample {
+                Some(sample) => RenderBuffer::new(sample.clone()),
+                None => RenderBuffer::empty(),
+            };
         }
 
         self.song = song;
         self.play_pos = 0;
+        self.ended = false;
         self.frames_per_tick = (self.output_format.sample_rate as usize / 1000) * self.song.time.wait as usize;
         self.frames_this_tick = 0;
         for i in self.lengths.iter_mut() {
@@ -137,6 +144,42 @@ impl OrgPlaybackEngine {
         for i in self.keys.iter_mut() {
             *i = 255
         }
+        self.volume.set(1.0);
+    }
+
+    /// Replaces the song while keeping the playback position and volume, used for live editing.
+    pub fn update_song(&mut self, song: Organya, samples: &SoundBank) {
+        let play_pos = self.play_pos;
+        let volume = self.volume;
+
+        self.start_song(song, samples);
+        self.volume = volume;
+
+        let loop_range = self.song.time.loop_range;
+        self.play_pos = if play_pos >= loop_range.end { loop_range.start } else { play_pos };
+    }
+
+    pub fn set_muted_tracks(&mut self, muted_tracks: u16) {
+        self.muted_tracks = muted_tracks;
+        self.silence_muted_tracks();
+    }
+
+    fn silence_muted_tracks(&mut self) {
+        if self.muted_tracks == 0 {
+            return;
+        }
+
+        for (idx, buf) in self.track_buffers.iter_mut().enumerate() {
+            let track = if idx < 128 { idx % 8 } else { idx - 120 };
+
+            if self.muted_tracks & (1 << track) != 0 {
+                buf.playing = false;
+            }
+        }
+    }
+
+    pub fn position(&self) -> i32 {
+        self.play_pos
     }
 
     pub fn set_position(&mut self, position: i32) {
@@ -147,6 +190,24 @@ impl OrgPlaybackEngine {
         self.set_position(0);
     }
 
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume.set(volume);
+    }
+
+    /// Fades song volume to target over given amount of seconds.
+    pub fn fade_volume(&mut self, target: f32, seconds: f32) {
+        self.volume.fade_to(target, seconds * self.output_format.sample_rate as f32);
+    }
+
+    /// Fades the song out the same way `<FMU` does in vanilla, where volume drops to zero over 50 ticks.
+    pub fn fade_out(&mut self) {
+        self.volume.fade_to(0.0, (self.frames_per_tick * 50) as f32);
+    }
+
+    pub fn is_silent(&self) -> bool {
+        self.volume.is_silent()
+    }
+
     #[allow(unused)]
     pub fn get_total_samples(&self) -> u32 {
         let ticks_intro = self.song.time.loop_range.start;
@@ -286,30 +347,38 @@ impl OrgPlaybackEngine {
         }
     }
 
+    /// Returns true once `render_to` has reached the end of the last loop.
+    pub fn has_ended(&self) -> bool {
+        self.ended
+    }
+
     pub fn render_to(&mut self, buf: &mut [u16]) -> usize {
         for (i, frame) in buf.iter_mut().enumerate() {
             if self.frames_this_tick == 0 {
-                self.update_play_state()
+                self.update_play_state();
+                self.silence_muted_tracks();
             }
 
-            mix(std::slice::from_mut(frame), self.output_format, &mut self.track_buffers);
+            let volume = self.volume.advance();
+            mix(std::slice::from_mut(frame), self.output_format, &mut self.track_buffers, volume);
 
             self.frames_this_tick += 1;
 
             if self.frames_this_tick == self.frames_per_tick {
                 self.play_pos += 1;
+                // reset before returning at the end of last loop, so the song can still be continued afterwards
+                self.frames_this_tick = 0;
 
                 if self.play_pos == self.song.time.loop_range.end {
                     self.play_pos = self.song.time.loop_range.start;
 
                     if self.loops == 0 {
+                        self.ended = true;
                         return i + 1;
                     }
 
                     self.loops -= 1;
                 }
-
-                self.frames_this_tick = 0;
             }
         }
 
@@ -318,15 +387,16 @@ impl OrgPlaybackEngine {
 }
 
 // TODO: Create a MixingBuffer or something...
-pub fn mix(dst: &mut [u16], dst_fmt: WavFormat, srcs: &mut [RenderBuffer]) {
+pub fn mix(dst: &mut [u16], dst_fmt: WavFormat, srcs: &mut [RenderBuffer], master_volume: f32) {
     let freq = dst_fmt.sample_rate as f64;
 
     for buf in srcs {
-        if buf.playing {
+        // empty buffers have nothing to read from
+        if buf.playing && buf.len != 0 {
             // index into sound samples
             let advance = buf.frequency as f64 / freq;
 
-            let vol = centibel_to_scale(buf.volume);
+            let vol = centibel_to_scale(buf.volume) * master_volume;
 
             let (pan_l, pan_r) = match buf.pan.signum() {
                 0 => (1.0, 1.0),
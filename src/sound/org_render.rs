@@ -0,0 +1,226 @@
+use std::io;
+#[cfg(feature = "ogg-export")]
+use std::num::{NonZeroU32, NonZeroU8};
+use std::str::FromStr;
+
+#[cfg(feature = "ogg-export")]
+use vorbis_rs::VorbisEncoderBuilder;
+
+use crate::framework::context::Context;
+#[cfg(feature = "ogg-export")]
+use crate::framework::error::GameError::AudioError;
+use crate::framework::error::GameError::{InvalidValue, ParseError};
+use crate::framework::error::{GameError, GameResult};
+use crate::framework::filesystem;
+use crate::sound::org_playback::OrgPlaybackEngine;
+use crate::sound::organya::Song;
+use crate::sound::wav::{WavFormat, WavWriter};
+use crate::sound::wave_bank::SoundBank;
+
+/// Wavetables shipped in `/builtin/`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OrgWavetable {
+    /// Wavetable embedded in Doukutsu.exe, used in game.
+    Doukutsu,
+    /// Wavetable of OrgMaker, with the extended drum set.
+    OrgMaker,
+}
+
+impl OrgWavetable {
+    pub fn path(self) -> &'static str {
+        match self {
+            OrgWavetable::Doukutsu => "/builtin/organya-wavetable-doukutsu.bin",
+            OrgWavetable::OrgMaker => "/builtin/organya-wavetable-org.bin",
+        }
+    }
+}
+
+impl FromStr for OrgWavetable {
+    type Err = GameError;
+
+    fn from_str(s: &str) -> GameResult<OrgWavetable> {
+        match s {
+            "doukutsu" => Ok(OrgWavetable::Doukutsu),
+            "org" | "orgmaker" => Ok(OrgWavetable::OrgMaker),
+            _ => Err(ParseError(format!("Unknown wavetable: {}, expected doukutsu or org.", s))),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct OrgRenderOptions {
+    /// How many times the looped part of the song is played.
+    pub loops: usize,
+    /// Length of fade-out after the last loop in seconds, the song ends abruptly if it's zero.
+    pub fade_out: f32,
+    pub sample_rate: u32,
+    pub wavetable: OrgWavetable,
+}
+
+impl Default for OrgRenderOptions {
+    fn default() -> OrgRenderOptions {
+        OrgRenderOptions { loops: 2, fade_out: 10.0, sample_rate: 44100, wavetable: OrgWavetable::Doukutsu }
+    }
+}
+
+/// Renders an Organya song into interleaved 16-bit stereo samples, without involving the audio thread.
+pub fn render_org<R: io::Read>(org: R, options: &OrgRenderOptions, ctx: &mut Context) -> GameResult<Vec<i16>> {
+    if options.loops == 0 {
+        return Err(InvalidValue("Loop count must be at least 1.".to_string()));
+    }
+
+    // frames per tick are computed in whole milliseconds, anything lower would never advance the song
+    if options.sample_rate < 1000 {
+        return Err(InvalidValue("Sample rate must be at least 1000 Hz.".to_string()));
+    }
+
+    let song = Song::load_from(org)?;
+
+    if song.time.wait == 0 {
+        return Err(InvalidValue("Song has zero tempo.".to_string()));
+    }
+
+    if song.time.loop_range.end <= song.time.loop_range.start || song.time.loop_range.end <= 0 {
+        return Err(InvalidValue("Song has an empty loop range.".to_string()));
+    }
+
+    let bank = SoundBank::load_from(filesystem::open(ctx, options.wavetable.path())?)?;
+
+    let mut engine = OrgPlaybackEngine::new();
+    engine.set_sample_rate(options.sample_rate as usize);
+    engine.start_song(song, &bank);
+    engine.loops = options.loops - 1;
+
+    let mut samples = Vec::new();
+    let mut buf = vec![0x8080; 4096];
+
+    fn render_chunk(engine: &mut OrgPlaybackEngine, buf: &mut [u16], samples: &mut Vec<i16>) -> usize {
+        for i in buf.iter_mut() {
+            *i = 0x8080
+        }
+
+        let frames = engine.render_to(buf);
+
+        // Organya engine outputs 8-bit samples, left channel in the low byte
+        for &frame in buf[..frames].iter() {
+            samples.push((((frame & 0xff) << 8) ^ 0x8000) as i16);
+            samples.push(((frame & 0xff00) ^ 0x8000) as i16);
+        }
+
+        frames
+    }
+
+    while !engine.has_ended() {
+        render_chunk(&mut engine, &mut buf, &mut samples);
+    }
+
+    if options.fade_out > 0.0 {
+        engine.fade_volume(0.0, options.fade_out);
+
+        let mut remaining = (options.fade_out * options.sample_rate as f32) as usize;
+        while remaining > 0 {
+            let len = remaining.min(buf.len());
+            remaining -= render_chunk(&mut engine, &mut buf[..len], &mut samples);
+        }
+    }
+
+    Ok(samples)
+}
+
+/// Renders an Organya song and writes it out as a 16-bit stereo WAV file.
+pub fn render_org_to_wav<R: io::Read, W: io::Write + io::Seek>(
+    org: R,
+    out: W,
+    options: &OrgRenderOptions,
+    ctx: &mut Context,
+) -> GameResult {
+    let samples = render_org(org, options, ctx)?;
+
+    let format = WavFormat { channels: 2, sample_rate: options.sample_rate, bit_depth: 16 };
+    let mut writer = WavWriter::new(out, format)?;
+    writer.write_samples(&samples)?;
+    writer.finish()?;
+
+    Ok(())
+}
+
+/// Renders an Organya song and writes it out as a stereo Ogg Vorbis file.
+#[cfg(feature = "ogg-export")]
+pub fn render_org_to_ogg<R: io::Read, W: io::Write>(
+    org: R,
+    out: W,
+    options: &OrgRenderOptions,
+    ctx: &mut Context,
+) -> GameResult {
+    let samples = render_org(org, options, ctx)?;
+
+    let encoder_error = |err: vorbis_rs::VorbisError| AudioError(format!("Vorbis encoder error: {}", err));
+
+    // sample rate has already been checked by `render_org`
+    let sample_rate = NonZeroU32::new(options.sample_rate).unwrap();
+    let channels = NonZeroU8::new(2).unwrap();
+    let mut encoder =
+        VorbisEncoderBuilder::new(sample_rate, channels, out).map_err(encoder_error)?.build().map_err(encoder_error)?;
+
+    // the encoder takes planar samples
+    let mut block = [Vec::with_capacity(4096), Vec::with_capacity(4096)];
+    for chunk in samples.chunks(8192) {
+        block[0].clear();
+        block[1].clear();
+
+        for frame in chunk.chunks_exact(2) {
+            block[0].push(frame[0] as f32 / 32768.0);
+            block[1].push(frame[1] as f32 / 32768.0);
+        }
+
+        encoder.encode_audio_block(&block).map_err(encoder_error)?;
+    }
+
+    encoder.finish().map_err(encoder_error)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builtin_fs::BuiltinFS;
+    use crate::framework::filesystem::mount_vfs;
+    use crate::sound::organya::tests::test_song;
+
+    use super::*;
+
+    fn render_test_song(wavetable: OrgWavetable) -> Vec<i16> {
+        let mut ctx = Context::new();
+        mount_vfs(&mut ctx, Box::new(BuiltinFS::new()));
+
+        let mut org = Vec::new();
+        test_song().write_to(&mut org).unwrap();
+
+        let options = OrgRenderOptions { loops: 1, fade_out: 0.25, sample_rate: 11025, wavetable };
+        render_org(&org[..], &options, &mut ctx).unwrap()
+    }
+
+    /// Returns sample count, peak and sum of absolute values of rendered samples.
+    fn summary(samples: &[i16]) -> (usize, i32, i64) {
+        let peak = samples.iter().map(|&s| (s as i32).abs()).max().unwrap_or(0);
+        let level = samples.iter().map(|&s| (s as i64).abs()).sum();
+
+        (samples.len(), peak, level)
+    }
+
+    #[test]
+    fn render_doukutsu_wavetable() {
+        let samples = render_test_song(OrgWavetable::Doukutsu);
+
+        assert_eq!(summary(&samples), (47752, 24832, 26197760));
+        assert_eq!(render_test_song(OrgWavetable::Doukutsu), samples);
+    }
+
+    #[test]
+    fn render_orgmaker_wavetable() {
+        let samples = render_test_song(OrgWavetable::OrgMaker);
+
+        assert_eq!(summary(&samples), (47752, 24832, 35540224));
+        assert_eq!(render_test_song(OrgWavetable::OrgMaker), samples);
+    }
+}
@@ -1,6 +1,6 @@
 use std::io;
 
-use byteorder::{LE, ReadBytesExt};
+use byteorder::{LE, ReadBytesExt, WriteBytesExt};
 
 use crate::framework::error::{GameError, GameResult};
 
@@ -15,7 +15,7 @@ pub enum Version {
     Extended = b'3',
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct LoopRange {
     // inclusive
     pub start: i32,
@@ -23,20 +23,21 @@ pub struct LoopRange {
     pub end: i32,
 }
 
-#[derive(Debug, Copy, Clone)]
+/// Grid settings of the editor, they don't affect playback.
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Display {
     pub beats: u8,
     pub steps: u8,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Timing {
     pub wait: u16,
     pub loop_range: LoopRange,
 }
 
 #[derive(Copy, Clone)]
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct Instrument {
     pub freq: u16,
     pub inst: u8,
@@ -44,6 +45,7 @@ pub struct Instrument {
     pub notes: u16,
 }
 
+#[derive(PartialEq)]
 pub struct Track {
     pub inst: Instrument,
     pub notes: Vec<Note>,
@@ -65,7 +67,7 @@ impl std::fmt::Debug for Track {
 }
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Note {
     pub pos: i32,
     pub key: u8,
@@ -74,9 +76,10 @@ pub struct Note {
     pub pan: u8,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct Song {
     pub version: Version,
+    pub display: Display,
     pub time: Timing,
     pub tracks: [Track; 16],
 }
@@ -85,6 +88,7 @@ impl Clone for Song {
     fn clone(&self) -> Song {
         Song {
             version: self.version,
+            display: self.display,
             time: self.time,
             tracks: self.tracks.clone(),
         }
@@ -95,6 +99,7 @@ impl Song {
     pub fn empty() -> Song {
         Song {
             version: Version::Main,
+            display: Display { beats: 4, steps: 4 },
             time: Timing { wait: 8, loop_range: LoopRange { start: 0, end: 1 } },
             tracks: [
                 Track { inst: Instrument { freq: 1000, inst: 0, pipi: 0, notes: 0 }, notes: vec![] },
@@ -117,6 +122,29 @@ impl Song {
         }
     }
 
+    /// Returns index of the sample drum track `idx` (0-7) plays. Only Org-03 songs pick drum samples
+    /// by instrument, older versions use the sample matching the track.
+    pub fn drum_sample(&self, idx: usize) -> usize {
+        match self.version {
+            Version::Extended => self.tracks[8 + idx].inst.inst as usize,
+            _ => idx,
+        }
+    }
+
+    /// Switches the song to Org-03, so drum instruments can be changed. Instruments of drum tracks
+    /// are set to the samples they played before, so the song sounds the same.
+    pub fn upgrade_to_extended(&mut self) {
+        if self.version == Version::Extended {
+            return;
+        }
+
+        for idx in 0..8 {
+            self.tracks[8 + idx].inst.inst = self.drum_sample(idx) as u8;
+        }
+
+        self.version = Version::Extended;
+    }
+
     pub fn load_from<R: io::Read>(mut f: R) -> GameResult<Song> {
         let mut magic = [0; 6];
 
@@ -131,8 +159,8 @@ impl Song {
             };
 
         let wait = f.read_u16::<LE>()?;
-        let _bpm = f.read_u8()?;
-        let _spb = f.read_u8()?;
+        let beats = f.read_u8()?;
+        let steps = f.read_u8()?;
         let start = f.read_i32::<LE>()?;
         let end = f.read_i32::<LE>()?;
 
@@ -213,6 +241,7 @@ impl Song {
 
         let song = Song {
             version,
+            display: Display { beats, steps },
             time: Timing {
                 wait,
                 loop_range: LoopRange {
@@ -225,4 +254,133 @@ impl Song {
 
         Ok(song)
     }
+
+    pub fn write_to<W: io::Write>(&self, mut f: W) -> GameResult {
+        if let Some(idx) = self.tracks.iter().position(|track| track.notes.len() > u16::MAX as usize) {
+            return Err(GameError::InvalidValue(format!(
+                "Track {} has {} notes, Organya files can store at most {}.",
+                idx,
+                self.tracks[idx].notes.len(),
+                u16::MAX
+            )));
+        }
+
+        f.write_all(match self.version {
+            Version::Beta => b"Org-01",
+            Version::Main => b"Org-02",
+            Version::Extended => b"Org-03",
+        })?;
+
+        f.write_u16::<LE>(self.time.wait)?;
+        f.write_u8(self.display.beats)?;
+        f.write_u8(self.display.steps)?;
+        f.write_i32::<LE>(self.time.loop_range.start)?;
+        f.write_i32::<LE>(self.time.loop_range.end)?;
+
+        for track in self.tracks.iter() {
+            f.write_u16::<LE>(track.inst.freq)?;
+            f.write_u8(track.inst.inst)?;
+            f.write_u8(track.inst.pipi)?;
+            // the stored count is only meaningful for loading, so it's taken from actual notes
+            f.write_u16::<LE>(track.notes.len() as u16)?;
+        }
+
+        for track in self.tracks.iter() {
+            for note in track.notes.iter() {
+                f.write_i32::<LE>(note.pos)?;
+            }
+
+            for note in track.notes.iter() {
+                f.write_u8(note.key)?;
+            }
+
+            for note in track.notes.iter() {
+                f.write_u8(note.len)?;
+            }
+
+            for note in track.notes.iter() {
+                f.write_u8(note.vol)?;
+            }
+
+            for note in track.notes.iter() {
+                f.write_u8(note.pan)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Song with a few melody and drum notes, using only values which survive conversion to MIDI and back.
+    pub(crate) fn test_song() -> Song {
+        let mut song = Song::empty();
+        song.time = Timing { wait: 120, loop_range: LoopRange { start: 0, end: 16 } };
+
+        song.tracks[0].inst = Instrument { freq: 1000, inst: 5, pipi: 1, notes: 3 };
+        song.tracks[0].notes = vec![
+            Note { pos: 0, key: 48, len: 4, vol: 200, pan: 6 },
+            Note { pos: 4, key: 50, len: 2, vol: 100, pan: 0 },
+            Note { pos: 8, key: 255, len: 1, vol: 150, pan: 255 },
+        ];
+
+        song.tracks[8].inst = Instrument { freq: 1000, inst: 0, pipi: 0, notes: 1 };
+        song.tracks[8].notes = vec![Note { pos: 0, key: 36, len: 1, vol: 254, pan: 12 }];
+
+        song.tracks[10].inst = Instrument { freq: 1000, inst: 2, pipi: 0, notes: 2 };
+        song.tracks[10].notes = vec![
+            Note { pos: 2, key: 40, len: 1, vol: 200, pan: 255 },
+            Note { pos: 6, key: 40, len: 1, vol: 200, pan: 255 },
+        ];
+
+        song
+    }
+
+    #[test]
+    fn org_round_trip() {
+        let song = test_song();
+        let mut data = Vec::new();
+        song.write_to(&mut data).unwrap();
+
+        assert_eq!(&data[..6], b"Org-02");
+        assert_eq!(Song::load_from(&data[..]).unwrap(), song);
+    }
+
+    #[test]
+    fn upgrade_keeps_drum_samples() {
+        let mut song = test_song();
+        // Org-02 songs ignore drum instruments, so this one is left over from an older edit
+        song.tracks[12].inst.inst = 7;
+        let mut data = Vec::new();
+        song.write_to(&mut data).unwrap();
+
+        let mut song = Song::load_from(&data[..]).unwrap();
+        let before: Vec<usize> = (0..8).map(|idx| song.drum_sample(idx)).collect();
+        assert_eq!(before, (0..8).collect::<Vec<_>>());
+
+        // what the tracker does when a drum instrument is edited
+        song.upgrade_to_extended();
+        song.tracks[9].inst.inst = 5;
+
+        assert_eq!(song.version, Version::Extended);
+        for idx in (0..8).filter(|&idx| idx != 1) {
+            assert_eq!(song.drum_sample(idx), before[idx], "drum track {}", idx);
+        }
+        assert_eq!(song.drum_sample(1), 5);
+
+        // upgrading again leaves chosen instruments alone
+        song.upgrade_to_extended();
+        assert_eq!(song.drum_sample(1), 5);
+    }
+
+    #[test]
+    fn org_write_rejects_too_many_notes() {
+        let mut song = Song::empty();
+        song.tracks[3].notes = vec![Note { pos: 0, key: 255, len: 1, vol: 255, pan: 255 }; u16::MAX as usize + 1];
+
+        assert!(song.write_to(Vec::new()).is_err());
+    }
 }
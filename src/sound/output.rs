@@ -0,0 +1,306 @@
+use std::env;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicI32;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::Sample;
+
+use crate::framework::error::GameError::AudioError;
+use crate::framework::error::GameResult;
+use crate::sound::wav::{WavFormat, WavWriter};
+use crate::sound::wave_bank::SoundBank;
+use crate::sound::{Mixer, PlaybackMessage};
+use crate::str;
+
+/// Sample rate used by outputs that aren't backed by an audio device.
+const HEADLESS_SAMPLE_RATE: u32 = 44100;
+
+/// Destination of the mixed audio.
+#[derive(Clone, Debug)]
+pub enum AudioOutput {
+    /// Default output device of the system, falls back to `Null` if there's none.
+    Device,
+    /// Discards the mixed audio, while still keeping track of playback.
+    Null(AudioClock),
+    /// Records the mixed audio into a WAV file at given path.
+    Wav(PathBuf, AudioClock),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AudioClock {
+    /// Playback advances in real time.
+    Realtime,
+    /// Playback advances only by `SoundManager::advance_time`, so the output is the same on every run.
+    Simulated,
+}
+
+impl AudioOutput {
+    /// Picks the output from `CAVESTORY_AUDIO_OUTPUT`, which can be `device` (default), `null` or `wav:<path>`.
+    /// Headless outputs use a simulated clock if `CAVESTORY_AUDIO_SIMULATED` is set to `1`.
+    pub fn from_env() -> AudioOutput {
+        let clock = match env::var("CAVESTORY_AUDIO_SIMULATED") {
+            Ok(v) if v == "1" => AudioClock::Simulated,
+            _ => AudioClock::Realtime,
+        };
+
+        match env::var("CAVESTORY_AUDIO_OUTPUT") {
+            Ok(v) if v == "null" => AudioOutput::Null(clock),
+            Ok(v) if v.starts_with("wav:") => AudioOutput::Wav(PathBuf::from(&v[4..]), clock),
+            Ok(v) if v == "device" => AudioOutput::Device,
+            Ok(v) => {
+                log::warn!("Unknown audio output: {}, using the default device.", v);
+                AudioOutput::Device
+            }
+            Err(_) => AudioOutput::Device,
+        }
+    }
+
+    pub fn clock(&self) -> AudioClock {
+        match self {
+            AudioOutput::Device => AudioClock::Realtime,
+            AudioOutput::Null(clock) | AudioOutput::Wav(_, clock) => *clock,
+        }
+    }
+}
+
+/// Receives mixed audio of headless outputs as interleaved stereo samples.
+trait FrameSink: Send {
+    fn write(&mut self, samples: &[i16]) -> GameResult;
+
+    /// Finalizes the output, called on `PlaybackMessage::FinishOutput`.
+    fn finish(&mut self) -> GameResult {
+        Ok(())
+    }
+}
+
+struct NullSink;
+
+impl FrameSink for NullSink {
+    fn write(&mut self, _samples: &[i16]) -> GameResult {
+        Ok(())
+    }
+}
+
+struct WavSink {
+    writer: WavWriter<BufWriter<std::fs::File>>,
+}
+
+impl FrameSink for WavSink {
+    fn write(&mut self, samples: &[i16]) -> GameResult {
+        self.writer.write_samples(samples)?;
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> GameResult {
+        self.writer.finish()?;
+
+        Ok(())
+    }
+}
+
+/// Spawns the audio thread feeding given output with messages from `rx`.
+pub(super) fn start(
+    output: AudioOutput,
+    rx: Receiver<PlaybackMessage>,
+    bank: SoundBank,
+    org_position: Arc<AtomicI32>,
+) -> GameResult {
+    let clock = output.clock();
+
+    let sink: Box<dyn FrameSink> = match output {
+        AudioOutput::Device => match default_device() {
+            Ok((device, config)) => {
+                std::thread::spawn(move || {
+                    if let Err(err) = match config.sample_format() {
+                        cpal::SampleFormat::F32 => run_device::<f32>(rx, bank, org_position, &device, &config.into()),
+                        cpal::SampleFormat::I16 => run_device::<i16>(rx, bank, org_position, &device, &config.into()),
+                        cpal::SampleFormat::U16 => run_device::<u16>(rx, bank, org_position, &device, &config.into()),
+                    } {
+                        log::error!("Something went wrong in audio thread: {}", err);
+                    }
+                });
+
+                return Ok(());
+            }
+            Err(err) => {
+                log::warn!("Failed to initialize audio device, audio output is disabled: {}", err);
+                Box::new(NullSink)
+            }
+        },
+        AudioOutput::Null(_) => Box::new(NullSink),
+        AudioOutput::Wav(path, _) => {
+            log::info!("Recording audio to {:?}", path);
+
+            let file = BufWriter::new(std::fs::File::create(&path)?);
+            let format = WavFormat { channels: 2, sample_rate: HEADLESS_SAMPLE_RATE, bit_depth: 16 };
+
+            Box::new(WavSink { writer: WavWriter::new(file, format)? })
+        }
+    };
+
+    std::thread::spawn(move || {
+        if let Err(err) = run_headless(rx, bank, org_position, sink, clock) {
+            log::error!("Something went wrong in audio thread: {}", err);
+        }
+    });
+
+    Ok(())
+}
+
+fn default_device() -> GameResult<(cpal::Device, cpal::SupportedStreamConfig)> {
+    let host = cpal::default_host();
+    let device = host.default_output_device().ok_or_else(|| AudioError(str!("No audio output device found.")))?;
+    let config = device.default_output_config()?;
+
+    Ok((device, config))
+}
+
+fn run_device<T>(
+    rx: Receiver<PlaybackMessage>,
+    bank: SoundBank,
+    org_position: Arc<AtomicI32>,
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+) -> GameResult
+where
+    T: cpal::Sample,
+{
+    let sample_rate = config.sample_rate.0 as f32;
+    let channels = config.channels as usize;
+    let mut mixer = Mixer::new(bank, sample_rate, org_position);
+
+    log::info!("Audio format: {} {}", sample_rate, channels);
+
+    let err_fn = |err| eprintln!("an error occurred on stream: {}", err);
+
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            while let Ok(message) = rx.try_recv() {
+                mixer.handle_message(message);
+            }
+
+            for frame in data.chunks_mut(channels) {
+                let (sample_l, sample_r) = mixer.next_frame();
+
+                if frame.len() >= 2 {
+                    frame[0] = Sample::from::<u16>(&(sample_l as u16 ^ 0x8000));
+                    frame[1] = Sample::from::<u16>(&(sample_r as u16 ^ 0x8000));
+                } else {
+                    let sample = ((sample_l as i32 + sample_r as i32) / 2) as u16 ^ 0x8000;
+
+                    frame[0] = Sample::from::<u16>(&sample);
+                }
+            }
+        },
+        err_fn,
+    )?;
+
+    stream.play()?;
+
+    let mut saved_state = true;
+    loop {
+        std::thread::sleep(Duration::from_millis(10));
+
+        {
+            let mutex = crate::GAME_SUSPENDED.lock().unwrap();
+            let state = *mutex;
+            if saved_state != state {
+                saved_state = state;
+
+                if state {
+                    if let Err(e) = stream.pause() {
+                        log::error!("Failed to pause the stream: {}", e);
+                    }
+                } else {
+                    if let Err(e) = stream.play() {
+                        log::error!("Failed to unpause the stream: {}", e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Renders the mix without an audio device, until the `SoundManager` is dropped.
+fn run_headless(
+    rx: Receiver<PlaybackMessage>,
+    bank: SoundBank,
+    org_position: Arc<AtomicI32>,
+    mut sink: Box<dyn FrameSink>,
+    clock: AudioClock,
+) -> GameResult {
+    let sample_rate = HEADLESS_SAMPLE_RATE as f64;
+    let mut mixer = Mixer::new(bank, HEADLESS_SAMPLE_RATE as f32, org_position);
+    let mut buf = Vec::new();
+    // fractional part of frames due, carried over so no time is lost to rounding
+    let mut pending_frames = 0.0;
+
+    log::info!("Audio format: {} 2 (headless, {:?} clock)", HEADLESS_SAMPLE_RATE, clock);
+
+    let mut render = |mixer: &mut Mixer, sink: &mut Box<dyn FrameSink>, seconds: f64| -> GameResult {
+        pending_frames += seconds * sample_rate;
+        let frames = pending_frames.floor();
+        pending_frames -= frames;
+
+        buf.clear();
+        for _ in 0..frames as usize {
+            let (sample_l, sample_r) = mixer.next_frame();
+            buf.push(sample_l);
+            buf.push(sample_r);
+        }
+
+        sink.write(&buf)
+    };
+
+    match clock {
+        AudioClock::Realtime => {
+            let mut last_time = Instant::now();
+
+            loop {
+                std::thread::sleep(Duration::from_millis(10));
+
+                loop {
+                    match rx.try_recv() {
+                        Ok(PlaybackMessage::FinishOutput(reply)) => {
+                            sink.finish()?;
+                            let _ = reply.send(());
+                        }
+                        Ok(message) => mixer.handle_message(message),
+                        Err(TryRecvError::Empty) => break,
+                        Err(TryRecvError::Disconnected) => return Ok(()),
+                    }
+                }
+
+                let now = Instant::now();
+                let elapsed = now.duration_since(last_time);
+                last_time = now;
+
+                if *crate::GAME_SUSPENDED.lock().unwrap() {
+                    continue;
+                }
+
+                render(&mut mixer, &mut sink, elapsed.as_secs_f64())?;
+            }
+        }
+        AudioClock::Simulated => {
+            while let Ok(message) = rx.recv() {
+                match message {
+                    PlaybackMessage::AdvanceTime(seconds) => render(&mut mixer, &mut sink, seconds)?,
+                    PlaybackMessage::FinishOutput(reply) => {
+                        sink.finish()?;
+                        let _ = reply.send(());
+                    }
+                    message => mixer.handle_message(message),
+                }
+            }
+
+            Ok(())
+        }
+    }
+}
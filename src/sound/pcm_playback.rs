@@ -0,0 +1,222 @@
+use std::sync::{Arc, Mutex};
+
+use crate::framework::error::GameResult;
+use crate::sound::stuff::{LoopPoints, VolumeEnvelope};
+use crate::sound::wav::WavFormat;
+
+/// Length of `<FMU` fade in seconds, same as for Ogg songs.
+const FADE_OUT_TIME: f32 = 5.0;
+
+/// Decoder of a streamed song, producing interleaved 16-bit samples.
+pub trait PcmStream: Send {
+    fn sample_rate(&self) -> u32;
+
+    fn channels(&self) -> u16;
+
+    /// Decodes next chunk of samples into `buf`, returns false once the end of stream is reached.
+    fn read(&mut self, buf: &mut Vec<i16>) -> GameResult<bool>;
+
+    /// Moves decoding to given sample frame.
+    fn seek(&mut self, frame: u64) -> GameResult;
+
+    /// Part of the song which is repeated, the whole song loops if it's None.
+    fn loop_points(&self) -> Option<LoopPoints> {
+        None
+    }
+}
+
+type SharedStream = Arc<Mutex<Box<dyn PcmStream>>>;
+
+/// Plays songs decoded by a `PcmStream`, resampling them to the output rate and handling loop points.
+pub(crate) struct PcmPlaybackEngine {
+    stream: Option<SharedStream>,
+    output_format: WavFormat,
+    /// Frames read from the stream since its start.
+    position: u64,
+    /// Stereo frames at the source rate waiting to be resampled.
+    source: Vec<i16>,
+    /// Fractional position in `source` of the next output frame.
+    phase: f64,
+    buffer: Vec<i16>,
+    volume: VolumeEnvelope,
+}
+
+pub struct SavedPcmPlaybackState {
+    stream: Option<SharedStream>,
+    position: u64,
+}
+
+impl PcmPlaybackEngine {
+    pub fn new() -> PcmPlaybackEngine {
+        PcmPlaybackEngine {
+            stream: None,
+            output_format: WavFormat { channels: 2, sample_rate: 44100, bit_depth: 16 },
+            position: 0,
+            source: Vec::new(),
+            phase: 0.0,
+            buffer: Vec::with_capacity(4096),
+            volume: VolumeEnvelope::new(1.0),
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: usize) {
+        self.output_format.sample_rate = sample_rate as u32;
+    }
+
+    pub fn get_state(&self) -> SavedPcmPlaybackState {
+        SavedPcmPlaybackState { stream: self.stream.clone(), position: self.position }
+    }
+
+    pub fn set_state(&mut self, state: SavedPcmPlaybackState) {
+        self.stream = state.stream;
+        self.seek(state.position);
+        self.volume.set(1.0);
+    }
+
+    pub fn start(&mut self, stream: Box<dyn PcmStream>) {
+        self.stream = Some(Arc::new(Mutex::new(stream)));
+        self.seek(0);
+        self.volume.set(1.0);
+    }
+
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume.set(volume);
+    }
+
+    /// Fades song volume to target over given amount of seconds.
+    pub fn fade_volume(&mut self, target: f32, seconds: f32) {
+        self.volume.fade_to(target, seconds * self.output_format.sample_rate as f32);
+    }
+
+    pub fn fade_out(&mut self) {
+        self.fade_volume(0.0, FADE_OUT_TIME);
+    }
+
+    pub fn is_silent(&self) -> bool {
+        self.volume.is_silent()
+    }
+
+    pub fn rewind(&mut self) {
+        self.seek(0);
+    }
+
+    fn seek(&mut self, frame: u64) {
+        self.position = frame;
+        self.source.clear();
+        self.buffer.clear();
+        self.phase = 0.0;
+
+        if let Some(stream) = self.stream.as_ref() {
+            if let Err(err) = stream.lock().unwrap().seek(frame) {
+                log::warn!("Failed to seek the song: {}", err);
+            }
+        }
+    }
+
+    /// Reads next chunk from the stream into `source` as stereo frames, returns false if there's nothing to play.
+    fn decode(&mut self) -> bool {
+        let stream = match self.stream.as_ref() {
+            Some(stream) => stream.clone(),
+            None => return false,
+        };
+        let mut stream = stream.lock().unwrap();
+
+        let channels = stream.channels().max(1) as usize;
+        let loop_points = stream.loop_points().unwrap_or(LoopPoints { start: 0, end: None });
+        let mut buf = Vec::new();
+
+        let more = match stream.read(&mut buf) {
+            Ok(more) => more,
+            Err(err) => {
+                log::error!("Failed to decode the song: {}", err);
+                return false;
+            }
+        };
+
+        let mut frames = (buf.len() / channels) as u64;
+        let mut jump = !more;
+
+        if let Some(end) = loop_points.end {
+            if self.position + frames >= end {
+                frames = end.saturating_sub(self.position);
+                jump = true;
+            }
+        }
+
+        for frame in buf.chunks_exact(channels).take(frames as usize) {
+            let left = frame[0];
+            let right = if channels > 1 { frame[1] } else { left };
+            self.source.push(left);
+            self.source.push(right);
+        }
+
+        self.position += frames;
+
+        if jump {
+            // an empty song or one with the loop at its very end would never produce anything
+            if frames == 0 && self.position <= loop_points.start {
+                return false;
+            }
+
+            if let Err(err) = stream.seek(loop_points.start) {
+                log::warn!("Failed to seek the song: {}", err);
+                return false;
+            }
+            self.position = loop_points.start;
+        }
+
+        true
+    }
+
+    /// Resamples decoded frames to the output rate using linear interpolation.
+    fn resample(&mut self, sample_rate: u32) {
+        let step = sample_rate as f64 / self.output_format.sample_rate as f64;
+        let frames = self.source.len() / 2;
+
+        while self.phase + 1.0 < frames as f64 {
+            let idx = self.phase as usize;
+            let mu = self.phase.fract() as f32;
+
+            for ch in 0..2 {
+                let s1 = self.source[idx * 2 + ch] as f32;
+                let s2 = self.source[idx * 2 + 2 + ch] as f32;
+                self.buffer.push((s1 + (s2 - s1) * mu) as i16);
+            }
+
+            self.phase += step;
+        }
+
+        // keep the last frame, it's needed to interpolate towards the next chunk
+        let consumed = (self.phase as usize).min(frames.saturating_sub(1));
+        self.source.drain(..consumed * 2);
+        self.phase -= consumed as f64;
+    }
+
+    pub fn render_to(&mut self, buf: &mut [u16]) -> usize {
+        while self.buffer.len() < buf.len() {
+            let sample_rate = match self.stream.as_ref() {
+                Some(stream) => stream.lock().unwrap().sample_rate(),
+                None => 0,
+            };
+
+            if sample_rate == 0 || !self.decode() {
+                self.buffer.resize(buf.len(), 0);
+                break;
+            }
+
+            self.resample(sample_rate);
+        }
+
+        let mut volume = 1.0;
+        for (i, (n, tgt)) in self.buffer.drain(0..buf.len()).zip(buf.iter_mut()).enumerate() {
+            // samples are interleaved, so the envelope advances once per stereo frame
+            if i % 2 == 0 {
+                volume = self.volume.advance();
+            }
+
+            *tgt = (n as f32 * volume) as i16 as u16 ^ 0x8000;
+        }
+
+        buf.len()
+    }
+}
@@ -1,10 +1,19 @@
 use std::collections::HashMap;
+use std::io;
+use std::io::{BufRead, BufReader};
+use std::str::FromStr;
 
 use lazy_static::lazy_static;
 use vec_mut_scan::VecMutScan;
 
+use crate::framework::error::{GameError, GameResult};
+use crate::sound::org_playback::centibel_to_scale;
 use crate::sound::pixtone_sfx::DEFAULT_PIXTONE_TABLE;
 use crate::sound::stuff::cubic_interp;
+use crate::sound::wav::{WavFormat, WavWriter};
+
+/// Rate at which PixTone sounds are synthesized.
+pub const PIXTONE_SAMPLE_RATE: u32 = 22050;
 
 lazy_static! {
     static ref WAVEFORMS: [[i8; 0x100]; 6] = {
@@ -30,7 +39,7 @@ lazy_static! {
     };
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Waveform {
     pub waveform_type: u8,
     pub pitch: f32,
@@ -44,7 +53,7 @@ impl Waveform {
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Envelope {
     pub initial: i32,
     pub time_a: i32,
@@ -94,7 +103,7 @@ impl Envelope {
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Channel {
     pub enabled: bool,
     pub length: u32,
@@ -117,7 +126,7 @@ impl Channel {
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct PixToneParameters {
     pub channels: [Channel; 4],
 }
@@ -129,6 +138,92 @@ impl PixToneParameters {
         }
     }
 
+    /// Parses parameters from a PixTone .pxt file.
+    pub fn load_from<R: io::Read>(data: R) -> GameResult<PixToneParameters> {
+        let mut lines = BufReader::new(data).lines();
+        let mut params = PixToneParameters::empty();
+
+        fn next_value<T: FromStr, R: io::Read>(lines: &mut io::Lines<BufReader<R>>) -> GameResult<T> {
+            while let Some(Ok(line)) = lines.next() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+
+                return match line.split(':').nth(1) {
+                    Some(value) => value
+                        .trim()
+                        .parse::<T>()
+                        .map_err(|_| GameError::ParseError("failed to parse the value as specified type.".to_string())),
+                    None => break,
+                };
+            }
+
+            Err(GameError::ParseError("unexpected end.".to_string()))
+        }
+
+        for channel in params.channels.iter_mut() {
+            channel.enabled = next_value::<u8, R>(&mut lines)? != 0;
+            channel.length = next_value::<u32, R>(&mut lines)?;
+
+            for waveform in [&mut channel.carrier, &mut channel.frequency, &mut channel.amplitude].iter_mut() {
+                waveform.waveform_type = next_value::<u8, R>(&mut lines)?;
+                waveform.pitch = next_value::<f32, R>(&mut lines)?;
+                waveform.level = next_value::<i32, R>(&mut lines)?;
+                waveform.offset = next_value::<i32, R>(&mut lines)?;
+            }
+
+            channel.envelope.initial = next_value::<i32, R>(&mut lines)?;
+            channel.envelope.time_a = next_value::<i32, R>(&mut lines)?;
+            channel.envelope.value_a = next_value::<i32, R>(&mut lines)?;
+            channel.envelope.time_b = next_value::<i32, R>(&mut lines)?;
+            channel.envelope.value_b = next_value::<i32, R>(&mut lines)?;
+            channel.envelope.time_c = next_value::<i32, R>(&mut lines)?;
+            channel.envelope.value_c = next_value::<i32, R>(&mut lines)?;
+        }
+
+        Ok(params)
+    }
+
+    /// Writes parameters in the same layout PixTone saves .pxt files in.
+    pub fn write_to<W: io::Write>(&self, mut f: W) -> GameResult {
+        for channel in self.channels.iter() {
+            writeln!(f, "use  :{}", channel.enabled as u8)?;
+            writeln!(f, "size :{}", channel.length)?;
+
+            for (name, waveform) in
+                [("main", &channel.carrier), ("pitch", &channel.frequency), ("volume", &channel.amplitude)].iter()
+            {
+                writeln!(f, "{:<13}:{}", format!("{}_model", name), waveform.waveform_type)?;
+                // shortest representation which parses back to the same value
+                writeln!(f, "{:<13}:{}", format!("{}_freq", name), waveform.pitch)?;
+                writeln!(f, "{:<13}:{}", format!("{}_top", name), waveform.level)?;
+                writeln!(f, "{:<13}:{}", format!("{}_offset", name), waveform.offset)?;
+            }
+
+            writeln!(f, "initialY:{}", channel.envelope.initial)?;
+            writeln!(f, "ax      :{}", channel.envelope.time_a)?;
+            writeln!(f, "ay      :{}", channel.envelope.value_a)?;
+            writeln!(f, "bx      :{}", channel.envelope.time_b)?;
+            writeln!(f, "by      :{}", channel.envelope.value_b)?;
+            writeln!(f, "cx      :{}", channel.envelope.time_c)?;
+            writeln!(f, "cy      :{}", channel.envelope.value_c)?;
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+
+    /// Synthesizes the sound and writes it out as a 16-bit mono WAV file.
+    pub fn write_wav<W: io::Write + io::Seek>(&self, f: W) -> GameResult {
+        let format = WavFormat { channels: 1, sample_rate: PIXTONE_SAMPLE_RATE, bit_depth: 16 };
+        let mut writer = WavWriter::new(f, format)?;
+        writer.write_samples(&self.synth())?;
+        writer.finish()?;
+
+        Ok(())
+    }
+
     pub fn synth(&self) -> Vec<i16> {
         let length = self.channels.iter().map(|c| c.length as usize).max().unwrap_or(0);
         if length == 0 {
@@ -182,6 +277,19 @@ pub struct PlaybackState {
     pos: f32,
     tag: u32,
     looping: bool,
+    gain_l: f32,
+    gain_r: f32,
+}
+
+/// Computes gain of left and right channel, `pan` is in hundredths of decibel like DirectSound and Organya use.
+fn pan_gains(volume: f32, pan: i32) -> (f32, f32) {
+    let pan = pan.max(-10000).min(10000);
+
+    match pan.signum() {
+        1 => (volume * centibel_to_scale(-pan), volume),
+        -1 => (volume, volume * centibel_to_scale(pan)),
+        _ => (volume, volume),
+    }
 }
 
 pub struct PixTonePlayback {
@@ -218,15 +326,24 @@ impl PixTonePlayback {
     }
 
     pub fn play_sfx(&mut self, id: u8) {
+        self.play_sfx_panned(id, 1.0, 0);
+    }
+
+    /// Plays a sound effect with given volume and stereo pan, restarting it if it's already playing.
+    pub fn play_sfx_panned(&mut self, id: u8, volume: f32, pan: i32) {
+        let (gain_l, gain_r) = pan_gains(volume, pan);
+
         for state in self.playback_state.iter_mut() {
             if state.id == id && state.tag == 0 {
                 state.pos = 0.0;
                 state.looping = false;
+                state.gain_l = gain_l;
+                state.gain_r = gain_r;
                 return;
             }
         }
 
-        self.playback_state.push(PlaybackState { id, pos: 0.0, tag: 0, looping: false });
+        self.playback_state.push(PlaybackState { id, pos: 0.0, tag: 0, looping: false, gain_l, gain_r });
     }
 
     pub fn loop_sfx(&mut self, id: u8) {
@@ -237,7 +354,7 @@ impl PixTonePlayback {
             }
         }
 
-        self.playback_state.push(PlaybackState { id, pos: 0.0, tag: 0, looping: true });
+        self.playback_state.push(PlaybackState { id, pos: 0.0, tag: 0, looping: true, gain_l: 1.0, gain_r: 1.0 });
     }
 
     pub fn stop_sfx(&mut self, id: u8) {
@@ -248,12 +365,13 @@ impl PixTonePlayback {
 
     #[allow(dead_code)]
     pub fn play_concurrent(&mut self, id: u8, tag: u32) {
-        self.playback_state.push(PlaybackState { id, pos: 0.0, tag, looping: false });
+        self.playback_state.push(PlaybackState { id, pos: 0.0, tag, looping: false, gain_l: 1.0, gain_r: 1.0 });
     }
 
+    /// Mixes playing sounds into interleaved stereo samples.
     pub fn mix(&mut self, dst: &mut [u16], sample_rate: f32) {
         let mut scan = VecMutScan::new(&mut self.playback_state);
-        let delta = 22050.0 / sample_rate;
+        let delta = PIXTONE_SAMPLE_RATE as f32 / sample_rate;
 
         while let Some(item) = scan.next() {
             let mut state = *item;
@@ -265,7 +383,7 @@ impl PixTonePlayback {
                     continue;
                 };
 
-                for result in dst.iter_mut() {
+                for frame in dst.chunks_exact_mut(2) {
                     if state.pos >= sample.len() as f32 {
                         if state.looping {
                             state.pos = 0.0;
@@ -283,8 +401,10 @@ impl PixTonePlayback {
 
                     let s = cubic_interp(s1, s2, s4, s3, state.pos.fract()) * 32768.0;
                     // let s = sample[pos] as f32;
-                    let sam = (*result ^ 0x8000) as i16;
-                    *result = sam.saturating_add(s as i16) as u16 ^ 0x8000;
+                    for (result, gain) in frame.iter_mut().zip([state.gain_l, state.gain_r].iter()) {
+                        let sam = (*result ^ 0x8000) as i16;
+                        *result = sam.saturating_add((s * gain) as i16) as u16 ^ 0x8000;
+                    }
 
                     state.pos += delta;
                 }
@@ -298,3 +418,62 @@ impl PixTonePlayback {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(params: &PixToneParameters) -> PixToneParameters {
+        let mut data = Vec::new();
+        params.write_to(&mut data).unwrap();
+
+        PixToneParameters::load_from(&data[..]).unwrap()
+    }
+
+    #[test]
+    fn pxt_round_trip() {
+        for (id, params) in DEFAULT_PIXTONE_TABLE.iter().enumerate() {
+            assert_eq!(&round_trip(params), params, "sound {}", id);
+        }
+    }
+
+    #[test]
+    fn pxt_round_trip_keeps_pitch_precision() {
+        let mut params = DEFAULT_PIXTONE_TABLE[1];
+        params.channels[0].carrier.pitch = 1.0 / 3.0;
+        params.channels[0].frequency.pitch = 0.1;
+        params.channels[0].amplitude.pitch = -0.015625;
+        params.channels[1].carrier.pitch = f32::MIN_POSITIVE;
+
+        assert_eq!(round_trip(&params), params);
+    }
+
+    fn assert_gains(actual: (f32, f32), expected: (f32, f32)) {
+        assert!(
+            (actual.0 - expected.0).abs() < 1e-5 && (actual.1 - expected.1).abs() < 1e-5,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn pan_gains_centre_and_sides() {
+        assert_gains(pan_gains(0.8, 0), (0.8, 0.8));
+
+        // the opposite channel is attenuated, -15 dB at the edge of the screen
+        let attenuated = 0.8 * 10f32.powf(-0.75);
+        assert_gains(pan_gains(0.8, -1500), (0.8, attenuated));
+        assert_gains(pan_gains(0.8, 1500), (attenuated, 0.8));
+    }
+
+    #[test]
+    fn pan_gains_hard_left_and_right() {
+        // -100 dB is as far as DirectSound goes, anything past it is clamped
+        assert_gains(pan_gains(1.0, -10000), (1.0, 1e-5));
+        assert_gains(pan_gains(1.0, 10000), (1e-5, 1.0));
+        assert_eq!(pan_gains(1.0, -20000), pan_gains(1.0, -10000));
+        assert_eq!(pan_gains(1.0, 20000), pan_gains(1.0, 10000));
+        assert_eq!(pan_gains(0.0, 10000), (0.0, 0.0));
+    }
+}
@@ -49,3 +49,199 @@ pub fn cubic_interp(s1: f32, s2: f32, sp: f32, sn: f32, mu: f32) -> f32 {
 
     a0 * mu * mu2 + a1 * mu2 + a2 * mu + a3
 }
+
+/// Linear volume ramp applied to a whole song, advanced once per output frame.
+#[derive(Copy, Clone)]
+pub struct VolumeEnvelope {
+    volume: f32,
+    target: f32,
+    step: f32,
+}
+
+impl VolumeEnvelope {
+    pub fn new(volume: f32) -> VolumeEnvelope {
+        VolumeEnvelope { volume, target: volume, step: 0.0 }
+    }
+
+    /// Changes volume instantly, cancelling any fade in progress.
+    pub fn set(&mut self, volume: f32) {
+        *self = VolumeEnvelope::new(volume);
+    }
+
+    /// Fades to target volume over given number of frames.
+    pub fn fade_to(&mut self, target: f32, frames: f32) {
+        if frames < 1.0 {
+            self.set(target);
+            return;
+        }
+
+        self.target = target;
+        self.step = (target - self.volume) / frames;
+    }
+
+    pub fn is_silent(&self) -> bool {
+        self.volume <= 0.0 && self.target <= 0.0
+    }
+
+    /// Returns current volume and advances the envelope by one frame.
+    pub fn advance(&mut self) -> f32 {
+        let volume = self.volume;
+
+        if self.step != 0.0 {
+            self.volume += self.step;
+
+            if (self.step > 0.0 && self.volume >= self.target) || (self.step < 0.0 && self.volume <= self.target) {
+                self.volume = self.target;
+                self.step = 0.0;
+            }
+        }
+
+        volume
+    }
+}
+
+/// Loop range of a streamed song in sample frames.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LoopPoints {
+    pub start: u64,
+    /// Frame the song jumps back from (exclusive), the end of stream if None.
+    pub end: Option<u64>,
+}
+
+impl LoopPoints {
+    /// Reads loop points from `LOOPSTART`, `LOOPEND` and `LOOPLENGTH` tags, the convention of RPG Maker
+    /// and many soundtrack rips. Tag names are case insensitive, None is returned if there's no loop start.
+    pub fn from_tags<'a, I: IntoIterator<Item = (&'a str, &'a str)>>(tags: I) -> Option<LoopPoints> {
+        let mut start = None;
+        let mut end = None;
+        let mut length = None;
+
+        for (name, value) in tags {
+            let value = match value.trim().parse::<u64>() {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
+
+            match name.trim().to_ascii_uppercase().as_str() {
+                "LOOPSTART" => start = Some(value),
+                "LOOPEND" => end = Some(value),
+                "LOOPLENGTH" => length = Some(value),
+                _ => {}
+            }
+        }
+
+        let start = start?;
+        let end = end.or_else(|| length.map(|length| start + length)).filter(|&end| end > start);
+
+        Some(LoopPoints { start, end })
+    }
+
+    /// Parses `NAME=value` lines of a loop sidecar file.
+    pub fn from_sidecar(text: &str) -> Option<LoopPoints> {
+        LoopPoints::from_tags(text.lines().filter_map(|line| {
+            let mut split = line.splitn(2, '=');
+            Some((split.next()?, split.next()?))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ramp(envelope: &mut VolumeEnvelope, frames: usize) -> Vec<f32> {
+        (0..frames).map(|_| envelope.advance()).collect()
+    }
+
+    #[test]
+    fn volume_envelope_ramp_endpoints() {
+        let mut envelope = VolumeEnvelope::new(1.0);
+        envelope.fade_to(0.0, 4.0);
+
+        // starts from the current volume and lands exactly on the target, then stays there
+        assert_eq!(ramp(&mut envelope, 7), vec![1.0, 0.75, 0.5, 0.25, 0.0, 0.0, 0.0]);
+        assert!(envelope.is_silent());
+
+        let mut envelope = VolumeEnvelope::new(0.0);
+        envelope.fade_to(1.0, 3.0);
+
+        // steps which don't add up exactly don't overshoot the target
+        let volumes = ramp(&mut envelope, 5);
+        assert_eq!(volumes[0], 0.0);
+        assert!(volumes.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(volumes[3..], [1.0, 1.0]);
+        assert!(!envelope.is_silent());
+    }
+
+    #[test]
+    fn volume_envelope_short_fade_and_set() {
+        let mut envelope = VolumeEnvelope::new(0.5);
+
+        // fades shorter than a frame are applied instantly
+        envelope.fade_to(0.0, 0.5);
+        assert_eq!(ramp(&mut envelope, 2), vec![0.0, 0.0]);
+
+        envelope.fade_to(1.0, 100.0);
+        envelope.advance();
+        envelope.set(0.25);
+        assert_eq!(ramp(&mut envelope, 2), vec![0.25, 0.25]);
+    }
+
+    #[test]
+    fn crossfade_keeps_summed_gain() {
+        let frames = 48000.0 * 0.5;
+        let mut outgoing = VolumeEnvelope::new(1.0);
+        let mut incoming = VolumeEnvelope::new(0.0);
+        outgoing.fade_to(0.0, frames);
+        incoming.fade_to(1.0, frames);
+
+        for frame in 0..frames as usize + 10 {
+            let sum = outgoing.advance() + incoming.advance();
+            assert!((sum - 1.0).abs() < 1e-3, "summed gain {} at frame {}", sum, frame);
+        }
+
+        assert!(outgoing.is_silent());
+        assert_eq!(incoming.advance(), 1.0);
+    }
+
+    #[test]
+    fn loop_points_from_tags() {
+        let points = LoopPoints::from_tags(vec![("LOOPSTART", "1000"), ("LOOPEND", "5000")]);
+        assert_eq!(points, Some(LoopPoints { start: 1000, end: Some(5000) }));
+
+        let points = LoopPoints::from_tags(vec![("loopstart", " 1000 "), ("LoopLength", "250")]);
+        assert_eq!(points, Some(LoopPoints { start: 1000, end: Some(1250) }));
+
+        // explicit end wins over the length
+        let points = LoopPoints::from_tags(vec![("LOOPSTART", "10"), ("LOOPLENGTH", "5"), ("LOOPEND", "40")]);
+        assert_eq!(points, Some(LoopPoints { start: 10, end: Some(40) }));
+
+        let points = LoopPoints::from_tags(vec![("LOOPSTART", "10"), ("TITLE", "Moonsong")]);
+        assert_eq!(points, Some(LoopPoints { start: 10, end: None }));
+    }
+
+    #[test]
+    fn loop_points_from_tags_rejects_invalid() {
+        assert_eq!(LoopPoints::from_tags(vec![("LOOPEND", "5000")]), None);
+        assert_eq!(LoopPoints::from_tags(vec![("LOOPSTART", "soon")]), None);
+        assert_eq!(LoopPoints::from_tags(Vec::new()), None);
+
+        // an end which isn't past the start loops the whole rest of the song
+        let points = LoopPoints::from_tags(vec![("LOOPSTART", "500"), ("LOOPEND", "500")]);
+        assert_eq!(points, Some(LoopPoints { start: 500, end: None }));
+
+        let points = LoopPoints::from_tags(vec![("LOOPSTART", "500"), ("LOOPEND", "-1")]);
+        assert_eq!(points, Some(LoopPoints { start: 500, end: None }));
+    }
+
+    #[test]
+    fn loop_points_from_sidecar() {
+        let points = LoopPoints::from_sidecar("LOOPSTART=44100\r\nLOOPEND=88200\r\n");
+        assert_eq!(points, Some(LoopPoints { start: 44100, end: Some(88200) }));
+
+        let points = LoopPoints::from_sidecar("# comment\nLOOPSTART = 4\ngarbage\nLOOPLENGTH=2=3\n");
+        assert_eq!(points, Some(LoopPoints { start: 4, end: None }));
+
+        assert_eq!(LoopPoints::from_sidecar(""), None);
+    }
+}
@@ -0,0 +1,65 @@
+use std::io::Read;
+
+use openmpt::module::{Logger, Module};
+
+use crate::framework::error::GameError::ResourceLoadError;
+use crate::framework::error::GameResult;
+use crate::sound::pcm_playback::PcmStream;
+
+/// Rate tracker modules are rendered at, resampling to the output rate is left to the playback engine.
+const SAMPLE_RATE: u32 = 48000;
+
+/// Frames rendered per `read` call.
+const CHUNK_FRAMES: usize = 4096;
+
+/// Renders tracker modules (.xm, .it, .mod, .s3m and others supported by libopenmpt).
+/// Modules loop by themselves, following the restart position stored in them.
+pub struct TrackerStream {
+    module: Module,
+}
+
+// libopenmpt modules aren't tied to the thread they were created on, the wrapper is only missing the marker.
+unsafe impl Send for TrackerStream {}
+
+impl TrackerStream {
+    pub fn new<R: Read>(mut reader: R) -> GameResult<TrackerStream> {
+        let mut module = Module::create(&mut reader, Logger::None, &[])
+            .map_err(|_| ResourceLoadError("Failed to load the tracker module.".to_string()))?;
+        module.set_repeat_count(-1);
+
+        Ok(TrackerStream { module })
+    }
+}
+
+impl PcmStream for TrackerStream {
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn read(&mut self, buf: &mut Vec<i16>) -> GameResult<bool> {
+        let start = buf.len();
+        buf.resize(start + CHUNK_FRAMES * 2, 0);
+
+        let frames = self.module.read_interleaved_stereo(SAMPLE_RATE as i32, &mut buf[start..]);
+        buf.truncate(start + frames * 2);
+
+        Ok(frames > 0)
+    }
+
+    fn seek(&mut self, frame: u64) -> GameResult {
+        // the playback engine counts frames across loops, while modules can only seek within one
+        let duration = self.module.get_duration_seconds();
+        let mut seconds = frame as f64 / SAMPLE_RATE as f64;
+        if duration > 0.0 {
+            seconds %= duration;
+        }
+
+        self.module.set_position_seconds(seconds);
+
+        Ok(())
+    }
+}
@@ -1,7 +1,7 @@
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub struct RiffChunk {
-    id: [u8; 4],
-    length: u32
+    pub id: [u8; 4],
+    pub length: u32
 }
 
 use std::fmt;
@@ -53,7 +53,7 @@ impl fmt::Display for WavSample {
     }
 }
 
-use byteorder::{LE, ReadBytesExt};
+use byteorder::{LE, ReadBytesExt, WriteBytesExt};
 use std::io;
 
 impl RiffChunk {
@@ -118,3 +118,71 @@ impl WavSample {
         )
     }
 }
+
+/// Streams 16-bit PCM samples into a WAV file. Chunk sizes in the header are filled in by `finish`,
+/// which is also done when the writer is dropped.
+pub struct WavWriter<W: io::Write + io::Seek> {
+    writer: W,
+    data_len: u32,
+    finished: bool,
+}
+
+impl<W: io::Write + io::Seek> WavWriter<W> {
+    pub fn new(mut writer: W, format: WavFormat) -> io::Result<WavWriter<W>> {
+        let block_align = format.channels * format.bit_depth / 8;
+
+        writer.write_all(b"RIFF")?;
+        writer.write_u32::<LE>(36)?;
+        writer.write_all(b"WAVE")?;
+
+        writer.write_all(b"fmt ")?;
+        writer.write_u32::<LE>(16)?;
+        writer.write_u16::<LE>(1)?; // PCM
+        writer.write_u16::<LE>(format.channels)?;
+        writer.write_u32::<LE>(format.sample_rate)?;
+        writer.write_u32::<LE>(format.sample_rate * block_align as u32)?;
+        writer.write_u16::<LE>(block_align)?;
+        writer.write_u16::<LE>(format.bit_depth)?;
+
+        writer.write_all(b"data")?;
+        writer.write_u32::<LE>(0)?;
+
+        Ok(WavWriter { writer, data_len: 0, finished: false })
+    }
+
+    /// Appends interleaved samples.
+    pub fn write_samples(&mut self, samples: &[i16]) -> io::Result<()> {
+        for &sample in samples {
+            self.writer.write_i16::<LE>(sample)?;
+        }
+
+        self.data_len = self.data_len.saturating_add(samples.len() as u32 * 2);
+        self.finished = false;
+
+        Ok(())
+    }
+
+    /// Writes chunk sizes into the header and flushes the file. Samples can still be appended afterwards,
+    /// in which case the header is updated again once the writer is finished or dropped.
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.writer.seek(io::SeekFrom::Start(4))?;
+        self.writer.write_u32::<LE>(36 + self.data_len)?;
+        self.writer.seek(io::SeekFrom::Start(40))?;
+        self.writer.write_u32::<LE>(self.data_len)?;
+        self.writer.seek(io::SeekFrom::End(0))?;
+        self.writer.flush()?;
+        self.finished = true;
+
+        Ok(())
+    }
+}
+
+impl<W: io::Write + io::Seek> Drop for WavWriter<W> {
+    fn drop(&mut self) {
+        if !self.finished {
+            if let Err(err) = self.finish() {
+                log::error!("Failed to finish WAV file: {}", err);
+            }
+        }
+    }
+}
@@ -0,0 +1,312 @@
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+
+use byteorder::{ReadBytesExt, LE};
+
+use crate::framework::error::GameError::ResourceLoadError;
+use crate::framework::error::GameResult;
+use crate::sound::pcm_playback::PcmStream;
+use crate::sound::stuff::LoopPoints;
+use crate::sound::wav::RiffChunk;
+
+/// Frames decoded per `read` call.
+const CHUNK_FRAMES: usize = 4096;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum SampleFormat {
+    /// Unsigned 8-bit, signed 16, 24 or 32-bit PCM.
+    Int(u16),
+    Float32,
+}
+
+/// Streams PCM or IEEE float WAV files. Loop points are taken from the `smpl` chunk if the file has one.
+pub struct WavStream<R: Read + Seek + Send> {
+    reader: R,
+    channels: u16,
+    sample_rate: u32,
+    format: SampleFormat,
+    data_offset: u64,
+    frames: u64,
+    position: u64,
+    loop_points: Option<LoopPoints>,
+}
+
+impl<R: Read + Seek + Send> WavStream<R> {
+    /// Parses the header of a WAV file, `loop_points` override the ones embedded in it.
+    pub fn new(mut reader: R, loop_points: Option<LoopPoints>) -> GameResult<WavStream<R>> {
+        let riff = RiffChunk::read_from(&mut reader)?;
+        let mut wave = [0u8; 4];
+        reader.read_exact(&mut wave)?;
+
+        if riff.id != *b"RIFF" || wave != *b"WAVE" {
+            return Err(ResourceLoadError("Not a RIFF WAVE file.".to_string()));
+        }
+
+        let mut fmt = None;
+        let mut data = None;
+        let mut embedded_loop = None;
+
+        loop {
+            let chunk = match RiffChunk::read_from(&mut reader) {
+                Ok(chunk) => chunk,
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err.into()),
+            };
+            let start = reader.stream_position()?;
+
+            match &chunk.id {
+                b"fmt " => {
+                    let tag = reader.read_u16::<LE>()?;
+                    let channels = reader.read_u16::<LE>()?;
+                    let sample_rate = reader.read_u32::<LE>()?;
+                    let _byte_rate = reader.read_u32::<LE>()?;
+                    let _block_align = reader.read_u16::<LE>()?;
+                    let bits = reader.read_u16::<LE>()?;
+                    // WAVE_FORMAT_EXTENSIBLE keeps the actual format in the first two bytes of the GUID
+                    let tag = if tag == 0xfffe && chunk.length >= 26 {
+                        reader.seek(SeekFrom::Current(8))?;
+                        reader.read_u16::<LE>()?
+                    } else {
+                        tag
+                    };
+
+                    let format = match (tag, bits) {
+                        (1, 8) | (1, 16) | (1, 24) | (1, 32) => SampleFormat::Int(bits),
+                        (3, 32) => SampleFormat::Float32,
+                        _ => {
+                            return Err(ResourceLoadError(format!(
+                                "Unsupported WAV sample format {} with {} bits per sample.",
+                                tag, bits
+                            )))
+                        }
+                    };
+
+                    fmt = Some((channels, sample_rate, format));
+                }
+                b"data" => data = Some((start, chunk.length as u64)),
+                b"smpl" if chunk.length >= 36 + 24 => {
+                    reader.seek(SeekFrom::Current(28))?;
+                    let loop_count = reader.read_u32::<LE>()?;
+                    reader.seek(SeekFrom::Current(4))?;
+
+                    if loop_count > 0 {
+                        let _cue_id = reader.read_u32::<LE>()?;
+                        let _loop_type = reader.read_u32::<LE>()?;
+                        let loop_start = reader.read_u32::<LE>()? as u64;
+                        let loop_end = reader.read_u32::<LE>()? as u64;
+
+                        // the end is inclusive in smpl chunks
+                        embedded_loop = Some(LoopPoints { start: loop_start, end: Some(loop_end + 1) });
+                    }
+                }
+                _ => {}
+            }
+
+            // chunks are padded to even length
+            reader.seek(SeekFrom::Start(start + chunk.length as u64 + (chunk.length & 1) as u64))?;
+        }
+
+        let (channels, sample_rate, format) =
+            fmt.ok_or_else(|| ResourceLoadError("WAV file has no fmt chunk.".to_string()))?;
+        let (data_offset, data_len) =
+            data.ok_or_else(|| ResourceLoadError("WAV file has no data chunk.".to_string()))?;
+
+        if channels == 0 || sample_rate == 0 {
+            return Err(ResourceLoadError("WAV file has no channels or zero sample rate.".to_string()));
+        }
+
+        let mut stream = WavStream {
+            reader,
+            channels,
+            sample_rate,
+            format,
+            data_offset,
+            frames: 0,
+            position: 0,
+            loop_points: loop_points.or(embedded_loop),
+        };
+        stream.frames = data_len / stream.frame_size() as u64;
+        stream.seek(0)?;
+
+        Ok(stream)
+    }
+
+    fn frame_size(&self) -> usize {
+        let bytes = match self.format {
+            SampleFormat::Int(bits) => bits as usize / 8,
+            SampleFormat::Float32 => 4,
+        };
+
+        bytes * self.channels as usize
+    }
+}
+
+impl<R: Read + Seek + Send> PcmStream for WavStream<R> {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn read(&mut self, buf: &mut Vec<i16>) -> GameResult<bool> {
+        let frames = (self.frames - self.position).min(CHUNK_FRAMES as u64) as usize;
+        let mut bytes = vec![0u8; frames * self.frame_size()];
+        self.reader.read_exact(&mut bytes)?;
+        self.position += frames as u64;
+
+        match self.format {
+            SampleFormat::Int(8) => buf.extend(bytes.iter().map(|&s| ((s as i16) - 128) << 8)),
+            SampleFormat::Int(16) => buf.extend(bytes.chunks_exact(2).map(|s| i16::from_le_bytes([s[0], s[1]]))),
+            SampleFormat::Int(24) => buf.extend(bytes.chunks_exact(3).map(|s| i16::from_le_bytes([s[1], s[2]]))),
+            SampleFormat::Int(_) => buf.extend(bytes.chunks_exact(4).map(|s| i16::from_le_bytes([s[2], s[3]]))),
+            SampleFormat::Float32 => buf.extend(bytes.chunks_exact(4).map(|s| {
+                let sample = f32::from_le_bytes([s[0], s[1], s[2], s[3]]);
+                (sample.clamp(-1.0, 1.0) * 32767.0) as i16
+            })),
+        }
+
+        Ok(self.position < self.frames)
+    }
+
+    fn seek(&mut self, frame: u64) -> GameResult {
+        self.position = frame.min(self.frames);
+        self.reader.seek(SeekFrom::Start(self.data_offset + self.position * self.frame_size() as u64))?;
+
+        Ok(())
+    }
+
+    fn loop_points(&self) -> Option<LoopPoints> {
+        self.loop_points
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use byteorder::WriteBytesExt;
+
+    use super::*;
+
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut out = id.to_vec();
+        out.write_u32::<LE>(data.len() as u32).unwrap();
+        out.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            out.push(0);
+        }
+        out
+    }
+
+    fn fmt_chunk(tag: u16, channels: u16, sample_rate: u32, bits: u16) -> Vec<u8> {
+        let block_align = channels * bits / 8;
+        let mut data = Vec::new();
+        data.write_u16::<LE>(tag).unwrap();
+        data.write_u16::<LE>(channels).unwrap();
+        data.write_u32::<LE>(sample_rate).unwrap();
+        data.write_u32::<LE>(sample_rate * block_align as u32).unwrap();
+        data.write_u16::<LE>(block_align).unwrap();
+        data.write_u16::<LE>(bits).unwrap();
+        chunk(b"fmt ", &data)
+    }
+
+    fn smpl_chunk(loops: &[(u32, u32)]) -> Vec<u8> {
+        let mut data = vec![0u8; 28];
+        data.write_u32::<LE>(loops.len() as u32).unwrap();
+        data.write_u32::<LE>(0).unwrap();
+        for &(start, end) in loops {
+            data.write_u32::<LE>(0).unwrap();
+            data.write_u32::<LE>(0).unwrap();
+            data.write_u32::<LE>(start).unwrap();
+            data.write_u32::<LE>(end).unwrap();
+            data.write_u32::<LE>(0).unwrap();
+            data.write_u32::<LE>(0).unwrap();
+        }
+        chunk(b"smpl", &data)
+    }
+
+    fn wave(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body: Vec<u8> = b"WAVE".iter().copied().chain(chunks.iter().flatten().copied()).collect();
+        chunk(b"RIFF", &body)
+    }
+
+    #[test]
+    fn wav_parses_fmt_and_data() {
+        let samples: Vec<u8> = [0i16, 1000, -1000, i16::MAX].iter().flat_map(|s| s.to_le_bytes().to_vec()).collect();
+        let file = wave(&[fmt_chunk(1, 2, 32000, 16), chunk(b"LIST", b"odd"), chunk(b"data", &samples)]);
+
+        let mut stream = WavStream::new(Cursor::new(file), None).unwrap();
+        assert_eq!(stream.channels(), 2);
+        assert_eq!(stream.sample_rate(), 32000);
+        assert_eq!(stream.frames, 2);
+        assert_eq!(stream.loop_points(), None);
+
+        let mut buf = Vec::new();
+        assert!(!stream.read(&mut buf).unwrap());
+        assert_eq!(buf, vec![0, 1000, -1000, i16::MAX]);
+
+        buf.clear();
+        stream.seek(1).unwrap();
+        stream.read(&mut buf).unwrap();
+        assert_eq!(buf, vec![-1000, i16::MAX]);
+    }
+
+    #[test]
+    fn wav_converts_sample_formats() {
+        let file = wave(&[fmt_chunk(1, 1, 22050, 8), chunk(b"data", &[0, 128, 255])]);
+        let mut buf = Vec::new();
+        WavStream::new(Cursor::new(file), None).unwrap().read(&mut buf).unwrap();
+        assert_eq!(buf, vec![-32768, 0, 127 << 8]);
+
+        let samples: Vec<u8> = [1.0f32, -2.0, 0.0].iter().flat_map(|s| s.to_le_bytes().to_vec()).collect();
+        let file = wave(&[fmt_chunk(3, 1, 22050, 32), chunk(b"data", &samples)]);
+        let mut buf = Vec::new();
+        WavStream::new(Cursor::new(file), None).unwrap().read(&mut buf).unwrap();
+        assert_eq!(buf, vec![32767, -32767, 0]);
+    }
+
+    #[test]
+    fn wav_reads_extensible_fmt() {
+        let mut fmt = fmt_chunk(0xfffe, 1, 44100, 16);
+        // cbSize, valid bits, channel mask and the subformat GUID starting with the PCM tag
+        fmt.write_u16::<LE>(22).unwrap();
+        fmt.write_u16::<LE>(16).unwrap();
+        fmt.write_u32::<LE>(4).unwrap();
+        fmt.write_u16::<LE>(1).unwrap();
+        fmt.extend_from_slice(&[0; 14]);
+        fmt[4..8].copy_from_slice(&40u32.to_le_bytes());
+
+        let file = wave(&[fmt, chunk(b"data", &[0; 4])]);
+        let stream = WavStream::new(Cursor::new(file), None).unwrap();
+        assert!(stream.format == SampleFormat::Int(16));
+        assert_eq!(stream.frames, 2);
+    }
+
+    #[test]
+    fn wav_reads_smpl_loop() {
+        let file = wave(&[fmt_chunk(1, 1, 44100, 16), smpl_chunk(&[(100, 199), (5, 10)]), chunk(b"data", &[0; 800])]);
+        let stream = WavStream::new(Cursor::new(file.clone()), None).unwrap();
+        // only the first loop is used and its end is inclusive
+        assert_eq!(stream.loop_points(), Some(LoopPoints { start: 100, end: Some(200) }));
+
+        let points = LoopPoints { start: 1, end: None };
+        let stream = WavStream::new(Cursor::new(file), Some(points)).unwrap();
+        assert_eq!(stream.loop_points(), Some(points));
+
+        let file = wave(&[fmt_chunk(1, 1, 44100, 16), smpl_chunk(&[]), chunk(b"data", &[0; 8])]);
+        assert_eq!(WavStream::new(Cursor::new(file), None).unwrap().loop_points(), None);
+    }
+
+    #[test]
+    fn wav_rejects_invalid_files() {
+        let data = chunk(b"data", &[0; 4]);
+
+        assert!(WavStream::new(Cursor::new(chunk(b"RIFX", b"WAVE")), None).is_err());
+        assert!(WavStream::new(Cursor::new(wave(std::slice::from_ref(&data))), None).is_err());
+        assert!(WavStream::new(Cursor::new(wave(&[fmt_chunk(1, 1, 44100, 16)])), None).is_err());
+        assert!(WavStream::new(Cursor::new(wave(&[fmt_chunk(2, 1, 44100, 4), data.clone()])), None).is_err());
+        assert!(WavStream::new(Cursor::new(wave(&[fmt_chunk(1, 0, 44100, 16), data])), None).is_err());
+    }
+}
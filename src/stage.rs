@@ -13,6 +13,7 @@ use crate::framework::error::GameResult;
 use crate::framework::filesystem;
 use crate::map::{Map, NPCData};
 use crate::text_script::TextScript;
+use crate::tiled::TiledMap;
 use crate::common::Color;
 
 #[derive(Debug, PartialEq, Eq, Hash)]
@@ -193,6 +194,26 @@ pub struct PxPackStageData {
     pub size_bg: (u16, u16),
     pub offset_mg: u32,
     pub offset_bg: u32,
+    /// Names of the maps adjacent to this one, empty if there's none.
+    pub map_left: String,
+    pub map_right: String,
+    pub map_up: String,
+    pub map_down: String,
+    pub spritesheet: String,
+    pub background_color: Color,
+    /// Entities placed on the map, spawned when the stage has no .pxe file.
+    pub units: Vec<PxPackUnit>,
+}
+
+/// Entity record from the unit list of a PxPack map. Unit types are Kero Blaster ones, they're spawned
+/// as NPCs only if they're mapped to an NPC type in `pxpack_unit_types` of engine constants.
+#[derive(Debug, Clone)]
+pub struct PxPackUnit {
+    pub flags: u8,
+    pub unit_type: u8,
+    /// Position in tiles.
+    pub x: u16,
+    pub y: u16,
 }
 
 #[derive(Debug)]
@@ -514,6 +535,9 @@ impl StageData {
 pub struct Stage {
     pub map: Map,
     pub data: StageData,
+    /// Entities from the object layers of a Tiled map, kept from the map load so it isn't parsed twice.
+    tiled_npcs: Option<Vec<NPCData>>,
+    tiles_revision: u32,
 }
 
 impl Stage {
@@ -522,7 +546,30 @@ impl Stage {
 
         if let Ok(pxpack_file) = filesystem::open(ctx, [root, "Stage/", &data.map, ".pxpack"].join("")) {
             let map = Map::load_pxpack(pxpack_file, root, &mut data, ctx)?;
-            let stage = Self { map, data };
+            let stage = Self { map, data, tiled_npcs: None, tiles_revision: 0 };
+
+            Ok(stage)
+        } else if TiledMap::exists(root, &data.map, ctx) {
+            let tiled_map = TiledMap::load(root, &data.map, ctx)?;
+            let (mut map, has_attributes) = tiled_map.to_map(&mut data)?;
+            let tiled_npcs = if tiled_map.has_entities() { Some(tiled_map.to_npc_data()?) } else { None };
+
+            if !has_attributes {
+                let tileset = match data.pxpack_data.as_ref() {
+                    Some(pxpack_data) => pxpack_data.tileset_fg.clone(),
+                    None => data.tileset.name.clone(),
+                };
+
+                if let Ok(mut attrib_file) = filesystem::open(ctx, [root, "Stage/", &tileset, ".pxa"].join("")) {
+                    if attrib_file.read_exact(&mut map.attrib).is_err() {
+                        log::warn!("Map attribute data is shorter than 256 bytes!");
+                    }
+                } else {
+                    log::warn!("No tile attribute data found for tileset {}, collision might be broken.", tileset);
+                }
+            }
+
+            let stage = Self { map, data, tiled_npcs, tiles_revision: 0 };
 
             Ok(stage)
         } else {
@@ -531,7 +578,7 @@ impl Stage {
 
             let map = Map::load_pxm(map_file, attrib_file)?;
 
-            let stage = Self { map, data };
+            let stage = Self { map, data, tiled_npcs: None, tiles_revision: 0 };
 
             Ok(stage)
         }
@@ -549,13 +596,56 @@ impl Stage {
         Ok(text_script)
     }
 
-    pub fn load_npcs(&self, root: &str, ctx: &mut Context) -> GameResult<Vec<NPCData>> {
-        let pxe_file = filesystem::open(ctx, [root, "Stage/", &self.data.map, ".pxe"].join(""))?;
+    pub fn load_npcs(&self, root: &str, constants: &EngineConstants, ctx: &mut Context) -> GameResult<Vec<NPCData>> {
+        if let Some(npcs) = self.tiled_npcs.as_ref() {
+            return Ok(npcs.clone());
+        }
+
+        let pxe_file = match filesystem::open(ctx, [root, "Stage/", &self.data.map, ".pxe"].join("")) {
+            Ok(file) => file,
+            Err(err) => {
+                return match self.data.pxpack_data.as_ref() {
+                    Some(pxpack_data) => Ok(Stage::convert_pxpack_units(&pxpack_data.units, constants)),
+                    None => Err(err),
+                };
+            }
+        };
         let npc_data = NPCData::load_from(pxe_file)?;
 
         Ok(npc_data)
     }
 
+    /// Turns PxPack units into NPC spawns, skipping ones with no NPC type assigned to their unit type.
+    fn convert_pxpack_units(units: &[PxPackUnit], constants: &EngineConstants) -> Vec<NPCData> {
+        let mut npcs = Vec::with_capacity(units.len());
+
+        for unit in units.iter() {
+            if let Some(&npc_type) = constants.pxpack_unit_types.get(&unit.unit_type) {
+                npcs.push(NPCData {
+                    id: 170 + npcs.len() as u16,
+                    x: unit.x as i16,
+                    y: unit.y as i16,
+                    flag_num: 0,
+                    event_num: 0,
+                    npc_type,
+                    flags: unit.flags as u16,
+                    layer: 0,
+                });
+            }
+        }
+
+        // the mapping is empty unless a mod provides one, so this is expected for unmodded PxPack maps
+        if npcs.len() != units.len() {
+            log::info!(
+                "{} of {} PxPack units have no NPC type assigned in pxpack_unit_types and weren't spawned.",
+                units.len() - npcs.len(),
+                units.len()
+            );
+        }
+
+        npcs
+    }
+
     /// Returns map tile from foreground layer.
     pub fn tile_at(&self, x: usize, y: usize) -> u8 {
         if let Some(&tile) = self.map.tiles.get(y.wrapping_mul(self.map.width as usize).wrapping_add(x)) {
@@ -565,11 +655,18 @@ impl Stage {
         }
     }
 
+    /// Returns a number that changes every time a map tile is changed, used to tell when cached tile layers
+    /// have to be rebuilt.
+    pub fn tiles_revision(&self) -> u32 {
+        self.tiles_revision
+    }
+
     /// Changes map tile on foreground layer. Returns true if smoke should be emitted
     pub fn change_tile(&mut self, x: usize, y: usize, tile_type: u8) -> bool {
         if let Some(ptr) = self.map.tiles.get_mut(y.wrapping_mul(self.map.width as usize).wrapping_add(x)) {
             if *ptr != tile_type {
                 *ptr = tile_type;
+                self.tiles_revision = self.tiles_revision.wrapping_add(1);
                 return true;
             }
         }
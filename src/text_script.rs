@@ -12,6 +12,7 @@ use byteorder::ReadBytesExt;
 use itertools::Itertools;
 use num_derive::FromPrimitive;
 use num_traits::{clamp, FromPrimitive};
+use serde::{Deserialize, Serialize};
 
 use crate::bitfield;
 use crate::common::{Direction, FadeDirection, FadeState, Rect};
@@ -25,7 +26,7 @@ use crate::framework::error::GameResult;
 use crate::input::touch_controls::TouchControlType;
 use crate::npc::NPC;
 use crate::player::{ControlMode, TargetPlayer};
-use crate::scene::game_scene::GameScene;
+use crate::scene::game_scene::{DynamicLight, GameScene};
 use crate::scene::title_scene::TitleScene;
 use crate::shared_game_state::SharedGameState;
 use crate::str;
@@ -294,6 +295,20 @@ pub enum OpCode {
     /// <FRE related to player 2?
     FR2,
     // ---- Custom opcodes, for use by modders ----
+    /// <LIAxxxx:yyyy:zzzz:wwww, Adds or replaces light xxxx at tile yyyy:zzzz, wwww being its size in tenths
+    LIA,
+    /// <LICxxxx:yyyy:zzzz:wwww, Sets color of light xxxx to red yyyy, green zzzz and blue wwww
+    LIC,
+    /// <LIDxxxx, Removes light xxxx, or all lights added by scripts if 0
+    LID,
+    /// <CZMxxxx:yyyy, Zooms the camera to xxxx percent over yyyy ticks
+    CZM,
+    /// <CLAxxxx:yyyy, Sets how far in pixels the camera leads ahead of the player horizontally and vertically
+    CLA,
+    /// <CBDxxxx:yyyy:zzzz:wwww, Keeps the camera within tiles xxxx:yyyy to zzzz:wwww, stage bounds if all 0
+    CBD,
+    /// <CMTxxxx, Keeps both players in view in co-op if xxxx is 1, disables it if 0
+    CMT,
 }
 
 bitfield! {
@@ -307,7 +322,7 @@ bitfield! {
   pub cutscene_skip, set_cutscene_skip: 7;
 }
 
-#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum TextScriptEncoding {
     UTF8 = 0,
@@ -1255,6 +1270,10 @@ impl TextScriptVM {
                         new_scene.player1.cond.set_interacted(false);
                         new_scene.player2.cond.set_interacted(false);
                         new_scene.frame.wait = game_scene.frame.wait;
+                        new_scene.frame.target_zoom = game_scene.frame.target_zoom;
+                        new_scene.frame.zoom_wait = game_scene.frame.zoom_wait;
+                        new_scene.frame.look_ahead = game_scene.frame.look_ahead;
+                        new_scene.frame.multi_target = game_scene.frame.multi_target;
 
                         let skip = state.textscript_vm.flags.cutscene_skip();
                         state.control_flags.set_tick_world(true);
@@ -1381,7 +1400,7 @@ impl TextScriptVM {
                         exec_state = TextScriptExecutionState::Running(event, cursor.position() as u32);
                     }
                     OpCode::FMU => {
-                        state.sound_manager.play_song(0, &state.constants, &state.settings, ctx)?;
+                        state.sound_manager.fade_out_song()?;
 
                         exec_state = TextScriptExecutionState::Running(event, cursor.position() as u32);
                     }
@@ -1602,6 +1621,83 @@ impl TextScriptVM {
 
                         exec_state = TextScriptExecutionState::Running(event, cursor.position() as u32);
                     }
+                    OpCode::LIA => {
+                        let id = read_cur_varint(&mut cursor)? as u16;
+                        let x = read_cur_varint(&mut cursor)? as i32;
+                        let y = read_cur_varint(&mut cursor)? as i32;
+                        let size = read_cur_varint(&mut cursor)? as f32 / 10.0;
+                        let block_size = state.tile_size.as_int() * 0x200;
+
+                        game_scene.set_light(DynamicLight {
+                            id,
+                            x: x * block_size,
+                            y: y * block_size,
+                            size,
+                            color: (200, 200, 200),
+                        });
+
+                        exec_state = TextScriptExecutionState::Running(event, cursor.position() as u32);
+                    }
+                    OpCode::LIC => {
+                        let id = read_cur_varint(&mut cursor)? as u16;
+                        let r = read_cur_varint(&mut cursor)?.clamp(0, 255) as u8;
+                        let g = read_cur_varint(&mut cursor)?.clamp(0, 255) as u8;
+                        let b = read_cur_varint(&mut cursor)?.clamp(0, 255) as u8;
+
+                        for light in game_scene.lights.iter_mut().filter(|light| light.id == id) {
+                            light.color = (r, g, b);
+                        }
+
+                        exec_state = TextScriptExecutionState::Running(event, cursor.position() as u32);
+                    }
+                    OpCode::LID => {
+                        let id = read_cur_varint(&mut cursor)? as u16;
+
+                        game_scene.remove_light(id);
+
+                        exec_state = TextScriptExecutionState::Running(event, cursor.position() as u32);
+                    }
+                    OpCode::CZM => {
+                        let zoom = read_cur_varint(&mut cursor)?.max(1) as f32 / 100.0;
+                        let ticks = read_cur_varint(&mut cursor)?;
+
+                        game_scene.frame.set_zoom(zoom, ticks);
+
+                        exec_state = TextScriptExecutionState::Running(event, cursor.position() as u32);
+                    }
+                    OpCode::CLA => {
+                        let x = read_cur_varint(&mut cursor)?;
+                        let y = read_cur_varint(&mut cursor)?;
+
+                        game_scene.frame.look_ahead = (x, y);
+
+                        exec_state = TextScriptExecutionState::Running(event, cursor.position() as u32);
+                    }
+                    OpCode::CBD => {
+                        let left = read_cur_varint(&mut cursor)?;
+                        let top = read_cur_varint(&mut cursor)?;
+                        let right = read_cur_varint(&mut cursor)?;
+                        let bottom = read_cur_varint(&mut cursor)?;
+                        let block_size = state.tile_size.as_int() * 0x200;
+
+                        game_scene.frame.bounds = if left == 0 && top == 0 && right == 0 && bottom == 0 {
+                            None
+                        } else {
+                            Some(Rect {
+                                left: left * block_size,
+                                top: top * block_size,
+                                right: right * block_size,
+                                bottom: bottom * block_size,
+                            })
+                        };
+
+                        exec_state = TextScriptExecutionState::Running(event, cursor.position() as u32);
+                    }
+                    OpCode::CMT => {
+                        game_scene.frame.multi_target = read_cur_varint(&mut cursor)? != 0;
+
+                        exec_state = TextScriptExecutionState::Running(event, cursor.position() as u32);
+                    }
                     OpCode::LIp => {
                         let life = read_cur_varint(&mut cursor)? as u16;
 
@@ -2104,7 +2200,9 @@ impl TextScript {
             | OpCode::SSS
             | OpCode::ACH
             | OpCode::S2MV
-            | OpCode::PSH => {
+            | OpCode::PSH
+            | OpCode::LID
+            | OpCode::CMT => {
                 let operand = TextScript::read_number(iter)?;
                 TextScript::put_varint(instr as i32, out);
                 TextScript::put_varint(operand as i32, out);
@@ -2122,7 +2220,9 @@ impl TextScript {
             | OpCode::SMP
             | OpCode::PSp
             | OpCode::IpN
-            | OpCode::FFm => {
+            | OpCode::FFm
+            | OpCode::CZM
+            | OpCode::CLA => {
                 let operand_a = TextScript::read_number(iter)?;
                 if strict {
                     TextScript::expect_char(b':', iter)?;
@@ -2157,7 +2257,7 @@ impl TextScript {
                 TextScript::put_varint(operand_c as i32, out);
             }
             // Four operand codes
-            OpCode::TRA | OpCode::MNP | OpCode::SNP => {
+            OpCode::TRA | OpCode::MNP | OpCode::SNP | OpCode::LIA | OpCode::LIC | OpCode::CBD => {
                 let operand_a = TextScript::read_number(iter)?;
                 if strict {
                     TextScript::expect_char(b':', iter)?;
@@ -2284,7 +2384,9 @@ impl TextScript {
                         | OpCode::SSS
                         | OpCode::ACH
                         | OpCode::S2MV
-                        | OpCode::PSH => {
+                        | OpCode::PSH
+                        | OpCode::LID
+                        | OpCode::CMT => {
                             let par_a = read_cur_varint(&mut cursor)?;
 
                             result.push_str(format!("{:?}({})\n", op, par_a).as_str());
@@ -2302,7 +2404,9 @@ impl TextScript {
                         | OpCode::SMP
                         | OpCode::PSp
                         | OpCode::IpN
-                        | OpCode::FFm => {
+                        | OpCode::FFm
+                        | OpCode::CZM
+                        | OpCode::CLA => {
                             let par_a = read_cur_varint(&mut cursor)?;
                             let par_b = read_cur_varint(&mut cursor)?;
 
@@ -2317,7 +2421,7 @@ impl TextScript {
                             result.push_str(format!("{:?}({}, {}, {})\n", op, par_a, par_b, par_c).as_str());
                         }
                         // Four operand codes
-                        OpCode::TRA | OpCode::MNP | OpCode::SNP => {
+                        OpCode::TRA | OpCode::MNP | OpCode::SNP | OpCode::LIA | OpCode::LIC | OpCode::CBD => {
                             let par_a = read_cur_varint(&mut cursor)?;
                             let par_b = read_cur_varint(&mut cursor)?;
                             let par_c = read_cur_varint(&mut cursor)?;
@@ -0,0 +1,409 @@
+use crate::common::Rect;
+use crate::framework::backend::{BackendTexture, SpriteBatchCommand};
+use crate::framework::context::Context;
+use crate::framework::error::GameResult;
+use crate::framework::graphics::create_texture;
+
+/// Size of atlas pages, small enough to be supported by every renderer.
+pub const PAGE_SIZE: u16 = 2048;
+/// Sheets larger than this in either dimension get a texture of their own.
+pub const MAX_PACKED_SIZE: u16 = 512;
+/// Transparent border left around packed sheets, so their edges don't pick up pixels of neighbours.
+const PADDING: u16 = 1;
+
+struct Shelf {
+    y: u16,
+    height: u16,
+    used_width: u16,
+}
+
+/// Places rectangles left to right in rows, a new row is opened below the last one when none of existing ones fit.
+pub struct ShelfPacker {
+    width: u16,
+    height: u16,
+    shelves: Vec<Shelf>,
+}
+
+impl ShelfPacker {
+    pub fn new(width: u16, height: u16) -> ShelfPacker {
+        ShelfPacker { width, height, shelves: Vec::new() }
+    }
+
+    /// Returns position of top left corner of the area reserved for given rectangle, or None if there's no room left.
+    pub fn pack(&mut self, width: u16, height: u16) -> Option<(u16, u16)> {
+        let (padded_width, padded_height) = (width + PADDING * 2, height + PADDING * 2);
+        if padded_width > self.width || padded_height > self.height {
+            return None;
+        }
+
+        // the lowest row it fits in wastes the least space
+        let free_width = self.width;
+        let best = self
+            .shelves
+            .iter()
+            .enumerate()
+            .filter(|(_, shelf)| shelf.height >= padded_height && free_width - shelf.used_width >= padded_width)
+            .min_by_key(|(_, shelf)| shelf.height)
+            .map(|(idx, _)| idx);
+
+        let idx = match best {
+            Some(idx) => idx,
+            None => {
+                let y = self.shelves.last().map_or(0, |shelf| shelf.y + shelf.height);
+                if self.height - y < padded_height {
+                    return None;
+                }
+
+                self.shelves.push(Shelf { y, height: padded_height, used_width: 0 });
+                self.shelves.len() - 1
+            }
+        };
+
+        let shelf = &mut self.shelves[idx];
+        let pos = (shelf.used_width + PADDING, shelf.y + PADDING);
+        shelf.used_width += padded_width;
+
+        Some(pos)
+    }
+}
+
+/// Location of a sheet packed into the atlas.
+#[derive(Copy, Clone, Debug)]
+pub struct PackedSheet {
+    /// Unique for each packed sheet, used to tell apart their commands queued on the same page.
+    pub id: usize,
+    pub page: usize,
+    pub offset: (f32, f32),
+}
+
+struct QueuedCommand {
+    sheet: usize,
+    command: SpriteBatchCommand,
+    /// Set once the sheet it belongs to has been drawn, otherwise it can still be cleared.
+    drawn: bool,
+}
+
+struct AtlasPage {
+    texture: Box<dyn BackendTexture>,
+    packer: ShelfPacker,
+    /// Commands of all sheets on the page in order they were added in.
+    queued: Vec<QueuedCommand>,
+}
+
+/// Shared textures small sprite sheets are copied into, so drawing from different sheets doesn't need to switch them.
+/// Every sheet still behaves like a sprite batch of its own, commands are kept apart until the sheet is drawn.
+pub struct TextureAtlas {
+    pages: Vec<AtlasPage>,
+    sheet_count: usize,
+    deferred: bool,
+    /// Page with commands queued while deferred.
+    pending: Option<usize>,
+}
+
+impl TextureAtlas {
+    pub fn new() -> TextureAtlas {
+        TextureAtlas { pages: Vec::new(), sheet_count: 0, deferred: false, pending: None }
+    }
+
+    /// Copies a sheet into free space of one of the pages, creating a new page if necessary.
+    /// Returns None if it's too big to be packed.
+    pub fn insert(
+        &mut self,
+        ctx: &mut Context,
+        width: u16,
+        height: u16,
+        data: &[u8],
+    ) -> GameResult<Option<PackedSheet>> {
+        if width > MAX_PACKED_SIZE || height > MAX_PACKED_SIZE {
+            return Ok(None);
+        }
+
+        if let Some(sheet) = self.pack(width, height, data)? {
+            return Ok(Some(sheet));
+        }
+
+        let empty = vec![0u8; PAGE_SIZE as usize * PAGE_SIZE as usize * 4];
+        let texture = create_texture(ctx, PAGE_SIZE, PAGE_SIZE, &empty)?;
+
+        log::info!("Created texture atlas page {}.", self.pages.len());
+        self.add_page(texture);
+
+        self.pack(width, height, data)
+    }
+
+    fn add_page(&mut self, texture: Box<dyn BackendTexture>) {
+        self.pages.push(AtlasPage { texture, packer: ShelfPacker::new(PAGE_SIZE, PAGE_SIZE), queued: Vec::new() });
+    }
+
+    /// Copies a sheet into the first page with enough free space.
+    fn pack(&mut self, width: u16, height: u16, data: &[u8]) -> GameResult<Option<PackedSheet>> {
+        for (idx, page) in self.pages.iter_mut().enumerate() {
+            if let Some((x, y)) = page.packer.pack(width, height) {
+                page.texture.update(x, y, width, height, data)?;
+                self.sheet_count += 1;
+
+                return Ok(Some(PackedSheet { id: self.sheet_count - 1, page: idx, offset: (x as f32, y as f32) }));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Queues a command of given sheet, with source rectangle relative to the sheet.
+    pub fn add(&mut self, sheet: &PackedSheet, command: SpriteBatchCommand) {
+        if self.deferred {
+            if let Some(pending) = self.pending {
+                if pending != sheet.page {
+                    if let Err(err) = self.draw_page(pending) {
+                        log::error!("Failed to draw texture atlas page: {}", err);
+                    }
+                }
+            }
+
+            self.pending = Some(sheet.page);
+        }
+
+        let offset = sheet.offset;
+        let move_rect = |rect: Rect<f32>| Rect {
+            left: rect.left + offset.0,
+            top: rect.top + offset.1,
+            right: rect.right + offset.0,
+            bottom: rect.bottom + offset.1,
+        };
+
+        let command = match command {
+            SpriteBatchCommand::DrawRect(src, dest) => SpriteBatchCommand::DrawRect(move_rect(src), dest),
+            SpriteBatchCommand::DrawRectFlip(src, dest, flip_x, flip_y) => {
+                SpriteBatchCommand::DrawRectFlip(move_rect(src), dest, flip_x, flip_y)
+            }
+            SpriteBatchCommand::DrawRectTinted(src, dest, color) => {
+                SpriteBatchCommand::DrawRectTinted(move_rect(src), dest, color)
+            }
+        };
+
+        self.pages[sheet.page].queued.push(QueuedCommand { sheet: sheet.id, command, drawn: false });
+    }
+
+    /// Draws commands queued for given sheet, unless draws are deferred.
+    pub fn draw(&mut self, sheet: &PackedSheet) -> GameResult {
+        for queued in self.pages[sheet.page].queued.iter_mut() {
+            if queued.sheet == sheet.id {
+                queued.drawn = true;
+            }
+        }
+
+        if self.deferred {
+            return Ok(());
+        }
+
+        self.draw_page(sheet.page)
+    }
+
+    /// Drops commands queued for given sheet that haven't been drawn yet.
+    pub fn clear(&mut self, sheet: &PackedSheet) {
+        self.pages[sheet.page].queued.retain(|queued| queued.drawn || queued.sheet != sheet.id);
+    }
+
+    /// Draws commands of sheets on the page that have been drawn, leaving the rest queued.
+    fn draw_page(&mut self, page: usize) -> GameResult {
+        let page_ref = &mut self.pages[page];
+
+        for queued in std::mem::take(&mut page_ref.queued) {
+            if queued.drawn {
+                page_ref.texture.add(queued.command);
+            } else {
+                page_ref.queued.push(queued);
+            }
+        }
+
+        page_ref.texture.draw()?;
+        page_ref.texture.clear();
+
+        if self.pending == Some(page) {
+            self.pending = None;
+        }
+
+        Ok(())
+    }
+    /// Makes draws of packed sheets collect their commands until `end_deferred`, to be drawn at once.
+    /// Only sprite batches of the texture set may be drawn in the meantime, as they flush them to keep the order.
+    pub fn begin_deferred(&mut self) {
+        self.deferred = true;
+    }
+
+    pub fn end_deferred(&mut self) -> GameResult {
+        self.deferred = false;
+        self.flush()
+    }
+
+    /// Draws commands collected while deferred.
+    pub fn flush(&mut self) -> GameResult {
+        if let Some(page) = self.pending.take() {
+            self.draw_page(page)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    fn overlaps(a: (u16, u16, u16, u16), b: (u16, u16, u16, u16)) -> bool {
+        a.0 < b.0 + b.2 && b.0 < a.0 + a.2 && a.1 < b.1 + b.3 && b.1 < a.1 + a.3
+    }
+
+    #[test]
+    fn packs_into_rows() {
+        let mut packer = ShelfPacker::new(64, 64);
+
+        assert_eq!(packer.pack(10, 10), Some((1, 1)));
+        assert_eq!(packer.pack(20, 8), Some((13, 1)));
+        // too tall for the first row
+        assert_eq!(packer.pack(10, 12), Some((1, 13)));
+        // lowest row it fits in
+        assert_eq!(packer.pack(6, 6), Some((35, 1)));
+    }
+
+    #[test]
+    fn opens_new_row_when_full() {
+        let mut packer = ShelfPacker::new(64, 64);
+
+        assert_eq!(packer.pack(30, 10), Some((1, 1)));
+        assert_eq!(packer.pack(30, 10), Some((33, 1)));
+        assert_eq!(packer.pack(30, 10), Some((1, 13)));
+    }
+
+    #[test]
+    fn runs_out_of_space() {
+        let mut packer = ShelfPacker::new(64, 64);
+        assert_eq!(packer.pack(63, 10), None);
+        assert_eq!(packer.pack(10, 63), None);
+
+        for row in 0..4 {
+            assert_eq!(packer.pack(62, 14), Some((1, 1 + row * 16)));
+        }
+        assert_eq!(packer.pack(1, 1), None);
+    }
+
+    #[test]
+    fn packed_areas_dont_overlap() {
+        let mut packer = ShelfPacker::new(256, 256);
+        let mut areas: Vec<(u16, u16, u16, u16)> = Vec::new();
+
+        for i in 0..200u16 {
+            let (width, height) = (1 + i * 7 % 23, 1 + i * 13 % 17);
+            if let Some((x, y)) = packer.pack(width, height) {
+                // padding included, so neighbours don't bleed into each other
+                let area = (x - PADDING, y - PADDING, width + PADDING * 2, height + PADDING * 2);
+                assert!(area.0 + area.2 <= 256 && area.1 + area.3 <= 256);
+                assert!(areas.iter().all(|other| !overlaps(area, *other)), "{:?} overlaps", area);
+                areas.push(area);
+            }
+        }
+
+        assert!(areas.len() > 100);
+    }
+
+    /// Records amount of commands drawn with each draw call.
+    struct MockTexture {
+        commands: usize,
+        draws: Rc<RefCell<Vec<usize>>>,
+    }
+
+    impl BackendTexture for MockTexture {
+        fn dimensions(&self) -> (u16, u16) {
+            (PAGE_SIZE, PAGE_SIZE)
+        }
+
+        fn add(&mut self, _command: SpriteBatchCommand) {
+            self.commands += 1;
+        }
+
+        fn clear(&mut self) {
+            self.commands = 0;
+        }
+
+        fn draw(&mut self) -> GameResult {
+            self.draws.borrow_mut().push(self.commands);
+            Ok(())
+        }
+
+        fn update(&mut self, _x: u16, _y: u16, _width: u16, _height: u16, _data: &[u8]) -> GameResult {
+            Ok(())
+        }
+    }
+
+    fn atlas_with_page() -> (TextureAtlas, Rc<RefCell<Vec<usize>>>) {
+        let draws = Rc::new(RefCell::new(Vec::new()));
+        let mut atlas = TextureAtlas::new();
+        atlas.add_page(Box::new(MockTexture { commands: 0, draws: draws.clone() }));
+
+        (atlas, draws)
+    }
+
+    fn command() -> SpriteBatchCommand {
+        SpriteBatchCommand::DrawRect(Rect::new(0.0, 0.0, 8.0, 8.0), Rect::new(0.0, 0.0, 8.0, 8.0))
+    }
+
+    #[test]
+    fn sheets_on_same_page_are_drawn_separately() {
+        let (mut atlas, draws) = atlas_with_page();
+        let a = atlas.pack(8, 8, &[]).unwrap().unwrap();
+        let b = atlas.pack(8, 8, &[]).unwrap().unwrap();
+        assert_eq!(a.page, b.page);
+        assert_ne!(a.id, b.id);
+
+        atlas.add(&a, command());
+        atlas.add(&b, command());
+        atlas.add(&b, command());
+        atlas.draw(&a).unwrap();
+        assert_eq!(*draws.borrow(), vec![1]);
+
+        atlas.draw(&b).unwrap();
+        assert_eq!(*draws.borrow(), vec![1, 2]);
+    }
+
+    #[test]
+    fn clearing_a_sheet_keeps_others() {
+        let (mut atlas, draws) = atlas_with_page();
+        let a = atlas.pack(8, 8, &[]).unwrap().unwrap();
+        let b = atlas.pack(8, 8, &[]).unwrap().unwrap();
+
+        atlas.add(&a, command());
+        atlas.add(&b, command());
+        atlas.clear(&a);
+        atlas.draw(&b).unwrap();
+        atlas.draw(&a).unwrap();
+
+        assert_eq!(*draws.borrow(), vec![1, 0]);
+    }
+
+    #[test]
+    fn deferred_draws_are_collected() {
+        let (mut atlas, draws) = atlas_with_page();
+        let a = atlas.pack(8, 8, &[]).unwrap().unwrap();
+        let b = atlas.pack(8, 8, &[]).unwrap().unwrap();
+
+        atlas.begin_deferred();
+        atlas.add(&a, command());
+        atlas.draw(&a).unwrap();
+        atlas.add(&b, command());
+        atlas.add(&b, command());
+        atlas.draw(&b).unwrap();
+        // queued, but not drawn by its sheet
+        atlas.add(&a, command());
+        assert!(draws.borrow().is_empty());
+
+        atlas.end_deferred().unwrap();
+        assert_eq!(*draws.borrow(), vec![3]);
+
+        atlas.clear(&a);
+        atlas.draw(&a).unwrap();
+        assert_eq!(*draws.borrow(), vec![3, 0]);
+    }
+}
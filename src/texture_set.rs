@@ -1,5 +1,7 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::rc::Rc;
 
 use image::RgbaImage;
 use itertools::Itertools;
@@ -16,12 +18,20 @@ use crate::framework::graphics::{create_texture, FilterMode};
 use crate::settings::Settings;
 use crate::shared_game_state::Season;
 use crate::str;
+use crate::texture_atlas::{PackedSheet, TextureAtlas};
 
 pub static mut I_MAG: f32 = 1.0;
 pub static mut G_MAG: f32 = 1.0;
 
+enum BatchTexture {
+    Own(Box<dyn BackendTexture>),
+    /// Sheet packed into a page of the texture atlas.
+    Packed(PackedSheet),
+}
+
 pub struct SizedBatch {
-    batch: Box<dyn BackendTexture>,
+    batch: BatchTexture,
+    atlas: Rc<RefCell<TextureAtlas>>,
     width: usize,
     height: usize,
     real_width: usize,
@@ -61,9 +71,18 @@ impl SizedBatch {
         common::Rect::<usize>::new(0, 0, self.width, self.height)
     }
 
-    #[inline(always)]
     pub fn clear(&mut self) {
-        self.batch.clear();
+        match &mut self.batch {
+            BatchTexture::Own(batch) => batch.clear(),
+            BatchTexture::Packed(sheet) => self.atlas.borrow_mut().clear(sheet),
+        }
+    }
+
+    fn push(&mut self, command: SpriteBatchCommand) {
+        match &mut self.batch {
+            BatchTexture::Own(batch) => batch.add(command),
+            BatchTexture::Packed(sheet) => self.atlas.borrow_mut().add(sheet, command),
+        }
     }
 
     pub fn add(&mut self, x: f32, y: f32) {
@@ -73,7 +92,7 @@ impl SizedBatch {
         }*/
         let mag = unsafe { I_MAG };
 
-        self.batch.add(SpriteBatchCommand::DrawRect(
+        self.push(SpriteBatchCommand::DrawRect(
             Rect {
                 left: 0 as f32,
                 top: 0 as f32,
@@ -105,7 +124,7 @@ impl SizedBatch {
         }*/
         let mag = unsafe { I_MAG };
 
-        self.batch.add(SpriteBatchCommand::DrawRectFlip(
+        self.push(SpriteBatchCommand::DrawRectFlip(
             Rect {
                 left: rect.left as f32 / self.scale_x,
                 top: rect.top as f32 / self.scale_y,
@@ -138,7 +157,7 @@ impl SizedBatch {
         }*/
         let mag = unsafe { I_MAG };
 
-        self.batch.add(SpriteBatchCommand::DrawRect(
+        self.push(SpriteBatchCommand::DrawRect(
             Rect {
                 left: rect.left as f32 / self.scale_x,
                 top: rect.top as f32 / self.scale_y,
@@ -165,7 +184,7 @@ impl SizedBatch {
         }*/
         let mag = unsafe { I_MAG };
 
-        self.batch.add(SpriteBatchCommand::DrawRectTinted(
+        self.push(SpriteBatchCommand::DrawRectTinted(
             Rect {
                 left: rect.left as f32,
                 top: rect.top as f32,
@@ -189,15 +208,66 @@ impl SizedBatch {
 
     pub fn draw_filtered(&mut self, _filter: FilterMode, _ctx: &mut Context) -> GameResult {
         //self.batch.set_filter(filter);
-        self.batch.draw()?;
-        self.batch.clear();
+        match &mut self.batch {
+            BatchTexture::Own(batch) => {
+                self.atlas.borrow_mut().flush()?;
+                batch.draw()?;
+                batch.clear();
+            }
+            BatchTexture::Packed(sheet) => self.atlas.borrow_mut().draw(sheet)?,
+        }
         Ok(())
     }
+
+    /// Returns true if queued commands can be kept in a retained buffer with `store_static`.
+    /// Sheets packed into the atlas share their texture, so they can't.
+    pub fn supports_static(&self) -> bool {
+        match &self.batch {
+            BatchTexture::Own(batch) => batch.supports_static(),
+            BatchTexture::Packed(_) => false,
+        }
+    }
+
+    pub fn has_static(&self, slot: usize) -> bool {
+        match &self.batch {
+            BatchTexture::Own(batch) => batch.has_static(slot),
+            BatchTexture::Packed(_) => false,
+        }
+    }
+
+    /// Moves commands queued since last draw into a retained buffer identified by `slot`, which can be drawn
+    /// repeatedly with `draw_static` without being rebuilt every frame.
+    pub fn store_static(&mut self, slot: usize) -> GameResult {
+        match &mut self.batch {
+            BatchTexture::Own(batch) => batch.store_static(slot),
+            BatchTexture::Packed(_) => {
+                Err(GameError::RenderError("Static batches of packed textures are not supported.".to_string()))
+            }
+        }
+    }
+
+    /// Draws a retained buffer moved by given offset, which is snapped to the pixel grid like sprite positions.
+    pub fn draw_static(&mut self, slot: usize, x: f32, y: f32) -> GameResult {
+        let (g_mag, mag) = unsafe { (G_MAG, I_MAG) };
+        let x = (x * g_mag).floor() / g_mag;
+        let y = (y * g_mag).floor() / g_mag;
+
+        match &mut self.batch {
+            BatchTexture::Own(batch) => {
+                self.atlas.borrow_mut().flush()?;
+                batch.draw_static(slot, (x * mag, y * mag))
+            }
+            BatchTexture::Packed(_) => Ok(()),
+        }
+    }
 }
 
 pub struct TextureSet {
     pub tex_map: HashMap<String, SizedBatch>,
     pub paths: Vec<String>,
+    /// Packs small sheets loaded from now on into the texture atlas.
+    pub use_atlas: bool,
+    atlas: Rc<RefCell<TextureAtlas>>,
 }
 
 impl TextureSet {
@@ -205,9 +275,21 @@ impl TextureSet {
         TextureSet {
             tex_map: HashMap::new(),
             paths: vec![base_path.to_string(), "".to_string()],
+            use_atlas: true,
+            atlas: Rc::new(RefCell::new(TextureAtlas::new())),
         }
     }
 
+    /// Makes draws of sheets packed into the atlas collect their commands until `end_deferred_draws`,
+    /// so they're drawn with as few draw calls as possible. Only sprite batches may be drawn in the meantime.
+    pub fn begin_deferred_draws(&mut self) {
+        self.atlas.borrow_mut().begin_deferred();
+    }
+
+    pub fn end_deferred_draws(&mut self) -> GameResult {
+        self.atlas.borrow_mut().end_deferred()
+    }
+
     pub fn apply_seasonal_content(&mut self, season: Season, settings: &Settings) {
         if settings.original_textures {
             self.paths.insert(0, "/base/ogph/".to_string())
@@ -228,7 +310,7 @@ impl TextureSet {
         }
     }
 
-    fn load_image(&self, ctx: &mut Context, path: &str) -> GameResult<Box<dyn BackendTexture>> {
+    fn load_image(&mut self, ctx: &mut Context, path: &str) -> GameResult<(BatchTexture, (u16, u16))> {
         let img = {
             let mut buf = [0u8; 8];
             let mut reader = filesystem::open(ctx, path)?;
@@ -243,24 +325,38 @@ impl TextureSet {
             rgba
         };
         let (width, height) = img.dimensions();
+        let size = (width as u16, height as u16);
+
+        if self.use_atlas {
+            match self.atlas.borrow_mut().insert(ctx, size.0, size.1, &img) {
+                Ok(Some(sheet)) => return Ok((BatchTexture::Packed(sheet), size)),
+                Ok(None) => {}
+                Err(err) => {
+                    log::warn!("Failed to pack texture into the atlas, disabling it: {}", err);
+                    self.use_atlas = false;
+                }
+            }
+        }
 
-        create_texture(ctx, width as u16, height as u16, &img)
+        Ok((BatchTexture::Own(create_texture(ctx, size.0, size.1, &img)?), size))
     }
 
-    pub fn load_texture(&self, ctx: &mut Context, constants: &EngineConstants, name: &str) -> GameResult<SizedBatch> {
-        let path = self.paths.iter().find_map(|s| FILE_TYPES
+    /// Returns the path of the image file given texture would be loaded from.
+    pub fn find_texture(&self, ctx: &Context, name: &str) -> Option<String> {
+        self.paths.iter().find_map(|s| FILE_TYPES
             .iter()
             .map(|ext| [s, name, ext].join(""))
-            .find(|path| {
-                println!("{}", path);
-                filesystem::exists(ctx, path)
-            })
-        ).ok_or_else(|| GameError::ResourceLoadError(format!("Texture {} does not exist.", name)))?;
+            .find(|path| filesystem::exists(ctx, path))
+        )
+    }
+
+    pub fn load_texture(&mut self, ctx: &mut Context, constants: &EngineConstants, name: &str) -> GameResult<SizedBatch> {
+        let path = self.find_texture(ctx, name)
+            .ok_or_else(|| GameError::ResourceLoadError(format!("Texture {} does not exist.", name)))?;
 
         info!("Loading texture: {}", path);
 
-        let batch = self.load_image(ctx, &path)?;
-        let size = batch.dimensions();
+        let (batch, size) = self.load_image(ctx, &path)?;
 
         assert_ne!(size.0 as isize, 0, "size.width == 0");
         assert_ne!(size.1 as isize, 0, "size.height == 0");
@@ -272,6 +368,7 @@ impl TextureSet {
 
         Ok(SizedBatch {
             batch,
+            atlas: self.atlas.clone(),
             width,
             height,
             scale_x: scale,
@@ -0,0 +1,1136 @@
+//! Importer for maps made in Tiled (https://www.mapeditor.org/), both the XML (.tmx) and JSON (.tmj) flavors.
+//!
+//! Conventions used while converting to engine data:
+//! - tile layers become PXM tiles, or PxPack foreground/middleground/background layers if the map is a PxPack one
+//!   (`pxpack` map property set, or layers named `fg`, `mg` and `bg`),
+//! - object layers become PXE entities, `type`, `flag`, `event`, `flags` and `layer` properties are used,
+//!   the object type/class is used as NPC type if the `type` property is missing,
+//! - tile attributes (PXA) are taken from `attribute` property of tileset tiles,
+//!   or from the `attributes` tileset property containing a list of 256 values.
+
+use std::collections::HashMap;
+use std::io;
+
+use serde_json::Value;
+
+use crate::framework::context::Context;
+use crate::framework::error::{GameError, GameResult};
+use crate::framework::filesystem;
+use crate::map::{Map, NPCData};
+use crate::shared_game_state::TileSize;
+use crate::stage::{PxPackScroll, PxPackStageData, StageData};
+
+const GID_MASK: u32 = 0x1fff_ffff;
+
+#[derive(Debug, Clone, Default)]
+pub struct TiledTileset {
+    pub first_gid: u32,
+    pub name: String,
+    pub tile_count: u32,
+    pub properties: HashMap<String, String>,
+    pub tile_properties: HashMap<u32, HashMap<String, String>>,
+}
+
+impl TiledTileset {
+    /// Returns PXA attributes defined in the tileset, if there are any.
+    pub fn attributes(&self) -> GameResult<Option<[u8; 0x100]>> {
+        let mut attrib = [0u8; 0x100];
+        let mut found = false;
+
+        if let Some(list) = self.properties.get("attributes") {
+            let values = list.split(|c: char| c == ',' || c.is_whitespace()).filter(|s| !s.is_empty());
+
+            for (attr, value) in attrib.iter_mut().zip(values) {
+                *attr = parse_int(value)? as u8;
+            }
+
+            found = true;
+        }
+
+        for (&id, properties) in self.tile_properties.iter() {
+            if let (Some(value), true) = (properties.get("attribute"), id < 0x100) {
+                attrib[id as usize] = parse_int(value)? as u8;
+                found = true;
+            }
+        }
+
+        Ok(if found { Some(attrib) } else { None })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TiledTileLayer {
+    pub name: String,
+    pub width: u16,
+    pub height: u16,
+    pub gids: Vec<u32>,
+    pub properties: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TiledObject {
+    pub id: u32,
+    pub name: String,
+    pub obj_type: String,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub gid: Option<u32>,
+    pub properties: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TiledObjectLayer {
+    pub name: String,
+    pub objects: Vec<TiledObject>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TiledMap {
+    pub width: u16,
+    pub height: u16,
+    pub tile_width: u16,
+    pub tile_height: u16,
+    pub properties: HashMap<String, String>,
+    pub tilesets: Vec<TiledTileset>,
+    /// Tile layers in Tiled order, which is bottom to top.
+    pub tile_layers: Vec<TiledTileLayer>,
+    pub object_layers: Vec<TiledObjectLayer>,
+}
+
+impl TiledMap {
+    /// Loads `Stage/<name>.tmj` or `Stage/<name>.tmx` from given root.
+    pub fn load(root: &str, name: &str, ctx: &mut Context) -> GameResult<TiledMap> {
+        let dir = [root, "Stage/"].join("");
+
+        if let Ok(file) = filesystem::open(ctx, [&dir, name, ".tmj"].join("")) {
+            TiledMap::load_tmj(file, &dir, ctx)
+        } else {
+            let file = filesystem::open(ctx, [&dir, name, ".tmx"].join(""))?;
+            TiledMap::load_tmx(file, &dir, ctx)
+        }
+    }
+
+    pub fn exists(root: &str, name: &str, ctx: &mut Context) -> bool {
+        let dir = [root, "Stage/"].join("");
+
+        filesystem::exists(ctx, [&dir, name, ".tmj"].join("")) || filesystem::exists(ctx, [&dir, name, ".tmx"].join(""))
+    }
+
+    /// Parses a map in Tiled JSON format, `dir` is used to resolve external tilesets.
+    pub fn load_tmj<R: io::Read>(data: R, dir: &str, ctx: &mut Context) -> GameResult<TiledMap> {
+        let root: Value = serde_json::from_reader(data)?;
+
+        if root["infinite"].as_bool().unwrap_or(false) {
+            return Err(GameError::ResourceLoadError("Infinite Tiled maps are not supported.".to_string()));
+        }
+
+        let mut map = TiledMap {
+            width: json_u32(&root["width"])? as u16,
+            height: json_u32(&root["height"])? as u16,
+            tile_width: json_u32(&root["tilewidth"])? as u16,
+            tile_height: json_u32(&root["tileheight"])? as u16,
+            properties: json_properties(&root["properties"]),
+            tilesets: Vec::new(),
+            tile_layers: Vec::new(),
+            object_layers: Vec::new(),
+        };
+
+        for tileset in root["tilesets"].as_array().into_iter().flatten() {
+            let first_gid = json_u32(&tileset["firstgid"])?;
+
+            let mut tileset = if let Some(source) = tileset["source"].as_str() {
+                load_external_tileset(source, dir, ctx)?
+            } else {
+                json_tileset(tileset)
+            };
+
+            tileset.first_gid = first_gid;
+            map.tilesets.push(tileset);
+        }
+
+        fn walk_layers(map: &mut TiledMap, layers: &Value) -> GameResult {
+            for layer in layers.as_array().into_iter().flatten() {
+                match layer["type"].as_str().unwrap_or("") {
+                    "tilelayer" => {
+                        let gids = match &layer["data"] {
+                            Value::Array(data) => data.iter().map(json_u32).collect::<GameResult<Vec<u32>>>()?,
+                            Value::String(data) => decode_gids(
+                                data,
+                                layer["encoding"].as_str().unwrap_or("base64"),
+                                layer["compression"].as_str().unwrap_or(""),
+                            )?,
+                            _ => {
+                                return Err(GameError::ResourceLoadError(
+                                    "Chunked Tiled layers are not supported.".to_string(),
+                                ))
+                            }
+                        };
+
+                        map.tile_layers.push(TiledTileLayer {
+                            name: layer["name"].as_str().unwrap_or("").to_string(),
+                            width: json_u32(&layer["width"])? as u16,
+                            height: json_u32(&layer["height"])? as u16,
+                            gids,
+                            properties: json_properties(&layer["properties"]),
+                        });
+                    }
+                    "objectgroup" => {
+                        let mut objects = Vec::new();
+
+                        for object in layer["objects"].as_array().into_iter().flatten() {
+                            let obj_type = [&object["type"], &object["class"]]
+                                .iter()
+                                .find_map(|v| v.as_str().filter(|s| !s.is_empty()))
+                                .unwrap_or("");
+
+                            objects.push(TiledObject {
+                                id: json_u32(&object["id"]).unwrap_or(0),
+                                name: object["name"].as_str().unwrap_or("").to_string(),
+                                obj_type: obj_type.to_string(),
+                                x: object["x"].as_f64().unwrap_or(0.0) as f32,
+                                y: object["y"].as_f64().unwrap_or(0.0) as f32,
+                                width: object["width"].as_f64().unwrap_or(0.0) as f32,
+                                height: object["height"].as_f64().unwrap_or(0.0) as f32,
+                                gid: object["gid"].as_u64().map(|gid| gid as u32),
+                                properties: json_properties(&object["properties"]),
+                            });
+                        }
+
+                        map.object_layers
+                            .push(TiledObjectLayer { name: layer["name"].as_str().unwrap_or("").to_string(), objects });
+                    }
+                    "group" => walk_layers(map, &layer["layers"])?,
+                    _ => {}
+                }
+            }
+
+            Ok(())
+        }
+
+        walk_layers(&mut map, &root["layers"])?;
+
+        Ok(map)
+    }
+
+    /// Parses a map in Tiled XML format, `dir` is used to resolve external tilesets.
+    pub fn load_tmx<R: io::Read>(mut data: R, dir: &str, ctx: &mut Context) -> GameResult<TiledMap> {
+        let mut text = String::new();
+        data.read_to_string(&mut text)?;
+
+        let root = XmlElement::parse(&text)?;
+        if root.name != "map" {
+            return Err(GameError::ResourceLoadError("Root element of TMX file is not <map>.".to_string()));
+        }
+
+        if root.attr("infinite") == "1" {
+            return Err(GameError::ResourceLoadError("Infinite Tiled maps are not supported.".to_string()));
+        }
+
+        let mut map = TiledMap {
+            width: parse_int(root.attr("width"))? as u16,
+            height: parse_int(root.attr("height"))? as u16,
+            tile_width: parse_int(root.attr("tilewidth"))? as u16,
+            tile_height: parse_int(root.attr("tileheight"))? as u16,
+            properties: xml_properties(&root),
+            tilesets: Vec::new(),
+            tile_layers: Vec::new(),
+            object_layers: Vec::new(),
+        };
+
+        for tileset in root.children_named("tileset") {
+            let first_gid = parse_int(tileset.attr("firstgid"))? as u32;
+            let source = tileset.attr("source");
+
+            let mut tileset =
+                if !source.is_empty() { load_external_tileset(source, dir, ctx)? } else { xml_tileset(tileset)? };
+
+            tileset.first_gid = first_gid;
+            map.tilesets.push(tileset);
+        }
+
+        fn walk_layers(map: &mut TiledMap, parent: &XmlElement) -> GameResult {
+            for layer in parent.children.iter() {
+                match layer.name.as_str() {
+                    "layer" => {
+                        let data = layer
+                            .child("data")
+                            .ok_or_else(|| GameError::ResourceLoadError("Tile layer has no data.".to_string()))?;
+
+                        if data.child("chunk").is_some() {
+                            return Err(GameError::ResourceLoadError(
+                                "Chunked Tiled layers are not supported.".to_string(),
+                            ));
+                        }
+
+                        let gids = match data.attr("encoding") {
+                            "" => data
+                                .children_named("tile")
+                                .map(|t| if t.attr("gid").is_empty() { Ok(0) } else { parse_int(t.attr("gid")) })
+                                .map(|r| r.map(|gid| gid as u32))
+                                .collect::<GameResult<Vec<u32>>>()?,
+                            encoding => decode_gids(&data.text, encoding, data.attr("compression"))?,
+                        };
+
+                        map.tile_layers.push(TiledTileLayer {
+                            name: layer.attr("name").to_string(),
+                            width: parse_int(layer.attr("width"))? as u16,
+                            height: parse_int(layer.attr("height"))? as u16,
+                            gids,
+                            properties: xml_properties(layer),
+                        });
+                    }
+                    "objectgroup" => {
+                        let mut objects = Vec::new();
+
+                        for object in layer.children_named("object") {
+                            let obj_type =
+                                if object.attr("type").is_empty() { object.attr("class") } else { object.attr("type") };
+
+                            objects.push(TiledObject {
+                                id: parse_int(object.attr("id")).unwrap_or(0) as u32,
+                                name: object.attr("name").to_string(),
+                                obj_type: obj_type.to_string(),
+                                x: object.attr("x").parse().unwrap_or(0.0),
+                                y: object.attr("y").parse().unwrap_or(0.0),
+                                width: object.attr("width").parse().unwrap_or(0.0),
+                                height: object.attr("height").parse().unwrap_or(0.0),
+                                gid: object.attr("gid").parse::<u32>().ok(),
+                                properties: xml_properties(object),
+                            });
+                        }
+
+                        map.object_layers.push(TiledObjectLayer { name: layer.attr("name").to_string(), objects });
+                    }
+                    "group" => walk_layers(map, layer)?,
+                    _ => {}
+                }
+            }
+
+            Ok(())
+        }
+
+        walk_layers(&mut map, &root)?;
+
+        Ok(map)
+    }
+
+    fn tileset_for_gid(&self, gid: u32) -> Option<&TiledTileset> {
+        let gid = gid & GID_MASK;
+        if gid == 0 {
+            return None;
+        }
+
+        self.tilesets.iter().filter(|t| t.first_gid <= gid).max_by_key(|t| t.first_gid)
+    }
+
+    fn layer_tiles(&self, layer: &TiledTileLayer) -> GameResult<Vec<u8>> {
+        let size = layer.width as usize * layer.height as usize;
+        if layer.gids.len() != size {
+            return Err(GameError::ResourceLoadError(format!(
+                "Tile layer {} has {} tiles, expected {} for its size of {}x{}.",
+                layer.name,
+                layer.gids.len(),
+                size,
+                layer.width,
+                layer.height
+            )));
+        }
+
+        let mut out_of_range = 0;
+        let tiles = layer
+            .gids
+            .iter()
+            .map(|&gid| {
+                let gid = gid & GID_MASK;
+                match self.tileset_for_gid(gid) {
+                    Some(tileset) if gid - tileset.first_gid > 0xff => {
+                        out_of_range += 1;
+                        0
+                    }
+                    Some(tileset) => (gid - tileset.first_gid) as u8,
+                    None => 0,
+                }
+            })
+            .collect();
+
+        if out_of_range > 0 {
+            log::warn!(
+                "{} tiles in layer {} are out of range, maximum is 255. They were replaced with tile 0.",
+                out_of_range,
+                layer.name
+            );
+        }
+
+        Ok(tiles)
+    }
+
+    fn layer_tileset(&self, layer: &TiledTileLayer) -> Option<&TiledTileset> {
+        layer.gids.iter().find(|&&gid| gid & GID_MASK != 0).and_then(|&gid| self.tileset_for_gid(gid))
+    }
+
+    fn is_pxpack(&self) -> bool {
+        matches!(self.properties.get("pxpack").map(|s| s.as_str()), Some("true") | Some("1"))
+            || (self.find_layer(&["mg", "middleground"]).is_some() && self.find_layer(&["bg", "background"]).is_some())
+    }
+
+    fn find_layer(&self, names: &[&str]) -> Option<&TiledTileLayer> {
+        self.tile_layers.iter().find(|l| names.iter().any(|n| l.name.eq_ignore_ascii_case(n)))
+    }
+
+    /// Converts tile layers into a `Map`. The second tuple element tells whether the tile attributes
+    /// were defined in the tileset, if not they have to be loaded from a .pxa file.
+    pub fn to_map(&self, data: &mut StageData) -> GameResult<(Map, bool)> {
+        if self.tile_layers.is_empty() {
+            return Err(GameError::ResourceLoadError("Tiled map has no tile layers.".to_string()));
+        }
+
+        let tile_size = if self.tile_width == 8 { TileSize::Tile8x8 } else { TileSize::Tile16x16 };
+
+        if data.pxpack_data.is_some() || self.is_pxpack() {
+            let count = self.tile_layers.len();
+            let fg = self.find_layer(&["fg", "foreground"]).unwrap_or(&self.tile_layers[count - 1]);
+            let nth_from_top = |n: usize| count.checked_sub(n).map(|i| &self.tile_layers[i]);
+            let mg = self.find_layer(&["mg", "middleground"]).or_else(|| nth_from_top(2));
+            let bg = self.find_layer(&["bg", "background"]).or_else(|| nth_from_top(3));
+
+            let mut tiles = self.layer_tiles(fg)?;
+            let size_fg = (fg.width, fg.height);
+            let size_mg = mg.map(|l| (l.width, l.height)).unwrap_or((0, 0));
+            let size_bg = bg.map(|l| (l.width, l.height)).unwrap_or((0, 0));
+            let offset_mg = tiles.len() as u32;
+            if let Some(mg) = mg {
+                tiles.extend(self.layer_tiles(mg)?);
+            }
+            let offset_bg = tiles.len() as u32;
+            if let Some(bg) = bg {
+                tiles.extend(self.layer_tiles(bg)?);
+            }
+
+            let tileset_name = |layer: Option<&TiledTileLayer>| {
+                layer
+                    .and_then(|l| {
+                        l.properties.get("tileset").cloned().or_else(|| self.layer_tileset(l).map(|t| t.name.clone()))
+                    })
+                    .unwrap_or_else(|| data.tileset.filename())
+            };
+            let scroll = |layer: Option<&TiledTileLayer>| {
+                let scroll = layer.and_then(|l| l.properties.get("scroll")).and_then(|s| s.parse::<u8>().ok());
+                PxPackScroll::from(scroll.unwrap_or(0))
+            };
+
+            let attributes = self.layer_tileset(fg).map(|t| t.attributes()).transpose()?.flatten();
+
+            let pxpack_data = PxPackStageData {
+                tileset_fg: tileset_name(Some(fg)),
+                tileset_mg: tileset_name(mg),
+                tileset_bg: tileset_name(bg),
+                scroll_fg: scroll(Some(fg)),
+                scroll_mg: scroll(mg),
+                scroll_bg: scroll(bg),
+                size_fg,
+                size_mg,
+                size_bg,
+                offset_mg,
+                offset_bg,
+                map_left: self.properties.get("map_left").cloned().unwrap_or_default(),
+                map_right: self.properties.get("map_right").cloned().unwrap_or_default(),
+                map_up: self.properties.get("map_up").cloned().unwrap_or_default(),
+                map_down: self.properties.get("map_down").cloned().unwrap_or_default(),
+                spritesheet: self.properties.get("spritesheet").cloned().unwrap_or_default(),
+                background_color: data.background_color,
+                units: Vec::new(),
+            };
+            data.pxpack_data = Some(pxpack_data);
+
+            let attrib = attributes.unwrap_or([0u8; 0x100]);
+            let map = Map { width: fg.width, height: fg.height, tiles, attrib, tile_size };
+
+            return Ok((map, attributes.is_some()));
+        }
+
+        if self.tile_layers.len() > 1 {
+            log::warn!("Tiled map has more than one tile layer, only the topmost one will be used.");
+        }
+
+        let layer = self.find_layer(&["fg", "foreground"]).unwrap_or(&self.tile_layers[self.tile_layers.len() - 1]);
+        let attributes = self.layer_tileset(layer).map(|t| t.attributes()).transpose()?.flatten();
+
+        let map = Map {
+            width: layer.width,
+            height: layer.height,
+            tiles: self.layer_tiles(layer)?,
+            attrib: attributes.unwrap_or([0u8; 0x100]),
+            tile_size,
+        };
+
+        Ok((map, attributes.is_some()))
+    }
+
+    /// Returns true if there's at least one object layer, used to decide whether a .pxe file should be loaded instead.
+    pub fn has_entities(&self) -> bool {
+        !self.object_layers.is_empty()
+    }
+
+    /// Converts objects from all object layers into PXE entities.
+    pub fn to_npc_data(&self) -> GameResult<Vec<NPCData>> {
+        let tile_width = self.tile_width.max(1) as f32;
+        let tile_height = self.tile_height.max(1) as f32;
+        let mut npcs = Vec::new();
+
+        for layer in self.object_layers.iter() {
+            for object in layer.objects.iter() {
+                let prop = |names: &[&str]| -> GameResult<u16> {
+                    match names.iter().find_map(|n| object.properties.get(*n)) {
+                        Some(value) => Ok(parse_int(value)? as u16),
+                        None => Ok(0),
+                    }
+                };
+
+                let npc_type = match object.properties.get("type").or_else(|| object.properties.get("npc_type")) {
+                    Some(value) => parse_int(value)? as u16,
+                    None if !object.obj_type.is_empty() => parse_int(&object.obj_type)? as u16,
+                    None => 0,
+                };
+
+                // tile objects are anchored at the bottom left corner
+                let y = if object.gid.is_some() { object.y - object.height } else { object.y };
+
+                npcs.push(NPCData {
+                    id: 170 + npcs.len() as u16,
+                    x: (object.x / tile_width).floor() as i16,
+                    y: (y / tile_height).floor() as i16,
+                    flag_num: prop(&["flag", "flag_num"])?,
+                    event_num: prop(&["event", "event_num"])?,
+                    npc_type,
+                    flags: prop(&["flags"])?,
+                    layer: prop(&["layer"])? as u8,
+                });
+            }
+        }
+
+        Ok(npcs)
+    }
+}
+
+fn parse_int(value: &str) -> GameResult<i64> {
+    let value = value.trim();
+    let result = if let Some(hex) = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16)
+    } else {
+        value.parse::<i64>()
+    };
+
+    result.map_err(|_| GameError::ParseError(format!("Invalid integer value: {:?}", value)))
+}
+
+fn json_u32(value: &Value) -> GameResult<u32> {
+    value
+        .as_u64()
+        .map(|v| v as u32)
+        .ok_or_else(|| GameError::ParseError(format!("Expected an unsigned integer, got: {}", value)))
+}
+
+fn json_properties(value: &Value) -> HashMap<String, String> {
+    let mut properties = HashMap::new();
+
+    for property in value.as_array().into_iter().flatten() {
+        if let Some(name) = property["name"].as_str() {
+            let value = match &property["value"] {
+                Value::String(s) => s.clone(),
+                Value::Null => String::new(),
+                other => other.to_string(),
+            };
+
+            properties.insert(name.to_string(), value);
+        }
+    }
+
+    properties
+}
+
+fn json_tileset(tileset: &Value) -> TiledTileset {
+    let mut tile_properties = HashMap::new();
+
+    for tile in tileset["tiles"].as_array().into_iter().flatten() {
+        if let Some(id) = tile["id"].as_u64() {
+            tile_properties.insert(id as u32, json_properties(&tile["properties"]));
+        }
+    }
+
+    TiledTileset {
+        first_gid: 0,
+        name: tileset["name"].as_str().unwrap_or("").to_string(),
+        tile_count: tileset["tilecount"].as_u64().unwrap_or(0) as u32,
+        properties: json_properties(&tileset["properties"]),
+        tile_properties,
+    }
+}
+
+fn xml_properties(element: &XmlElement) -> HashMap<String, String> {
+    let mut properties = HashMap::new();
+
+    if let Some(props) = element.child("properties") {
+        for property in props.children_named("property") {
+            let value = if property.attributes.contains_key("value") { property.attr("value") } else { &property.text };
+            properties.insert(property.attr("name").to_string(), value.to_string());
+        }
+    }
+
+    properties
+}
+
+fn xml_tileset(tileset: &XmlElement) -> GameResult<TiledTileset> {
+    let mut tile_properties = HashMap::new();
+
+    for tile in tileset.children_named("tile") {
+        tile_properties.insert(parse_int(tile.attr("id"))? as u32, xml_properties(tile));
+    }
+
+    Ok(TiledTileset {
+        first_gid: 0,
+        name: tileset.attr("name").to_string(),
+        tile_count: tileset.attr("tilecount").parse().unwrap_or(0),
+        properties: xml_properties(tileset),
+        tile_properties,
+    })
+}
+
+fn load_external_tileset(source: &str, dir: &str, ctx: &mut Context) -> GameResult<TiledTileset> {
+    let path = [dir, source].join("");
+    let mut file = filesystem::open(ctx, &path)?;
+
+    if source.to_lowercase().ends_with(".tsx") {
+        let mut text = String::new();
+        io::Read::read_to_string(&mut file, &mut text)?;
+
+        xml_tileset(&XmlElement::parse(&text)?)
+    } else {
+        let value: Value = serde_json::from_reader(file)?;
+
+        Ok(json_tileset(&value))
+    }
+}
+
+fn decode_gids(data: &str, encoding: &str, compression: &str) -> GameResult<Vec<u32>> {
+    match (encoding, compression) {
+        ("csv", _) => data
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(|s| s.parse::<u32>().map_err(|_| GameError::ParseError(format!("Invalid tile value: {:?}", s))))
+            .collect(),
+        ("base64", "") => {
+            let bytes = decode_base64(data)?;
+
+            Ok(bytes.chunks_exact(4).map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect())
+        }
+        ("base64", compression) => Err(GameError::ResourceLoadError(format!(
+            "Compressed Tiled layers ({}) are not supported, please use CSV or uncompressed Base64.",
+            compression
+        ))),
+        (encoding, _) => Err(GameError::ResourceLoadError(format!("Unsupported tile layer encoding: {}", encoding))),
+    }
+}
+
+fn decode_base64(data: &str) -> GameResult<Vec<u8>> {
+    let mut result = Vec::with_capacity(data.len() * 3 / 4);
+    let mut acc = 0u32;
+    let mut bits = 0;
+
+    for c in data.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            c if c.is_ascii_whitespace() => continue,
+            _ => return Err(GameError::ParseError("Invalid Base64 data.".to_string())),
+        };
+
+        acc = (acc << 6) | value as u32;
+        bits += 6;
+
+        if bits >= 8 {
+            bits -= 8;
+            result.push((acc >> bits) as u8);
+        }
+    }
+
+    Ok(result)
+}
+
+/// Bare minimum XML DOM, just enough to read files written by Tiled.
+#[derive(Debug, Clone, Default)]
+struct XmlElement {
+    name: String,
+    attributes: HashMap<String, String>,
+    children: Vec<XmlElement>,
+    text: String,
+}
+
+impl XmlElement {
+    fn attr(&self, name: &str) -> &str {
+        self.attributes.get(name).map(|s| s.as_str()).unwrap_or("")
+    }
+
+    fn child(&self, name: &str) -> Option<&XmlElement> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a XmlElement> {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+
+    fn parse(text: &str) -> GameResult<XmlElement> {
+        let mut stack: Vec<XmlElement> = Vec::new();
+        let mut root = None;
+        let mut rest = text;
+        let error = |msg: &str| GameError::ParseError(format!("XML parse error: {}", msg));
+
+        while !rest.is_empty() {
+            if let Some(stripped) = rest.strip_prefix("<!--") {
+                let end = stripped.find("-->").ok_or_else(|| error("unterminated comment"))?;
+                rest = &stripped[end + 3..];
+            } else if let Some(stripped) = rest.strip_prefix("<![CDATA[") {
+                let end = stripped.find("]]>").ok_or_else(|| error("unterminated CDATA"))?;
+                if let Some(current) = stack.last_mut() {
+                    current.text.push_str(&stripped[..end]);
+                }
+                rest = &stripped[end + 3..];
+            } else if rest.starts_with("<?") || rest.starts_with("<!") {
+                let end = rest.find('>').ok_or_else(|| error("unterminated declaration"))?;
+                rest = &rest[end + 1..];
+            } else if let Some(stripped) = rest.strip_prefix("</") {
+                let end = stripped.find('>').ok_or_else(|| error("unterminated closing tag"))?;
+                let name = stripped[..end].trim();
+                let element = stack.pop().ok_or_else(|| error("unexpected closing tag"))?;
+                if element.name != name {
+                    return Err(error(&format!("mismatched closing tag </{}>", name)));
+                }
+
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => root = Some(element),
+                }
+                rest = &stripped[end + 1..];
+            } else if let Some(stripped) = rest.strip_prefix('<') {
+                let end = find_tag_end(stripped).ok_or_else(|| error("unterminated tag"))?;
+                let mut tag = &stripped[..end];
+                let self_closing = tag.ends_with('/');
+                if self_closing {
+                    tag = &tag[..tag.len() - 1];
+                }
+
+                let name_end = tag.find(|c: char| c.is_whitespace()).unwrap_or_else(|| tag.len());
+                let mut element = XmlElement { name: tag[..name_end].to_string(), ..Default::default() };
+                let mut attrs = tag[name_end..].trim_start();
+
+                while !attrs.is_empty() {
+                    let eq = attrs.find('=').ok_or_else(|| error("invalid attribute"))?;
+                    let key = attrs[..eq].trim().to_string();
+                    let value_part = attrs[eq + 1..].trim_start();
+                    let quote = value_part.chars().next().ok_or_else(|| error("invalid attribute"))?;
+                    if quote != '"' && quote != '\'' {
+                        return Err(error("attribute value is not quoted"));
+                    }
+                    let value_end = value_part[1..].find(quote).ok_or_else(|| error("unterminated attribute"))?;
+                    element.attributes.insert(key, unescape(&value_part[1..value_end + 1]));
+                    attrs = value_part[value_end + 2..].trim_start();
+                }
+
+                if self_closing {
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(element),
+                        None => root = Some(element),
+                    }
+                } else {
+                    stack.push(element);
+                }
+                rest = &stripped[end + 1..];
+            } else {
+                let end = rest.find('<').unwrap_or_else(|| rest.len());
+                if let Some(current) = stack.last_mut() {
+                    current.text.push_str(&unescape(&rest[..end]));
+                }
+                rest = &rest[end..];
+            }
+        }
+
+        if !stack.is_empty() {
+            return Err(error("unclosed tags at the end of document"));
+        }
+
+        root.ok_or_else(|| error("no root element"))
+    }
+}
+
+fn find_tag_end(tag: &str) -> Option<usize> {
+    let mut quote = None;
+
+    for (i, c) in tag.char_indices() {
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '>') => return Some(i),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn unescape(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = match rest.find(';') {
+            Some(end) => end,
+            None => break,
+        };
+
+        let entity = &rest[1..end];
+        let chr = match entity {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ if entity.starts_with("#x") => u32::from_str_radix(&entity[2..], 16).ok().and_then(std::char::from_u32),
+            _ if entity.starts_with('#') => entity[1..].parse::<u32>().ok().and_then(std::char::from_u32),
+            _ => None,
+        };
+
+        match chr {
+            Some(chr) => {
+                result.push(chr);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::common::Color;
+    use crate::stage::{Background, BackgroundType, NpcType, Tileset};
+
+    use super::*;
+
+    fn stage_data() -> StageData {
+        StageData {
+            name: "Test".to_string(),
+            map: "Test".to_string(),
+            boss_no: 0,
+            tileset: Tileset::new("Cave"),
+            pxpack_data: None,
+            background: Background::new("bk0"),
+            background_type: BackgroundType::Black,
+            background_color: Color::from_rgb(0, 0, 0),
+            npc1: NpcType::new("0"),
+            npc2: NpcType::new("0"),
+        }
+    }
+
+    fn tmx(data: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.8" orientation="orthogonal" width="2" height="2" tilewidth="16" tileheight="16" infinite="0">
+ <tileset firstgid="1" name="Cave" tilecount="256"/>
+ <layer id="1" name="fg" width="2" height="2">
+  {}
+ </layer>
+</map>"#,
+            data
+        )
+    }
+
+    fn load_tmx(text: &str) -> GameResult<TiledMap> {
+        TiledMap::load_tmx(Cursor::new(text.as_bytes()), "", &mut Context::new())
+    }
+
+    fn load_tmj(text: &str) -> GameResult<TiledMap> {
+        TiledMap::load_tmj(Cursor::new(text.as_bytes()), "", &mut Context::new())
+    }
+
+    #[test]
+    fn decode_base64_handles_padding_and_whitespace() {
+        assert_eq!(decode_base64("TWFu").unwrap(), b"Man");
+        assert_eq!(decode_base64("TWE=").unwrap(), b"Ma");
+        assert_eq!(decode_base64("TQ==").unwrap(), b"M");
+        assert_eq!(decode_base64("\n  TWFu\n  TWE=\n").unwrap(), b"ManMa");
+        assert_eq!(decode_base64("").unwrap(), b"");
+        assert!(decode_base64("TW*u").is_err());
+    }
+
+    #[test]
+    fn decode_gids_reads_csv_and_base64() {
+        assert_eq!(decode_gids("1,2,\n0,3\n", "csv", "").unwrap(), vec![1, 2, 0, 3]);
+        assert_eq!(decode_gids("AQAAAAIAAAAAAAAAAwAAAA==", "base64", "").unwrap(), vec![1, 2, 0, 3]);
+        assert!(decode_gids("1,x", "csv", "").is_err());
+        assert!(decode_gids("AQAAAA==", "base64", "zlib").is_err());
+        assert!(decode_gids("", "hex", "").is_err());
+    }
+
+    #[test]
+    fn xml_parses_elements_attributes_and_text() {
+        let root = XmlElement::parse(
+            r#"<?xml version="1.0"?>
+<!-- comment <with> tags -->
+<map a="1" b='two &amp; &#x33;'>
+ <empty/>
+ <item name="x">some &lt;text&gt;</item>
+ <item name="y"><![CDATA[<raw>]]></item>
+</map>"#,
+        )
+        .unwrap();
+
+        assert_eq!(root.name, "map");
+        assert_eq!(root.attr("a"), "1");
+        assert_eq!(root.attr("b"), "two & 3");
+        assert_eq!(root.attr("missing"), "");
+        assert!(root.child("empty").is_some());
+
+        let items: Vec<&XmlElement> = root.children_named("item").collect();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].attr("name"), "x");
+        assert_eq!(items[0].text, "some <text>");
+        assert_eq!(items[1].text, "<raw>");
+    }
+
+    #[test]
+    fn xml_rejects_malformed_documents() {
+        assert!(XmlElement::parse("<a><b></a>").is_err());
+        assert!(XmlElement::parse("<a>").is_err());
+        assert!(XmlElement::parse("</a>").is_err());
+        assert!(XmlElement::parse("<a b=c/>").is_err());
+        assert!(XmlElement::parse("just text").is_err());
+    }
+
+    #[test]
+    fn tmx_csv_layer() {
+        let map = load_tmx(&tmx("<data encoding=\"csv\">2,3,\n0,257</data>")).unwrap();
+        let (map, has_attributes) = map.to_map(&mut stage_data()).unwrap();
+
+        assert_eq!((map.width, map.height), (2, 2));
+        // 256 doesn't fit in a byte, out of range tiles are replaced with tile 0
+        assert_eq!(map.tiles, vec![1, 2, 0, 0]);
+        assert!(!has_attributes);
+    }
+
+    #[test]
+    fn tmx_xml_layer() {
+        let map = load_tmx(&tmx(r#"<data><tile gid="5"/><tile/><tile gid="1"/><tile gid="2"/></data>"#)).unwrap();
+        let (map, _) = map.to_map(&mut stage_data()).unwrap();
+
+        assert_eq!(map.tiles, vec![4, 0, 0, 1]);
+    }
+
+    #[test]
+    fn tmx_base64_layer() {
+        let map = load_tmx(&tmx(r#"<data encoding="base64">
+   AQAAAAIAAAAAAAAAAwAAAA==
+  </data>"#))
+        .unwrap();
+        let (map, _) = map.to_map(&mut stage_data()).unwrap();
+
+        assert_eq!(map.tiles, vec![0, 1, 0, 2]);
+    }
+
+    #[test]
+    fn tmj_array_and_base64_layers() {
+        let map = load_tmj(
+            r#"{
+                "width": 2, "height": 2, "tilewidth": 16, "tileheight": 16, "infinite": false,
+                "tilesets": [{ "firstgid": 1, "name": "Cave", "tilecount": 256 }],
+                "layers": [
+                    { "type": "tilelayer", "name": "bg", "width": 2, "height": 2, "data": [1, 1, 1, 1] },
+                    { "type": "tilelayer", "name": "mg", "width": 2, "height": 2, "data": [0, 2, 0, 2] },
+                    {
+                        "type": "tilelayer", "name": "fg", "width": 2, "height": 2,
+                        "encoding": "base64", "data": "AQAAAAIAAAAAAAAAAwAAAA=="
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let mut data = stage_data();
+        let (map, _) = map.to_map(&mut data).unwrap();
+
+        assert_eq!(map.tiles, vec![0, 1, 0, 2, 0, 1, 0, 1, 0, 0, 0, 0]);
+
+        let pxpack_data = data.pxpack_data.unwrap();
+        assert_eq!((pxpack_data.offset_mg, pxpack_data.offset_bg), (4, 8));
+    }
+
+    #[test]
+    fn mismatched_layer_size_is_rejected() {
+        let truncated = load_tmx(&tmx(r#"<data encoding="csv">1,2,3</data>"#)).unwrap();
+        assert!(truncated.to_map(&mut stage_data()).is_err());
+
+        let oversized = load_tmx(&tmx(r#"<data encoding="csv">1,2,3,4,5</data>"#)).unwrap();
+        assert!(oversized.to_map(&mut stage_data()).is_err());
+    }
+
+    fn npc_fields(npc: &NPCData) -> (u16, i16, i16, u16, u16, u16, u16, u8) {
+        (npc.id, npc.x, npc.y, npc.flag_num, npc.event_num, npc.npc_type, npc.flags, npc.layer)
+    }
+
+    #[test]
+    fn tmx_objects_become_npcs() {
+        let map = load_tmx(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.8" orientation="orthogonal" width="4" height="4" tilewidth="16" tileheight="16" infinite="0">
+ <objectgroup id="2" name="npcs">
+  <object id="1" x="48" y="16">
+   <properties>
+    <property name="type" type="int" value="16"/>
+    <property name="flag" value="0x1F4"/>
+    <property name="event" value="200"/>
+    <property name="flags" value="0x8100"/>
+    <property name="layer" value="1"/>
+   </properties>
+  </object>
+  <object id="2" class="211" x="8.5" y="40"/>
+ </objectgroup>
+ <group id="3" name="nested">
+  <objectgroup id="4" name="tiles">
+   <object id="3" type="18" gid="5" x="32" y="64" width="16" height="32">
+    <properties>
+     <property name="event_num" value="100"/>
+    </properties>
+   </object>
+  </objectgroup>
+ </group>
+</map>"#,
+        )
+        .unwrap();
+
+        assert!(map.has_entities());
+
+        let npcs: Vec<_> = map.to_npc_data().unwrap().iter().map(npc_fields).collect();
+        assert_eq!(
+            npcs,
+            vec![
+                (170, 3, 1, 500, 200, 16, 0x8100, 1),
+                // object class is used when there's no type property, positions are floored to the tile grid
+                (171, 0, 2, 0, 0, 211, 0, 0),
+                // tile objects are anchored at their bottom left corner
+                (172, 2, 2, 0, 100, 18, 0, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_npc_properties_are_rejected() {
+        let map = load_tmx(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.8" orientation="orthogonal" width="1" height="1" tilewidth="16" tileheight="16" infinite="0">
+ <objectgroup id="1" name="npcs">
+  <object id="1" type="door" x="0" y="0"/>
+ </objectgroup>
+</map>"#,
+        )
+        .unwrap();
+
+        assert!(map.to_npc_data().is_err());
+
+        let map = load_tmx(&tmx(r#"<data encoding="csv">1,1,1,1</data>"#)).unwrap();
+        assert!(!map.has_entities());
+        assert!(map.to_npc_data().unwrap().is_empty());
+    }
+
+    #[test]
+    fn tmx_tileset_attributes() {
+        let map = load_tmx(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.8" orientation="orthogonal" width="2" height="1" tilewidth="16" tileheight="16" infinite="0">
+ <tileset firstgid="1" name="Cave" tilecount="256">
+  <properties>
+   <property name="attributes">0, 0x41, 0x41
+0x43</property>
+  </properties>
+  <tile id="2">
+   <properties>
+    <property name="attribute" value="0x10"/>
+   </properties>
+  </tile>
+  <tile id="5">
+   <properties>
+    <property name="attribute" value="0x50"/>
+   </properties>
+  </tile>
+ </tileset>
+ <layer id="1" name="fg" width="2" height="1">
+  <data encoding="csv">2,6</data>
+ </layer>
+</map>"#,
+        )
+        .unwrap();
+
+        let (map, has_attributes) = map.to_map(&mut stage_data()).unwrap();
+
+        assert!(has_attributes);
+        // per-tile attributes take precedence over the list
+        assert_eq!(map.attrib[..6], [0x00, 0x41, 0x10, 0x43, 0x00, 0x50]);
+        assert!(map.attrib[6..].iter().all(|&a| a == 0));
+    }
+
+    #[test]
+    fn tmj_tileset_attributes() {
+        let map = load_tmj(
+            r#"{
+                "width": 1, "height": 1, "tilewidth": 16, "tileheight": 16, "infinite": false,
+                "tilesets": [{
+                    "firstgid": 1, "name": "Cave", "tilecount": 256,
+                    "tiles": [
+                        { "id": 1, "properties": [{ "name": "attribute", "type": "int", "value": 65 }] },
+                        { "id": 300, "properties": [{ "name": "attribute", "type": "int", "value": 65 }] },
+                        { "id": 4, "properties": [{ "name": "unrelated", "type": "bool", "value": true }] }
+                    ]
+                }],
+                "layers": [{ "type": "tilelayer", "name": "fg", "width": 1, "height": 1, "data": [2] }]
+            }"#,
+        )
+        .unwrap();
+
+        let (map, has_attributes) = map.to_map(&mut stage_data()).unwrap();
+
+        assert!(has_attributes);
+        assert_eq!(map.tiles, vec![1]);
+        assert_eq!(map.attrib[1], 0x41);
+        // tiles past the 256 PXA entries are ignored
+        assert_eq!(map.attrib.iter().filter(|&&a| a != 0).count(), 1);
+    }
+
+    #[test]
+    fn invalid_tileset_attributes_are_rejected() {
+        let tileset = TiledTileset {
+            properties: [("attributes".to_string(), "0,1,solid".to_string())].iter().cloned().collect(),
+            ..TiledTileset::default()
+        };
+
+        assert!(tileset.attributes().is_err());
+        assert_eq!(TiledTileset::default().attributes().unwrap(), None);
+    }
+}
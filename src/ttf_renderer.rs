@@ -0,0 +1,221 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use fontdue::{Font, FontSettings};
+
+use crate::common::Rect;
+use crate::framework::backend::{BackendTexture, SpriteBatchCommand};
+use crate::framework::context::Context;
+use crate::framework::error::GameError::ResourceLoadError;
+use crate::framework::error::GameResult;
+use crate::framework::filesystem;
+use crate::framework::graphics::create_texture;
+use crate::texture_atlas::ShelfPacker;
+use crate::texture_set::I_MAG;
+
+const GLYPH_PAGE_SIZE: u16 = 1024;
+/// Glyph caches are kept for this many scales, the least recently used one is dropped after that.
+const MAX_CACHED_SCALES: usize = 3;
+
+#[derive(Clone, Copy)]
+struct Glyph {
+    /// Page the glyph bitmap is stored in, None for glyphs without one, like spaces.
+    page: Option<usize>,
+    /// Area of the page the bitmap occupies, in texture pixels.
+    src: Rect<f32>,
+    /// Position of the bitmap relative to the top left corner of the line, in canvas pixels.
+    offset: (f32, f32),
+    /// Size of the bitmap in canvas pixels.
+    size: (f32, f32),
+}
+
+struct GlyphPage {
+    texture: Box<dyn BackendTexture>,
+    packer: ShelfPacker,
+    dirty: bool,
+}
+
+/// Bitmaps of glyphs rasterized so far at given scale.
+struct GlyphCache {
+    mag: f32,
+    pages: Vec<GlyphPage>,
+    glyphs: HashMap<char, Glyph>,
+}
+
+impl GlyphCache {
+    fn new(mag: f32) -> GlyphCache {
+        GlyphCache { mag, pages: Vec::new(), glyphs: HashMap::new() }
+    }
+
+    fn glyph(&mut self, ctx: &mut Context, face: &Font, chr: char, size: f32, ascent: f32) -> GameResult<Glyph> {
+        if let Some(glyph) = self.glyphs.get(&chr) {
+            return Ok(*glyph);
+        }
+
+        let (metrics, coverage) = face.rasterize(chr, size * self.mag);
+        let (width, height) = (metrics.width as u16, metrics.height as u16);
+        let mut glyph = Glyph {
+            page: None,
+            src: Rect::new(0.0, 0.0, 0.0, 0.0),
+            offset: (metrics.xmin as f32 / self.mag, ascent - (metrics.ymin as f32 + metrics.height as f32) / self.mag),
+            size: (width as f32 / self.mag, height as f32 / self.mag),
+        };
+
+        if width != 0 && height != 0 {
+            let mut data = Vec::with_capacity(coverage.len() * 4);
+            for &alpha in coverage.iter() {
+                data.extend_from_slice(&[255, 255, 255, alpha]);
+            }
+
+            if let Some((page, x, y)) = self.pack(ctx, width, height)? {
+                self.pages[page].texture.update(x, y, width, height, &data)?;
+                glyph.page = Some(page);
+                glyph.src = Rect::new(x as f32, y as f32, (x + width) as f32, (y + height) as f32);
+            } else {
+                log::warn!("Glyph for {:?} doesn't fit in the glyph atlas.", chr);
+            }
+        }
+
+        self.glyphs.insert(chr, glyph);
+        Ok(glyph)
+    }
+
+    fn pack(&mut self, ctx: &mut Context, width: u16, height: u16) -> GameResult<Option<(usize, u16, u16)>> {
+        for (idx, page) in self.pages.iter_mut().enumerate() {
+            if let Some((x, y)) = page.packer.pack(width, height) {
+                return Ok(Some((idx, x, y)));
+            }
+        }
+
+        let empty = vec![0u8; GLYPH_PAGE_SIZE as usize * GLYPH_PAGE_SIZE as usize * 4];
+        let mut page = GlyphPage {
+            texture: create_texture(ctx, GLYPH_PAGE_SIZE, GLYPH_PAGE_SIZE, &empty)?,
+            packer: ShelfPacker::new(GLYPH_PAGE_SIZE, GLYPH_PAGE_SIZE),
+            dirty: false,
+        };
+
+        let pos = page.packer.pack(width, height);
+        self.pages.push(page);
+
+        Ok(pos.map(|(x, y)| (self.pages.len() - 1, x, y)))
+    }
+}
+
+/// Draws text using TrueType/OpenType fonts, rasterizing glyphs into an atlas the first time they're used.
+/// Characters missing in the first font are looked up in following ones.
+pub struct TTFFontRenderer {
+    faces: Vec<Font>,
+    /// Font size in canvas pixels.
+    size: f32,
+    ascent: f32,
+    line_height: f32,
+    /// Caches of recently used scales, most recently used first.
+    caches: RefCell<Vec<GlyphCache>>,
+}
+
+impl TTFFontRenderer {
+    /// Loads fonts from given paths, skipping ones that fail to load as long as at least one of them doesn't.
+    pub fn load(root: &str, paths: &[String], size: f32, ctx: &mut Context) -> GameResult<TTFFontRenderer> {
+        let root = PathBuf::from(root);
+        let mut faces = Vec::new();
+
+        for path in paths.iter() {
+            match TTFFontRenderer::load_face(&root.join(path), size, ctx) {
+                Ok(face) => faces.push(face),
+                Err(err) => log::warn!("Failed to load font {}: {}", path, err),
+            }
+        }
+
+        let metrics = faces
+            .first()
+            .ok_or_else(|| ResourceLoadError(format!("None of the fonts could be loaded: {:?}", paths)))?
+            .horizontal_line_metrics(size);
+        let (ascent, line_height) = metrics.map_or((size, size), |m| (m.ascent.round(), m.new_line_size.round()));
+
+        Ok(TTFFontRenderer { faces, size, ascent, line_height, caches: RefCell::new(Vec::new()) })
+    }
+
+    fn load_face(path: &Path, size: f32, ctx: &mut Context) -> GameResult<Font> {
+        let mut data = Vec::new();
+        filesystem::open(ctx, path)?.read_to_end(&mut data)?;
+
+        Font::from_bytes(data, FontSettings { scale: size, ..FontSettings::default() })
+            .map_err(|err| ResourceLoadError(err.to_string()))
+    }
+
+    /// Returns the first font containing given character, or the first one which draws its placeholder glyph.
+    fn face_for(&self, chr: char) -> &Font {
+        self.faces.iter().find(|face| face.lookup_glyph_index(chr) != 0).unwrap_or(&self.faces[0])
+    }
+
+    fn advance(&self, chr: char) -> f32 {
+        self.face_for(chr).metrics(chr, self.size).advance_width.round()
+    }
+
+    pub fn line_height(&self) -> f32 {
+        self.line_height
+    }
+
+    pub fn text_width<I: Iterator<Item = char>>(&self, iter: I) -> f32 {
+        iter.map(|chr| self.advance(chr)).sum()
+    }
+
+    pub fn draw_colored_text_scaled<I: Iterator<Item = char>>(
+        &self,
+        iter: I,
+        x: f32,
+        y: f32,
+        scale: f32,
+        color: (u8, u8, u8, u8),
+        ctx: &mut Context,
+    ) -> GameResult {
+        let mag = unsafe { I_MAG };
+        let mut caches = self.caches.borrow_mut();
+
+        // glyphs are rasterized at whole multiples of the canvas resolution, so zooming the camera
+        // only switches between a few of them instead of rasterizing everything again every frame
+        let raster_mag = mag.ceil().max(1.0);
+        let cache = match caches.iter().position(|cache| cache.mag == raster_mag) {
+            Some(idx) => caches.remove(idx),
+            None => GlyphCache::new(raster_mag),
+        };
+        caches.insert(0, cache);
+        caches.truncate(MAX_CACHED_SCALES);
+        let cache = &mut caches[0];
+
+        let mut offset_x = x;
+        for chr in iter {
+            let glyph = cache.glyph(ctx, self.face_for(chr), chr, self.size, self.ascent)?;
+
+            if let Some(page) = glyph.page {
+                let left = offset_x + glyph.offset.0 * scale;
+                let top = y + glyph.offset.1 * scale;
+                let page = &mut cache.pages[page];
+
+                page.texture.add(SpriteBatchCommand::DrawRectTinted(
+                    glyph.src,
+                    Rect {
+                        left: left * mag,
+                        top: top * mag,
+                        right: (left + glyph.size.0 * scale) * mag,
+                        bottom: (top + glyph.size.1 * scale) * mag,
+                    },
+                    color.into(),
+                ));
+                page.dirty = true;
+            }
+
+            offset_x += self.advance(chr) * scale;
+        }
+
+        for page in cache.pages.iter_mut().filter(|page| page.dirty) {
+            page.texture.draw()?;
+            page.texture.clear();
+            page.dirty = false;
+        }
+
+        Ok(())
+    }
+}
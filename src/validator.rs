@@ -0,0 +1,293 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::framework::context::Context;
+use crate::framework::error::GameResult;
+use crate::framework::filesystem;
+use crate::npc::NPCTable;
+use crate::shared_game_state::SharedGameState;
+use crate::sound::SoundManager;
+use crate::stage::{Stage, StageData};
+
+/// A single problem found in game data, `subject` names the stage, song or table entry it refers to.
+pub struct ValidationError {
+    pub subject: String,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.subject, self.message)
+    }
+}
+
+/// Cross-checks the stage table, music table and NPC table against mounted data files,
+/// so broken references are reported up front instead of when the player enters a room.
+pub struct AssetValidator<'a> {
+    state: &'a SharedGameState,
+    errors: Vec<ValidationError>,
+}
+
+impl<'a> AssetValidator<'a> {
+    pub fn new(state: &'a SharedGameState) -> AssetValidator<'a> {
+        AssetValidator { state, errors: Vec::new() }
+    }
+
+    pub fn validate(mut self, ctx: &mut Context) -> GameResult<Vec<ValidationError>> {
+        let root = self.state.base_path.clone();
+
+        let stages = StageData::load_stage_table(ctx, &root)?;
+        for (id, stage) in stages.iter().enumerate() {
+            self.validate_stage(id, stage, ctx);
+        }
+
+        self.validate_music(ctx);
+
+        match filesystem::open(ctx, [&root, "npc.tbl"].join("")).and_then(NPCTable::load_from) {
+            Ok(npc_table) => self.validate_npc_table(&npc_table, ctx),
+            Err(err) => self.error("npc.tbl", format!("Failed to load NPC table: {}", err)),
+        }
+
+        Ok(self.errors)
+    }
+
+    fn error(&mut self, subject: &str, message: String) {
+        self.errors.push(ValidationError { subject: subject.to_owned(), message });
+    }
+
+    fn check_texture(&mut self, subject: &str, name: &str, ctx: &Context) {
+        if self.state.texture_set.find_texture(ctx, name).is_none() {
+            self.error(subject, format!("Texture {} does not exist.", name));
+        }
+    }
+
+    fn validate_stage(&mut self, id: usize, data: &StageData, ctx: &mut Context) {
+        // the stage table usually contains a few unused, empty entries
+        if data.map.is_empty() {
+            return;
+        }
+
+        let subject = format!("Stage {} ({})", id, data.map);
+        let root = self.state.base_path.clone();
+
+        // loading the stage fills in PxPack data, so textures are checked against the loaded copy
+        let stage = match Stage::load(&root, data, ctx) {
+            Ok(stage) => Some(stage),
+            Err(err) => {
+                self.error(&subject, format!("Failed to load map: {}", err));
+                None
+            }
+        };
+
+        if let Some(stage) = stage.as_ref() {
+            if let Err(err) = stage.load_npcs(&root, &self.state.constants, ctx) {
+                self.error(&subject, format!("Failed to load entities: {}", err));
+            }
+
+            if let Err(err) = stage.load_text_script(&root, &self.state.constants, ctx) {
+                self.error(&subject, format!("Failed to load text script: {}", err));
+            }
+        }
+
+        let data = stage.as_ref().map(|stage| &stage.data).unwrap_or(data);
+
+        if let Some(pxpack_data) = data.pxpack_data.as_ref() {
+            for tileset in [&pxpack_data.tileset_fg, &pxpack_data.tileset_mg, &pxpack_data.tileset_bg].iter() {
+                self.check_texture(&subject, &["Stage/", tileset.as_str()].join(""), ctx);
+            }
+        } else {
+            self.check_texture(&subject, &["Stage/", &data.tileset.filename()].join(""), ctx);
+        }
+
+        if !data.background.name().is_empty() {
+            self.check_texture(&subject, &data.background.filename(), ctx);
+        }
+
+        self.check_texture(&subject, &["Npc/", &data.npc1.filename()].join(""), ctx);
+        self.check_texture(&subject, &["Npc/", &data.npc2.filename()].join(""), ctx);
+    }
+
+    fn validate_music(&mut self, ctx: &mut Context) {
+        let state = self.state;
+
+        // song 0 is reserved for stopping the music
+        for (id, song_name) in state.constants.music_table.iter().enumerate().skip(1) {
+            if SoundManager::find_song_files(song_name, &state.constants, &state.settings, ctx).is_none() {
+                self.error(
+                    &format!("Song {} ({})", id, song_name),
+                    "Not found in any of the soundtrack search paths.".to_owned(),
+                );
+            }
+        }
+    }
+
+    fn validate_npc_table(&mut self, npc_table: &NPCTable, ctx: &Context) {
+        // group NPC types by texture, so a missing sheet is reported once
+        let mut textures = BTreeMap::<&str, Vec<usize>>::new();
+
+        for (npc_type, entry) in npc_table.entries().iter().enumerate() {
+            match entry.spritesheet_id {
+                // tileset and stage NPC sheets, checked per stage
+                2 | 21 | 22 => {}
+                id => textures.entry(npc_table.get_texture_name(id as u16)).or_default().push(npc_type),
+            }
+        }
+
+        for (name, npc_types) in textures {
+            if self.state.texture_set.find_texture(ctx, name).is_none() {
+                self.error("npc.tbl", format!("Texture {} used by NPC types {:?} does not exist.", name, npc_types));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use crate::builtin_fs::BuiltinFS;
+    use crate::framework::filesystem::{mount_user_vfs, mount_vfs};
+    use crate::framework::vfs::PhysicalFS;
+    use crate::sound::{AudioClock, AudioOutput};
+
+    use super::*;
+
+    fn copy_dir(from: &Path, to: &Path) {
+        fs::create_dir_all(to).unwrap();
+
+        for entry in fs::read_dir(from).unwrap() {
+            let entry = entry.unwrap();
+            let target = to.join(entry.file_name());
+
+            if entry.file_type().unwrap().is_dir() {
+                copy_dir(&entry.path(), &target);
+            } else {
+                fs::copy(entry.path(), target).unwrap();
+            }
+        }
+    }
+
+    /// Validates a copy of the data in `tests/fixtures/data` after `modify` had a chance to break it.
+    /// The music table is replaced with `songs`, the default one refers to songs the fixture doesn't have.
+    fn validate(name: &str, songs: &[&str], modify: impl FnOnce(&Path)) -> Vec<String> {
+        let dir = env::temp_dir().join("doukutsu-rs-validator").join(name);
+        let _ = fs::remove_dir_all(&dir);
+
+        let data_dir = dir.join("data");
+        let user_dir = dir.join("user");
+        copy_dir(&PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join("data"), &data_dir);
+        fs::create_dir_all(data_dir.join("org")).unwrap();
+        fs::write(data_dir.join("org").join("fixture.org"), b"Org-02").unwrap();
+        fs::create_dir_all(&user_dir).unwrap();
+        modify(&data_dir);
+
+        let mut ctx = Context::new();
+        mount_vfs(&mut ctx, Box::new(BuiltinFS::new()));
+        mount_vfs(&mut ctx, Box::new(PhysicalFS::new(&data_dir, true)));
+        mount_user_vfs(&mut ctx, Box::new(PhysicalFS::new(&user_dir, false)));
+
+        let mut state = SharedGameState::with_audio_output(AudioOutput::Null(AudioClock::Simulated), &mut ctx).unwrap();
+        state.constants.music_table = songs.iter().map(|song| song.to_string()).collect();
+
+        let errors = AssetValidator::new(&state).validate(&mut ctx).unwrap();
+        errors.iter().map(|error| error.to_string()).collect()
+    }
+
+    fn remove(path: &'static str) -> impl FnOnce(&Path) {
+        move |dir: &Path| fs::remove_file(dir.join(path)).unwrap()
+    }
+
+    fn assert_reported(errors: &[String], expected: &str) {
+        assert!(errors.iter().any(|error| error.starts_with(expected)), "{:?} not in {:?}", expected, errors);
+    }
+
+    #[test]
+    fn valid_data_has_no_errors() {
+        let errors = validate("valid", &["", "fixture"], |_| {});
+
+        assert!(errors.is_empty(), "{:?}", errors);
+    }
+
+    #[test]
+    fn missing_map() {
+        let errors = validate("missing_map", &[], remove("Stage/Fixture.pxm"));
+
+        assert_reported(&errors, "Stage 0 (Fixture): Failed to load map:");
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+    }
+
+    #[test]
+    fn missing_entities() {
+        let errors = validate("missing_entities", &[], remove("Stage/Fixture.pxe"));
+
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert_reported(&errors, "Stage 0 (Fixture): Failed to load entities:");
+    }
+
+    #[test]
+    fn missing_text_script() {
+        let errors = validate("missing_text_script", &[], remove("Stage/Fixture.tsc"));
+
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert_reported(&errors, "Stage 0 (Fixture): Failed to load text script:");
+    }
+
+    #[test]
+    fn missing_stage_textures() {
+        let errors = validate("missing_stage_textures", &[], |dir| {
+            for path in ["Stage/PrtFixture.png", "bkFixture.png"].iter() {
+                fs::remove_file(dir.join(path)).unwrap();
+            }
+        });
+
+        assert_eq!(
+            errors,
+            vec![
+                "Stage 0 (Fixture): Texture Stage/PrtFixture does not exist.".to_string(),
+                "Stage 0 (Fixture): Texture bkFixture does not exist.".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_stage_npc_sheet() {
+        // both NPC sheets of the fixture stage are Npc0, it's also the fallback of unknown NPC table sheets
+        let errors = validate("missing_stage_npc_sheet", &[], remove("Npc/Npc0.png"));
+
+        assert_eq!(
+            errors,
+            vec![
+                "Stage 0 (Fixture): Texture Npc/Npc0 does not exist.".to_string(),
+                "Stage 0 (Fixture): Texture Npc/Npc0 does not exist.".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_song() {
+        let errors = validate("missing_song", &["", "fixture", "missing"], |_| {});
+
+        assert_eq!(errors, vec!["Song 2 (missing): Not found in any of the soundtrack search paths.".to_string()]);
+    }
+
+    #[test]
+    fn missing_npc_table() {
+        let errors = validate("missing_npc_table", &[], remove("npc.tbl"));
+
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert_reported(&errors, "npc.tbl: Failed to load NPC table:");
+    }
+
+    #[test]
+    fn missing_npc_table_texture() {
+        let errors = validate("missing_npc_table_texture", &[], remove("Npc/NpcSym.png"));
+
+        let npc_types: Vec<usize> = (0..20).collect();
+        assert_eq!(
+            errors,
+            vec![format!("npc.tbl: Texture Npc/NpcSym used by NPC types {:?} does not exist.", npc_types)]
+        );
+    }
+}
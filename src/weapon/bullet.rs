@@ -361,7 +361,7 @@ impl Bullet {
         if (self.flags.hit_left_wall() && self.flags.hit_right_wall()) || (self.flags.hit_top_wall() && self.flags.hit_bottom_wall()) {
             self.cond.set_alive(false);
             state.create_caret(self.x, self.y, CaretType::ProjectileDissipation, Direction::Left);
-            state.sound_manager.play_sfx(28);
+            state.sound_manager.play_sfx_at(28, self.x, self.y);
             return;
         }
 
@@ -1399,7 +1399,7 @@ impl Bullet {
         match self.btype {
             // spur is a special case
             37 | 38 | 39 => state.create_caret(self.x, self.y, CaretType::ProjectileDissipation, Direction::Up),
-            _ => state.sound_manager.play_sfx(28),
+            _ => state.sound_manager.play_sfx_at(28, self.x, self.y),
         }
 
         self.cond.set_alive(false);
@@ -1637,9 +1637,9 @@ impl PhysicalEntity for Bullet {
                             let _ = npc_list.spawn(0x100, npc.clone());
                         }
 
-                        if let Some(tile) = stage.map.tiles.get_mut(stage.map.width as usize * (y + oy) as usize + (x + ox) as usize) {
-                            *tile = tile.wrapping_sub(1);
-                        }
+                        let (tile_x, tile_y) = ((x + ox) as usize, (y + oy) as usize);
+                        let tile = stage.tile_at(tile_x, tile_y);
+                        stage.change_tile(tile_x, tile_y, tile.wrapping_sub(1));
                     }
 
                     self.flags.0 |= old_hit.0;